@@ -0,0 +1,382 @@
+use std::collections::btree_map::Entry;
+use std::collections::VecDeque;
+
+use super::eval::{eval_bool, Bindings, Value};
+use super::simplify::reduce;
+use super::solve::Satisfiability;
+use super::tools::{ToolArgs, ToolHost};
+use super::typecheck::scope;
+use super::{Direction, Engine, Float, Sym, Type};
+
+
+// ============================================================================
+// Model backends
+// ============================================================================
+//
+// An agent's next move comes from a `ModelBackend`: it is given the rendered
+// prompt and the actions enabled at the agent's position, and replies with
+// free text that `decode_action` reads back. Besides clients for real models,
+// two offline backends make whole systems reproducible: `ScriptedBackend`
+// replays canned replies from a file, and `RandomBackend` picks uniformly
+// among the enabled actions, with args that satisfy their guards, from a
+// seed. Each comes with a matching `ToolHost`, so agents that call tools run
+// offline too.
+
+/// What a backend is asked for one turn.
+pub struct ModelRequest<'a> {
+    pub agent: &'a str,
+    pub prompt: &'a str,
+    /// The actions the prompt lists, in its order.
+    pub actions: &'a [Direction<Sym>],
+    /// The agent's position bindings, which the actions' guards read.
+    pub bindings: &'a Bindings,
+}
+
+pub trait ModelBackend {
+    /// The reply to `request`, or why there is none.
+    fn reply(&mut self, engine: &Engine, request: &ModelRequest) -> Result<String, String>;
+}
+
+
+// ============================================================================
+// Scripted backend
+// ============================================================================
+
+/// Canned replies, given out in order. A script separates replies with
+/// `---` lines; `--- Name` makes the reply that follows one for agent `Name`
+/// only, so an agent skips replies meant for others. `--- tool Tool.Call`
+/// sections hold results for `ScriptedTools` instead:
+///
+///     --- Solver
+///     GetWeather[location="Oslo"]
+///     --- tool Weather.GetWeather
+///     "Oslo: -3C, snow"
+///     --- Solver
+///     Answer[response="Cold."]
+#[derive(Clone, Debug, Default)]
+pub struct ScriptedBackend {
+    replies: VecDeque<(Option<String>, String)>,
+}
+
+impl ScriptedBackend {
+    pub fn parse(script: &str) -> Self {
+        let replies = script_sections(script)
+            .into_iter()
+            .filter(|(header, _)| !header.as_deref().is_some_and(|h| h.starts_with("tool ")))
+            .collect();
+        ScriptedBackend { replies }
+    }
+}
+
+/// The non-empty sections of a script, with the names in their `---`
+/// headers.
+fn script_sections(script: &str) -> Vec<(Option<String>, String)> {
+    let mut sections = Vec::new();
+    let mut header = None;
+    let mut text: Vec<&str> = Vec::new();
+    let mut flush = |header: Option<String>, text: &mut Vec<&str>| {
+        let body = text.join("\n").trim().to_string();
+        text.clear();
+        if !body.is_empty() {
+            sections.push((header, body));
+        }
+    };
+    for line in script.lines() {
+        match line.strip_prefix("---") {
+            Some(name) => {
+                flush(header.take(), &mut text);
+                header = Some(name.trim().to_string()).filter(|n| !n.is_empty());
+            }
+            None => text.push(line),
+        }
+    }
+    flush(header, &mut text);
+    sections
+}
+
+impl ModelBackend for ScriptedBackend {
+    fn reply(&mut self, _engine: &Engine, request: &ModelRequest) -> Result<String, String> {
+        let i = self
+            .replies
+            .iter()
+            .position(|(agent, _)| agent.as_deref().is_none_or(|a| a == request.agent))
+            .ok_or_else(|| format!("the script has no reply left for {}", request.agent))?;
+        Ok(self.replies.remove(i).unwrap().1)
+    }
+}
+
+
+// ============================================================================
+// Showing prompts
+// ============================================================================
+
+/// Passes each request on to `inner`, first writing the prompt it carries to
+/// `out`, so that a run with an offline backend still shows what a model
+/// would have been asked.
+pub struct ShowPrompts<W> {
+    pub inner: Box<dyn ModelBackend>,
+    pub out: W,
+}
+
+impl<W: std::io::Write> ModelBackend for ShowPrompts<W> {
+    fn reply(&mut self, engine: &Engine, request: &ModelRequest) -> Result<String, String> {
+        writeln!(self.out, "=== prompt for {}\n{}", request.agent, request.prompt.trim_end())
+            .map_err(|e| format!("could not show the prompt: {e}"))?;
+        self.inner.reply(engine, request)
+    }
+}
+
+
+// ============================================================================
+// Random backend
+// ============================================================================
+
+/// Random args drawn per action before asking the solver for some.
+const RANDOM_TRIES: usize = 32;
+
+const WORDS: &[&str] = &["alpha", "bravo", "charlie", "delta", "echo", "foxtrot", "golf", "hotel"];
+
+/// Uniform over the enabled actions, replying in the text form. Args are
+/// drawn at random until the guard holds; failing that, the solver's model
+/// of the guard is used, and an action it finds none for is dropped from the
+/// draw. The same seed gives the same replies.
+#[derive(Clone, Debug)]
+pub struct RandomBackend {
+    state: u64,
+}
+
+impl RandomBackend {
+    pub fn new(seed: u64) -> Self {
+        RandomBackend { state: seed }
+    }
+
+    /// splitmix64.
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn value(&mut self, engine: &Engine, ty: &Type<Sym>) -> Option<Value> {
+        Some(match ty {
+            Type::Int => Value::Int(self.below(21) as i64 - 10),
+            Type::Float => Value::Float(Float((self.below(81) as f64 - 40.0) / 4.0)),
+            Type::Bool => Value::Bool(self.below(2) == 1),
+            Type::Str => Value::Str(WORDS[self.below(WORDS.len())].to_string()),
+            Type::Named(_) => {
+                let mut samples = engine.sample_values(ty, 0);
+                if samples.is_empty() {
+                    return None;
+                }
+                samples.swap_remove(self.below(samples.len()))
+            }
+        })
+    }
+
+    /// Args for `d` under which its guard holds, if any can be found.
+    fn args(&mut self, engine: &Engine, d: &Direction<Sym>, bindings: &Bindings) -> Option<Bindings> {
+        let holds = |args: &Bindings| match &d.guard {
+            None => true,
+            Some(g) => {
+                let mut scope = bindings.clone();
+                scope.extend(args.clone());
+                matches!(eval_bool(engine, g, &scope), Ok(true))
+            }
+        };
+        for _ in 0..RANDOM_TRIES {
+            let args: Option<Bindings> =
+                d.params.iter().map(|p| Some((p.name, self.value(engine, &p.ty)?))).collect();
+            if let Some(args) = args.filter(&holds) {
+                return Some(args);
+            }
+        }
+        let Satisfiability::Sat(mut model) = engine.satisfiable(&reduce(engine, &scope(&d.params), d.guard.as_ref()?, bindings), &d.params)
+        else {
+            return None;
+        };
+        // The guard may leave some params free.
+        for p in &d.params {
+            if let Entry::Vacant(e) = model.entry(p.name) {
+                e.insert(self.value(engine, &p.ty)?);
+            }
+        }
+        Some(model).filter(holds)
+    }
+}
+
+impl ModelBackend for RandomBackend {
+    fn reply(&mut self, engine: &Engine, request: &ModelRequest) -> Result<String, String> {
+        let mut candidates: Vec<&Direction<Sym>> = request.actions.iter().collect();
+        while !candidates.is_empty() {
+            let d = candidates.remove(self.below(candidates.len()));
+            if let Some(args) = self.args(engine, d, request.bindings) {
+                return Ok(format!("{}{}", engine.resolve(d.name), engine.fmt_bindings(&args)));
+            }
+        }
+        Err(format!("no action enabled for {} has args satisfying its guard", request.agent))
+    }
+}
+
+/// As a tool host: a random value of the call's result type.
+impl ToolHost for RandomBackend {
+    fn invoke(&mut self, engine: &Engine, tool: &str, call: &str, _args: &ToolArgs) -> Result<Value, String> {
+        let result = engine
+            .interner
+            .find(tool)
+            .and_then(|t| engine.tools.get(&t))
+            .and_then(|t| t.calls.iter().find(|c| engine.resolve(c.name) == call))
+            .map(|c| &c.result)
+            .ok_or_else(|| format!("no tool call {tool}.{call}"))?;
+        self.value(engine, &result.ty)
+            .ok_or_else(|| format!("no value of type {} to return", engine.fmt_type(&result.ty)))
+    }
+}
+
+
+// ============================================================================
+// Scripted tool results
+// ============================================================================
+
+/// The `--- tool Tool.Call` sections of a script (see `ScriptedBackend`),
+/// each a literal result, given out in order per call.
+#[derive(Clone, Debug, Default)]
+pub struct ScriptedTools {
+    results: VecDeque<(String, String)>,
+}
+
+impl ScriptedTools {
+    pub fn parse(script: &str) -> Self {
+        let results = script_sections(script)
+            .into_iter()
+            .filter_map(|(header, body)| Some((header?.strip_prefix("tool ")?.trim().to_string(), body)))
+            .collect();
+        ScriptedTools { results }
+    }
+}
+
+impl ToolHost for ScriptedTools {
+    fn invoke(&mut self, engine: &Engine, tool: &str, call: &str, _args: &ToolArgs) -> Result<Value, String> {
+        let key = format!("{tool}.{call}");
+        let i = self
+            .results
+            .iter()
+            .position(|(k, _)| *k == key)
+            .ok_or_else(|| format!("the script has no result left for {key}"))?;
+        let (_, text) = self.results.remove(i).unwrap();
+        engine.parse_value(&text)
+    }
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUCTION: &str = "interface Auction
+            Open[budget: Int] {
+                Bid[amount: Int] if (amount > 0 and amount <= budget) -> Open[budget - amount],
+                Raise[by: Int] if (by > budget and by < 0) -> Open[budget + by],
+                Pass -> Closed
+            },
+            terminal Closed
+        agent Bidder : Auction.Open
+            \"\"\"You have {budget} left.\"\"\"";
+
+    fn request<'a>(actions: &'a [Direction<Sym>], b: &'a Bindings) -> ModelRequest<'a> {
+        ModelRequest { agent: "Bidder", prompt: "", actions, bindings: b }
+    }
+
+    fn open(eng: &Engine, budget: i64) -> (Bindings, Vec<Direction<Sym>>) {
+        let b = Bindings::from([(eng.interner.find("budget").unwrap(), Value::Int(budget))]);
+        let (auction, open) = (eng.interner.find("Auction").unwrap(), eng.interner.find("Open").unwrap());
+        let actions = eng.enabled_directions(auction, open, &b).unwrap().into_iter().cloned().collect();
+        (b, actions)
+    }
+
+    #[test]
+    fn guards_over_args_are_enabled_unless_refuted() {
+        let eng = Engine::load(AUCTION).unwrap_or_else(|e| panic!("{e:?}"));
+        let names = |budget| -> Vec<String> {
+            open(&eng, budget).1.iter().map(|d| eng.resolve(d.name).to_string()).collect()
+        };
+        assert_eq!(names(5), vec!["Bid", "Pass"]);
+        assert_eq!(names(0), vec!["Pass"]);
+        let (b, _) = open(&eng, 5);
+        assert!(eng.render_prompt("Bidder", &b).unwrap().ends_with("  Bid[amount: Int]\n  Pass\n"));
+    }
+
+    #[test]
+    fn random_replies_decode_and_repeat_by_seed() {
+        let eng = Engine::load(AUCTION).unwrap_or_else(|e| panic!("{e:?}"));
+        let (b, actions) = open(&eng, 5);
+        let pos = eng.interfaces[&eng.interner.find("Auction").unwrap()].positions[0].clone();
+        let run = |seed| -> Vec<String> {
+            let mut backend = RandomBackend::new(seed);
+            (0..20).map(|_| backend.reply(&eng, &request(&actions, &b)).unwrap()).collect()
+        };
+        let replies = run(7);
+        assert_eq!(replies, run(7));
+        assert_ne!(replies, run(8));
+        for r in &replies {
+            let d = eng.decode_action(&pos, &b, r).unwrap_or_else(|e| panic!("{r}: {}", eng.fmt_decode_error(&e)));
+            match (eng.resolve(d.action), d.args.values().next()) {
+                ("Bid", Some(Value::Int(n))) => assert!((1..=5).contains(n), "{r}"),
+                ("Pass", None) => {}
+                _ => panic!("unexpected reply {r}"),
+            }
+        }
+        assert!(replies.iter().any(|r| r.starts_with("Bid[")) && replies.iter().any(|r| r == "Pass"));
+
+        let (b, _) = open(&eng, 0);
+        let none: Vec<Direction<Sym>> = actions.iter().filter(|d| eng.resolve(d.name) == "Bid").cloned().collect();
+        assert_eq!(
+            RandomBackend::new(1).reply(&eng, &request(&none, &b)),
+            Err("no action enabled for Bidder has args satisfying its guard".to_string()),
+        );
+    }
+
+    #[test]
+    fn scripts_replay_in_order_per_agent() {
+        let eng = Engine::load(AUCTION).unwrap_or_else(|e| panic!("{e:?}"));
+        let mut script = ScriptedBackend::parse(
+            "Bid[amount=2]\n\
+             --- Seller\n\
+             Close\n\
+             --- Bidder\n\
+             I'll bid once more.\n\
+             {\"action\": \"Bid\", \"amount\": 3}\n\
+             ---\n\
+             Pass\n",
+        );
+        let (b, actions) = open(&eng, 5);
+        let mut ask = |agent| {
+            let req = ModelRequest { agent, ..request(&actions, &b) };
+            script.reply(&eng, &req)
+        };
+        assert_eq!(ask("Bidder").unwrap(), "Bid[amount=2]");
+        assert_eq!(ask("Bidder").unwrap(), "I'll bid once more.\n{\"action\": \"Bid\", \"amount\": 3}");
+        assert_eq!(ask("Bidder").unwrap(), "Pass");
+        assert_eq!(ask("Bidder"), Err("the script has no reply left for Bidder".to_string()));
+        assert_eq!(ask("Seller").unwrap(), "Close");
+    }
+
+    #[test]
+    fn prompts_are_shown_before_the_reply() {
+        let eng = Engine::load(AUCTION).unwrap_or_else(|e| panic!("{e:?}"));
+        let mut shown = ShowPrompts { inner: Box::new(ScriptedBackend::parse("Pass")), out: Vec::new() };
+        let (b, actions) = open(&eng, 5);
+        let req = ModelRequest { prompt: "You have 5 left.\n", ..request(&actions, &b) };
+        assert_eq!(shown.reply(&eng, &req).unwrap(), "Pass");
+        assert_eq!(String::from_utf8(shown.out).unwrap(), "=== prompt for Bidder\nYou have 5 left.\n");
+    }
+}
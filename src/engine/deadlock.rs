@@ -0,0 +1,158 @@
+use super::eval::{conjoin, Bindings};
+use super::simplify::{contains_var, reduce};
+use super::typecheck::scope;
+use super::{Engine, Expr, Interface, Param, Position, Sym, UnOp};
+
+
+// ============================================================================
+// Deadlock detection
+// ============================================================================
+//
+// A position is a deadlock for the parameter values where its guard holds and
+// every direction guard is false, and a dead end if it has no directions at
+// all. For each non-`terminal` position we build
+//
+//     guard ∧ ¬g₁ ∧ … ∧ ¬gₙ
+//
+// and run it through `simplify::reduce`. A `false` result proves the position
+// always has an enabled action; anything else is searched for a concrete
+// witness over small values of the interface and position params.
+//
+// A direction with no guard is always enabled. A direction whose guard
+// mentions its own params is enabled when *some* args satisfy it, which the
+// negation above cannot express, so it is left out of the conjunction; a
+// witness is only reported if no args enable it there either (see
+// `Engine::enabled_directions`).
+
+#[derive(Clone, Debug)]
+pub struct Deadlock {
+    pub interface: Sym,
+    pub position: Sym,
+    pub kind: DeadlockKind,
+    /// The residual of `guard ∧ ¬g₁ ∧ … ∧ ¬gₙ`.
+    pub condition: Expr<Sym>,
+    /// Interface and position bindings with no enabled action, if one was
+    /// found within the search budget.
+    pub witness: Option<Bindings>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeadlockKind {
+    NoDirections,
+    AllGuardsFalse,
+}
+
+impl Engine {
+    /// Deadlocks in every interface, in declaration order of positions.
+    pub fn deadlocks(&self) -> Vec<Deadlock> {
+        self.interfaces
+            .values()
+            .flat_map(|i| i.positions.iter().filter_map(move |p| self.position_deadlock(i, p)))
+            .collect()
+    }
+
+    fn position_deadlock(&self, iface: &Interface<Sym>, pos: &Position<Sym>) -> Option<Deadlock> {
+        if pos.terminal {
+            return None;
+        }
+        let mut parts: Vec<Expr<Sym>> = pos.guard.iter().cloned().collect();
+        let mut over_args = false;
+        for d in &pos.directions {
+            let g = d.guard.as_ref()?;
+            if d.params.iter().any(|p| contains_var(g, p.name)) {
+                over_args = true;
+                continue;
+            }
+            parts.push(Expr::UnOp(UnOp::Not, Box::new(g.clone())));
+        }
+        let kind = if pos.directions.is_empty() {
+            DeadlockKind::NoDirections
+        } else {
+            DeadlockKind::AllGuardsFalse
+        };
+        let full = conjoin(&parts).unwrap_or(Expr::LitBool(true));
+        let params: Vec<&Param<Sym>> = iface.params.iter().chain(&pos.params).collect();
+        let condition = reduce(self, &scope(params.iter().copied()), &full, &Bindings::new());
+        if condition == Expr::LitBool(false) {
+            return None;
+        }
+        let witness = self.find_witness(&params, &full, &condition);
+        if over_args {
+            let b = witness.as_ref()?;
+            if !self.enabled_directions(iface.name, pos.name, b).is_ok_and(|e| e.is_empty()) {
+                return None;
+            }
+        }
+        Some(Deadlock { interface: iface.name, position: pos.name, kind, condition, witness })
+    }
+
+    pub fn fmt_deadlock(&self, d: &Deadlock) -> String {
+        let at = format!("{}.{}", self.resolve(d.interface), self.resolve(d.position));
+        let mut out = match d.kind {
+            DeadlockKind::NoDirections => {
+                format!("{at}: dead end, no directions (mark it `terminal` if intended)\n")
+            }
+            DeadlockKind::AllGuardsFalse => format!(
+                "{at}: no direction is enabled when {}\n",
+                self.fmt_expr(&d.condition, 0),
+            ),
+        };
+        match &d.witness {
+            Some(b) => out.push_str(&format!("  e.g. {at}{}\n", self.fmt_bindings(b))),
+            None => out.push_str("  (no witness found among small values)\n"),
+        }
+        out
+    }
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(src: &str) -> String {
+        let eng = Engine::load(src).unwrap_or_else(|e| panic!("{e:?}"));
+        eng.deadlocks().iter().map(|d| eng.fmt_deadlock(d)).collect()
+    }
+
+    #[test]
+    fn counter_always_has_an_action() {
+        assert_eq!(report(&std::fs::read_to_string("examples/counter.poly").unwrap()), "");
+    }
+
+    #[test]
+    fn one_cell_grid_is_stuck() {
+        let out = report(&std::fs::read_to_string("examples/grid.poly").unwrap());
+        assert!(out.starts_with("Grid.Cell: no direction is enabled when "), "{out}");
+        assert!(out.ends_with("  e.g. Grid.Cell[Width=1, Height=1, c=Coordinate(x=1, y=1)]\n"), "{out}");
+    }
+
+    #[test]
+    fn directions_guarded_by_their_args_are_checked_at_the_witness() {
+        let dial = |turn: &str| {
+            report(&format!(
+                "interface Dial\n    At[n: Int] {{\n        \
+                 Turn[v: Int] if ({turn}) -> At[v],\n        \
+                 Reset if (n > 0) -> At[0]\n    }}",
+            ))
+        };
+        // No `v` lies strictly between `n` and `n + 1`: stuck once Reset is.
+        assert_eq!(dial("v > n and v < n + 1"), "Dial.At: no direction is enabled when n <= 0\n  e.g. Dial.At[n=0]\n");
+        assert_eq!(dial("v > n"), "");
+    }
+
+    #[test]
+    fn terminal_positions_are_excluded() {
+        let body = "interface Loop\n    Working[q: Int] { Answer -> Done[q] },\n    ";
+        let out = report(&format!("{body}Done[r: Int]"));
+        assert_eq!(
+            out,
+            "Loop.Done: dead end, no directions (mark it `terminal` if intended)\n  e.g. Loop.Done[r=0]\n",
+        );
+        assert_eq!(report(&format!("{body}terminal Done[r: Int]")), "");
+    }
+}
@@ -0,0 +1,631 @@
+use super::eval::{eval_bool, Bindings, EvalError, Value};
+use super::literal::{named_arg, split_top_commas};
+use super::{Direction, Engine, Expr, Float, Param, Position, SchemaBody, Sym, Type};
+
+
+// ============================================================================
+// Action decoding
+// ============================================================================
+//
+// A model answers a prompt with free text. `decode_action` reads one of the
+// position's directions out of it, in either of two forms:
+//
+//     Edits[notes="tighten the intro", score=3]
+//     {"action": "Edits", "notes": "tighten the intro", "score": 3}
+//
+// The text form is the one `render_prompt` lists actions in; it may be the
+// whole reply or its last line, and its values are read by
+// `Engine::parse_value`, except that an unquoted value for a `String` param is
+// taken verbatim. The JSON object may sit anywhere in the reply, with the args
+// at its top level or under "args". Either way the args are coerced to the
+// declared types — a record from a JSON object or from a literal like
+// `Task(id=1, title="x")` — and the direction's guard must hold under the
+// position's bindings plus the args. Errors say what to fix, in words meant
+// to be sent back to the model when asking it to try again.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedAction {
+    pub action: Sym,
+    pub args: Bindings,
+}
+
+#[derive(Clone, Debug)]
+pub enum DecodeError {
+    /// Neither form could be read from the reply.
+    Unreadable(String),
+    /// Not a direction at the position; `enabled` are the ones that are.
+    UnknownAction { action: String, enabled: Vec<Sym> },
+    MissingArg { action: Sym, param: Param<Sym> },
+    UnexpectedArg { action: Sym, name: String },
+    BadArg { action: Sym, param: Param<Sym>, reason: String },
+    GuardFailed { action: Sym, guard: Expr<Sym> },
+    EvalFailed(EvalError),
+}
+
+/// An action as read from the reply, before it is checked against the
+/// position.
+struct RawAction {
+    name: String,
+    args: Vec<(String, RawArg)>,
+}
+
+enum RawArg {
+    Text(String),
+    Json(Json),
+}
+
+impl Engine {
+    /// Read the action a reply picks at `pos`, whose params (and the
+    /// interface's) are bound by `bindings`.
+    pub fn decode_action(
+        &self,
+        pos: &Position<Sym>,
+        bindings: &Bindings,
+        reply: &str,
+    ) -> Result<DecodedAction, DecodeError> {
+        let raw = read_reply(reply).map_err(DecodeError::Unreadable)?;
+        let Some(dir) = pos.directions.iter().find(|d| self.resolve(d.name) == raw.name) else {
+            // A guard over the direction's own args can't be judged yet.
+            let enabled = pos
+                .directions
+                .iter()
+                .filter(|d| d.guard.as_ref().is_none_or(|g| !matches!(eval_bool(self, g, bindings), Ok(false))))
+                .map(|d| d.name)
+                .collect();
+            return Err(DecodeError::UnknownAction { action: raw.name, enabled });
+        };
+        let args = self.decode_args(dir, &raw.args)?;
+        if let Some(g) = &dir.guard {
+            let mut scope = bindings.clone();
+            scope.extend(args.clone());
+            if !eval_bool(self, g, &scope).map_err(DecodeError::EvalFailed)? {
+                return Err(DecodeError::GuardFailed { action: dir.name, guard: g.clone() });
+            }
+        }
+        Ok(DecodedAction { action: dir.name, args })
+    }
+
+    fn decode_args(
+        &self,
+        dir: &Direction<Sym>,
+        raw: &[(String, RawArg)],
+    ) -> Result<Bindings, DecodeError> {
+        if let Some((name, _)) =
+            raw.iter().find(|(n, _)| !dir.params.iter().any(|p| self.resolve(p.name) == n))
+        {
+            return Err(DecodeError::UnexpectedArg { action: dir.name, name: name.clone() });
+        }
+        let mut args = Bindings::new();
+        for p in &dir.params {
+            let Some((_, arg)) = raw.iter().find(|(n, _)| self.resolve(p.name) == n) else {
+                return Err(DecodeError::MissingArg { action: dir.name, param: p.clone() });
+            };
+            let value = match arg {
+                RawArg::Text(t) => self.coerce_text(t, &p.ty),
+                RawArg::Json(j) => self.coerce_json(j, &p.ty),
+            };
+            let value = value.map_err(|reason| DecodeError::BadArg {
+                action: dir.name,
+                param: p.clone(),
+                reason,
+            })?;
+            args.insert(p.name, value);
+        }
+        Ok(args)
+    }
+
+    fn coerce_text(&self, text: &str, ty: &Type<Sym>) -> Result<Value, String> {
+        let text = text.trim();
+        if *ty == Type::Str && !text.starts_with('"') {
+            return Ok(Value::Str(text.to_string()));
+        }
+        if *ty == Type::Float {
+            return parse_float(text);
+        }
+        let v = self.parse_value(text)?;
+        self.check_value(&v, ty)?;
+        Ok(v)
+    }
+
+    fn coerce_json(&self, j: &Json, ty: &Type<Sym>) -> Result<Value, String> {
+        let mismatch = || Err(format!("expected {}, got {}", self.fmt_type(ty), j.kind()));
+        match (ty, j) {
+            (Type::Int, Json::Num(n) | Json::Str(n)) => {
+                n.trim().parse().map(Value::Int).map_err(|_| format!("expected an integer, got {n}"))
+            }
+            (Type::Float, Json::Num(n) | Json::Str(n)) => parse_float(n),
+            (Type::Str, Json::Str(s) | Json::Num(s)) => Ok(Value::Str(s.clone())),
+            (Type::Str, Json::Bool(b)) => Ok(Value::Str(b.to_string())),
+            (Type::Bool, Json::Bool(b)) => Ok(Value::Bool(*b)),
+            (Type::Bool, Json::Str(s)) if s == "true" || s == "false" => Ok(Value::Bool(s == "true")),
+            // A literal in `fmt_value`'s form, e.g. "Priority::High".
+            (Type::Named(_), Json::Str(s)) => {
+                let s = s.trim();
+                let bare = self.schemas.get(&self.named(ty)).and_then(|schema| match &schema.body {
+                    SchemaBody::Sum(vs) => vs.iter().find(|v| self.resolve(v.name) == s),
+                    SchemaBody::Record(_) => None,
+                });
+                match bare {
+                    Some(v) => self.coerce_fields(ty, Some(v.name), &[]),
+                    None => self.coerce_text(s, ty),
+                }
+            }
+            (Type::Named(_), Json::Object(fields)) => {
+                let variant = fields.iter().find(|(k, _)| k == "variant");
+                let variant = match variant {
+                    Some((_, Json::Str(name))) => Some(self.variant_named(ty, name)?),
+                    Some(_) => return Err("\"variant\" must be a string".to_string()),
+                    None => None,
+                };
+                let fields: Vec<(String, Json)> =
+                    fields.iter().filter(|(k, _)| k != "variant").cloned().collect();
+                self.coerce_fields(ty, variant, &fields)
+            }
+            _ => mismatch(),
+        }
+    }
+
+    fn named(&self, ty: &Type<Sym>) -> Sym {
+        match ty {
+            Type::Named(s) => *s,
+            _ => unreachable!("only called on schema types"),
+        }
+    }
+
+    /// A record of schema `ty`, or its `variant`, from JSON fields.
+    fn coerce_fields(
+        &self,
+        ty: &Type<Sym>,
+        variant: Option<Sym>,
+        fields: &[(String, Json)],
+    ) -> Result<Value, String> {
+        let schema = self.named(ty);
+        let body = &self.schemas.get(&schema).ok_or_else(|| format!("unknown schema {}", self.resolve(schema)))?.body;
+        let params = match (body, variant) {
+            (SchemaBody::Record(params), None) => params,
+            (SchemaBody::Sum(vs), Some(v)) => &vs.iter().find(|x| x.name == v).unwrap().params,
+            (SchemaBody::Record(_), Some(_)) => {
+                return Err(format!("{} is a record, not a variant", self.resolve(schema)))
+            }
+            (SchemaBody::Sum(_), None) => {
+                return Err(format!("expected a variant of {}, with a \"variant\" key", self.resolve(schema)))
+            }
+        };
+        if let Some((k, _)) = fields.iter().find(|(k, _)| !params.iter().any(|p| self.resolve(p.name) == k)) {
+            return Err(format!("no field `{k}`"));
+        }
+        let mut out = std::collections::BTreeMap::new();
+        for p in params {
+            let field = self.resolve(p.name);
+            let (_, j) = fields
+                .iter()
+                .find(|(k, _)| k == field)
+                .ok_or_else(|| format!("missing field `{field}`"))?;
+            let v = self.coerce_json(j, &p.ty).map_err(|e| format!("field `{field}`: {e}"))?;
+            out.insert(p.name, v);
+        }
+        Ok(match variant {
+            Some(variant) => Value::Variant { schema, variant, fields: out },
+            None => Value::Record { schema, fields: out },
+        })
+    }
+
+    fn variant_named(&self, ty: &Type<Sym>, name: &str) -> Result<Sym, String> {
+        let schema = self.named(ty);
+        let name = name.rsplit("::").next().unwrap_or(name);
+        match self.schemas.get(&schema).map(|s| &s.body) {
+            Some(SchemaBody::Sum(vs)) => vs
+                .iter()
+                .find(|v| self.resolve(v.name) == name)
+                .map(|v| v.name)
+                .ok_or_else(|| format!("{} has no variant {name}", self.resolve(schema))),
+            _ => Err(format!("{} has no variants", self.resolve(schema))),
+        }
+    }
+
+    /// Whether `v`, as `parse_value` read it, is a `ty` all the way down.
+    pub(super) fn check_value(&self, v: &Value, ty: &Type<Sym>) -> Result<(), String> {
+        let params = match (ty, v) {
+            (Type::Int, Value::Int(_))
+            | (Type::Float, Value::Float(_))
+            | (Type::Str, Value::Str(_))
+            | (Type::Bool, Value::Bool(_)) => {
+                return Ok(())
+            }
+            (Type::Named(s), Value::Record { schema, fields }) if s == schema => {
+                match &self.schemas[s].body {
+                    SchemaBody::Record(params) => Some((params, fields)),
+                    SchemaBody::Sum(_) => None,
+                }
+            }
+            (Type::Named(s), Value::Variant { schema, variant, fields }) if s == schema => {
+                match &self.schemas[s].body {
+                    SchemaBody::Sum(vs) => vs.iter().find(|x| x.name == *variant).map(|x| (&x.params, fields)),
+                    SchemaBody::Record(_) => None,
+                }
+            }
+            _ => None,
+        };
+        let Some((params, fields)) = params else {
+            return Err(format!("expected {}, got {}", self.fmt_type(ty), self.fmt_value(v)));
+        };
+        for p in params {
+            let field = self.resolve(p.name);
+            self.check_value(&fields[&p.name], &p.ty).map_err(|e| format!("field `{field}`: {e}"))?;
+        }
+        Ok(())
+    }
+
+    pub fn fmt_decode_error(&self, e: &DecodeError) -> String {
+        match e {
+            DecodeError::Unreadable(why) => format!(
+                "{why}. Reply with exactly one action, as Action[name=value, ...] \
+                 or as a JSON object {{\"action\": \"Action\", \"name\": value, ...}}"
+            ),
+            DecodeError::UnknownAction { action, enabled } if enabled.is_empty() => {
+                format!("`{action}` is not an action you can take now; none are available")
+            }
+            DecodeError::UnknownAction { action, enabled } => {
+                let names: Vec<&str> = enabled.iter().map(|s| self.resolve(*s)).collect();
+                format!(
+                    "`{action}` is not an action you can take now; choose one of: {}",
+                    names.join(", "),
+                )
+            }
+            DecodeError::MissingArg { action, param } => {
+                format!("{} needs {}", self.resolve(*action), self.fmt_param(param))
+            }
+            DecodeError::UnexpectedArg { action, name } => {
+                format!("{} has no parameter `{name}`", self.resolve(*action))
+            }
+            DecodeError::BadArg { action, param, reason } => format!(
+                "{}: {} must be {}: {reason}",
+                self.resolve(*action),
+                self.resolve(param.name),
+                self.fmt_type(&param.ty),
+            ),
+            DecodeError::GuardFailed { action, guard } => format!(
+                "{} is not allowed with these values: {} does not hold",
+                self.resolve(*action),
+                self.fmt_expr(guard, 0),
+            ),
+            DecodeError::EvalFailed(e) => format!("evaluation failed: {}", self.fmt_eval_error(e)),
+        }
+    }
+}
+
+/// A `Float` arg: any finite number, with or without a decimal point.
+fn parse_float(text: &str) -> Result<Value, String> {
+    match text.trim().parse::<f64>() {
+        Ok(x) if x.is_finite() => Ok(Value::Float(Float(x))),
+        _ => Err(format!("expected a number, got {}", text.trim())),
+    }
+}
+
+
+// ============================================================================
+// Reading the reply
+// ============================================================================
+
+fn read_reply(reply: &str) -> Result<RawAction, String> {
+    let text = strip_fences(reply.trim());
+    if text.starts_with('{') {
+        return read_json_action(text);
+    }
+    let last_line = text.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("");
+    for candidate in [text, last_line] {
+        if let Some(raw) = read_text_action(candidate)? {
+            return Ok(raw);
+        }
+    }
+    match text.find('{') {
+        Some(start) => read_json_action(&text[start..]),
+        None => Err("no action found in the reply".to_string()),
+    }
+}
+
+/// The inside of a ```-fenced block, if the reply is one.
+fn strip_fences(s: &str) -> &str {
+    let Some(rest) = s.strip_prefix("```") else { return s };
+    let body = rest.split_once('\n').map_or("", |(_, body)| body);
+    body.trim_end().strip_suffix("```").unwrap_or(body).trim()
+}
+
+/// `Action` or `Action[name=value, ...]`, possibly in backticks. `None` if
+/// `s` doesn't have that shape at all.
+fn read_text_action(s: &str) -> Result<Option<RawAction>, String> {
+    let s = s.trim().trim_matches('`').trim();
+    let (name, args) = match s.find('[') {
+        Some(open) if s.ends_with(']') => (s[..open].trim(), &s[open + 1..s.len() - 1]),
+        Some(_) => return Ok(None),
+        None => (s, ""),
+    };
+    let is_ident = name.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if !is_ident {
+        return Ok(None);
+    }
+    let mut out = Vec::new();
+    for part in split_top_commas(args)? {
+        let (k, v) = named_arg(part)
+            .ok_or_else(|| format!("expected name=value in {name}[...], got `{part}`"))?;
+        out.push((k.to_string(), RawArg::Text(v.to_string())));
+    }
+    Ok(Some(RawAction { name: name.to_string(), args: out }))
+}
+
+/// `{"action": "Name", ...args}` or `{"action": "Name", "args": {...}}`.
+fn read_json_action(s: &str) -> Result<RawAction, String> {
+    let (json, _) = parse_json(s)?;
+    let Json::Object(mut fields) = json else {
+        return Err("the reply's JSON is not an object".to_string());
+    };
+    let name = match fields.iter().position(|(k, _)| k == "action") {
+        Some(i) => match fields.remove(i).1 {
+            Json::Str(name) => name,
+            other => return Err(format!("\"action\" must be a string, got {}", other.kind())),
+        },
+        None => return Err("the reply's JSON object has no \"action\" key".to_string()),
+    };
+    if let Some(i) = fields.iter().position(|(k, _)| k == "args") {
+        match fields.remove(i).1 {
+            Json::Object(args) => fields.extend(args),
+            other => return Err(format!("\"args\" must be an object, got {}", other.kind())),
+        }
+    }
+    let args = fields.into_iter().map(|(k, v)| (k, RawArg::Json(v))).collect();
+    Ok(RawAction { name, args })
+}
+
+
+// ============================================================================
+// JSON
+// ============================================================================
+//
+// Just enough to read a reply: numbers are kept as their text, since the
+// declared type decides what they mean.
+
+#[derive(Clone, Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Num(String),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn kind(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::Bool(_) => "a boolean",
+            Json::Num(_) => "a number",
+            Json::Str(_) => "a string",
+            Json::Array(_) => "an array",
+            Json::Object(_) => "an object",
+        }
+    }
+}
+
+/// One JSON value from the start of `s`, and the byte length it took.
+fn parse_json(s: &str) -> Result<(Json, usize), String> {
+    let mut p = JsonParser { s, at: 0 };
+    let v = p.value()?;
+    Ok((v, p.at))
+}
+
+struct JsonParser<'a> {
+    s: &'a str,
+    at: usize,
+}
+
+impl JsonParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.s[self.at..].chars().next()
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.at += c.len_utf8();
+        }
+    }
+
+    fn eat(&mut self, c: char) -> Result<(), String> {
+        self.skip_ws();
+        if self.peek() == Some(c) {
+            self.at += 1;
+            Ok(())
+        } else {
+            Err(format!("malformed JSON: expected `{c}` at byte {}", self.at))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_ws();
+        let rest = &self.s[self.at..];
+        for (word, v) in [("null", Json::Null), ("true", Json::Bool(true)), ("false", Json::Bool(false))] {
+            if rest.starts_with(word) {
+                self.at += word.len();
+                return Ok(v);
+            }
+        }
+        match self.peek() {
+            Some('"') => self.string().map(Json::Str),
+            Some('[') => {
+                self.at += 1;
+                let mut items = Vec::new();
+                self.skip_ws();
+                if self.peek() == Some(']') {
+                    self.at += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_ws();
+                    if self.peek() == Some(',') {
+                        self.at += 1;
+                    } else {
+                        self.eat(']')?;
+                        return Ok(Json::Array(items));
+                    }
+                }
+            }
+            Some('{') => {
+                self.at += 1;
+                let mut fields = Vec::new();
+                self.skip_ws();
+                if self.peek() == Some('}') {
+                    self.at += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_ws();
+                    let key = self.string()?;
+                    self.eat(':')?;
+                    fields.push((key, self.value()?));
+                    self.skip_ws();
+                    if self.peek() == Some(',') {
+                        self.at += 1;
+                    } else {
+                        self.eat('}')?;
+                        return Ok(Json::Object(fields));
+                    }
+                }
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+                    .unwrap_or(rest.len());
+                self.at += len;
+                Ok(Json::Num(rest[..len].to_string()))
+            }
+            _ => Err(format!("malformed JSON at byte {}", self.at)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.peek() != Some('"') {
+            return Err(format!("malformed JSON: expected a string at byte {}", self.at));
+        }
+        self.at += 1;
+        let mut out = String::new();
+        let mut chars = self.s[self.at..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.at += i + 1;
+                    return Ok(out);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some('r') => out.push('\r'),
+                    Some('b') => out.push('\u{8}'),
+                    Some('f') => out.push('\u{c}'),
+                    Some('u') => {
+                        let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                        let code = u32::from_str_radix(&hex, 16)
+                            .map_err(|_| format!("malformed JSON: bad escape \\u{hex}"))?;
+                        out.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    }
+                    Some(c) => out.push(c),
+                    None => break,
+                },
+                c => out.push(c),
+            }
+        }
+        Err("malformed JSON: unterminated string".to_string())
+    }
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORKER: &str = "schema Task
+            id: Int,
+            description: String
+        schema Priority
+            Low,
+            Urgent[reason: String]
+        interface Worker
+            Waiting {
+                Assign[task: Task, priority: Priority] -> Working[task]
+            },
+            Working[task: Task] {
+                Complete[summary: String, score: Int] if (score >= 0) -> Waiting,
+                Escalate if (task.id > 100) -> Waiting
+            }";
+
+    fn decode(eng: &Engine, pos: &str, bindings: &Bindings, reply: &str) -> Result<DecodedAction, String> {
+        let iface = &eng.interfaces[&eng.interner.find("Worker").unwrap()];
+        let pos = iface.position(&eng.interner.find(pos).unwrap()).unwrap();
+        eng.decode_action(pos, bindings, reply).map_err(|e| eng.fmt_decode_error(&e))
+    }
+
+    fn fmt(eng: &Engine, d: &DecodedAction) -> String {
+        format!("{}{}", eng.resolve(d.action), eng.fmt_bindings(&d.args))
+    }
+
+    #[test]
+    fn text_and_json_forms_agree() {
+        let eng = Engine::load(WORKER).unwrap_or_else(|e| panic!("{e:?}"));
+        let none = Bindings::new();
+        let want = "Assign[task=Task(id=7, description=\"ship it\"), priority=Priority::Urgent(reason=\"prod\")]";
+        for reply in [
+            "Assign[task=Task(id=7, description=\"ship it\"), priority=Priority::Urgent(reason=prod)]",
+            "Let me think.\nThe task is urgent.\n`Assign[priority=Priority::Urgent(reason=\"prod\"), task=Task(7, \"ship it\")]`",
+            "{\"action\": \"Assign\", \"task\": {\"id\": 7, \"description\": \"ship it\"}, \
+              \"priority\": {\"variant\": \"Urgent\", \"reason\": \"prod\"}}",
+            "Sure!\n```json\n{\"action\": \"Assign\", \"args\": {\"task\": {\"id\": \"7\", \"description\": \"ship it\"}, \
+              \"priority\": \"Priority::Urgent(reason=\\\"prod\\\")\"}}\n```",
+        ] {
+            assert_eq!(decode(&eng, "Waiting", &none, reply).map(|d| fmt(&eng, &d)), Ok(want.to_string()), "{reply}");
+        }
+        let d = decode(&eng, "Waiting", &none, "{\"action\": \"Assign\", \"task\": {\"id\": 1, \"description\": \"x\"}, \"priority\": \"Low\"}");
+        assert!(d.is_ok_and(|d| fmt(&eng, &d).ends_with("priority=Priority::Low]")));
+    }
+
+    #[test]
+    fn strings_are_verbatim_and_guards_see_the_args() {
+        let eng = Engine::load(WORKER).unwrap_or_else(|e| panic!("{e:?}"));
+        let task = eng.parse_value("Task(id=7, description=\"ship it\")").unwrap();
+        let b = Bindings::from([(eng.interner.find("task").unwrap(), task)]);
+        let d = decode(&eng, "Working", &b, "Complete[summary=shipped, on time, score=3]");
+        assert_eq!(d.unwrap_err(), "expected name=value in Complete[...], got `on time`. \
+            Reply with exactly one action, as Action[name=value, ...] or as a JSON object {\"action\": \"Action\", \"name\": value, ...}");
+        let d = decode(&eng, "Working", &b, "Complete[summary=shipped on time, score=3]").unwrap();
+        assert_eq!(fmt(&eng, &d), "Complete[summary=\"shipped on time\", score=3]");
+        assert_eq!(
+            decode(&eng, "Working", &b, "Complete[summary=late, score=-1]").unwrap_err(),
+            "Complete is not allowed with these values: score >= 0 does not hold",
+        );
+    }
+
+    #[test]
+    fn errors_say_what_to_fix() {
+        let eng = Engine::load(WORKER).unwrap_or_else(|e| panic!("{e:?}"));
+        let task = eng.parse_value("Task(id=7, description=\"ship it\")").unwrap();
+        let b = Bindings::from([(eng.interner.find("task").unwrap(), task)]);
+        let err = |reply: &str| decode(&eng, "Working", &b, reply).unwrap_err();
+        assert_eq!(err("Finish"), "`Finish` is not an action you can take now; choose one of: Complete");
+        assert_eq!(err("Complete[summary=done]"), "Complete needs score: Int");
+        assert_eq!(err("Complete[summary=done, score=3, mood=good]"), "Complete has no parameter `mood`");
+        assert_eq!(
+            err("{\"action\": \"Complete\", \"summary\": \"done\", \"score\": 2.5}"),
+            "Complete: score must be Int: expected an integer, got 2.5",
+        );
+        assert_eq!(
+            err("Complete[summary=done, score=\"high\"]"),
+            "Complete: score must be Int: expected Int, got \"high\"",
+        );
+        assert!(err("I'm not sure what to do.").starts_with("no action found in the reply. Reply with"));
+        assert!(err("{\"action\": \"Complete\", \"summary\": ").starts_with("malformed JSON"));
+    }
+}
@@ -0,0 +1,165 @@
+use chumsky::error::{Simple, SimpleReason};
+
+use super::Span;
+
+
+// ============================================================================
+// Source-located diagnostics
+// ============================================================================
+//
+// Parse and validation errors are both reduced to a `Diagnostic`: a message
+// plus the byte span it refers to. Rendering needs the original source text,
+// which the engine does not keep, so the caller supplies it.
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self { message: message.into(), span }
+    }
+
+    pub fn from_parse_error(e: &Simple<char>) -> Self {
+        let message = match e.reason() {
+            SimpleReason::Custom(msg) => msg.clone(),
+            SimpleReason::Unclosed { delimiter, .. } => {
+                format!("unclosed delimiter `{delimiter}`")
+            }
+            SimpleReason::Unexpected => {
+                let found = match e.found() {
+                    Some(c) => format!("unexpected `{}`", c.escape_default()),
+                    None => "unexpected end of input".to_string(),
+                };
+                // `#` and whitespace are only ever expected as padding.
+                let mut expected: Vec<String> = e
+                    .expected()
+                    .filter(|c| !matches!(c, Some(c) if *c == '#' || c.is_whitespace()))
+                    .map(|c| match c {
+                        Some(c) => format!("`{}`", c.escape_default()),
+                        None => "end of input".to_string(),
+                    })
+                    .collect();
+                expected.sort();
+                expected.dedup();
+                match expected.len() {
+                    0 => found,
+                    1 => format!("{found}, expected {}", expected[0]),
+                    _ => format!("{found}, expected one of {}", expected.join(", ")),
+                }
+            }
+        };
+        Self { message, span: e.span() }
+    }
+
+    /// Render as `path:line:col` followed by the offending source line with
+    /// the span underlined. Spans that run past the end of their first line
+    /// are underlined up to the line break.
+    pub fn render(&self, src: &str, path: &str) -> String {
+        let span = trim_span(src, &self.span);
+        let (line_no, col, line) = locate(src, span.start);
+        let line_end_off = span.start + (line.len() - byte_col(line, col));
+        let underline_end = span.end.min(line_end_off).max(span.start);
+        let width = src[span.start..underline_end].chars().count().max(1);
+        let gutter = line_no.to_string().len();
+        let pad = " ".repeat(gutter);
+        let lead: String = line
+            .chars()
+            .take(col - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        format!(
+            "error: {msg}\n{pad}--> {path}:{line_no}:{col}\n{pad} |\n{line_no} | {line}\n{pad} | {lead}{carets}\n\n",
+            msg = self.message,
+            carets = "^".repeat(width),
+        )
+    }
+}
+
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// Parsers pad tokens with whitespace and comments on both sides, so raw
+/// spans often start on a blank or comment line. Narrow to the first and last
+/// significant characters.
+fn trim_span(src: &str, span: &Span) -> Span {
+    let end = span.end.min(src.len());
+    let mut start = span.start.min(end);
+    let bytes = src.as_bytes();
+    loop {
+        while start < end && bytes[start].is_ascii_whitespace() {
+            start += 1;
+        }
+        if start < end && bytes[start] == b'#' {
+            while start < end && bytes[start] != b'\n' {
+                start += 1;
+            }
+            continue;
+        }
+        break;
+    }
+    let mut stop = end;
+    while stop > start && bytes[stop - 1].is_ascii_whitespace() {
+        stop -= 1;
+    }
+    start..stop
+}
+
+/// 1-based line number, 1-based column (in chars), and the line text.
+fn locate(src: &str, offset: usize) -> (usize, usize, &str) {
+    let offset = offset.min(src.len());
+    let line_start = src[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line_end = src[offset..].find('\n').map(|i| offset + i).unwrap_or(src.len());
+    let line_no = src[..line_start].matches('\n').count() + 1;
+    let col = src[line_start..offset].chars().count() + 1;
+    (line_no, col, &src[line_start..line_end])
+}
+
+fn byte_col(line: &str, col: usize) -> usize {
+    line.char_indices().nth(col - 1).map(|(i, _)| i).unwrap_or(line.len())
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Engine, EngineError};
+
+    #[test]
+    fn render_points_at_line_and_column() {
+        let src = "interface A\n    X { Go }\n";
+        let d = Diagnostic::new("bad position", 16..24);
+        let out = d.render(src, "a.poly");
+        assert!(out.contains("--> a.poly:2:5"), "{out}");
+        assert!(out.contains("2 |     X { Go }"), "{out}");
+        assert!(out.contains("  |     ^^^^^^^^\n"), "{out}");
+    }
+
+    #[test]
+    fn leading_padding_and_comments_are_skipped() {
+        let src = "interface A\n# note\n    X { Go }\n";
+        // Span starts at the end of line 1, as padded parsers produce.
+        let d = Diagnostic::new("bad position", 11..29);
+        let out = d.render(src, "a.poly");
+        assert!(out.contains("--> a.poly:3:5"), "{out}");
+    }
+
+    #[test]
+    fn parse_errors_render_with_location() {
+        let src = "interface Counter\n    Count[n: Int] if (n >= ) { Tick }\n";
+        let EngineError::Parse(diags) = Engine::load(src).unwrap_err() else {
+            panic!("expected a parse error")
+        };
+        let out = diags[0].render(src, "c.poly");
+        assert!(out.contains("--> c.poly:2:28"), "{out}");
+        assert!(out.starts_with("error: unexpected `)`"), "{out}");
+    }
+}
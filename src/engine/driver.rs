@@ -0,0 +1,452 @@
+use super::backend::{ModelBackend, ModelRequest};
+use super::eval::{fmt_float, Bindings, Value};
+use super::query::QueryError;
+use super::runtime::{Runtime, RuntimeError};
+use super::tools::ToolHost;
+use super::{Engine, Position, Sym};
+
+
+// ============================================================================
+// Agent driver
+// ============================================================================
+//
+// `drive` runs one agent against a backend. Each turn renders the agent's
+// prompt from its instance's bindings, asks the backend, and decodes the
+// reply into either an action at the agent's position or a call to one of
+// its tools. An action fires on the agent's instance, and so on anything
+// linked to it. A tool call goes through `call_tool`, which also steps the
+// agent's instance through the routing defer, and its result is listed in
+// every later prompt. A reply that does not decode, or whose action the
+// runtime rejects, is sent back with the reason, up to `retries` times a
+// turn. The run ends when the agent's instance leaves the agent's position,
+// when nothing is enabled there, or after `max_turns` turns. Every reply and
+// every tool call is reported as a `TraceEvent`; `fmt_trace_event` writes one
+// as a line of JSON.
+
+#[derive(Clone, Debug)]
+pub struct DriveConfig {
+    pub max_turns: usize,
+    /// Extra replies asked for in one turn after a rejected one.
+    pub retries: usize,
+}
+
+impl Default for DriveConfig {
+    fn default() -> Self {
+        DriveConfig { max_turns: 20, retries: 2 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceEvent {
+    /// One reply and what came of it. `state` is the agent's instance when
+    /// it was asked.
+    Turn { turn: usize, attempt: usize, agent: String, state: String, reply: String, outcome: TurnOutcome },
+    ToolCall { turn: usize, tool: String, call: String, args: Bindings, result: Result<Value, String> },
+    Finished { turns: usize, end: RunEnd, state: String },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TurnOutcome {
+    Action { action: String, args: Bindings },
+    ToolCall { tool: String, call: String, args: Bindings },
+    /// Sent back to the backend with this reason.
+    Rejected(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunEnd {
+    /// The agent's instance left the agent's position.
+    Left,
+    OutOfTurns,
+    /// Nothing is enabled at the agent's position.
+    Stuck,
+}
+
+#[derive(Clone, Debug)]
+pub enum DriveError {
+    Query(QueryError),
+    Runtime(RuntimeError),
+    Backend { turn: usize, message: String },
+    /// Every reply in the turn was rejected; `reason` is the last one's.
+    Rejected { turn: usize, reason: String },
+}
+
+impl Runtime {
+    /// Spawn `agent`'s instance, named after it, at its position with
+    /// `bindings`, and an instance of each of its tools (named after the
+    /// tool, unless one exists) linked to it by the routing defer.
+    pub fn start_agent(&mut self, agent: &str, bindings: Bindings) -> Result<(), DriveError> {
+        let a = self.engine.agent(agent).map_err(DriveError::Query)?.clone();
+        let (iface, pos) = (self.engine.resolve(a.interface).to_string(), self.engine.resolve(a.position).to_string());
+        self.spawn(agent, &iface, &pos, bindings).map_err(DriveError::Runtime)?;
+        for t in &a.tools {
+            let tool = self.engine.resolve(*t).to_string();
+            if self.instance(&tool).is_none() {
+                self.spawn(&tool, &tool, "Idle", Bindings::new()).map_err(DriveError::Runtime)?;
+            }
+            let route = self
+                .engine
+                .tool_defer(&a, *t)
+                .map(|d| self.engine.resolve(d.name).to_string())
+                .ok_or_else(|| DriveError::Runtime(RuntimeError::UnknownDefer(format!("{agent}::{tool}"))))?;
+            self.link(&route, agent, &tool).map_err(DriveError::Runtime)?;
+        }
+        Ok(())
+    }
+
+    /// Run `agent`, started with `start_agent`, until it leaves its position
+    /// or the budget runs out, reporting each event to `trace`.
+    pub fn drive(
+        &mut self,
+        agent: &str,
+        backend: &mut dyn ModelBackend,
+        tools: &mut dyn ToolHost,
+        config: &DriveConfig,
+        trace: &mut dyn FnMut(&TraceEvent),
+    ) -> Result<RunEnd, DriveError> {
+        let a = self.engine.agent(agent).map_err(DriveError::Query)?.clone();
+        let calls = self.engine.tool_actions(&a);
+        let mut results: Vec<String> = Vec::new();
+        let mut turns = 0;
+        let end = loop {
+            let inst = self
+                .instance(agent)
+                .ok_or_else(|| DriveError::Runtime(RuntimeError::UnknownInstance(agent.to_string())))?
+                .clone();
+            if inst.interface != a.interface || inst.position != a.position {
+                break RunEnd::Left;
+            }
+            let enabled = self
+                .engine
+                .enabled_directions(a.interface, a.position, &inst.bindings)
+                .map_err(DriveError::Query)?;
+            let mut actions: Vec<_> = enabled.into_iter().cloned().collect();
+            if actions.is_empty() {
+                break RunEnd::Stuck;
+            }
+            if turns == config.max_turns {
+                break RunEnd::OutOfTurns;
+            }
+            turns += 1;
+            actions.extend(calls.iter().cloned());
+            // Decoding sees every direction, so a closed one is reported as such.
+            let mut pos = self.engine.interfaces[&a.interface].position(&a.position).unwrap().clone();
+            let own: Vec<Sym> = pos.directions.iter().map(|d| d.name).collect();
+            pos.directions.extend(calls.iter().cloned());
+
+            let mut prompt = self.engine.render_prompt(agent, &inst.bindings).map_err(DriveError::Query)?;
+            if !results.is_empty() {
+                prompt.push_str("\nTool results so far:\n");
+                for r in &results {
+                    prompt.push_str(&format!("  {r}\n"));
+                }
+            }
+            let state = self.engine.fmt_instance(&inst);
+            let mut asked = prompt.clone();
+            for attempt in 1.. {
+                let request = ModelRequest { agent, prompt: &asked, actions: &actions, bindings: &inst.bindings };
+                let reply = backend
+                    .reply(&self.engine, &request)
+                    .map_err(|message| DriveError::Backend { turn: turns, message })?;
+                let outcome = self.take_reply(agent, &a.tools, &own, &pos, &inst.bindings, &reply)?;
+                trace(&TraceEvent::Turn {
+                    turn: turns,
+                    attempt,
+                    agent: agent.to_string(),
+                    state: state.clone(),
+                    reply,
+                    outcome: outcome.clone(),
+                });
+                match outcome {
+                    TurnOutcome::Rejected(reason) if attempt > config.retries => {
+                        return Err(DriveError::Rejected { turn: turns, reason });
+                    }
+                    TurnOutcome::Rejected(reason) => {
+                        asked = format!("{prompt}\nYour last reply could not be used: {reason}\nReply again.\n");
+                    }
+                    TurnOutcome::Action { .. } => break,
+                    TurnOutcome::ToolCall { tool, call, args } => {
+                        let result = match self.call_tool(&tool, &call, args.clone(), tools) {
+                            Ok((v, _)) => Ok(v),
+                            Err(RuntimeError::ToolFailed { message, .. }) => Err(message),
+                            Err(e) => return Err(DriveError::Runtime(e)),
+                        };
+                        let head = format!("{tool}.{call}{}", self.engine.fmt_bindings(&args));
+                        results.push(match &result {
+                            Ok(v) => format!("{head} = {}", self.engine.fmt_value(v)),
+                            Err(message) => format!("{head} failed: {message}"),
+                        });
+                        trace(&TraceEvent::ToolCall { turn: turns, tool, call, args, result });
+                        break;
+                    }
+                }
+            }
+        };
+        let state = self.instance(agent).map(|i| self.engine.fmt_instance(i)).unwrap_or_default();
+        trace(&TraceEvent::Finished { turns, end, state });
+        Ok(end)
+    }
+
+    /// Decode `reply` at `pos` (the agent's position plus its tool calls)
+    /// and fire it if it is one of the position's `own` actions. Tool calls
+    /// are left to the caller.
+    fn take_reply(
+        &mut self,
+        agent: &str,
+        tools: &[Sym],
+        own: &[Sym],
+        pos: &Position<Sym>,
+        bindings: &Bindings,
+        reply: &str,
+    ) -> Result<TurnOutcome, DriveError> {
+        let eng = &self.engine;
+        let d = match eng.decode_action(pos, bindings, reply) {
+            Ok(d) => d,
+            Err(e) => return Ok(TurnOutcome::Rejected(eng.fmt_decode_error(&e))),
+        };
+        let action = eng.resolve(d.action).to_string();
+        if !own.contains(&d.action) {
+            let tool = tools
+                .iter()
+                .find(|t| eng.tools[*t].calls.iter().any(|c| c.name == d.action))
+                .map(|t| eng.resolve(*t).to_string())
+                .unwrap();
+            return Ok(TurnOutcome::ToolCall { tool, call: action, args: d.args });
+        }
+        match self.apply(agent, &action, d.args.clone()) {
+            Ok(_) => Ok(TurnOutcome::Action { action, args: d.args }),
+            Err(RuntimeError::Rejected { error, .. }) => Ok(TurnOutcome::Rejected(self.engine.fmt_query_error(&error))),
+            Err(e) => Err(DriveError::Runtime(e)),
+        }
+    }
+}
+
+
+// ============================================================================
+// Display
+// ============================================================================
+
+impl Engine {
+    /// One JSON object, on one line. Values are written as JSON the way
+    /// `decode_action` reads them back: records as objects, variants as
+    /// objects with a "variant" key.
+    pub fn fmt_trace_event(&self, e: &TraceEvent) -> String {
+        match e {
+            TraceEvent::Turn { turn, attempt, agent, state, reply, outcome } => {
+                let outcome = match outcome {
+                    TurnOutcome::Action { action, args } => format!(
+                        "\"outcome\":\"action\",\"action\":{},\"args\":{}",
+                        json_str(action),
+                        self.json_bindings(args),
+                    ),
+                    TurnOutcome::ToolCall { tool, call, args } => format!(
+                        "\"outcome\":\"tool_call\",\"tool\":{},\"call\":{},\"args\":{}",
+                        json_str(tool),
+                        json_str(call),
+                        self.json_bindings(args),
+                    ),
+                    TurnOutcome::Rejected(reason) => {
+                        format!("\"outcome\":\"rejected\",\"reason\":{}", json_str(reason))
+                    }
+                };
+                format!(
+                    "{{\"event\":\"turn\",\"turn\":{turn},\"attempt\":{attempt},\"agent\":{},\"state\":{},\"reply\":{},{outcome}}}",
+                    json_str(agent),
+                    json_str(state),
+                    json_str(reply),
+                )
+            }
+            TraceEvent::ToolCall { turn, tool, call, args, result } => {
+                let result = match result {
+                    Ok(v) => format!("\"result\":{}", self.json_value(v)),
+                    Err(message) => format!("\"error\":{}", json_str(message)),
+                };
+                format!(
+                    "{{\"event\":\"tool_call\",\"turn\":{turn},\"tool\":{},\"call\":{},\"args\":{},{result}}}",
+                    json_str(tool),
+                    json_str(call),
+                    self.json_bindings(args),
+                )
+            }
+            TraceEvent::Finished { turns, end, state } => {
+                let end = match end {
+                    RunEnd::Left => "left",
+                    RunEnd::OutOfTurns => "out_of_turns",
+                    RunEnd::Stuck => "stuck",
+                };
+                format!(
+                    "{{\"event\":\"finished\",\"turns\":{turns},\"end\":\"{end}\",\"state\":{}}}",
+                    json_str(state),
+                )
+            }
+        }
+    }
+
+    fn json_value(&self, v: &Value) -> String {
+        match v {
+            Value::Int(n) => n.to_string(),
+            Value::Float(x) if x.0.is_finite() => fmt_float(x.0),
+            Value::Float(_) => "null".to_string(),
+            Value::Bool(b) => b.to_string(),
+            Value::Str(s) => json_str(s),
+            Value::Record { fields, .. } => self.json_bindings(fields),
+            Value::Variant { variant, fields, .. } => {
+                let mut parts = vec![format!("\"variant\":{}", json_str(self.resolve(*variant)))];
+                parts.extend(fields.iter().map(|(k, v)| format!("{}:{}", json_str(self.resolve(*k)), self.json_value(v))));
+                format!("{{{}}}", parts.join(","))
+            }
+        }
+    }
+
+    fn json_bindings(&self, b: &Bindings) -> String {
+        let parts: Vec<String> =
+            b.iter().map(|(k, v)| format!("{}:{}", json_str(self.resolve(*k)), self.json_value(v))).collect();
+        format!("{{{}}}", parts.join(","))
+    }
+
+    pub fn fmt_drive_error(&self, e: &DriveError) -> String {
+        match e {
+            DriveError::Query(e) => self.fmt_query_error(e),
+            DriveError::Runtime(e) => self.fmt_runtime_error(e),
+            DriveError::Backend { turn, message } => format!("turn {turn}: the backend failed: {message}"),
+            DriveError::Rejected { turn, reason } => {
+                format!("turn {turn}: no usable reply after retries; last: {reason}")
+            }
+        }
+    }
+}
+
+fn json_str(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::backend::{RandomBackend, ScriptedBackend, ScriptedTools};
+    use super::*;
+
+    fn tool_use() -> Runtime {
+        let src = std::fs::read_to_string("examples/01_tool_use/tool_use.poly").unwrap();
+        Runtime::new(Engine::load(&src).unwrap_or_else(|e| panic!("{e:?}")))
+    }
+
+    fn question(rt: &Runtime, q: &str) -> Bindings {
+        Bindings::from([(rt.engine.interner.find("question").unwrap(), Value::Str(q.into()))])
+    }
+
+    #[test]
+    fn scripted_run_calls_a_tool_then_answers() {
+        let script = std::fs::read_to_string("examples/01_tool_use/solver.script").unwrap();
+        let mut rt = tool_use();
+        rt.start_agent("Solver", question(&rt, "What's the weather in Oslo?")).unwrap();
+        let mut events = Vec::new();
+        let end = rt
+            .drive(
+                "Solver",
+                &mut ScriptedBackend::parse(&script),
+                &mut ScriptedTools::parse(&script),
+                &DriveConfig::default(),
+                &mut |e| events.push(e.clone()),
+            )
+            .unwrap();
+        assert_eq!(end, RunEnd::Left);
+        let events: Vec<String> = events.iter().map(|e| rt.engine.fmt_trace_event(e)).collect();
+        let kinds: Vec<&str> = events.iter().map(|e| e.split(',').next().unwrap()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                "{\"event\":\"turn\"",
+                "{\"event\":\"tool_call\"",
+                "{\"event\":\"turn\"",
+                "{\"event\":\"turn\"",
+                "{\"event\":\"finished\"",
+            ],
+        );
+        assert!(events[1].ends_with("\"args\":{\"location\":\"Oslo\"},\"result\":\"Oslo: -3C, snow\"}"));
+        assert!(events[2].contains("\"outcome\":\"rejected\",\"reason\":\"Answer needs response: String\""));
+        assert_eq!(
+            events[4],
+            "{\"event\":\"finished\",\"turns\":2,\"end\":\"left\",\
+             \"state\":\"Loop.Done[response=\\\"It is -3C and snowing in Oslo.\\\"]\"}",
+        );
+        assert_eq!(rt.engine.fmt_instance(rt.instance("Weather").unwrap()), "Weather.Idle");
+    }
+
+    #[test]
+    fn the_prompt_carries_tools_results_and_retry_reasons() {
+        struct Echo(Vec<String>);
+        impl ModelBackend for Echo {
+            fn reply(&mut self, _: &Engine, request: &ModelRequest) -> Result<String, String> {
+                self.0.push(request.prompt.to_string());
+                Ok(["GetCurrentTime", "Answer[]", "Answer[response=noon]"][self.0.len() - 1].to_string())
+            }
+        }
+        let mut rt = tool_use();
+        rt.start_agent("Solver", question(&rt, "What time is it?")).unwrap();
+        let mut backend = Echo(Vec::new());
+        let mut tools = ScriptedTools::parse("--- tool Clock.GetCurrentTime\n\"12:00\"");
+        rt.drive("Solver", &mut backend, &mut tools, &DriveConfig::default(), &mut |_| {}).unwrap();
+        let prompts = backend.0;
+        assert!(prompts[0].ends_with(
+            "Reply with exactly one of these actions:\n  Answer[response: String]\n\n\
+             Or call one of these tools:\n  Calculate[expression: String]\n  GetWeather[location: String]\n  \
+             GetCurrentTime\n",
+        ));
+        assert!(prompts[1].ends_with("\nTool results so far:\n  Clock.GetCurrentTime = \"12:00\"\n"));
+        assert!(prompts[2].ends_with(
+            "  Clock.GetCurrentTime = \"12:00\"\n\
+             \nYour last reply could not be used: Answer needs response: String\nReply again.\n",
+        ));
+    }
+
+    #[test]
+    fn budgets_and_retries_bound_the_run() {
+        let mut rt = tool_use();
+        rt.start_agent("Solver", question(&rt, "2+2?")).unwrap();
+        let config = DriveConfig { max_turns: 3, retries: 1 };
+        let mut calls = ScriptedBackend::parse("Calculate[expression=\"2+2\"]\n---\n".repeat(5).as_str());
+        let mut tools = RandomBackend::new(3);
+        let mut events = Vec::new();
+        let end = rt.drive("Solver", &mut calls, &mut tools, &config, &mut |e| events.push(e.clone())).unwrap();
+        assert_eq!(end, RunEnd::OutOfTurns);
+        assert!(matches!(events.last(), Some(TraceEvent::Finished { turns: 3, .. })));
+        // Deterministic: the cached result answers the repeated calls.
+        let results: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                TraceEvent::ToolCall { result, .. } => Some(result.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|r| r == &results[0] && r.is_ok()));
+
+        let mut rt = tool_use();
+        rt.start_agent("Solver", question(&rt, "2+2?")).unwrap();
+        let mut confused = ScriptedBackend::parse("I don't know.\n---\nFinish");
+        let err = rt.drive("Solver", &mut confused, &mut tools, &config, &mut |_| {}).unwrap_err();
+        assert_eq!(
+            rt.engine.fmt_drive_error(&err),
+            "turn 1: no usable reply after retries; last: `Finish` is not an action you can take now; \
+             choose one of: Answer, Calculate, GetWeather, GetCurrentTime",
+        );
+    }
+}
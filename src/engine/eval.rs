@@ -1,5 +1,6 @@
 use std::collections::BTreeMap;
-use super::{BinOp, Engine, Expr, SchemaBody, Sym, UnOp};
+use super::literal::escape_str;
+use super::{BinOp, Engine, Expr, Float, SchemaBody, Sym, UnOp};
 
 
 // ============================================================================
@@ -26,9 +27,11 @@ use super::{BinOp, Engine, Expr, SchemaBody, Sym, UnOp};
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Value {
     Int(i64),
+    Float(Float),
     Bool(bool),
     Str(String),
     Record { schema: Sym, fields: BTreeMap<Sym, Value> },
+    Variant { schema: Sym, variant: Sym, fields: BTreeMap<Sym, Value> },
 }
 
 pub type Bindings = BTreeMap<Sym, Value>;
@@ -39,10 +42,13 @@ pub enum EvalError {
     UnknownSchema(Sym),
     UnknownField { schema: Sym, field: Sym },
     NotARecord,
-    SumNotSupported(Sym),
+    NotASum,
+    NotARecordSchema(Sym),
+    UnknownVariant { schema: Sym, variant: Sym },
     ConstructArity { schema: Sym, expected: usize, got: usize },
     TypeMismatch { op: &'static str },
     DivByZero,
+    Overflow,
 }
 
 
@@ -53,6 +59,7 @@ pub enum EvalError {
 pub fn eval(eng: &Engine, e: &Expr<Sym>, b: &Bindings) -> Result<Value, EvalError> {
     match e {
         Expr::LitInt(n) => Ok(Value::Int(*n)),
+        Expr::LitFloat(x) => Ok(Value::Float(*x)),
         Expr::LitStr(s) => Ok(Value::Str(s.clone())),
         Expr::LitBool(v) => Ok(Value::Bool(*v)),
         Expr::Var(s) => b.get(s).cloned().ok_or(EvalError::Unbound(*s)),
@@ -83,17 +90,36 @@ pub fn eval(eng: &Engine, e: &Expr<Sym>, b: &Bindings) -> Result<Value, EvalErro
                     }
                     Ok(Value::Record { schema: *name, fields })
                 }
-                SchemaBody::Sum(_) => Err(EvalError::SumNotSupported(*name)),
+                SchemaBody::Sum(_) => Err(EvalError::NotARecordSchema(*name)),
             }
         }
-        Expr::UnOp(op, inner) => {
-            let v = eval(eng, inner, b)?;
-            match (op, v) {
-                (UnOp::Neg, Value::Int(n)) => Ok(Value::Int(-n)),
-                (UnOp::Not, Value::Bool(p)) => Ok(Value::Bool(!p)),
-                _ => Err(EvalError::TypeMismatch { op: "unary" }),
+        Expr::Variant(name, variant, args) => {
+            let schema = eng.schemas.get(name).ok_or(EvalError::UnknownSchema(*name))?;
+            let SchemaBody::Sum(variants) = &schema.body else {
+                return Err(EvalError::NotASum);
+            };
+            let v = variants
+                .iter()
+                .find(|v| v.name == *variant)
+                .ok_or(EvalError::UnknownVariant { schema: *name, variant: *variant })?;
+            if v.params.len() != args.len() {
+                return Err(EvalError::ConstructArity {
+                    schema: *name,
+                    expected: v.params.len(),
+                    got: args.len(),
+                });
             }
+            let mut fields: BTreeMap<Sym, Value> = BTreeMap::new();
+            for (p, a) in v.params.iter().zip(args.iter()) {
+                fields.insert(p.name, eval(eng, a, b)?);
+            }
+            Ok(Value::Variant { schema: *name, variant: *variant, fields })
         }
+        Expr::Is(inner, variant) => match eval(eng, inner, b)? {
+            Value::Variant { variant: v, .. } => Ok(Value::Bool(v == *variant)),
+            _ => Err(EvalError::NotASum),
+        },
+        Expr::UnOp(op, inner) => eval_unop(*op, eval(eng, inner, b)?),
         Expr::BinOp(op, l, r) => {
             let lv = eval(eng, l, b)?;
             let rv = eval(eng, r, b)?;
@@ -106,18 +132,29 @@ fn eval_binop(op: BinOp, l: Value, r: Value) -> Result<Value, EvalError> {
     use BinOp::*;
     use Value::*;
     match (op, l, r) {
-        (Add, Int(a), Int(b)) => Ok(Int(a + b)),
-        (Sub, Int(a), Int(b)) => Ok(Int(a - b)),
-        (Mul, Int(a), Int(b)) => Ok(Int(a * b)),
+        (Add, Int(a), Int(b)) => a.checked_add(b).map(Int).ok_or(EvalError::Overflow),
+        (Sub, Int(a), Int(b)) => a.checked_sub(b).map(Int).ok_or(EvalError::Overflow),
+        (Mul, Int(a), Int(b)) => a.checked_mul(b).map(Int).ok_or(EvalError::Overflow),
         (Div, Int(_), Int(0)) | (Mod, Int(_), Int(0)) => Err(EvalError::DivByZero),
-        (Div, Int(a), Int(b)) => Ok(Int(a / b)),
-        (Mod, Int(a), Int(b)) => Ok(Int(a % b)),
+        (Div, Int(a), Int(b)) => a.checked_div(b).map(Int).ok_or(EvalError::Overflow),
+        (Mod, Int(a), Int(b)) => a.checked_rem(b).map(Int).ok_or(EvalError::Overflow),
+        (Add, Float(a), Float(b)) => Ok(Float(super::Float(a.0 + b.0))),
+        (Sub, Float(a), Float(b)) => Ok(Float(super::Float(a.0 - b.0))),
+        (Mul, Float(a), Float(b)) => Ok(Float(super::Float(a.0 * b.0))),
+        (Div, Float(_), Float(b)) if b.0 == 0.0 => Err(EvalError::DivByZero),
+        (Div, Float(a), Float(b)) => Ok(Float(super::Float(a.0 / b.0))),
+        (Eq, Float(a), Float(b)) => Ok(Bool(a.0 == b.0)),
+        (Neq, Float(a), Float(b)) => Ok(Bool(a.0 != b.0)),
         (Eq, a, b) => Ok(Bool(a == b)),
         (Neq, a, b) => Ok(Bool(a != b)),
         (Lt, Int(a), Int(b)) => Ok(Bool(a < b)),
         (Le, Int(a), Int(b)) => Ok(Bool(a <= b)),
         (Gt, Int(a), Int(b)) => Ok(Bool(a > b)),
         (Ge, Int(a), Int(b)) => Ok(Bool(a >= b)),
+        (Lt, Float(a), Float(b)) => Ok(Bool(a.0 < b.0)),
+        (Le, Float(a), Float(b)) => Ok(Bool(a.0 <= b.0)),
+        (Gt, Float(a), Float(b)) => Ok(Bool(a.0 > b.0)),
+        (Ge, Float(a), Float(b)) => Ok(Bool(a.0 >= b.0)),
         (And, Bool(a), Bool(b)) => Ok(Bool(a && b)),
         (Or, Bool(a), Bool(b)) => Ok(Bool(a || b)),
         _ => Err(EvalError::TypeMismatch { op: "binary" }),
@@ -133,7 +170,7 @@ pub fn eval_bool(eng: &Engine, e: &Expr<Sym>, b: &Bindings) -> Result<bool, Eval
 
 pub fn const_fold(eng: &Engine, e: &Expr<Sym>, b: &Bindings) -> Expr<Sym> {
     match e {
-        Expr::LitInt(_) | Expr::LitStr(_) | Expr::LitBool(_) => e.clone(),
+        Expr::LitInt(_) | Expr::LitFloat(_) | Expr::LitStr(_) | Expr::LitBool(_) => e.clone(),
         Expr::Var(s) => match b.get(s) {
             Some(v) => value_to_expr(eng, v).unwrap_or_else(|| e.clone()),
             None => e.clone(),
@@ -178,12 +215,24 @@ pub fn const_fold(eng: &Engine, e: &Expr<Sym>, b: &Bindings) -> Expr<Sym> {
             let args_s: Vec<_> = args.iter().map(|a| const_fold(eng, a, b)).collect();
             Expr::Construct(*name, args_s)
         }
+        Expr::Variant(schema, variant, args) => {
+            let args_s: Vec<_> = args.iter().map(|a| const_fold(eng, a, b)).collect();
+            Expr::Variant(*schema, *variant, args_s)
+        }
+        Expr::Is(inner, variant) => {
+            let inner_s = const_fold(eng, inner, b);
+            if let Expr::Variant(_, v, _) = &inner_s {
+                return Expr::LitBool(v == variant);
+            }
+            Expr::Is(Box::new(inner_s), *variant)
+        }
     }
 }
 
 fn eval_unop(op: UnOp, v: Value) -> Result<Value, EvalError> {
     match (op, v) {
-        (UnOp::Neg, Value::Int(n)) => Ok(Value::Int(-n)),
+        (UnOp::Neg, Value::Int(n)) => n.checked_neg().map(Value::Int).ok_or(EvalError::Overflow),
+        (UnOp::Neg, Value::Float(x)) => Ok(Value::Float(Float(-x.0))),
         (UnOp::Not, Value::Bool(p)) => Ok(Value::Bool(!p)),
         _ => Err(EvalError::TypeMismatch { op: "unary" }),
     }
@@ -192,6 +241,7 @@ fn eval_unop(op: UnOp, v: Value) -> Result<Value, EvalError> {
 fn expr_as_value(e: &Expr<Sym>) -> Option<Value> {
     match e {
         Expr::LitInt(n) => Some(Value::Int(*n)),
+        Expr::LitFloat(x) => Some(Value::Float(*x)),
         Expr::LitBool(p) => Some(Value::Bool(*p)),
         Expr::LitStr(s) => Some(Value::Str(s.clone())),
         _ => None,
@@ -201,6 +251,7 @@ fn expr_as_value(e: &Expr<Sym>) -> Option<Value> {
 fn value_to_expr(eng: &Engine, v: &Value) -> Option<Expr<Sym>> {
     match v {
         Value::Int(n) => Some(Expr::LitInt(*n)),
+        Value::Float(x) => Some(Expr::LitFloat(*x)),
         Value::Bool(p) => Some(Expr::LitBool(*p)),
         Value::Str(s) => Some(Expr::LitStr(s.clone())),
         Value::Record { schema, fields } => {
@@ -212,6 +263,17 @@ fn value_to_expr(eng: &Engine, v: &Value) -> Option<Expr<Sym>> {
                 .collect();
             Some(Expr::Construct(*schema, args?))
         }
+        Value::Variant { schema, variant, fields } => {
+            let s = eng.schemas.get(schema)?;
+            let SchemaBody::Sum(variants) = &s.body else { return None };
+            let v = variants.iter().find(|v| v.name == *variant)?;
+            let args: Option<Vec<Expr<Sym>>> = v
+                .params
+                .iter()
+                .map(|p| fields.get(&p.name).and_then(|fv| value_to_expr(eng, fv)))
+                .collect();
+            Some(Expr::Variant(*schema, *variant, args?))
+        }
     }
 }
 
@@ -232,8 +294,9 @@ impl Engine {
     pub fn fmt_value(&self, v: &Value) -> String {
         match v {
             Value::Int(n) => n.to_string(),
+            Value::Float(x) => fmt_float(x.0),
             Value::Bool(b) => b.to_string(),
-            Value::Str(s) => format!("\"{s}\""),
+            Value::Str(s) => escape_str(s),
             Value::Record { schema, fields } => {
                 let parts: Vec<String> = fields
                     .iter()
@@ -241,6 +304,17 @@ impl Engine {
                     .collect();
                 format!("{}({})", self.resolve(*schema), parts.join(", "))
             }
+            Value::Variant { schema, variant, fields } => {
+                let head = format!("{}::{}", self.resolve(*schema), self.resolve(*variant));
+                if fields.is_empty() {
+                    return head;
+                }
+                let parts: Vec<String> = fields
+                    .iter()
+                    .map(|(k, v)| format!("{}={}", self.resolve(*k), self.fmt_value(v)))
+                    .collect();
+                format!("{head}({})", parts.join(", "))
+            }
         }
     }
 
@@ -265,9 +339,17 @@ impl Engine {
                 self.resolve(*field),
             ),
             EvalError::NotARecord => "field access on non-record value".to_string(),
-            EvalError::SumNotSupported(s) => {
-                format!("sum constructors not yet supported: {}", self.resolve(*s))
-            }
+            EvalError::NotASum => "`is` test on non-variant value".to_string(),
+            EvalError::NotARecordSchema(s) => format!(
+                "{} is a sum schema; construct it with {}::<Variant>",
+                self.resolve(*s),
+                self.resolve(*s),
+            ),
+            EvalError::UnknownVariant { schema, variant } => format!(
+                "schema {} has no variant {}",
+                self.resolve(*schema),
+                self.resolve(*variant),
+            ),
             EvalError::ConstructArity { schema, expected, got } => format!(
                 "constructor {} has {} arg(s), expected {}",
                 self.resolve(*schema),
@@ -276,6 +358,80 @@ impl Engine {
             ),
             EvalError::TypeMismatch { op } => format!("type mismatch in {op} expression"),
             EvalError::DivByZero => "division by zero".to_string(),
+            EvalError::Overflow => "integer overflow".to_string(),
         }
     }
 }
+
+/// Always with a decimal point, so a `Float` reads back as one.
+pub(super) fn fmt_float(x: f64) -> String {
+    let s = x.to_string();
+    if x.is_finite() && !s.contains('.') { format!("{s}.0") } else { s }
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load() -> Engine {
+        let src = std::fs::read_to_string("examples/task_router.poly").unwrap();
+        Engine::load(&src).expect("load task_router")
+    }
+
+    fn guard(eng: &Engine, action: &str) -> Expr<Sym> {
+        let router = &eng.interfaces[&eng.interner.find("Router").unwrap()];
+        let inbox = router.position(&eng.interner.find("Inbox").unwrap()).unwrap();
+        let action = eng.interner.find(action).unwrap();
+        inbox.directions.iter().find(|d| d.name == action).unwrap().guard.clone().unwrap()
+    }
+
+    fn task(eng: &Engine, priority: Value) -> Bindings {
+        let sym = |s: &str| eng.interner.find(s).unwrap();
+        let fields = BTreeMap::from([
+            (sym("title"), Value::Str("t".into())),
+            (sym("priority"), priority),
+        ]);
+        Bindings::from([(sym("task"), Value::Record { schema: sym("Task"), fields })])
+    }
+
+    #[test]
+    fn is_tests_the_variant_tag() {
+        let eng = load();
+        let sym = |s: &str| eng.interner.find(s).unwrap();
+        let urgent = Value::Variant {
+            schema: sym("Priority"),
+            variant: sym("Urgent"),
+            fields: BTreeMap::from([(sym("reason"), Value::Str("down".into()))]),
+        };
+        let low = Value::Variant {
+            schema: sym("Priority"),
+            variant: sym("Low"),
+            fields: BTreeMap::new(),
+        };
+        let g = guard(&eng, "Escalate");
+        assert!(eval_bool(&eng, &g, &task(&eng, urgent)).unwrap());
+        assert!(!eval_bool(&eng, &g, &task(&eng, low)).unwrap());
+    }
+
+    #[test]
+    fn variants_compare_structurally_and_fold() {
+        let eng = load();
+        let sym = |s: &str| eng.interner.find(s).unwrap();
+        let high = Expr::Variant(sym("Priority"), sym("High"), vec![]);
+        let low = Expr::Variant(sym("Priority"), sym("Low"), vec![]);
+        let eq = |l: &Expr<Sym>, r: &Expr<Sym>| {
+            Expr::BinOp(BinOp::Eq, Box::new(l.clone()), Box::new(r.clone()))
+        };
+        let b = Bindings::new();
+        assert!(eval_bool(&eng, &eq(&high, &high), &b).unwrap());
+        assert!(!eval_bool(&eng, &eq(&high, &low), &b).unwrap());
+        let is_high = Expr::Is(Box::new(high.clone()), sym("High"));
+        assert_eq!(const_fold(&eng, &is_high, &b), Expr::LitBool(true));
+        assert_eq!(eng.fmt_value(&eval(&eng, &high, &b).unwrap()), "Priority::High");
+    }
+}
@@ -1,3 +1,5 @@
+use super::eval::Value;
+use super::trajectory::Event;
 use super::*;
 
 
@@ -23,10 +25,12 @@ pub struct IfaceFact {
     pub params: Vec<Param<Sym>>,
 }
 
+/// `state_block(S, Params)`: a state block. Its positions share the
+/// `position/4` relation with interfaces'.
 #[derive(Clone, Debug)]
-pub struct IfaceInternalFact {
-    pub internal: Sym,
-    pub external: Sym,
+pub struct StateBlockFact {
+    pub state: Sym,
+    pub params: Vec<Param<Sym>>,
 }
 
 #[derive(Clone, Debug)]
@@ -81,6 +85,32 @@ pub struct DeferDirFact {
     pub source_dir: DirRef<Sym>,
 }
 
+/// `instance(Inst, I)`: a runtime instance of interface `I`, from a spawn.
+#[derive(Clone, Debug)]
+pub struct InstanceFact {
+    pub instance: Sym,
+    pub iface: Sym,
+}
+
+/// `step(T, Inst, P, A, P2)`: event `T` moved `Inst` from `P` to `P2` by `A`.
+#[derive(Clone, Debug)]
+pub struct StepFact {
+    pub seq: usize,
+    pub instance: Sym,
+    pub position: Sym,
+    pub action: Sym,
+    pub target_pos: Sym,
+}
+
+/// `binding(T, Var, Value)`: what `Var` was bound to when event `T` fired —
+/// the instance's bindings plus any action args, i.e. what the guards saw.
+#[derive(Clone, Debug)]
+pub struct BindingFact {
+    pub seq: usize,
+    pub name: Sym,
+    pub value: Value,
+}
+
 
 // ============================================================================
 // Container
@@ -91,13 +121,16 @@ pub struct Facts {
     pub schema_records: Vec<SchemaRecordFact>,
     pub schema_sums: Vec<SchemaSumFact>,
     pub ifaces: Vec<IfaceFact>,
-    pub iface_internals: Vec<IfaceInternalFact>,
+    pub state_blocks: Vec<StateBlockFact>,
     pub positions: Vec<PositionFact>,
     pub directions: Vec<DirectionFact>,
     pub transitions: Vec<TransitionFact>,
     pub defers: Vec<DeferFact>,
     pub defer_entries: Vec<DeferEntryFact>,
     pub defer_dirs: Vec<DeferDirFact>,
+    pub instances: Vec<InstanceFact>,
+    pub steps: Vec<StepFact>,
+    pub bindings: Vec<BindingFact>,
 }
 
 
@@ -105,8 +138,6 @@ pub struct Facts {
 // Projection
 // ============================================================================
 
-const INTERNAL_SUFFIX: &str = "::Internal";
-
 const PREC_TOP: u8 = 0;
 
 impl Engine {
@@ -132,18 +163,6 @@ impl Engine {
                 params: iface.params.clone(),
             });
 
-            let name = self.resolve(iface.name);
-            if let Some(stripped) = name.strip_suffix(INTERNAL_SUFFIX) {
-                if let Some(ext_sym) = self.interner.find(stripped) {
-                    if self.interfaces.contains_key(&ext_sym) {
-                        f.iface_internals.push(IfaceInternalFact {
-                            internal: iface.name,
-                            external: ext_sym,
-                        });
-                    }
-                }
-            }
-
             for pos in &iface.positions {
                 f.positions.push(PositionFact {
                     iface: iface.name,
@@ -172,6 +191,21 @@ impl Engine {
             }
         }
 
+        for st in self.states.values() {
+            f.state_blocks.push(StateBlockFact {
+                state: st.name,
+                params: st.params.clone(),
+            });
+            for pos in &st.positions {
+                f.positions.push(PositionFact {
+                    iface: st.name,
+                    position: pos.name,
+                    params: pos.params.clone(),
+                    guard: pos.guard.clone(),
+                });
+            }
+        }
+
         for d in &self.defers {
             f.defers.push(DeferFact {
                 defer: d.name,
@@ -202,6 +236,38 @@ impl Engine {
         f
     }
 
+    /// Add the history relations for a trajectory. Instance names are
+    /// interned so queries can name them like any other constant.
+    pub fn add_trajectory_facts(&mut self, f: &mut Facts, events: &[Event]) {
+        for e in events {
+            let seq = e.seq() as usize;
+            let Some(instance) = e.instance() else { continue };
+            let instance = self.interner.intern(instance);
+            let seen: Vec<(Sym, Value)> = match e {
+                Event::Spawn { interface, bindings, .. } => {
+                    f.instances.push(InstanceFact { instance, iface: *interface });
+                    bindings.clone().into_iter().collect()
+                }
+                Event::Step { source, source_bindings, action, args, target, .. } => {
+                    f.steps.push(StepFact {
+                        seq,
+                        instance,
+                        position: *source,
+                        action: *action,
+                        target_pos: *target,
+                    });
+                    let mut b = source_bindings.clone();
+                    b.extend(args.clone());
+                    b.into_iter().collect()
+                }
+                Event::Link { .. } | Event::Restore { .. } => continue,
+            };
+            for (name, value) in seen {
+                f.bindings.push(BindingFact { seq, name, value });
+            }
+        }
+    }
+
     pub fn fmt_facts(&self, facts: &Facts) -> String {
         let mut out = String::new();
         let mut emit = |buf: &mut String, lines: Vec<String>| {
@@ -264,13 +330,13 @@ impl Engine {
         emit(&mut out, lines);
 
         let lines: Vec<String> = facts
-            .iface_internals
+            .state_blocks
             .iter()
-            .map(|i| {
+            .map(|s| {
                 format!(
-                    "iface_internal({}, {}).",
-                    self.resolve(i.internal),
-                    self.resolve(i.external),
+                    "state_block({}, {}).",
+                    self.resolve(s.state),
+                    self.fmt_param_list(&s.params),
                 )
             })
             .collect();
@@ -374,6 +440,43 @@ impl Engine {
             .collect();
         emit(&mut out, lines);
 
+        let lines: Vec<String> = facts
+            .instances
+            .iter()
+            .map(|i| format!("instance({}, {}).", self.resolve(i.instance), self.resolve(i.iface)))
+            .collect();
+        emit(&mut out, lines);
+
+        let lines: Vec<String> = facts
+            .steps
+            .iter()
+            .map(|s| {
+                format!(
+                    "step({}, {}, {}, {}, {}).",
+                    s.seq,
+                    self.resolve(s.instance),
+                    self.resolve(s.position),
+                    self.resolve(s.action),
+                    self.resolve(s.target_pos),
+                )
+            })
+            .collect();
+        emit(&mut out, lines);
+
+        let lines: Vec<String> = facts
+            .bindings
+            .iter()
+            .map(|b| {
+                format!(
+                    "binding({}, {}, {}).",
+                    b.seq,
+                    self.resolve(b.name),
+                    self.fmt_value(&b.value),
+                )
+            })
+            .collect();
+        emit(&mut out, lines);
+
         out
     }
 
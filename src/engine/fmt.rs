@@ -62,7 +62,8 @@ impl Engine {
     }
 
     pub fn fmt_interface(&self, iface: &Interface<Sym>) -> String {
-        let mut out = format!("interface {}", self.resolve(iface.name));
+        let kw = if iface.external { "external interface" } else { "interface" };
+        let mut out = format!("{kw} {}", self.resolve(iface.name));
         if !iface.params.is_empty() {
             out.push_str(&self.fmt_param_list(&iface.params));
         }
@@ -94,6 +95,18 @@ impl Engine {
         out
     }
 
+    pub fn fmt_state_block(&self, s: &StateBlock<Sym>) -> String {
+        let mut out = format!("state {}", self.resolve(s.name));
+        if !s.params.is_empty() {
+            out.push_str(&self.fmt_param_list(&s.params));
+        }
+        for (i, pos) in s.positions.iter().enumerate() {
+            let sep = if i + 1 < s.positions.len() { "," } else { "" };
+            out.push_str(&format!("\n    {}{}", self.fmt_position(pos), sep));
+        }
+        out
+    }
+
     fn implicit_single_state<'a>(&self, iface: &'a Interface<Sym>) -> Option<&'a Position<Sym>> {
         if iface.positions.len() != 1 {
             return None;
@@ -108,6 +121,54 @@ impl Engine {
         Some(pos)
     }
 
+    pub fn fmt_agent(&self, a: &Agent<Sym>) -> String {
+        let mut body = String::new();
+        for seg in &a.template {
+            match seg {
+                Segment::Text(t) => body.push_str(&t.replace('{', "{{").replace('}', "}}")),
+                Segment::Hole(e, _) => body.push_str(&format!("{{{}}}", self.fmt_expr(e, PREC_TOP))),
+            }
+        }
+        let mut out = format!(
+            "agent {} : {}.{}",
+            self.resolve(a.name),
+            self.resolve(a.interface),
+            self.resolve(a.position),
+        );
+        if !a.tools.is_empty() {
+            let names: Vec<&str> = a.tools.iter().map(|t| self.resolve(*t)).collect();
+            out.push_str(&format!("\n    tools: [{}]", names.join(", ")));
+        }
+        out.push_str("\n    \"\"\"");
+        for line in body.split('\n') {
+            out.push('\n');
+            if !line.is_empty() {
+                out.push_str("    ");
+                out.push_str(line);
+            }
+        }
+        out.push_str("\n    \"\"\"");
+        out
+    }
+
+    pub fn fmt_tool(&self, t: &Tool<Sym>) -> String {
+        let mut out = format!("tool {}", self.resolve(t.name));
+        for (i, c) in t.calls.iter().enumerate() {
+            let sep = if i + 1 < t.calls.len() { "," } else { "" };
+            let params = if c.params.is_empty() { String::new() } else { self.fmt_param_list(&c.params) };
+            out.push_str(&format!(
+                "\n    {}{params} -> {}{sep}",
+                self.resolve(c.name),
+                self.fmt_param(&c.result),
+            ));
+        }
+        out
+    }
+
+    pub fn fmt_invariant(&self, i: &Invariant<Sym>) -> String {
+        format!("invariant {}: {}", self.resolve(i.interface), self.fmt_expr(&i.expr, PREC_TOP))
+    }
+
     pub fn fmt_defer(&self, d: &Defer<Sym>) -> String {
         let mut out = format!(
             "defer {} : {} -> {}",
@@ -181,7 +242,7 @@ impl Engine {
     pub fn fmt_dir_ref(&self, r: &DirRef<Sym>) -> String {
         match r {
             DirRef::Named(s) => self.resolve(*s).to_string(),
-            DirRef::Abstract { src_pos, src_pattern, tgt_pos, tgt_args } => {
+            DirRef::Abstract { src_pos, src_pattern, tgt_pos, tgt_args, .. } => {
                 let mut out = self.resolve(*src_pos).to_string();
                 if !src_pattern.is_empty() {
                     out.push_str(&self.fmt_pattern_list(src_pattern));
@@ -198,7 +259,8 @@ impl Engine {
     }
 
     fn fmt_position(&self, pos: &Position<Sym>) -> String {
-        let mut out = self.resolve(pos.name).to_string();
+        let mut out = if pos.terminal { "terminal ".to_string() } else { String::new() };
+        out.push_str(self.resolve(pos.name));
         if !pos.params.is_empty() {
             out.push_str(&self.fmt_param_list(&pos.params));
         }
@@ -258,7 +320,7 @@ impl Engine {
         format!("[{}]", parts.join(", "))
     }
 
-    fn fmt_variant(&self, v: &Variant<Sym>) -> String {
+    pub fn fmt_variant(&self, v: &Variant<Sym>) -> String {
         let mut out = self.resolve(v.name).to_string();
         if !v.params.is_empty() {
             out.push_str(&self.fmt_param_list(&v.params));
@@ -266,9 +328,10 @@ impl Engine {
         out
     }
 
-    fn fmt_type(&self, ty: &Type<Sym>) -> String {
+    pub fn fmt_type(&self, ty: &Type<Sym>) -> String {
         match ty {
             Type::Int => "Int".to_string(),
+            Type::Float => "Float".to_string(),
             Type::Str => "String".to_string(),
             Type::Bool => "Bool".to_string(),
             Type::Named(s) => self.resolve(*s).to_string(),
@@ -278,6 +341,7 @@ impl Engine {
     pub fn fmt_expr(&self, e: &Expr<Sym>, parent_prec: u8) -> String {
         match e {
             Expr::LitInt(n) => n.to_string(),
+            Expr::LitFloat(x) => super::eval::fmt_float(x.0),
             Expr::LitStr(s) => format!("\"{s}\""),
             Expr::LitBool(b) => b.to_string(),
             Expr::Var(s) => self.resolve(*s).to_string(),
@@ -289,6 +353,19 @@ impl Engine {
                     args.iter().map(|a| self.fmt_expr(a, PREC_TOP)).collect();
                 format!("{}({})", self.resolve(*name), parts.join(", "))
             }
+            Expr::Variant(schema, variant, args) => {
+                let head = format!("{}::{}", self.resolve(*schema), self.resolve(*variant));
+                if args.is_empty() {
+                    return head;
+                }
+                let parts: Vec<String> =
+                    args.iter().map(|a| self.fmt_expr(a, PREC_TOP)).collect();
+                format!("{head}({})", parts.join(", "))
+            }
+            Expr::Is(inner, variant) => {
+                let s = format!("{} is {}", self.fmt_expr(inner, PREC_ATOM), self.resolve(*variant));
+                if PREC_UNARY < parent_prec { format!("({s})") } else { s }
+            }
             Expr::BinOp(op, l, r) => {
                 let p = bin_prec(*op);
                 let s = format!(
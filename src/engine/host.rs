@@ -0,0 +1,254 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use super::eval::Bindings;
+use super::propagate::Propagation;
+use super::runtime::{Instance, Runtime, RuntimeError};
+use super::Engine;
+
+
+// ============================================================================
+// External hosts
+// ============================================================================
+//
+// An `external interface` has positions and directions like any other, but
+// its actions come from the host program — a model call, an HTTP request, a
+// person — rather than from Poly. The runtime tells the host each time an
+// external instance enters a position, whether by `spawn` or by a step fired
+// directly or induced through a link. The host answers whenever its answer is
+// ready, by submitting one of that position's actions. `Runtime::serve` runs
+// the exchange until the host has nothing ready.
+//
+// `poly serve` runs an external interface offline this way, its host a
+// `MockHost` reading canned replies from a file.
+
+/// One action a host supplies for one of its instances.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Submission {
+    pub instance: String,
+    pub action: String,
+    pub args: Bindings,
+}
+
+pub trait ExternalHost {
+    /// `name`, an instance of an external interface, entered `inst`'s
+    /// position with `inst`'s bindings. Called once per entry, in the order
+    /// the entries happened, with the instance as it stood then.
+    fn entered(&mut self, engine: &Engine, name: &str, inst: &Instance);
+
+    /// The next action ready to submit, if any. `None` means the host is
+    /// still waiting on something outside the runtime.
+    fn poll(&mut self) -> Option<Submission>;
+}
+
+impl Runtime {
+    /// Fire `action` on external instance `name`, on behalf of its host.
+    pub fn submit(
+        &mut self,
+        name: &str,
+        action: &str,
+        args: Bindings,
+    ) -> Result<Propagation, RuntimeError> {
+        let inst = self
+            .instance(name)
+            .ok_or_else(|| RuntimeError::UnknownInstance(name.to_string()))?;
+        if !self.engine.interfaces[&inst.interface].external {
+            return Err(RuntimeError::NotExternal(name.to_string()));
+        }
+        self.apply_any(name, action, args)
+    }
+
+    /// Tell `host` about every external instance that entered a position,
+    /// then submit what it has ready, until it has nothing. Stops at the
+    /// first rejected submission.
+    pub fn serve(&mut self, host: &mut dyn ExternalHost) -> Result<Vec<Propagation>, RuntimeError> {
+        let mut done = Vec::new();
+        loop {
+            for (name, inst) in std::mem::take(&mut self.entered) {
+                host.entered(&self.engine, &name, &inst);
+            }
+            let Some(s) = host.poll() else {
+                return Ok(done);
+            };
+            done.push(self.submit(&s.instance, &s.action, s.args)?);
+        }
+    }
+}
+
+
+// ============================================================================
+// Mock host
+// ============================================================================
+
+/// A host with canned answers, for running external interfaces offline.
+/// Each time an instance enters `Interface.Position`, the next reply
+/// scripted for that position is submitted; with none left, the instance
+/// waits.
+#[derive(Clone, Debug, Default)]
+pub struct MockHost {
+    replies: BTreeMap<(String, String), VecDeque<(String, Bindings)>>,
+    ready: VecDeque<Submission>,
+    /// Every entry the runtime reported, as `name : Interface.Position[...]`.
+    pub seen: Vec<String>,
+}
+
+impl MockHost {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replies written one per line as `Interface.Position Action[args]`,
+    /// queued in order; blank lines and `#` comments are skipped.
+    pub fn parse(engine: &Engine, replies: &str) -> Result<Self, String> {
+        let mut host = Self::new();
+        for (i, line) in replies.lines().enumerate().map(|(i, l)| (i + 1, l.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parsed = line.split_once(char::is_whitespace).and_then(|(at, reply)| {
+                let (interface, position) = at.split_once('.')?;
+                let reply = reply.trim();
+                let (action, args) = reply.split_once('[').unwrap_or((reply, "]"));
+                Some((interface, position, action.trim(), args.strip_suffix(']')?))
+            });
+            let Some((interface, position, action, args)) = parsed else {
+                return Err(format!("line {i}: expected `Interface.Position Action[args]`, got: {line}"));
+            };
+            engine.parse_state(interface, position).map_err(|msg| format!("line {i}: {msg}"))?;
+            let args = engine.parse_bindings(args).map_err(|msg| format!("line {i}: {msg}"))?;
+            host.reply(interface, position, action, args);
+        }
+        Ok(host)
+    }
+
+    /// Queue `action` as the answer to the next entry into
+    /// `interface.position`.
+    pub fn reply(&mut self, interface: &str, position: &str, action: &str, args: Bindings) -> &mut Self {
+        self.replies
+            .entry((interface.to_string(), position.to_string()))
+            .or_default()
+            .push_back((action.to_string(), args));
+        self
+    }
+}
+
+impl ExternalHost for MockHost {
+    fn entered(&mut self, engine: &Engine, name: &str, inst: &Instance) {
+        self.seen.push(format!("{name} : {}", engine.fmt_instance(inst)));
+        let key = (engine.resolve(inst.interface).to_string(), engine.resolve(inst.position).to_string());
+        if let Some((action, args)) = self.replies.get_mut(&key).and_then(|q| q.pop_front()) {
+            self.ready.push_back(Submission { instance: name.to_string(), action, args });
+        }
+    }
+
+    fn poll(&mut self) -> Option<Submission> {
+        self.ready.pop_front()
+    }
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::eval::Value;
+    use super::*;
+
+    fn weather() -> Runtime {
+        let src = std::fs::read_to_string("examples/weather.poly").unwrap();
+        Runtime::new(Engine::load(&src).expect("load weather"))
+    }
+
+    fn args(rt: &Runtime, name: &str, v: Value) -> Bindings {
+        Bindings::from([(rt.engine.interner.find(name).unwrap(), v)])
+    }
+
+    #[test]
+    fn host_answers_each_position_it_is_told_about() {
+        let mut rt = weather();
+        let mut host = MockHost::new();
+        host.reply("WeatherAPI", "Idle", "Fetch", args(&rt, "city", Value::Str("Oslo".into())))
+            .reply("WeatherAPI", "Pending", "Report", args(&rt, "celsius", Value::Int(-3)));
+        rt.spawn("w", "WeatherAPI", "Idle", Bindings::new()).unwrap();
+
+        let done = rt.serve(&mut host).unwrap();
+        assert_eq!(done.len(), 2);
+        assert_eq!(
+            host.seen,
+            vec![
+                "w : WeatherAPI.Idle",
+                "w : WeatherAPI.Pending[city=\"Oslo\"]",
+                "w : WeatherAPI.Idle",
+            ],
+        );
+        assert_eq!(rt.fmt_instances(), "w : WeatherAPI.Idle\n");
+        // No reply left for Idle: the instance waits, and serving again is a no-op.
+        assert!(rt.serve(&mut host).unwrap().is_empty());
+    }
+
+    #[test]
+    fn entries_are_reported_as_they_happened() {
+        let mut rt = weather();
+        rt.spawn("w", "WeatherAPI", "Idle", Bindings::new()).unwrap();
+        rt.submit("w", "Fetch", args(&rt, "city", Value::Str("Oslo".into()))).unwrap();
+
+        let mut host = MockHost::new();
+        host.reply("WeatherAPI", "Pending", "Report", args(&rt, "celsius", Value::Int(-3)));
+        assert_eq!(rt.serve(&mut host).unwrap().len(), 1);
+        assert_eq!(
+            host.seen,
+            vec![
+                "w : WeatherAPI.Idle",
+                "w : WeatherAPI.Pending[city=\"Oslo\"]",
+                "w : WeatherAPI.Idle",
+            ],
+        );
+    }
+
+    #[test]
+    fn replies_are_read_from_a_file() {
+        let mut rt = weather();
+        let mut host = MockHost::parse(
+            &rt.engine,
+            "# Oslo, then nothing\n\
+             WeatherAPI.Idle Fetch[city=\"Oslo\"]\n\
+             WeatherAPI.Pending Report[celsius=-3]\n",
+        )
+        .unwrap();
+        rt.spawn("w", "WeatherAPI", "Idle", Bindings::new()).unwrap();
+        assert_eq!(rt.serve(&mut host).unwrap().len(), 2);
+        assert_eq!(host.seen.last().unwrap(), "w : WeatherAPI.Idle");
+
+        let err = |replies: &str| MockHost::parse(&rt.engine, replies).unwrap_err();
+        assert_eq!(err("WeatherAPI.Busy Fetch"), "line 1: WeatherAPI has no position Busy");
+        assert_eq!(err("\nFetch[city=\"Oslo\"]"), "line 2: expected `Interface.Position Action[args]`, got: Fetch[city=\"Oslo\"]");
+    }
+
+    #[test]
+    fn only_external_instances_take_submissions() {
+        let src = std::fs::read_to_string("examples/counter.poly").unwrap()
+            + &std::fs::read_to_string("examples/weather.poly").unwrap();
+        let mut rt = Runtime::new(Engine::load(&src).unwrap_or_else(|e| panic!("{e:?}")));
+        rt.spawn("b", "Button", "Button", Bindings::new()).unwrap();
+        rt.spawn("w", "WeatherAPI", "Idle", Bindings::new()).unwrap();
+        let err = rt.submit("b", "Press", Bindings::new()).unwrap_err();
+        assert_eq!(
+            rt.engine.fmt_runtime_error(&err),
+            "b is not an external instance; its actions do not come from the host",
+        );
+        // Nor can anyone but the host fire an external instance's actions.
+        let err = rt.apply("w", "Fetch", args(&rt, "city", Value::Str("Oslo".into()))).unwrap_err();
+        assert_eq!(rt.engine.fmt_runtime_error(&err), "w is an external instance; its actions come from the host");
+
+        // A rejected submission stops the exchange and leaves the store alone.
+        let mut host = MockHost::new();
+        host.reply("WeatherAPI", "Idle", "Report", args(&rt, "celsius", Value::Int(20)));
+        let err = rt.serve(&mut host).unwrap_err();
+        assert_eq!(rt.engine.fmt_runtime_error(&err), "w: unknown action: WeatherAPI.Idle.Report");
+        assert_eq!(rt.fmt_instances(), "b : Button.Button\nw : WeatherAPI.Idle\n");
+
+        let w = rt.engine.interner.find("WeatherAPI").unwrap();
+        assert!(rt.engine.fmt_interface(&rt.engine.interfaces[&w]).starts_with("external interface WeatherAPI\n"));
+    }
+}
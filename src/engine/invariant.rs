@@ -0,0 +1,236 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::eval::{conjoin, Bindings};
+use super::simplify::{flatten_and, reduce, substitute};
+use super::typecheck::scope;
+use super::{Engine, Expr, Interface, Invariant, Param, Position, Sym, UnOp};
+
+
+// ============================================================================
+// Inductive invariant checking
+// ============================================================================
+//
+// An invariant is preserved when no transition can start in a state that
+// satisfies it and land in one that does not. For every direction of the
+// interface with a transition `P --A--> Q[args]` (declared directly, or
+// realized through the sugar-generated `Iface::Run` defer) we build
+//
+//     guard_P ∧ inv_P ∧ guard_A ∧ guard_Q[args] ∧ ¬inv_Q[args]
+//
+// where `[args]` substitutes Q's params by the transition args, and run it
+// through `simplify::reduce`, one conjunct of `inv_Q` at a time so the
+// negation stays a conjunction. `false` proves the step preserves the
+// invariant; otherwise we look for a counterexample among small values. The
+// invariant only constrains positions it applies at (see `Invariant`), so a
+// transition into or out of any other position is not checked.
+//
+// This is the inductive step only: it does not check that the states a run
+// starts in satisfy the invariant.
+
+#[derive(Clone, Debug)]
+pub struct InvariantBreak {
+    /// Index into `Engine::invariants`.
+    pub invariant: usize,
+    pub position: Sym,
+    pub action: Sym,
+    pub target: Sym,
+    /// The residual of the violation condition.
+    pub condition: Expr<Sym>,
+    /// Source bindings (interface, position and direction params) from which
+    /// the step breaks the invariant, if one was found.
+    pub witness: Option<Bindings>,
+}
+
+/// A transition `position --action--> target[args]`, with args in terms of
+/// the source position's (and the direction's) params.
+pub struct TransitionRef<'a> {
+    pub position: &'a Position<Sym>,
+    pub action: Sym,
+    pub action_params: &'a [Param<Sym>],
+    pub action_guard: Option<&'a Expr<Sym>>,
+    pub target: &'a Position<Sym>,
+    pub args: &'a [Expr<Sym>],
+}
+
+impl Engine {
+    pub(super) fn invariant_applies(
+        &self,
+        inv: &Invariant<Sym>,
+        iface: &Interface<Sym>,
+        pos: &Position<Sym>,
+    ) -> bool {
+        let mut vars = BTreeSet::new();
+        free_vars(&inv.expr, &mut vars);
+        vars.iter().all(|v| {
+            iface.params.iter().chain(&pos.params).any(|p| p.name == *v)
+        })
+    }
+
+    /// Every transition of `iface`, whether written on the direction or moved
+    /// into `Iface::Run` by the state-machine sugar.
+    pub fn transitions<'a>(&'a self, iface: &'a Interface<Sym>) -> Vec<TransitionRef<'a>> {
+        let run = self.elaborated_defer(iface.name);
+        let mut out = Vec::new();
+        for pos in &iface.positions {
+            for dir in &pos.directions {
+                let (target, args) = match &dir.transition {
+                    Some(t) => (t.target_pos, &t.args[..]),
+                    None => {
+                        let realized = run.into_iter().flat_map(|d| &d.entries).find_map(|e| {
+                            e.directions.iter().find_map(|m| match (&m.target_dir, &m.source_dir) {
+                                (
+                                    super::DirRef::Named(a),
+                                    super::DirRef::Abstract { src_pos, tgt_pos, tgt_args, .. },
+                                ) if *a == dir.name && *src_pos == pos.name => {
+                                    Some((*tgt_pos, &tgt_args[..]))
+                                }
+                                _ => None,
+                            })
+                        });
+                        let Some(r) = realized else { continue };
+                        r
+                    }
+                };
+                let Some(target) = iface.position(&target) else { continue };
+                out.push(TransitionRef {
+                    position: pos,
+                    action: dir.name,
+                    action_params: &dir.params,
+                    action_guard: dir.guard.as_ref(),
+                    target,
+                    args,
+                });
+            }
+        }
+        out
+    }
+
+    /// Every transition that may break an invariant, in declaration order.
+    pub fn check_invariants(&self) -> Vec<InvariantBreak> {
+        let mut out = Vec::new();
+        for (idx, inv) in self.invariants.iter().enumerate() {
+            let Some(iface) = self.interfaces.get(&inv.interface) else { continue };
+            for t in self.transitions(iface) {
+                if !self.invariant_applies(inv, iface, t.position)
+                    || !self.invariant_applies(inv, iface, t.target)
+                {
+                    continue;
+                }
+                let into_target: BTreeMap<Sym, Expr<Sym>> =
+                    t.target.params.iter().map(|p| p.name).zip(t.args.iter().cloned()).collect();
+                let mut parts: Vec<Expr<Sym>> = Vec::new();
+                parts.extend(t.position.guard.iter().cloned());
+                parts.push(inv.expr.clone());
+                parts.extend(t.action_guard.cloned());
+                parts.extend(t.target.guard.iter().map(|g| substitute(g, &into_target)));
+                // ¬(c₁ ∧ … ∧ cₖ) splits into one case per conjunct; the step
+                // preserves the invariant when every case is unsatisfiable.
+                let post = substitute(&inv.expr, &into_target);
+                let types = scope(iface.params.iter().chain(&t.position.params).chain(t.action_params));
+                let broken = flatten_and(&post).into_iter().find_map(|c| {
+                    let mut parts = parts.clone();
+                    parts.push(Expr::UnOp(UnOp::Not, Box::new(c)));
+                    let full = conjoin(&parts).unwrap();
+                    let condition = reduce(self, &types, &full, &Bindings::new());
+                    (condition != Expr::LitBool(false)).then_some((full, condition))
+                });
+                let Some((full, condition)) = broken else { continue };
+                let params: Vec<&Param<Sym>> = iface
+                    .params
+                    .iter()
+                    .chain(&t.position.params)
+                    .chain(t.action_params)
+                    .collect();
+                let witness = self.find_witness(&params, &full, &condition);
+                out.push(InvariantBreak {
+                    invariant: idx,
+                    position: t.position.name,
+                    action: t.action,
+                    target: t.target.name,
+                    condition,
+                    witness,
+                });
+            }
+        }
+        out
+    }
+
+    pub fn fmt_invariant_break(&self, b: &InvariantBreak) -> String {
+        let inv = &self.invariants[b.invariant];
+        let iface = self.resolve(inv.interface);
+        let mut out = format!(
+            "{}\n  {} --{}--> {}: ",
+            self.fmt_invariant(inv),
+            self.resolve(b.position),
+            self.resolve(b.action),
+            self.resolve(b.target),
+        );
+        match &b.witness {
+            Some(w) => out.push_str(&format!(
+                "broken from {iface}.{}{}\n",
+                self.resolve(b.position),
+                self.fmt_bindings(w),
+            )),
+            None => out.push_str(&format!(
+                "not proved; may break when {}\n",
+                self.fmt_expr(&b.condition, 0),
+            )),
+        }
+        out
+    }
+}
+
+pub(super) fn free_vars(e: &Expr<Sym>, out: &mut BTreeSet<Sym>) {
+    match e {
+        Expr::Var(s) => {
+            out.insert(*s);
+        }
+        Expr::LitInt(_) | Expr::LitFloat(_) | Expr::LitStr(_) | Expr::LitBool(_) => {}
+        Expr::Field(e, _) | Expr::UnOp(_, e) | Expr::Is(e, _) => free_vars(e, out),
+        Expr::BinOp(_, l, r) => {
+            free_vars(l, out);
+            free_vars(r, out);
+        }
+        Expr::Construct(_, args) | Expr::Variant(_, _, args) => {
+            args.iter().for_each(|a| free_vars(a, out));
+        }
+    }
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(extra: &str) -> String {
+        let src = std::fs::read_to_string("examples/counter.poly").unwrap() + "\n" + extra;
+        let eng = Engine::load(&src).unwrap_or_else(|e| panic!("{e:?}"));
+        eng.check_invariants().iter().map(|b| eng.fmt_invariant_break(b)).collect()
+    }
+
+    #[test]
+    fn lower_bound_is_preserved() {
+        assert_eq!(report("invariant Counter: n >= 0"), "");
+    }
+
+    #[test]
+    fn upper_bound_breaks_on_increment() {
+        assert_eq!(
+            report("invariant Counter: n >= 0 and n <= 100"),
+            "invariant Counter: n >= 0 and n <= 100\n  Count --Increment--> Count: broken from Counter.Count[n=100]\n",
+        );
+    }
+
+    #[test]
+    fn invariants_are_typechecked() {
+        let src = std::fs::read_to_string("examples/counter.poly").unwrap() + "\ninvariant Counter: m > 0";
+        let Err(crate::engine::EngineError::Validate(diags)) = Engine::load(&src) else {
+            panic!("expected a validation error");
+        };
+        assert!(diags[0].message.contains("unbound variable `m`"), "{}", diags[0].message);
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::BTreeMap;
+
+use super::eval::{Bindings, Value};
+use super::{Engine, Float, Param, SchemaBody, Sym};
+
+
+// ============================================================================
+// Value literals
+// ============================================================================
+//
+// The text form of a `Value` is whatever `Engine::fmt_value` prints:
+//
+//     3    true    "a \"quoted\" line\n"    Coordinate(x=1, y=2)
+//     Priority::High    Result::Err(message="timeout")
+//
+// `parse_value` reads that form back, so it is the format trajectory logs
+// store. For hand-typed CLI input it also accepts positional record and
+// variant args (`Coordinate(1, 2)`) and treats any other bare text as a
+// string. `parse_state` reads a position with its args, `Count[3]` or
+// `Cell[c=Coordinate(1, 2)]`, against a given interface.
+
+impl Engine {
+    pub fn parse_value(&self, s: &str) -> Result<Value, String> {
+        let s = s.trim();
+        if let Ok(n) = s.parse::<i64>() {
+            return Ok(Value::Int(n));
+        }
+        if s.contains('.') {
+            if let Ok(x) = s.parse::<f64>() {
+                return Ok(Value::Float(Float(x)));
+            }
+        }
+        match s {
+            "true" => return Ok(Value::Bool(true)),
+            "false" => return Ok(Value::Bool(false)),
+            _ => {}
+        }
+        if s.starts_with('"') {
+            return parse_string(s);
+        }
+        if let Some((head, args)) = split_head(s) {
+            return match head.split_once("::") {
+                Some((schema, variant)) => self.parse_variant(schema, variant, args),
+                None => self.parse_record(head, args),
+            };
+        }
+        Ok(Value::Str(s.to_string()))
+    }
+
+    /// `name=value` pairs separated by commas, optionally wrapped in `[...]`
+    /// as `fmt_bindings` prints them.
+    pub fn parse_bindings(&self, s: &str) -> Result<Bindings, String> {
+        let s = s.trim();
+        let s = s.strip_prefix('[').and_then(|r| r.strip_suffix(']')).unwrap_or(s);
+        let mut out = Bindings::new();
+        for part in split_top_commas(s)? {
+            let Some((k, v)) = part.split_once('=') else {
+                return Err(format!("expected name=value, got: {part}"));
+            };
+            let key = self.interner.find(k.trim()).ok_or_else(|| format!("unknown parameter: {k}"))?;
+            let val = self
+                .parse_value(v)
+                .map_err(|msg| format!("could not parse value for {k}: {msg}"))?;
+            out.insert(key, val);
+        }
+        Ok(out)
+    }
+
+    /// `Pos[args]` or a bare `Pos` (no args bound) at `interface`.
+    pub fn parse_state(&self, interface: &str, s: &str) -> Result<(Sym, Bindings), String> {
+        let s = s.trim();
+        let (head, args) = match s.find('[') {
+            Some(open) if s.ends_with(']') => (s[..open].trim(), Some(&s[open + 1..s.len() - 1])),
+            Some(_) => return Err(format!("expected Position[args], got: {s}")),
+            None => (s, None),
+        };
+        let iface = self
+            .interner
+            .find(interface)
+            .and_then(|i| self.interfaces.get(&i))
+            .ok_or_else(|| format!("unknown interface: {interface}"))?;
+        let pos = self
+            .interner
+            .find(head)
+            .and_then(|p| iface.position(&p))
+            .ok_or_else(|| format!("{interface} has no position {head}"))?;
+        let bindings = match args {
+            Some(args) => self.parse_fields(head, &pos.params, args)?,
+            None => Bindings::new(),
+        };
+        Ok((pos.name, bindings))
+    }
+
+    fn parse_record(&self, name: &str, args: &str) -> Result<Value, String> {
+        let key = self.interner.find(name).ok_or_else(|| format!("unknown schema: {name}"))?;
+        let schema = self.schemas.get(&key).ok_or_else(|| format!("unknown schema: {name}"))?;
+        let SchemaBody::Record(params) = &schema.body else {
+            return Err(format!("{name} is a sum schema; use {name}::<Variant>"));
+        };
+        let fields = self.parse_fields(name, params, args)?;
+        Ok(Value::Record { schema: key, fields })
+    }
+
+    fn parse_variant(&self, name: &str, variant: &str, args: &str) -> Result<Value, String> {
+        let key = self.interner.find(name).ok_or_else(|| format!("unknown schema: {name}"))?;
+        let schema = self.schemas.get(&key).ok_or_else(|| format!("unknown schema: {name}"))?;
+        let SchemaBody::Sum(variants) = &schema.body else {
+            return Err(format!("{name} is a record schema; use {name}(...)"));
+        };
+        let v = variants
+            .iter()
+            .find(|v| self.resolve(v.name) == variant)
+            .ok_or_else(|| format!("schema {name} has no variant {variant}"))?;
+        let fields = self.parse_fields(&format!("{name}::{variant}"), &v.params, args)?;
+        Ok(Value::Variant { schema: key, variant: v.name, fields })
+    }
+
+    /// Args are either all positional or all `field=value`.
+    fn parse_fields(
+        &self,
+        head: &str,
+        params: &[Param<Sym>],
+        args: &str,
+    ) -> Result<BTreeMap<Sym, Value>, String> {
+        let parts = split_top_commas(args)?;
+        if parts.len() != params.len() {
+            return Err(format!("{head} expects {} arg(s), got {}", params.len(), parts.len()));
+        }
+        let mut fields = BTreeMap::new();
+        for (p, part) in params.iter().zip(&parts) {
+            let (name, value) = match named_arg(part) {
+                Some((n, v)) => {
+                    let param = params
+                        .iter()
+                        .find(|q| self.resolve(q.name) == n)
+                        .ok_or_else(|| format!("{head} has no field {n}"))?;
+                    (param.name, v)
+                }
+                None => (p.name, *part),
+            };
+            fields.insert(name, self.parse_value(value)?);
+        }
+        if fields.len() != params.len() {
+            return Err(format!("{head}: a field is given twice"));
+        }
+        Ok(fields)
+    }
+}
+
+pub fn escape_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn parse_string(s: &str) -> Result<Value, String> {
+    let inner = s
+        .strip_prefix('"')
+        .and_then(|r| r.strip_suffix('"'))
+        .filter(|_| s.len() >= 2)
+        .ok_or_else(|| format!("unterminated string: {s}"))?;
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some(c @ ('"' | '\\')) => out.push(c),
+            Some(c) => return Err(format!("unknown escape \\{c} in {s}")),
+            None => return Err(format!("unterminated string: {s}")),
+        }
+    }
+    Ok(Value::Str(out))
+}
+
+fn is_ident(s: &str) -> bool {
+    s.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// `Name(args)`, `Schema::Variant(args)` or a bare `Schema::Variant`.
+fn split_head(s: &str) -> Option<(&str, &str)> {
+    let (head, args) = match s.find('(') {
+        Some(open) if s.ends_with(')') => (s[..open].trim(), &s[open + 1..s.len() - 1]),
+        Some(_) => return None,
+        None if s.contains("::") => (s, ""),
+        None => return None,
+    };
+    let ok = match head.split_once("::") {
+        Some((schema, variant)) => is_ident(schema) && is_ident(variant),
+        None => is_ident(head),
+    };
+    ok.then_some((head, args))
+}
+
+pub(super) fn named_arg(s: &str) -> Option<(&str, &str)> {
+    let (name, value) = s.split_once('=')?;
+    let name = name.trim();
+    is_ident(name).then_some((name, value))
+}
+
+/// Split on commas outside parentheses, brackets and string literals.
+pub fn split_top_commas(s: &str) -> Result<Vec<&str>, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut out = Vec::new();
+    let mut depth = 0i32;
+    let mut start = 0usize;
+    let mut in_str = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if in_str {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_str = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_str = true,
+            '(' | '[' => depth += 1,
+            ')' | ']' => {
+                depth -= 1;
+                if depth < 0 {
+                    return Err("unbalanced parentheses".to_string());
+                }
+            }
+            ',' if depth == 0 => {
+                out.push(s[start..i].trim());
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err("unbalanced parentheses".to_string());
+    }
+    if in_str {
+        return Err("unterminated string".to_string());
+    }
+    out.push(s[start..].trim());
+    Ok(out)
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn printed_values_parse_back() {
+        let src = std::fs::read_to_string("examples/task_router.poly").unwrap();
+        let eng = Engine::load(&src).expect("load task_router");
+        for text in [
+            "-4",
+            "2.5",
+            "-3.0",
+            "true",
+            r#""tab\there, \"quoted\", comma""#,
+            r#"Task(priority=Priority::Urgent(reason="a, b"), title="x")"#,
+            "Priority::Low",
+        ] {
+            let v = eng.parse_value(text).unwrap();
+            let printed = eng.fmt_value(&v);
+            assert_eq!(eng.parse_value(&printed).unwrap(), v, "{text} -> {printed}");
+        }
+    }
+
+    #[test]
+    fn positional_args_and_bindings() {
+        let src = std::fs::read_to_string("examples/grid.poly").unwrap();
+        let eng = Engine::load(&src).expect("load grid");
+        let named = eng.parse_value("Coordinate(y=2, x=1)").unwrap();
+        assert_eq!(eng.parse_value("Coordinate(1, 2)").unwrap(), named);
+        let b = eng.parse_bindings("[Width=3, c=Coordinate(x=1, y=2)]").unwrap();
+        assert_eq!(eng.fmt_bindings(&b), "[Width=3, c=Coordinate(x=1, y=2)]");
+        assert!(eng.parse_value("Coordinate(1)").is_err());
+    }
+}
@@ -7,21 +7,31 @@ pub fn lower_decls(raw: Vec<Decl<String>>, interner: &mut Interner) -> Vec<Decl<
 fn lower_decl(d: Decl<String>, interner: &mut Interner) -> Decl<Sym> {
     match d {
         Decl::Interface(i) => Decl::Interface(lower_interface(i, interner)),
+        Decl::State(s) => Decl::State(lower_state(s, interner)),
         Decl::Defer(d) => Decl::Defer(lower_defer(d, interner)),
         Decl::Schema(s) => Decl::Schema(lower_schema(s, interner)),
+        Decl::Invariant(i) => Decl::Invariant(Invariant {
+            interface: interner.intern(&i.interface),
+            expr: lower_expr(i.expr, interner),
+            expr_spans: i.expr_spans,
+            span: i.span,
+        }),
+        Decl::Agent(a) => Decl::Agent(lower_agent(a, interner)),
+        Decl::Tool(t) => Decl::Tool(lower_tool(t, interner)),
     }
 }
 
 fn lower_type(ty: Type<String>, interner: &mut Interner) -> Type<Sym> {
     match ty {
         Type::Int => Type::Int,
+        Type::Float => Type::Float,
         Type::Str => Type::Str,
         Type::Bool => Type::Bool,
         Type::Named(s) => Type::Named(interner.intern(&s)),
     }
 }
 
-fn lower_param(p: Param<String>, interner: &mut Interner) -> Param<Sym> {
+pub(super) fn lower_param(p: Param<String>, interner: &mut Interner) -> Param<Sym> {
     Param {
         name: interner.intern(&p.name),
         ty: lower_type(p.ty, interner),
@@ -32,9 +42,10 @@ fn lower_params(ps: Vec<Param<String>>, interner: &mut Interner) -> Vec<Param<Sy
     ps.into_iter().map(|p| lower_param(p, interner)).collect()
 }
 
-fn lower_expr(e: Expr<String>, interner: &mut Interner) -> Expr<Sym> {
+pub(super) fn lower_expr(e: Expr<String>, interner: &mut Interner) -> Expr<Sym> {
     match e {
         Expr::LitInt(n) => Expr::LitInt(n),
+        Expr::LitFloat(x) => Expr::LitFloat(x),
         Expr::LitStr(s) => Expr::LitStr(s),
         Expr::LitBool(b) => Expr::LitBool(b),
         Expr::Var(s) => Expr::Var(interner.intern(&s)),
@@ -51,6 +62,14 @@ fn lower_expr(e: Expr<String>, interner: &mut Interner) -> Expr<Sym> {
             interner.intern(&name),
             args.into_iter().map(|a| lower_expr(a, interner)).collect(),
         ),
+        Expr::Variant(schema, variant, args) => Expr::Variant(
+            interner.intern(&schema),
+            interner.intern(&variant),
+            args.into_iter().map(|a| lower_expr(a, interner)).collect(),
+        ),
+        Expr::Is(inner, variant) => {
+            Expr::Is(Box::new(lower_expr(*inner, interner)), interner.intern(&variant))
+        }
     }
 }
 
@@ -58,6 +77,8 @@ fn lower_transition(t: Transition<String>, interner: &mut Interner) -> Transitio
     Transition {
         target_pos: interner.intern(&t.target_pos),
         args: t.args.into_iter().map(|e| lower_expr(e, interner)).collect(),
+        arg_spans: t.arg_spans,
+        span: t.span,
     }
 }
 
@@ -66,25 +87,32 @@ fn lower_direction(d: Direction<String>, interner: &mut Interner) -> Direction<S
         name: interner.intern(&d.name),
         params: lower_params(d.params, interner),
         guard: d.guard.map(|g| lower_expr(g, interner)),
+        guard_spans: d.guard_spans,
         transition: d.transition.map(|t| lower_transition(t, interner)),
+        span: d.span,
     }
 }
 
 fn lower_position(p: Position<String>, interner: &mut Interner) -> Position<Sym> {
     Position {
+        terminal: p.terminal,
         name: interner.intern(&p.name),
         params: lower_params(p.params, interner),
         guard: p.guard.map(|g| lower_expr(g, interner)),
+        guard_spans: p.guard_spans,
         directions: p
             .directions
             .into_iter()
             .map(|d| lower_direction(d, interner))
             .collect(),
+        span: p.span,
     }
 }
 
 fn lower_interface(i: Interface<String>, interner: &mut Interner) -> Interface<Sym> {
     Interface {
+        external: i.external,
+        deterministic: i.deterministic,
         name: interner.intern(&i.name),
         params: lower_params(i.params, interner),
         positions: i
@@ -92,6 +120,56 @@ fn lower_interface(i: Interface<String>, interner: &mut Interner) -> Interface<S
             .into_iter()
             .map(|p| lower_position(p, interner))
             .collect(),
+        span: i.span,
+    }
+}
+
+fn lower_state(s: StateBlock<String>, interner: &mut Interner) -> StateBlock<Sym> {
+    StateBlock {
+        name: interner.intern(&s.name),
+        params: lower_params(s.params, interner),
+        positions: s
+            .positions
+            .into_iter()
+            .map(|p| lower_position(p, interner))
+            .collect(),
+        elaborated: s.elaborated,
+        span: s.span,
+    }
+}
+
+fn lower_agent(a: Agent<String>, interner: &mut Interner) -> Agent<Sym> {
+    Agent {
+        name: interner.intern(&a.name),
+        interface: interner.intern(&a.interface),
+        position: interner.intern(&a.position),
+        template: a
+            .template
+            .into_iter()
+            .map(|s| match s {
+                Segment::Text(t) => Segment::Text(t),
+                Segment::Hole(e, span) => Segment::Hole(lower_expr(e, interner), span),
+            })
+            .collect(),
+        tools: a.tools.iter().map(|t| interner.intern(t)).collect(),
+        span: a.span,
+    }
+}
+
+fn lower_tool(t: Tool<String>, interner: &mut Interner) -> Tool<Sym> {
+    Tool {
+        name: interner.intern(&t.name),
+        calls: t
+            .calls
+            .into_iter()
+            .map(|c| ToolCall {
+                name: interner.intern(&c.name),
+                params: lower_params(c.params, interner),
+                result: lower_param(c.result, interner),
+                span: c.span,
+            })
+            .collect(),
+        span: t.span,
     }
 }
 
@@ -105,7 +183,7 @@ fn lower_pattern(p: Pattern<String>, interner: &mut Interner) -> Pattern<Sym> {
 fn lower_dir_ref(r: DirRef<String>, interner: &mut Interner) -> DirRef<Sym> {
     match r {
         DirRef::Named(name) => DirRef::Named(interner.intern(&name)),
-        DirRef::Abstract { src_pos, src_pattern, tgt_pos, tgt_args } => DirRef::Abstract {
+        DirRef::Abstract { src_pos, src_pattern, tgt_pos, tgt_args, arg_spans } => DirRef::Abstract {
             src_pos: interner.intern(&src_pos),
             src_pattern: src_pattern
                 .into_iter()
@@ -113,6 +191,7 @@ fn lower_dir_ref(r: DirRef<String>, interner: &mut Interner) -> DirRef<Sym> {
                 .collect(),
             tgt_pos: interner.intern(&tgt_pos),
             tgt_args: tgt_args.into_iter().map(|e| lower_expr(e, interner)).collect(),
+            arg_spans,
         },
     }
 }
@@ -121,6 +200,7 @@ fn lower_dir_mapping(m: DirMapping<String>, interner: &mut Interner) -> DirMappi
     DirMapping {
         target_dir: lower_dir_ref(m.target_dir, interner),
         source_dir: lower_dir_ref(m.source_dir, interner),
+        span: m.span,
     }
 }
 
@@ -133,9 +213,12 @@ fn lower_defer_entry(e: DeferEntry<String>, interner: &mut Interner) -> DeferEnt
             .map(|p| lower_pattern(p, interner))
             .collect(),
         source_guard: e.source_guard.map(|g| lower_expr(g, interner)),
+        guard_spans: e.guard_spans,
         target_pos: interner.intern(&e.target_pos),
         target_args: e.target_args.into_iter().map(|a| lower_expr(a, interner)).collect(),
+        arg_spans: e.arg_spans,
         directions: e.directions.into_iter().map(|m| lower_dir_mapping(m, interner)).collect(),
+        span: e.span,
     }
 }
 
@@ -145,6 +228,7 @@ fn lower_defer(d: Defer<String>, interner: &mut Interner) -> Defer<Sym> {
         source: interner.intern(&d.source),
         target: interner.intern(&d.target),
         entries: d.entries.into_iter().map(|e| lower_defer_entry(e, interner)).collect(),
+        span: d.span,
     }
 }
 
@@ -165,5 +249,6 @@ fn lower_schema(s: Schema<String>, interner: &mut Interner) -> Schema<Sym> {
     Schema {
         name: interner.intern(&s.name),
         body,
+        span: s.span,
     }
 }
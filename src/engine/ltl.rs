@@ -0,0 +1,239 @@
+use std::collections::BTreeSet;
+
+use chumsky::prelude::*;
+
+use super::diag::Diagnostic;
+use super::invariant::free_vars;
+use super::lower::lower_expr;
+use super::parse::{expr_parser, ident, keyword, ws};
+use super::{Engine, Expr, Span, Sym};
+
+
+// ============================================================================
+// Temporal properties
+// ============================================================================
+//
+// Linear-time formulas over the states of one interface:
+//
+//     G (at Working -> F at Done)
+//     G not ({n == 1} and enabled Decrement and X ({n == 1} and enabled Decrement))
+//
+// Atoms:
+//
+//   - `at P` holds in the states at position `P`;
+//   - `enabled A` holds where some args make action `A` fire;
+//   - `{ expr }` holds where the expression, in `.poly` syntax, evaluates to
+//     `true` over the state's bindings (an unbound name makes it false);
+//   - `true` / `false`.
+//
+// Connectives, loosest first: `->` (right-associative), `or`, `and`, `U`
+// (right-associative), then the prefix operators `not`, `X` (next), `F`
+// (eventually) and `G` (always). A state where no action is enabled repeats
+// forever, so every trace is infinite and `X` is always defined.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Formula<A> {
+    True,
+    False,
+    Atom(A),
+    Not(Box<Formula<A>>),
+    And(Box<Formula<A>>, Box<Formula<A>>),
+    Or(Box<Formula<A>>, Box<Formula<A>>),
+    Implies(Box<Formula<A>>, Box<Formula<A>>),
+    Next(Box<Formula<A>>),
+    Finally(Box<Formula<A>>),
+    Globally(Box<Formula<A>>),
+    Until(Box<Formula<A>>, Box<Formula<A>>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Prop<T> {
+    At(T),
+    Enabled(T),
+    Holds(Expr<T>),
+}
+
+impl<A> Formula<A> {
+    fn map_atoms<B>(self, f: &mut dyn FnMut(A) -> B) -> Formula<B> {
+        match self {
+            Formula::True => Formula::True,
+            Formula::False => Formula::False,
+            Formula::Atom(a) => Formula::Atom(f(a)),
+            Formula::Not(x) => Formula::Not(Box::new(x.map_atoms(f))),
+            Formula::Next(x) => Formula::Next(Box::new(x.map_atoms(f))),
+            Formula::Finally(x) => Formula::Finally(Box::new(x.map_atoms(f))),
+            Formula::Globally(x) => Formula::Globally(Box::new(x.map_atoms(f))),
+            Formula::And(l, r) => Formula::And(Box::new(l.map_atoms(f)), Box::new(r.map_atoms(f))),
+            Formula::Or(l, r) => Formula::Or(Box::new(l.map_atoms(f)), Box::new(r.map_atoms(f))),
+            Formula::Implies(l, r) => {
+                Formula::Implies(Box::new(l.map_atoms(f)), Box::new(r.map_atoms(f)))
+            }
+            Formula::Until(l, r) => Formula::Until(Box::new(l.map_atoms(f)), Box::new(r.map_atoms(f))),
+        }
+    }
+}
+
+
+// ============================================================================
+// Parser
+// ============================================================================
+
+type RawFormula = Formula<(Prop<String>, Span)>;
+
+fn formula_parser() -> impl Parser<char, RawFormula, Error = Simple<char>> {
+    recursive(|formula| {
+        let prop = keyword("at")
+            .ignore_then(ident())
+            .map(Prop::At)
+            .or(keyword("enabled").ignore_then(ident()).map(Prop::Enabled))
+            .or(expr_parser()
+                .delimited_by(just('{').padded_by(ws()), just('}').padded_by(ws()))
+                .map(Prop::Holds))
+            .map_with_span(|p, span: Span| Formula::Atom((p, span)));
+        let atom = keyword("true")
+            .to(Formula::True)
+            .or(keyword("false").to(Formula::False))
+            .or(prop)
+            .or(formula.delimited_by(just('(').padded_by(ws()), just(')').padded_by(ws())));
+
+        let unary = recursive(|unary| {
+            let prefix = |kw: &'static str, op: fn(Box<RawFormula>) -> RawFormula| {
+                keyword(kw).ignore_then(unary.clone()).map(move |x| op(Box::new(x)))
+            };
+            prefix("not", Formula::Not)
+                .or(prefix("X", Formula::Next))
+                .or(prefix("F", Formula::Finally))
+                .or(prefix("G", Formula::Globally))
+                .or(atom)
+        });
+        let until = recursive(|until| {
+            unary.then(keyword("U").ignore_then(until).or_not()).map(|(l, r)| match r {
+                Some(r) => Formula::Until(Box::new(l), Box::new(r)),
+                None => l,
+            })
+        });
+        let and = until
+            .clone()
+            .then(keyword("and").ignore_then(until).repeated())
+            .foldl(|l, r| Formula::And(Box::new(l), Box::new(r)));
+        let or = and
+            .clone()
+            .then(keyword("or").ignore_then(and).repeated())
+            .foldl(|l, r| Formula::Or(Box::new(l), Box::new(r)));
+        recursive(|implies| {
+            or.then(just("->").padded_by(ws()).ignore_then(implies).or_not()).map(|(l, r)| match r {
+                Some(r) => Formula::Implies(Box::new(l), Box::new(r)),
+                None => l,
+            })
+        })
+    })
+}
+
+
+// ============================================================================
+// Entry point
+// ============================================================================
+
+impl Engine {
+    /// Parse a temporal formula over the states of `interface`. Position and
+    /// action names must exist in the interface, and `{ ... }` atoms may only
+    /// mention interface and position params.
+    pub fn parse_formula(
+        &mut self,
+        interface: &str,
+        src: &str,
+    ) -> Result<Formula<Prop<Sym>>, Vec<Diagnostic>> {
+        let raw = ws()
+            .ignore_then(formula_parser())
+            .then_ignore(end())
+            .parse(src)
+            .map_err(|errs| errs.iter().map(Diagnostic::from_parse_error).collect::<Vec<_>>())?;
+        let Some(iface) = self.interner.find(interface).and_then(|s| self.interfaces.get(&s)).cloned()
+        else {
+            return Err(vec![Diagnostic::new(format!("unknown interface: {interface}"), 0..src.len())]);
+        };
+
+        let mut state_vars: BTreeSet<Sym> = iface.params.iter().map(|p| p.name).collect();
+        for pos in &iface.positions {
+            state_vars.extend(pos.params.iter().map(|p| p.name));
+        }
+        let mut errors = Vec::new();
+        let formula = raw.map_atoms(&mut |(prop, span): (Prop<String>, Span)| match prop {
+            Prop::At(p) => {
+                let sym = self.interner.find(&p).filter(|s| iface.position(s).is_some());
+                if sym.is_none() {
+                    errors.push(Diagnostic::new(format!("{interface} has no position `{p}`"), span));
+                }
+                Prop::At(sym.unwrap_or(iface.name))
+            }
+            Prop::Enabled(a) => {
+                let sym = self.interner.find(&a).filter(|s| {
+                    iface.positions.iter().any(|pos| pos.directions.iter().any(|d| d.name == *s))
+                });
+                if sym.is_none() {
+                    errors.push(Diagnostic::new(format!("{interface} has no action `{a}`"), span));
+                }
+                Prop::Enabled(sym.unwrap_or(iface.name))
+            }
+            Prop::Holds(e) => {
+                let e = lower_expr(e, &mut self.interner);
+                let mut vars = BTreeSet::new();
+                free_vars(&e, &mut vars);
+                for v in vars.difference(&state_vars) {
+                    errors.push(Diagnostic::new(
+                        format!("`{}` is not a param of {interface}", self.interner.resolve(*v)),
+                        span.clone(),
+                    ));
+                }
+                Prop::Holds(e)
+            }
+        });
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(formula)
+    }
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counter() -> Engine {
+        let src = std::fs::read_to_string("examples/counter.poly").unwrap();
+        Engine::load(&src).expect("load counter")
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        let mut eng = counter();
+        let f = eng.parse_formula("Counter", "G at Count -> F {n > 0} and not X true U false").unwrap();
+        let Formula::Implies(l, r) = f else { panic!("{f:?}") };
+        assert!(matches!(*l, Formula::Globally(_)));
+        let Formula::And(_, r) = *r else { panic!() };
+        let Formula::Until(l, r) = *r else { panic!() };
+        assert!(matches!(*l, Formula::Not(_)));
+        assert_eq!(*r, Formula::False);
+    }
+
+    #[test]
+    fn names_are_checked_against_the_interface() {
+        let mut eng = counter();
+        let src = "G (at Nowhere or enabled Press or {m > 0})";
+        let errs: Vec<String> =
+            eng.parse_formula("Counter", src).unwrap_err().iter().map(|d| d.message.clone()).collect();
+        assert_eq!(
+            errs,
+            [
+                "Counter has no position `Nowhere`",
+                "Counter has no action `Press`",
+                "`m` is not a param of Counter",
+            ],
+        );
+    }
+}
@@ -1,15 +1,36 @@
+pub mod backend;
+pub mod deadlock;
+pub mod decode;
+pub mod diag;
+pub mod driver;
 pub mod eval;
 pub mod facts;
 pub mod fmt;
+pub mod host;
 pub mod interner;
+pub mod invariant;
+pub mod literal;
+pub mod ltl;
 pub mod lower;
+pub mod modelcheck;
 pub mod parse;
+pub mod prompt;
+pub mod propagate;
+pub mod qparse;
 pub mod query;
+pub mod reach;
+pub mod runtime;
 pub mod simplify;
+pub mod solve;
+pub mod tools;
+pub mod trajectory;
+pub mod typecheck;
 pub mod types;
 pub mod uquery;
 pub mod validate;
+pub mod witness;
 
+pub use diag::Diagnostic;
 pub use interner::{Interner, Sym};
 pub use types::*;
 
@@ -22,8 +43,8 @@ use std::collections::BTreeMap;
 
 #[derive(Debug)]
 pub enum EngineError {
-    Parse(Vec<chumsky::error::Simple<char>>),
-    Validate(Vec<String>),
+    Parse(Vec<Diagnostic>),
+    Validate(Vec<Diagnostic>),
 }
 
 
@@ -36,7 +57,11 @@ pub struct Engine {
     pub interner: Interner,
     pub schemas: BTreeMap<Sym, Schema<Sym>>,
     pub interfaces: BTreeMap<Sym, Interface<Sym>>,
+    pub states: BTreeMap<Sym, StateBlock<Sym>>,
     pub defers: Vec<Defer<Sym>>,
+    pub invariants: Vec<Invariant<Sym>>,
+    pub agents: BTreeMap<Sym, Agent<Sym>>,
+    pub tools: BTreeMap<Sym, Tool<Sym>>,
 }
 
 impl Engine {
@@ -46,24 +71,32 @@ impl Engine {
             match decl {
                 Decl::Schema(s) => { engine.schemas.insert(s.name, s); }
                 Decl::Interface(i) => { engine.interfaces.insert(i.name, i); }
+                Decl::State(s) => { engine.states.insert(s.name, s); }
                 Decl::Defer(d) => engine.defers.push(d),
+                Decl::Invariant(i) => engine.invariants.push(i),
+                Decl::Agent(a) => { engine.agents.insert(a.name, a); }
+                Decl::Tool(t) => { engine.tools.insert(t.name, t); }
             }
         }
+        engine.route_tools();
         engine
     }
 
     pub fn load(src: &str) -> Result<Engine, EngineError> {
         use chumsky::Parser;
-        let raw: Vec<Decl<String>> =
-            parse::file().parse(src.to_string()).map_err(EngineError::Parse)?;
+        let raw: Vec<Decl<String>> = parse::file().parse(src.to_string()).map_err(|errs| {
+            EngineError::Parse(errs.iter().map(Diagnostic::from_parse_error).collect())
+        })?;
         let mut interner = Interner::new();
         let decls = lower::lower_decls(raw, &mut interner);
         let engine = Engine::new(interner, decls);
         let errors = engine.validate();
         if !errors.is_empty() {
-            let formatted: Vec<String> =
-                errors.iter().map(|e| engine.fmt_validation_error(e)).collect();
-            return Err(EngineError::Validate(formatted));
+            let diags: Vec<Diagnostic> = errors
+                .iter()
+                .map(|e| Diagnostic::new(engine.fmt_validation_error(e), e.span()))
+                .collect();
+            return Err(EngineError::Validate(diags));
         }
         Ok(engine)
     }
@@ -71,4 +104,31 @@ impl Engine {
     pub fn resolve(&self, sym: Sym) -> &str {
         self.interner.resolve(sym)
     }
+
+    /// The interface or state block named `name`.
+    pub fn signature(&self, name: Sym) -> Option<Signature<'_, Sym>> {
+        self.interfaces
+            .get(&name)
+            .map(Signature::Interface)
+            .or_else(|| self.states.get(&name).map(Signature::State))
+    }
+
+    /// Whether state block `state` is interface `iface`'s own state: it has
+    /// `iface`'s positions and some defer realizes `iface` from it, as with
+    /// the `X::Internal` of a desugared interface.
+    pub fn is_state_of(&self, state: Sym, iface: Sym) -> bool {
+        let (Some(s), Some(i)) = (self.states.get(&state), self.interfaces.get(&iface)) else {
+            return false;
+        };
+        s.positions.iter().map(|p| p.name).eq(i.positions.iter().map(|p| p.name))
+            && self.defers.iter().any(|d| d.source == state && d.target == iface)
+    }
+
+    /// The defer `iface`'s transitions were elaborated into, `Iface::Run`,
+    /// if it was written with any.
+    pub fn elaborated_defer(&self, iface: Sym) -> Option<&Defer<Sym>> {
+        self.defers
+            .iter()
+            .find(|d| d.target == iface && self.states.get(&d.source).is_some_and(|s| s.elaborated))
+    }
 }
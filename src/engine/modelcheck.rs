@@ -0,0 +1,626 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use super::eval::{eval_bool, Bindings};
+use super::ltl::{Formula, Prop};
+use super::query::{QueryError, Step};
+use super::reach::{in_ranges, ReachBounds};
+use super::{Engine, Interface, Sym};
+
+
+// ============================================================================
+// Bounded model checking
+// ============================================================================
+//
+// The automata-theoretic check for linear-time formulas, over the finite part
+// of the state space `reach` would explore:
+//
+//   1. Build the state graph from the start state with `next_position`,
+//      under the same `ReachBounds`. A state where no action fires gets a
+//      self-loop, so every maximal path is infinite.
+//   2. Negate the formula and expand it into a generalized Büchi automaton
+//      (the tableau of Gerth, Peled, Vardi and Wolper): each node carries the
+//      subformulas that must hold now and those that must hold next.
+//   3. Search the product of the two for a reachable cycle that visits every
+//      acceptance set. Such a lasso is a path violating the formula; it is
+//      reported as the steps to the cycle and the steps around it.
+//
+// States cut off by a bound have no successors, so no counterexample runs
+// through them: "no counterexample within bounds" is not a proof, but a
+// search that never hit a bound and never sampled a direction's params has
+// seen every state, so the formula then holds.
+
+#[derive(Clone, Debug)]
+pub enum Verdict {
+    Holds { states: usize, exhaustive: bool },
+    Violated(Counterexample),
+}
+
+#[derive(Clone, Debug)]
+pub struct Counterexample {
+    pub interface: Sym,
+    pub prefix: Vec<Step>,
+    /// Steps repeated forever after `prefix`. Empty when the trace instead
+    /// ends in `last`, where no action is enabled.
+    pub cycle: Vec<Step>,
+    pub last: (Sym, Bindings),
+}
+
+struct StateGraph {
+    states: Vec<(Sym, Bindings)>,
+    /// Outgoing edges; `None` marks the self-loop of a stuck state.
+    succ: Vec<Vec<(usize, Option<Step>)>>,
+    enabled: Vec<BTreeSet<Sym>>,
+    exhaustive: bool,
+}
+
+impl Engine {
+    /// Check `formula` on every path from `from` at `interface`. `from.1`
+    /// binds the interface params as well as the start position's.
+    pub fn check_formula(
+        &self,
+        interface: &str,
+        from: (Sym, Bindings),
+        formula: &Formula<Prop<Sym>>,
+        bounds: &ReachBounds,
+    ) -> Result<Verdict, QueryError> {
+        let iface = self.start_state(interface, &from)?;
+        let g = self.state_graph(iface, from, bounds);
+
+        let mut atoms = Vec::new();
+        let negated = nnf(formula, false, &mut atoms);
+        let labels: Vec<Vec<bool>> = (0..g.states.len())
+            .map(|s| atoms.iter().map(|a| self.holds(a, &g, s)).collect())
+            .collect();
+        let nodes = tableau(negated.clone());
+        let mut untils = Vec::new();
+        collect_untils(&negated, &mut untils);
+        let accept: Vec<Vec<bool>> = untils
+            .iter()
+            .map(|(u, rhs)| nodes.iter().map(|n| !n.old.contains(u) || n.old.contains(rhs)).collect())
+            .collect();
+
+        match find_lasso(&g, &labels, &nodes, &accept) {
+            Some((prefix, cycle)) => {
+                let steps = |edges: Vec<(usize, usize)>| -> Vec<Option<Step>> {
+                    edges.into_iter().map(|(s, e)| g.succ[s][e].1.clone()).collect()
+                };
+                let prefix: Vec<Step> = steps(prefix).into_iter().flatten().collect();
+                let cycle = steps(cycle);
+                let stuck = cycle.iter().any(Option::is_none);
+                let cycle: Vec<Step> =
+                    if stuck { Vec::new() } else { cycle.into_iter().flatten().collect() };
+                let last = match prefix.last() {
+                    Some(s) => (s.target_position, s.target_bindings.clone()),
+                    None => g.states[0].clone(),
+                };
+                Ok(Verdict::Violated(Counterexample { interface: iface.name, prefix, cycle, last }))
+            }
+            None => Ok(Verdict::Holds { states: g.states.len(), exhaustive: g.exhaustive }),
+        }
+    }
+
+    fn state_graph(&self, iface: &Interface<Sym>, from: (Sym, Bindings), bounds: &ReachBounds) -> StateGraph {
+        let interface = self.resolve(iface.name);
+        let ranges = self.param_ranges(iface, &from.1, bounds.int_span);
+        let mut index: BTreeMap<(Sym, String), usize> = BTreeMap::new();
+        index.insert((from.0, self.fmt_bindings(&from.1)), 0);
+        let mut g = StateGraph {
+            states: vec![from],
+            succ: vec![Vec::new()],
+            enabled: vec![BTreeSet::new()],
+            exhaustive: true,
+        };
+        let mut depth = vec![0usize];
+        let mut queue = VecDeque::from([0usize]);
+
+        while let Some(i) = queue.pop_front() {
+            let (position, bindings) = g.states[i].clone();
+            let pos = iface.position(&position).unwrap();
+            if depth[i] == bounds.max_depth && !pos.directions.is_empty() {
+                g.exhaustive = false;
+                continue;
+            }
+            let (mut fired, mut sampled) = (false, false);
+            for dir in &pos.directions {
+                let (arg_sets, complete) =
+                    self.direction_args(&dir.params, dir.guard.as_ref(), &bindings, bounds);
+                sampled |= !complete;
+                for args in arg_sets {
+                    let mut b = bindings.clone();
+                    b.extend(args);
+                    let Ok(step) =
+                        self.next_position(interface, self.resolve(position), self.resolve(dir.name), b)
+                    else {
+                        continue;
+                    };
+                    fired = true;
+                    g.enabled[i].insert(dir.name);
+                    if !in_ranges(&ranges, step.target_position, &step.target_bindings) {
+                        g.exhaustive = false;
+                        continue;
+                    }
+                    let key = (step.target_position, self.fmt_bindings(&step.target_bindings));
+                    let j = match index.get(&key) {
+                        Some(&j) => j,
+                        None if g.states.len() == bounds.max_states => {
+                            g.exhaustive = false;
+                            continue;
+                        }
+                        None => {
+                            let j = g.states.len();
+                            index.insert(key, j);
+                            g.states.push((step.target_position, step.target_bindings.clone()));
+                            g.succ.push(Vec::new());
+                            g.enabled.push(BTreeSet::new());
+                            depth.push(depth[i] + 1);
+                            queue.push_back(j);
+                            j
+                        }
+                    };
+                    g.succ[i].push((j, Some(step)));
+                }
+            }
+            g.exhaustive &= !sampled;
+            // A direction whose params were only sampled may still fire for
+            // other args, so the state is cut off rather than stuck.
+            if !fired && !sampled {
+                g.succ[i].push((i, None));
+            }
+        }
+        g
+    }
+
+    fn holds(&self, prop: &Prop<Sym>, g: &StateGraph, s: usize) -> bool {
+        let (position, bindings) = &g.states[s];
+        match prop {
+            Prop::At(p) => position == p,
+            Prop::Enabled(a) => g.enabled[s].contains(a),
+            Prop::Holds(e) => matches!(eval_bool(self, e, bindings), Ok(true)),
+        }
+    }
+}
+
+
+// ============================================================================
+// Tableau
+// ============================================================================
+
+/// Negation normal form, with atoms replaced by their index.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Nnf {
+    True,
+    False,
+    Lit(usize, bool),
+    And(Box<Nnf>, Box<Nnf>),
+    Or(Box<Nnf>, Box<Nnf>),
+    Next(Box<Nnf>),
+    Until(Box<Nnf>, Box<Nnf>),
+    Release(Box<Nnf>, Box<Nnf>),
+}
+
+fn nnf(f: &Formula<Prop<Sym>>, positive: bool, atoms: &mut Vec<Prop<Sym>>) -> Nnf {
+    let go = |f: &Formula<Prop<Sym>>, positive, atoms: &mut Vec<_>| Box::new(nnf(f, positive, atoms));
+    match (f, positive) {
+        (Formula::True, p) | (Formula::False, p) => {
+            if p == matches!(f, Formula::True) { Nnf::True } else { Nnf::False }
+        }
+        (Formula::Atom(a), p) => {
+            let id = atoms.iter().position(|x| x == a).unwrap_or_else(|| {
+                atoms.push(a.clone());
+                atoms.len() - 1
+            });
+            Nnf::Lit(id, p)
+        }
+        (Formula::Not(x), p) => nnf(x, !p, atoms),
+        (Formula::And(l, r), true) | (Formula::Or(l, r), false) => {
+            Nnf::And(go(l, positive, atoms), go(r, positive, atoms))
+        }
+        (Formula::Or(l, r), true) | (Formula::And(l, r), false) => {
+            Nnf::Or(go(l, positive, atoms), go(r, positive, atoms))
+        }
+        (Formula::Implies(l, r), true) => Nnf::Or(go(l, false, atoms), go(r, true, atoms)),
+        (Formula::Implies(l, r), false) => Nnf::And(go(l, true, atoms), go(r, false, atoms)),
+        (Formula::Next(x), p) => Nnf::Next(go(x, p, atoms)),
+        (Formula::Finally(x), true) | (Formula::Globally(x), false) => {
+            Nnf::Until(Box::new(Nnf::True), go(x, positive, atoms))
+        }
+        (Formula::Globally(x), true) | (Formula::Finally(x), false) => {
+            Nnf::Release(Box::new(Nnf::False), go(x, positive, atoms))
+        }
+        (Formula::Until(l, r), true) => Nnf::Until(go(l, true, atoms), go(r, true, atoms)),
+        (Formula::Until(l, r), false) => Nnf::Release(go(l, false, atoms), go(r, false, atoms)),
+    }
+}
+
+/// Every `U` subformula, paired with its right-hand side.
+fn collect_untils(f: &Nnf, out: &mut Vec<(Nnf, Nnf)>) {
+    match f {
+        Nnf::True | Nnf::False | Nnf::Lit(..) => {}
+        Nnf::Next(x) => collect_untils(x, out),
+        Nnf::And(l, r) | Nnf::Or(l, r) | Nnf::Release(l, r) => {
+            collect_untils(l, out);
+            collect_untils(r, out);
+        }
+        Nnf::Until(l, r) => {
+            if !out.iter().any(|(u, _)| u == f) {
+                out.push((f.clone(), (**r).clone()));
+            }
+            collect_untils(l, out);
+            collect_untils(r, out);
+        }
+    }
+}
+
+/// Stands for the automaton's start in `Node::incoming`.
+const INIT: usize = usize::MAX;
+
+struct Node {
+    incoming: BTreeSet<usize>,
+    old: BTreeSet<Nnf>,
+}
+
+struct Pending {
+    incoming: BTreeSet<usize>,
+    new: BTreeSet<Nnf>,
+    old: BTreeSet<Nnf>,
+    next: BTreeSet<Nnf>,
+}
+
+impl Pending {
+    fn with(&self, now: &[&Nnf], next: Option<&Nnf>) -> Pending {
+        let mut p = Pending {
+            incoming: self.incoming.clone(),
+            new: self.new.clone(),
+            old: self.old.clone(),
+            next: self.next.clone(),
+        };
+        p.new.extend(now.iter().filter(|f| !p.old.contains(**f)).map(|f| (*f).clone()));
+        p.next.extend(next.cloned());
+        p
+    }
+}
+
+/// Expand `f` into automaton nodes. A run reads state `i` in node `i`'s
+/// literals; node `j` may follow node `i` when `i` is in `j.incoming`.
+fn tableau(f: Nnf) -> Vec<Node> {
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut nexts: Vec<BTreeSet<Nnf>> = Vec::new();
+    let mut stack = vec![Pending {
+        incoming: BTreeSet::from([INIT]),
+        new: BTreeSet::from([f]),
+        old: BTreeSet::new(),
+        next: BTreeSet::new(),
+    }];
+    while let Some(mut p) = stack.pop() {
+        let Some(f) = p.new.pop_first() else {
+            if let Some(i) = (0..nodes.len()).find(|&i| nodes[i].old == p.old && nexts[i] == p.next) {
+                nodes[i].incoming.extend(p.incoming);
+                continue;
+            }
+            nodes.push(Node { incoming: p.incoming, old: p.old });
+            nexts.push(p.next.clone());
+            stack.push(Pending {
+                incoming: BTreeSet::from([nodes.len() - 1]),
+                new: p.next,
+                old: BTreeSet::new(),
+                next: BTreeSet::new(),
+            });
+            continue;
+        };
+        if p.old.contains(&f) {
+            stack.push(p);
+            continue;
+        }
+        match &f {
+            Nnf::False => continue,
+            Nnf::Lit(a, pos) if p.old.contains(&Nnf::Lit(*a, !pos)) => continue,
+            Nnf::True | Nnf::Lit(..) => {}
+            Nnf::And(l, r) => p = p.with(&[l, r], None),
+            Nnf::Next(x) => p = p.with(&[], Some(x)),
+            Nnf::Or(l, r) => {
+                let mut second = p.with(&[r], None);
+                second.old.insert(f.clone());
+                stack.push(second);
+                p = p.with(&[l], None);
+            }
+            Nnf::Until(l, r) => {
+                let mut second = p.with(&[r], None);
+                second.old.insert(f.clone());
+                stack.push(second);
+                p = p.with(&[l], Some(&f));
+            }
+            Nnf::Release(l, r) => {
+                let mut second = p.with(&[l, r], None);
+                second.old.insert(f.clone());
+                stack.push(second);
+                p = p.with(&[r], Some(&f));
+            }
+        }
+        p.old.insert(f);
+        stack.push(p);
+    }
+    nodes
+}
+
+
+// ============================================================================
+// Product search
+// ============================================================================
+
+/// A lasso in the product of `g` and the automaton, as (state, edge index)
+/// pairs into `g.succ`: the path from the start to the cycle, then the cycle.
+#[allow(clippy::type_complexity)]
+fn find_lasso(
+    g: &StateGraph,
+    labels: &[Vec<bool>],
+    nodes: &[Node],
+    accept: &[Vec<bool>],
+) -> Option<(Vec<(usize, usize)>, Vec<(usize, usize)>)> {
+    let sat = |s: usize, q: usize| {
+        nodes[q].old.iter().all(|f| match f {
+            Nnf::Lit(a, p) => labels[s][*a] == *p,
+            _ => true,
+        })
+    };
+    let followers: Vec<Vec<usize>> = (0..nodes.len())
+        .map(|q| (0..nodes.len()).filter(|&r| nodes[r].incoming.contains(&q)).collect())
+        .collect();
+
+    // Breadth-first, so `parent` gives shortest paths from the start.
+    let mut index: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+    let mut pairs: Vec<(usize, usize)> = Vec::new();
+    let mut parent: Vec<Option<(usize, usize)>> = Vec::new();
+    // (product target, edge index into `g.succ[state]`)
+    let mut edges: Vec<Vec<(usize, usize)>> = Vec::new();
+    let mut queue = VecDeque::new();
+    for q in (0..nodes.len()).filter(|&q| nodes[q].incoming.contains(&INIT) && sat(0, q)) {
+        index.insert((0, q), pairs.len());
+        queue.push_back(pairs.len());
+        pairs.push((0, q));
+        parent.push(None);
+        edges.push(Vec::new());
+    }
+    while let Some(i) = queue.pop_front() {
+        let (s, q) = pairs[i];
+        for (e, (t, _)) in g.succ[s].iter().enumerate() {
+            for &r in followers[q].iter().filter(|&&r| sat(*t, r)) {
+                let j = *index.entry((*t, r)).or_insert_with(|| {
+                    pairs.push((*t, r));
+                    parent.push(Some((i, e)));
+                    edges.push(Vec::new());
+                    queue.push_back(pairs.len() - 1);
+                    pairs.len() - 1
+                });
+                edges[i].push((j, e));
+            }
+        }
+    }
+
+    let comp = strongly_connected(&edges).into_iter().find(|c| {
+        (c.len() > 1 || edges[c[0]].iter().any(|(j, _)| *j == c[0]))
+            && accept.iter().all(|set| c.iter().any(|&i| set[pairs[i].1]))
+    })?;
+    let root = *comp.iter().min().unwrap();
+    let mut member = vec![false; pairs.len()];
+    for &i in &comp {
+        member[i] = true;
+    }
+
+    let mut prefix = Vec::new();
+    let mut at = root;
+    while let Some((p, e)) = parent[at] {
+        prefix.push((pairs[p].0, e));
+        at = p;
+    }
+    prefix.reverse();
+
+    let mut cycle = Vec::new();
+    let mut at = root;
+    for set in accept {
+        if !set[pairs[at].1] {
+            let target = *comp.iter().filter(|&&i| set[pairs[i].1]).min().unwrap();
+            cycle.extend(path_within(&edges, &member, &pairs, at, target));
+            at = target;
+        }
+    }
+    cycle.extend(path_within(&edges, &member, &pairs, at, root));
+    Some((prefix, cycle))
+}
+
+/// Shortest non-empty path from `from` to `to` through `member` nodes.
+fn path_within(
+    edges: &[Vec<(usize, usize)>],
+    member: &[bool],
+    pairs: &[(usize, usize)],
+    from: usize,
+    to: usize,
+) -> Vec<(usize, usize)> {
+    let mut parent: BTreeMap<usize, (usize, usize)> = BTreeMap::new();
+    let mut queue = VecDeque::from([from]);
+    while let Some(i) = queue.pop_front() {
+        for &(j, e) in &edges[i] {
+            if !member[j] || parent.contains_key(&j) {
+                continue;
+            }
+            parent.insert(j, (i, e));
+            if j == to {
+                let mut path = Vec::new();
+                let mut at = to;
+                loop {
+                    let (p, e) = parent[&at];
+                    path.push((pairs[p].0, e));
+                    at = p;
+                    if at == from {
+                        break;
+                    }
+                }
+                path.reverse();
+                return path;
+            }
+            queue.push_back(j);
+        }
+    }
+    unreachable!("both ends lie in one strongly connected component")
+}
+
+/// Tarjan's algorithm, iteratively.
+fn strongly_connected(edges: &[Vec<(usize, usize)>]) -> Vec<Vec<usize>> {
+    const UNSEEN: usize = usize::MAX;
+    let n = edges.len();
+    let (mut index, mut low, mut on_stack) = (vec![UNSEEN; n], vec![0; n], vec![false; n]);
+    let (mut stack, mut out, mut next) = (Vec::new(), Vec::new(), 0);
+    for root in 0..n {
+        if index[root] != UNSEEN {
+            continue;
+        }
+        let mut calls = vec![(root, 0usize)];
+        index[root] = next;
+        low[root] = next;
+        next += 1;
+        stack.push(root);
+        on_stack[root] = true;
+        while let Some(&(v, k)) = calls.last() {
+            if let Some(&(w, _)) = edges[v].get(k) {
+                calls.last_mut().unwrap().1 += 1;
+                if index[w] == UNSEEN {
+                    index[w] = next;
+                    low[w] = next;
+                    next += 1;
+                    stack.push(w);
+                    on_stack[w] = true;
+                    calls.push((w, 0));
+                } else if on_stack[w] {
+                    low[v] = low[v].min(index[w]);
+                }
+                continue;
+            }
+            calls.pop();
+            if let Some(&(u, _)) = calls.last() {
+                low[u] = low[u].min(low[v]);
+            }
+            if low[v] == index[v] {
+                let mut comp = Vec::new();
+                loop {
+                    let w = stack.pop().unwrap();
+                    on_stack[w] = false;
+                    comp.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                out.push(comp);
+            }
+        }
+    }
+    out
+}
+
+
+// ============================================================================
+// Display
+// ============================================================================
+
+impl Engine {
+    pub fn fmt_verdict(&self, v: &Verdict) -> String {
+        match v {
+            Verdict::Holds { states, exhaustive: true } => {
+                format!("holds: explored all {states} state(s)\n")
+            }
+            Verdict::Holds { states, exhaustive: false } => {
+                format!("no counterexample within bounds ({states} state(s) explored)\n")
+            }
+            Verdict::Violated(c) => {
+                let mut out = if c.cycle.is_empty() {
+                    format!(
+                        "counterexample: {} step(s), then stuck at {}.{}{}\n",
+                        c.prefix.len(),
+                        self.resolve(c.interface),
+                        self.resolve(c.last.0),
+                        self.fmt_bindings(&c.last.1),
+                    )
+                } else {
+                    format!(
+                        "counterexample: {} step(s), then a {}-step loop\n",
+                        c.prefix.len(),
+                        c.cycle.len(),
+                    )
+                };
+                for s in &c.prefix {
+                    out.push_str(&format!("  {}", self.fmt_step(s)));
+                }
+                if !c.cycle.is_empty() {
+                    out.push_str("  loop:\n");
+                    for s in &c.cycle {
+                        out.push_str(&format!("    {}", self.fmt_step(s)));
+                    }
+                }
+                out
+            }
+        }
+    }
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(src: &str, iface: &str, from: &str, formula: &str) -> String {
+        let mut eng = Engine::load(src).unwrap_or_else(|e| panic!("{e:?}"));
+        let f = eng.parse_formula(iface, formula).unwrap_or_else(|e| panic!("{formula}: {e:?}"));
+        let start = eng.parse_state(iface, from).unwrap();
+        let v = eng.check_formula(iface, start, &f, &ReachBounds::default()).unwrap();
+        eng.fmt_verdict(&v)
+    }
+
+    #[test]
+    fn counter_safety_and_liveness() {
+        let src = std::fs::read_to_string("examples/counter.poly").unwrap();
+        let out = check(&src, "Counter", "Count[0]", "G {n >= 0}");
+        assert_eq!(out, "no counterexample within bounds (65 state(s) explored)\n");
+        let never_twice = "G not ({n == 1} and enabled Decrement and X ({n == 1} and enabled Decrement))";
+        let out = check(&src, "Counter", "Count[0]", never_twice);
+        assert_eq!(out, "no counterexample within bounds (65 state(s) explored)\n");
+
+        let out = check(&src, "Counter", "Count[0]", "F {n == 3}");
+        assert_eq!(
+            out,
+            "counterexample: 0 step(s), then a 2-step loop\n  loop:\n    \
+             Counter.Count[n=0] --Increment--> Counter.Count[n=1]\n    \
+             Counter.Count[n=1] --Decrement--> Counter.Count[n=0]\n",
+        );
+    }
+
+    #[test]
+    fn router_always_returns_to_the_inbox() {
+        let src = std::fs::read_to_string("examples/task_router.poly").unwrap();
+        let from = "Inbox[Task(\"a\", Priority::Low)]";
+        let out = check(&src, "Router", from, "G (at Backlog -> F at Inbox)");
+        assert_eq!(out, "holds: explored all 2 state(s)\n");
+        let out = check(&src, "Router", from, "F at OnCall");
+        assert!(out.starts_with("counterexample: 0 step(s), then a 2-step loop\n"), "{out}");
+    }
+
+    #[test]
+    fn finite_traces_end_stuck() {
+        let src = "interface Loop\n    Working[q: Int] { Answer -> Done[q] },\n    terminal Done[r: Int]";
+        let out = check(src, "Loop", "Working[7]", "G not at Done");
+        assert_eq!(
+            out,
+            "counterexample: 1 step(s), then stuck at Loop.Done[r=7]\n  \
+             Loop.Working[q=7] --Answer--> Loop.Done[r=7]\n",
+        );
+        assert_eq!(check(src, "Loop", "Working[7]", "F G at Done"), "holds: explored all 2 state(s)\n");
+    }
+
+    #[test]
+    fn clamped_direction_params_are_not_a_proof() {
+        let src = "interface Dial\n    At[n: Int] if (n >= 0 and n <= 1000) { Set[k: Int] -> At[k] }";
+        let out = check(src, "Dial", "At[0]", "G {n <= 64}");
+        assert_eq!(out, "no counterexample within bounds (65 state(s) explored)\n");
+    }
+}
@@ -1,14 +1,17 @@
 use chumsky::prelude::*;
 
-use super::{BinOp, Decl, Defer, DeferEntry, DirMapping, DirRef, Direction, Expr, Interface, Param,
-    Pattern, Position, Schema, SchemaBody, Transition, Type, UnOp, Variant};
+use super::{
+    Agent, BinOp, Decl, Defer, DeferEntry, DirMapping, DirRef, Direction, Expr, ExprSpans, Interface, Invariant,
+    Float, Param, Pattern, Position, Schema, SchemaBody, Segment, Span, StateBlock, Tool, ToolCall, Transition,
+    Type, UnOp, Variant,
+};
 
 
 // ============================================================================
 // Lexical helpers
 // ============================================================================
 
-fn ws() -> impl Parser<char, (), Error = Simple<char>> + Clone {
+pub(super) fn ws() -> impl Parser<char, (), Error = Simple<char>> + Clone {
     let line_comment = just('#')
         .ignore_then(none_of::<_, _, Simple<char>>("\n").repeated())
         .ignored();
@@ -16,11 +19,11 @@ fn ws() -> impl Parser<char, (), Error = Simple<char>> + Clone {
     line_comment.or(space).repeated().ignored()
 }
 
-fn ident() -> impl Parser<char, String, Error = Simple<char>> + Clone {
+pub(super) fn ident() -> impl Parser<char, String, Error = Simple<char>> + Clone {
     text::ident().padded_by(ws())
 }
 
-fn qualified_ident() -> impl Parser<char, String, Error = Simple<char>> + Clone {
+pub(super) fn qualified_ident() -> impl Parser<char, String, Error = Simple<char>> + Clone {
     text::ident()
         .then(just("::").ignore_then(text::ident()).repeated())
         .map(|(head, rest): (String, Vec<String>)| {
@@ -38,7 +41,7 @@ fn qualified_ident() -> impl Parser<char, String, Error = Simple<char>> + Clone
         .padded_by(ws())
 }
 
-fn keyword(kw: &'static str) -> impl Parser<char, (), Error = Simple<char>> + Clone {
+pub(super) fn keyword(kw: &'static str) -> impl Parser<char, (), Error = Simple<char>> + Clone {
     text::ident()
         .try_map(move |s: String, span| {
             if s.as_str() == kw {
@@ -58,13 +61,14 @@ fn keyword(kw: &'static str) -> impl Parser<char, (), Error = Simple<char>> + Cl
 fn type_parser() -> impl Parser<char, Type<String>, Error = Simple<char>> + Clone {
     ident().map(|s: String| match s.as_str() {
         "Int" => Type::Int,
+        "Float" => Type::Float,
         "String" => Type::Str,
         "Bool" => Type::Bool,
         _ => Type::Named(s),
     })
 }
 
-fn param() -> impl Parser<char, Param<String>, Error = Simple<char>> + Clone {
+pub(super) fn param() -> impl Parser<char, Param<String>, Error = Simple<char>> + Clone {
     ident()
         .then_ignore(just(':').padded_by(ws()))
         .then(type_parser())
@@ -84,59 +88,124 @@ fn param_list() -> impl Parser<char, Vec<Param<String>>, Error = Simple<char>> +
 // Expression parser
 // ============================================================================
 
-fn expr_parser() -> impl Parser<char, Expr<String>, Error = Simple<char>> + Clone {
+pub(super) fn expr_parser() -> impl Parser<char, Expr<String>, Error = Simple<char>> + Clone {
+    spanned_expr().map(|(e, _)| e)
+}
+
+/// An expression paired with its node spans.
+type Spanned = (Expr<String>, ExprSpans);
+
+fn node(e: Expr<String>, span: Span, children: Vec<ExprSpans>) -> Spanned {
+    (e, ExprSpans { span, children })
+}
+
+fn binop(op: BinOp, (l, ls): Spanned, (r, rs): Spanned) -> Spanned {
+    let span = ls.span.start..rs.span.end;
+    node(Expr::BinOp(op, Box::new(l), Box::new(r)), span, vec![ls, rs])
+}
+
+/// An expression with the span of each of its nodes. Spans leave out the
+/// surrounding whitespace and, for a parenthesized expression, the parens.
+pub(super) fn spanned_expr() -> impl Parser<char, Spanned, Error = Simple<char>> + Clone {
     recursive(|expr| {
         let lit_int = text::int::<_, Simple<char>>(10)
-            .padded_by(ws())
-            .try_map(|s: String, span| {
+            .try_map(|s: String, span: Span| {
                 s.parse::<i64>()
-                    .map(Expr::LitInt)
+                    .map(|n| node(Expr::LitInt(n), span.clone(), Vec::new()))
                     .map_err(|e| Simple::custom(span, e.to_string()))
-            });
+            })
+            .padded_by(ws());
+
+        let lit_float = text::int::<_, Simple<char>>(10)
+            .then_ignore(just('.'))
+            .then(text::digits(10))
+            .try_map(|(int, frac): (String, String), span: Span| {
+                format!("{int}.{frac}")
+                    .parse::<f64>()
+                    .map(|x| node(Expr::LitFloat(Float(x)), span.clone(), Vec::new()))
+                    .map_err(|e| Simple::custom(span, e.to_string()))
+            })
+            .padded_by(ws());
 
         let lit_str = none_of::<_, _, Simple<char>>("\"")
             .repeated()
             .collect::<String>()
             .delimited_by(just('"'), just('"'))
-            .map(Expr::LitStr)
+            .map_with_span(|s, span| node(Expr::LitStr(s), span, Vec::new()))
             .padded_by(ws());
 
+        let args = expr
+            .clone()
+            .separated_by(just(',').padded_by(ws()))
+            .delimited_by(just('(').padded_by(ws()), just(')'));
+
         let constructor = text::ident()
-            .then(
-                expr.clone()
-                    .separated_by(just(',').padded_by(ws()))
-                    .delimited_by(just('(').padded_by(ws()), just(')').padded_by(ws())),
-            )
-            .map(|(name, args): (String, Vec<Expr<String>>)| Expr::Construct(name, args))
+            .then(args.clone())
+            .map_with_span(|(name, args): (String, Vec<Spanned>), span| {
+                let (args, spans) = args.into_iter().unzip();
+                node(Expr::Construct(name, args), span, spans)
+            })
+            .padded_by(ws());
+
+        let variant = text::ident()
+            .then_ignore(just("::"))
+            .then(text::ident())
+            .then(args.or_not())
+            .map_with_span(|((schema, variant), args): ((String, String), Option<Vec<Spanned>>), span| {
+                let (args, spans) = args.unwrap_or_default().into_iter().unzip();
+                node(Expr::Variant(schema, variant, args), span, spans)
+            })
             .padded_by(ws());
 
         let id_or_kw = text::ident::<_, Simple<char>>()
-            .padded_by(ws())
-            .map(|s: String| match s.as_str() {
-                "true" => Expr::LitBool(true),
-                "false" => Expr::LitBool(false),
-                _ => Expr::Var(s),
-            });
+            .map_with_span(|s: String, span| {
+                let e = match s.as_str() {
+                    "true" => Expr::LitBool(true),
+                    "false" => Expr::LitBool(false),
+                    _ => Expr::Var(s),
+                };
+                node(e, span, Vec::new())
+            })
+            .padded_by(ws());
 
         let parens = expr
             .clone()
             .delimited_by(just('(').padded_by(ws()), just(')').padded_by(ws()));
 
-        let atom = constructor.or(id_or_kw).or(lit_int).or(lit_str).or(parens);
+        let atom = variant.or(constructor).or(id_or_kw).or(lit_float).or(lit_int).or(lit_str).or(parens);
 
+        let spanned_ident = text::ident().map_with_span(|s: String, span: Span| (s, span.end)).padded_by(ws());
         let postfix = atom
-            .then(just('.').padded_by(ws()).ignore_then(text::ident()).repeated())
-            .foldl(|base, field: String| Expr::Field(Box::new(base), field));
+            .then(just('.').padded_by(ws()).ignore_then(spanned_ident.clone()).repeated())
+            .foldl(|(base, bs), (field, end)| {
+                let span = bs.span.start..end;
+                node(Expr::Field(Box::new(base), field), span, vec![bs])
+            })
+            .then(keyword("is").ignore_then(spanned_ident).or_not())
+            .map(|((e, es), variant)| match variant {
+                Some((v, end)) => {
+                    let span = es.span.start..end;
+                    node(Expr::Is(Box::new(e), v), span, vec![es])
+                }
+                None => (e, es),
+            });
 
         let unary = recursive(|unary| {
+            let prefix = |op: UnOp, start: usize, (e, es): Spanned| {
+                let span = start..es.span.end;
+                node(Expr::UnOp(op, Box::new(e)), span, vec![es])
+            };
             choice((
                 just('-')
+                    .map_with_span(|_, span: Span| span.start)
                     .padded_by(ws())
-                    .ignore_then(unary.clone())
-                    .map(|e| Expr::UnOp(UnOp::Neg, Box::new(e))),
-                keyword("not")
-                    .ignore_then(unary)
-                    .map(|e| Expr::UnOp(UnOp::Not, Box::new(e))),
+                    .then(unary.clone())
+                    .map(move |(start, e)| prefix(UnOp::Neg, start, e)),
+                ws()
+                    .ignore_then(text::keyword("not").map_with_span(|_, span: Span| span.start))
+                    .then_ignore(ws())
+                    .then(unary)
+                    .map(move |(start, e)| prefix(UnOp::Not, start, e)),
                 postfix.clone(),
             ))
         });
@@ -149,7 +218,7 @@ fn expr_parser() -> impl Parser<char, Expr<String>, Error = Simple<char>> + Clon
         let mul = unary
             .clone()
             .then(mul_op.then(unary).repeated())
-            .foldl(|l, (op, r)| Expr::BinOp(op, Box::new(l), Box::new(r)));
+            .foldl(|l, (op, r)| binop(op, l, r));
 
         let add_op = choice((
             just('+').padded_by(ws()).to(BinOp::Add),
@@ -158,7 +227,7 @@ fn expr_parser() -> impl Parser<char, Expr<String>, Error = Simple<char>> + Clon
         let add = mul
             .clone()
             .then(add_op.then(mul).repeated())
-            .foldl(|l, (op, r)| Expr::BinOp(op, Box::new(l), Box::new(r)));
+            .foldl(|l, (op, r)| binop(op, l, r));
 
         let cmp_op = choice((
             just("==").padded_by(ws()).to(BinOp::Eq),
@@ -175,29 +244,24 @@ fn expr_parser() -> impl Parser<char, Expr<String>, Error = Simple<char>> + Clon
                 if rest.is_empty() {
                     return first;
                 }
-                let mut parts: Vec<Expr<String>> = Vec::new();
+                let mut parts: Vec<Spanned> = Vec::new();
                 let mut prev = first;
                 for (op, next) in rest {
-                    let cmp_node =
-                        Expr::BinOp(op, Box::new(prev.clone()), Box::new(next.clone()));
-                    parts.push(cmp_node);
+                    parts.push(binop(op, prev, next.clone()));
                     prev = next;
                 }
-                parts
-                    .into_iter()
-                    .reduce(|a, b| Expr::BinOp(BinOp::And, Box::new(a), Box::new(b)))
-                    .unwrap()
+                parts.into_iter().reduce(|a, b| binop(BinOp::And, a, b)).unwrap()
             });
 
         let and_lvl = cmp
             .clone()
             .then(keyword("and").to(BinOp::And).then(cmp).repeated())
-            .foldl(|l, (op, r)| Expr::BinOp(op, Box::new(l), Box::new(r)));
+            .foldl(|l, (op, r)| binop(op, l, r));
 
         and_lvl
             .clone()
             .then(keyword("or").to(BinOp::Or).then(and_lvl).repeated())
-            .foldl(|l, (op, r)| Expr::BinOp(op, Box::new(l), Box::new(r)))
+            .foldl(|l, (op, r)| binop(op, l, r))
     })
 }
 
@@ -207,15 +271,9 @@ fn expr_parser() -> impl Parser<char, Expr<String>, Error = Simple<char>> + Clon
 // ============================================================================
 
 fn transition_parser() -> impl Parser<char, Transition<String>, Error = Simple<char>> + Clone {
-    let arg_list = expr_parser()
-        .separated_by(just(',').padded_by(ws()))
-        .delimited_by(just('[').padded_by(ws()), just(']').padded_by(ws()))
-        .or_not()
-        .map(|opt| opt.unwrap_or_default());
-
     ident()
-        .then(arg_list)
-        .map(|(target_pos, args)| Transition { target_pos, args })
+        .then(arg_list())
+        .map_with_span(|(target_pos, (args, arg_spans)), span| Transition { target_pos, args, arg_spans, span })
 }
 
 
@@ -226,18 +284,20 @@ fn transition_parser() -> impl Parser<char, Transition<String>, Error = Simple<c
 fn direction_parser() -> impl Parser<char, Direction<String>, Error = Simple<char>> + Clone {
     ident()
         .then(param_list())
-        .then(keyword("if").ignore_then(expr_parser()).or_not())
+        .then(guard())
         .then(
             just("->")
                 .padded_by(ws())
                 .ignore_then(transition_parser())
                 .or_not(),
         )
-        .map(|(((name, params), guard), transition)| Direction {
+        .map_with_span(|(((name, params), (guard, guard_spans)), transition), span| Direction {
             name,
             params,
             guard,
+            guard_spans,
             transition,
+            span,
         })
 }
 
@@ -247,9 +307,12 @@ fn direction_parser() -> impl Parser<char, Direction<String>, Error = Simple<cha
 // ============================================================================
 
 fn position_parser() -> impl Parser<char, Position<String>, Error = Simple<char>> + Clone {
-    ident()
+    keyword("terminal")
+        .padded_by(ws())
+        .or_not()
+        .then(ident())
         .then(param_list())
-        .then(keyword("if").ignore_then(expr_parser()).or_not())
+        .then(guard())
         .then(
             direction_parser()
                 .separated_by(just(',').padded_by(ws()))
@@ -257,11 +320,14 @@ fn position_parser() -> impl Parser<char, Position<String>, Error = Simple<char>
                 .or_not()
                 .map(|opt| opt.unwrap_or_default()),
         )
-        .map(|(((name, params), guard), directions)| Position {
+        .map_with_span(|((((terminal, name), params), (guard, guard_spans)), directions), span| Position {
+            terminal: terminal.is_some(),
             name,
             params,
             guard,
+            guard_spans,
             directions,
+            span,
         })
 }
 
@@ -277,28 +343,40 @@ fn interface_decls() -> impl Parser<char, Vec<Decl<String>>, Error = Simple<char
 
     enum Body {
         Positions(Vec<Position<String>>),
-        SingleState(Vec<Direction<String>>),
+        SingleState(Vec<Direction<String>>, Span),
     }
 
     let body = single_state_body
-        .map(Body::SingleState)
+        .map_with_span(Body::SingleState)
         .or(position_parser().separated_by(just(',').padded_by(ws())).map(Body::Positions));
 
-    keyword("interface")
-        .ignore_then(ident())
+    keyword("external")
+        .or_not()
+        .then_ignore(keyword("interface"))
+        .then(ident())
         .then(param_list())
         .then(body)
-        .map(|((name, params), body)| {
+        .map_with_span(|(((external, name), params), body), span| {
             let positions = match body {
                 Body::Positions(ps) => ps,
-                Body::SingleState(directions) => vec![Position {
+                Body::SingleState(directions, body_span) => vec![Position {
+                    terminal: false,
                     name: name.clone(),
                     params: Vec::new(),
                     guard: None,
+                    guard_spans: None,
                     directions,
+                    span: body_span,
                 }],
             };
-            let iface = Interface { name, params, positions };
+            let iface = Interface {
+                external: external.is_some(),
+                deterministic: false,
+                name,
+                params,
+                positions,
+                span,
+            };
             desugar_interface(iface)
         })
 }
@@ -313,13 +391,15 @@ fn desugar_interface(iface: Interface<String>) -> Vec<Decl<String>> {
         return vec![Decl::Interface(iface)];
     }
 
-    let external_positions: Vec<Position<String>> = iface
+    let outer_positions: Vec<Position<String>> = iface
         .positions
         .iter()
         .map(|p| Position {
+            terminal: p.terminal,
             name: p.name.clone(),
             params: p.params.clone(),
             guard: p.guard.clone(),
+            guard_spans: p.guard_spans.clone(),
             directions: p
                 .directions
                 .iter()
@@ -327,32 +407,43 @@ fn desugar_interface(iface: Interface<String>) -> Vec<Decl<String>> {
                     name: d.name.clone(),
                     params: d.params.clone(),
                     guard: d.guard.clone(),
+                    guard_spans: d.guard_spans.clone(),
                     transition: None,
+                    span: d.span.clone(),
                 })
                 .collect(),
+            span: p.span.clone(),
         })
         .collect();
-    let external = Interface {
+    let outer = Interface {
+        external: iface.external,
+        deterministic: iface.deterministic,
         name: iface.name.clone(),
         params: iface.params.clone(),
-        positions: external_positions,
+        positions: outer_positions,
+        span: iface.span.clone(),
     };
 
     let internal_positions: Vec<Position<String>> = iface
         .positions
         .iter()
         .map(|p| Position {
+            terminal: false,
             name: p.name.clone(),
             params: p.params.clone(),
             guard: p.guard.clone(),
+            guard_spans: p.guard_spans.clone(),
             directions: Vec::new(),
+            span: p.span.clone(),
         })
         .collect();
     let internal_name = format!("{}::Internal", iface.name);
-    let internal = Interface {
+    let internal = StateBlock {
         name: internal_name.clone(),
         params: iface.params.clone(),
         positions: internal_positions,
+        elaborated: true,
+        span: iface.span.clone(),
     };
 
     let mut entries: Vec<DeferEntry<String>> = Vec::new();
@@ -378,7 +469,9 @@ fn desugar_interface(iface: Interface<String>) -> Vec<Decl<String>> {
                         src_pattern: source_pattern.clone(),
                         tgt_pos: trans.target_pos.clone(),
                         tgt_args: trans.args.clone(),
+                        arg_spans: trans.arg_spans.clone(),
                     },
+                    span: d.span.clone(),
                 })
             })
             .collect();
@@ -386,9 +479,12 @@ fn desugar_interface(iface: Interface<String>) -> Vec<Decl<String>> {
             source_pos: p.name.clone(),
             source_pattern,
             source_guard: None,
+            guard_spans: None,
             target_pos: p.name.clone(),
             target_args,
+            arg_spans: Vec::new(),
             directions,
+            span: p.span.clone(),
         });
     }
     let defer = Defer {
@@ -396,16 +492,44 @@ fn desugar_interface(iface: Interface<String>) -> Vec<Decl<String>> {
         source: internal_name,
         target: iface.name.clone(),
         entries,
+        span: iface.span.clone(),
     };
 
     vec![
-        Decl::Interface(external),
-        Decl::Interface(internal),
+        Decl::Interface(outer),
+        Decl::State(internal),
         Decl::Defer(defer),
     ]
 }
 
 
+// ============================================================================
+// State block
+// ============================================================================
+
+fn state_decl() -> impl Parser<char, StateBlock<String>, Error = Simple<char>> {
+    let position = ident()
+        .then(param_list())
+        .then(guard())
+        .map_with_span(|((name, params), (guard, guard_spans)), span| Position {
+            terminal: false,
+            name,
+            params,
+            guard,
+            guard_spans,
+            directions: Vec::new(),
+            span,
+        });
+    keyword("state")
+        .ignore_then(qualified_ident())
+        .then(param_list())
+        .then(position.separated_by(just(',').padded_by(ws())))
+        .map_with_span(|((name, params), positions), span| {
+            StateBlock { name, params, positions, elaborated: false, span }
+        })
+}
+
+
 // ============================================================================
 // Schema
 // ============================================================================
@@ -432,7 +556,7 @@ fn schema_decl() -> impl Parser<char, Schema<String>, Error = Simple<char>> {
     keyword("schema")
         .ignore_then(ident())
         .then(schema_entry().separated_by(just(',').padded_by(ws())))
-        .try_map(|(name, entries), span| {
+        .try_map(|(name, entries), span: Span| {
             let mut fields: Vec<Param<String>> = Vec::new();
             let mut variants: Vec<Variant<String>> = Vec::new();
             for e in entries {
@@ -452,7 +576,7 @@ fn schema_decl() -> impl Parser<char, Schema<String>, Error = Simple<char>> {
                     ))
                 }
             };
-            Ok(Schema { name, body })
+            Ok(Schema { name, body, span })
         })
 }
 
@@ -475,12 +599,18 @@ fn pattern_list() -> impl Parser<char, Vec<Pattern<String>>, Error = Simple<char
         .map(|opt| opt.unwrap_or_default())
 }
 
-fn arg_list() -> impl Parser<char, Vec<Expr<String>>, Error = Simple<char>> + Clone {
-    expr_parser()
+/// `[e, ...]`, or nothing, with the args' spans alongside.
+fn arg_list() -> impl Parser<char, (Vec<Expr<String>>, Vec<ExprSpans>), Error = Simple<char>> + Clone {
+    spanned_expr()
         .separated_by(just(',').padded_by(ws()))
         .delimited_by(just('[').padded_by(ws()), just(']').padded_by(ws()))
         .or_not()
-        .map(|opt| opt.unwrap_or_default())
+        .map(|opt| opt.unwrap_or_default().into_iter().unzip())
+}
+
+/// `if e`, or nothing, with the guard's spans alongside.
+fn guard() -> impl Parser<char, (Option<Expr<String>>, Option<ExprSpans>), Error = Simple<char>> + Clone {
+    keyword("if").ignore_then(spanned_expr()).or_not().map(|g| g.unzip())
 }
 
 fn abstract_dir_ref() -> impl Parser<char, DirRef<String>, Error = Simple<char>> + Clone {
@@ -489,11 +619,12 @@ fn abstract_dir_ref() -> impl Parser<char, DirRef<String>, Error = Simple<char>>
         .then_ignore(just("=>").padded_by(ws()))
         .then(ident())
         .then(arg_list())
-        .map(|(((src_pos, src_pattern), tgt_pos), tgt_args)| DirRef::Abstract {
+        .map(|(((src_pos, src_pattern), tgt_pos), (tgt_args, arg_spans))| DirRef::Abstract {
             src_pos,
             src_pattern,
             tgt_pos,
             tgt_args,
+            arg_spans,
         })
 }
 
@@ -506,12 +637,13 @@ fn dir_mapping() -> impl Parser<char, Vec<DirMapping<String>>, Error = Simple<ch
         .separated_by(just('|').padded_by(ws()))
         .then_ignore(just("->").padded_by(ws()))
         .then(dir_ref())
-        .map(|(target_dirs, source_dir)| {
+        .map_with_span(|(target_dirs, source_dir), span: Span| {
             target_dirs
                 .into_iter()
                 .map(|target_dir| DirMapping {
                     target_dir,
                     source_dir: source_dir.clone(),
+                    span: span.clone(),
                 })
                 .collect()
         })
@@ -520,7 +652,7 @@ fn dir_mapping() -> impl Parser<char, Vec<DirMapping<String>>, Error = Simple<ch
 fn defer_entry() -> impl Parser<char, DeferEntry<String>, Error = Simple<char>> {
     ident()
         .then(pattern_list())
-        .then(keyword("if").ignore_then(expr_parser()).or_not())
+        .then(guard())
         .then_ignore(just("->").padded_by(ws()))
         .then(ident().or_not())
         .then(arg_list())
@@ -531,16 +663,20 @@ fn defer_entry() -> impl Parser<char, DeferEntry<String>, Error = Simple<char>>
                 .or_not()
                 .map(|opt| opt.unwrap_or_default()),
         )
-        .map(
-            |(((((source_pos, source_pattern), source_guard), target_pos), target_args), groups)| {
+        .map_with_span(
+            |(((((source_pos, source_pattern), (source_guard, guard_spans)), target_pos), (target_args, arg_spans)), groups),
+             span| {
                 let directions: Vec<DirMapping<String>> = groups.into_iter().flatten().collect();
                 DeferEntry {
                     source_pos,
                     source_pattern,
                     source_guard,
+                    guard_spans,
                     target_pos: target_pos.unwrap_or_default(),
                     target_args,
+                    arg_spans,
                     directions,
+                    span,
                 }
             },
         )
@@ -554,7 +690,7 @@ fn defer_decl() -> impl Parser<char, Defer<String>, Error = Simple<char>> {
         .then_ignore(just("->").padded_by(ws()))
         .then(qualified_ident())
         .then(defer_entry().separated_by(just(',').padded_by(ws())))
-        .map(|(((name, source), target), entries)| {
+        .map_with_span(|(((name, source), target), entries), span| {
             let entries = entries
                 .into_iter()
                 .map(|mut e| {
@@ -564,20 +700,254 @@ fn defer_decl() -> impl Parser<char, Defer<String>, Error = Simple<char>> {
                     e
                 })
                 .collect();
-            Defer { name, source, target, entries }
+            Defer { name, source, target, entries, span }
         })
 }
 
 
+// ============================================================================
+// Invariant
+// ============================================================================
+
+fn invariant_decl() -> impl Parser<char, Invariant<String>, Error = Simple<char>> {
+    keyword("invariant")
+        .ignore_then(ident())
+        .then_ignore(just(':').padded_by(ws()))
+        .then(spanned_expr())
+        .map_with_span(|(interface, (expr, expr_spans)), span| Invariant { interface, expr, expr_spans, span })
+}
+
+
+// ============================================================================
+// Agent
+// ============================================================================
+
+fn agent_decl() -> impl Parser<char, Agent<String>, Error = Simple<char>> {
+    let template = just("\"\"\"")
+        .ignore_then(take_until(just("\"\"\"")))
+        .map_with_span(|(body, _), span: Span| (body.into_iter().collect::<String>(), span.start + 3))
+        .try_map(|(body, start), _| {
+            template_segments(&body, start).map_err(|(msg, span)| Simple::custom(span, msg))
+        })
+        .padded_by(ws());
+    let tools = keyword("tools")
+        .ignore_then(just(':').padded_by(ws()))
+        .ignore_then(
+            ident()
+                .separated_by(just(',').padded_by(ws()))
+                .delimited_by(just('[').padded_by(ws()), just(']').padded_by(ws())),
+        );
+    keyword("agent")
+        .ignore_then(ident())
+        .then_ignore(just(':').padded_by(ws()))
+        .then(ident())
+        .then_ignore(just('.'))
+        .then(ident())
+        .then(tools.or_not().map(Option::unwrap_or_default))
+        .then(template)
+        .map_with_span(|((((name, interface), position), tools), template), span| Agent {
+            name,
+            interface,
+            position,
+            template,
+            tools,
+            span,
+        })
+}
+
+/// Split a triple-quoted template body (starting at offset `start` in the
+/// file) into text and `{hole}`s. The lines are dedented by their common
+/// indentation, and a blank first or last line — the ones holding the
+/// quotes — is dropped. `{{` and `}}` stand for literal braces.
+fn template_segments(body: &str, start: usize) -> Result<Vec<Segment<String>>, (String, Span)> {
+    let mut lines: Vec<(usize, &str)> = Vec::new();
+    let mut offset = start;
+    for line in body.split('\n') {
+        lines.push((offset, line));
+        offset += line.len() + 1;
+    }
+    if lines.first().is_some_and(|(_, l)| l.trim().is_empty()) {
+        lines.remove(0);
+    }
+    if lines.last().is_some_and(|(_, l)| l.trim().is_empty()) {
+        lines.pop();
+    }
+    let indent = lines
+        .iter()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(_, l)| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    for (i, (offset, line)) in lines.iter().enumerate() {
+        if i > 0 {
+            text.push('\n');
+        }
+        let cut = indent.min(line.len() - line.trim_start().len());
+        let line = &line[cut..];
+        let offset = offset + cut;
+        let mut at = 0;
+        while let Some(c) = line[at..].chars().next() {
+            let here = offset + at;
+            let rest = &line[at + 1..];
+            match c {
+                '{' | '}' if rest.starts_with(c) => {
+                    text.push(c);
+                    at += 2;
+                }
+                '{' => {
+                    let Some(len) = rest.find('}') else {
+                        let msg = "unclosed `{` in template; write `{{` for a literal brace";
+                        return Err((msg.into(), here..here + 1));
+                    };
+                    let span = here..here + len + 2;
+                    let Some(hole) = template_hole(rest[..len].trim()) else {
+                        let msg = format!(
+                            "expected `{{name}}` or `{{name.field}}` in template, got `{{{}}}`",
+                            &rest[..len],
+                        );
+                        return Err((msg, span));
+                    };
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Hole(hole, span));
+                    at += len + 2;
+                }
+                '}' => {
+                    let msg = "unmatched `}` in template; write `}}` for a literal brace";
+                    return Err((msg.into(), here..here + 1));
+                }
+                _ => {
+                    text.push(c);
+                    at += c.len_utf8();
+                }
+            }
+        }
+    }
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    Ok(segments)
+}
+
+/// `name` or `name.field.field...`, as a variable with field accesses.
+fn template_hole(path: &str) -> Option<Expr<String>> {
+    let is_ident = |s: &str| {
+        s.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+            && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+    };
+    let mut parts = path.split('.');
+    let mut e = Expr::Var(parts.next().filter(|s| is_ident(s))?.to_string());
+    for field in parts {
+        if !is_ident(field) {
+            return None;
+        }
+        e = Expr::Field(Box::new(e), field.to_string());
+    }
+    Some(e)
+}
+
+
+
+// ============================================================================
+// Tool
+// ============================================================================
+
+fn tool_decls() -> impl Parser<char, Vec<Decl<String>>, Error = Simple<char>> {
+    let call = ident()
+        .then(param_list())
+        .then_ignore(just("->").padded_by(ws()))
+        .then(ident())
+        .then_ignore(just(':').padded_by(ws()))
+        .then(type_parser())
+        .map_with_span(|(((name, params), result), ty), span| ToolCall {
+            name,
+            params,
+            result: Param { name: result, ty },
+            span,
+        });
+    keyword("tool")
+        .ignore_then(ident())
+        .then(call.separated_by(just(',').padded_by(ws())).at_least(1))
+        .map_with_span(|(name, calls), span| desugar_tool(Tool { name, calls, span }))
+}
+
+/// `Idle { Call[params, result] -> Returned[result], ... }` and
+/// `Returned[result] { Collect -> Idle }`: the host fires the call with its
+/// result filled in. A tool with several calls gets a `Returned<Call>`
+/// position per call.
+fn desugar_tool(tool: Tool<String>) -> Vec<Decl<String>> {
+    let returned = |c: &ToolCall<String>| {
+        if tool.calls.len() == 1 {
+            "Returned".to_string()
+        } else {
+            format!("Returned{}", c.name)
+        }
+    };
+    let step = |name: &str, params: Vec<Param<String>>, target_pos: String, args, span: &Span| Direction {
+        name: name.to_string(),
+        params,
+        guard: None,
+        guard_spans: None,
+        transition: Some(Transition { target_pos, args, arg_spans: Vec::new(), span: span.clone() }),
+        span: span.clone(),
+    };
+    let mut idle = Position {
+        terminal: false,
+        name: "Idle".to_string(),
+        params: Vec::new(),
+        guard: None,
+        guard_spans: None,
+        directions: Vec::new(),
+        span: tool.span.clone(),
+    };
+    let mut positions = Vec::new();
+    for c in &tool.calls {
+        let mut params = c.params.clone();
+        params.push(c.result.clone());
+        let result = vec![Expr::Var(c.result.name.clone())];
+        idle.directions.push(step(&c.name, params, returned(c), result, &c.span));
+        positions.push(Position {
+            terminal: false,
+            name: returned(c),
+            params: vec![c.result.clone()],
+            guard: None,
+            guard_spans: None,
+            directions: vec![step("Collect", Vec::new(), "Idle".to_string(), Vec::new(), &c.span)],
+            span: c.span.clone(),
+        });
+    }
+    positions.insert(0, idle);
+    let iface = Interface {
+        external: true,
+        deterministic: true,
+        name: tool.name.clone(),
+        params: Vec::new(),
+        positions,
+        span: tool.span.clone(),
+    };
+    let mut decls = desugar_interface(iface);
+    decls.push(Decl::Tool(tool));
+    decls
+}
+
+
 // ============================================================================
 // File-level
 // ============================================================================
 
 pub fn file() -> impl Parser<char, Vec<Decl<String>>, Error = Simple<char>> {
     let interface = interface_decls();
+    let state = state_decl().map(|s| vec![Decl::State(s)]);
     let defer = defer_decl().map(|d| vec![Decl::Defer(d)]);
     let schema = schema_decl().map(|s| vec![Decl::Schema(s)]);
-    let decl = interface.or(defer).or(schema);
+    let invariant = invariant_decl().map(|i| vec![Decl::Invariant(i)]);
+    let agent = agent_decl().map(|a| vec![Decl::Agent(a)]);
+    let tool = tool_decls();
+    let decl = interface.or(state).or(defer).or(schema).or(invariant).or(agent).or(tool);
     decl.padded_by(ws())
         .repeated()
         .map(|chunks: Vec<Vec<Decl<String>>>| chunks.into_iter().flatten().collect())
@@ -0,0 +1,231 @@
+use super::eval::{eval, eval_bool, Bindings, Value};
+use super::query::QueryError;
+use super::simplify::reduce;
+use super::solve::Satisfiability;
+use super::typecheck::scope;
+use super::{Agent, Direction, Engine, Segment, Sym};
+
+
+// ============================================================================
+// Prompt rendering
+// ============================================================================
+//
+// An agent is prompted with its template, holes filled from the instance's
+// bindings, followed by the actions it may answer with: the directions of
+// its position whose guards hold under those bindings, with their parameter
+// signatures, and the calls of the tools it may use. Rendering reads nothing
+// but the engine and the bindings, so the same state always renders the same
+// prompt.
+
+impl Engine {
+    /// The prompt for `agent` when its position is filled with `bindings`
+    /// (the interface params plus the position params).
+    pub fn render_prompt(&self, agent: &str, bindings: &Bindings) -> Result<String, QueryError> {
+        let a = self.agent(agent)?;
+        let mut out = String::new();
+        for seg in &a.template {
+            match seg {
+                Segment::Text(t) => out.push_str(t),
+                Segment::Hole(e, _) => {
+                    let v = eval(self, e, bindings).map_err(QueryError::EvalFailed)?;
+                    out.push_str(&self.fmt_prompt_value(&v));
+                }
+            }
+        }
+        out.push_str("\n\nReply with exactly one of these actions:\n");
+        for d in self.enabled_directions(a.interface, a.position, bindings)? {
+            out.push_str(&format!("  {}\n", self.fmt_action_signature(d)));
+        }
+        let calls = self.tool_actions(a);
+        if !calls.is_empty() {
+            out.push_str("\nOr call one of these tools:\n");
+            for d in &calls {
+                out.push_str(&format!("  {}\n", self.fmt_action_signature(d)));
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn agent(&self, name: &str) -> Result<&Agent<Sym>, QueryError> {
+        self.interner
+            .find(name)
+            .and_then(|s| self.agents.get(&s))
+            .ok_or_else(|| QueryError::UnknownAgent(name.to_string()))
+    }
+
+    /// The calls of `agent`'s tools, as directions taking the call's params
+    /// (the result is the tool's to fill in).
+    pub fn tool_actions(&self, agent: &Agent<Sym>) -> Vec<Direction<Sym>> {
+        agent
+            .tools
+            .iter()
+            .filter_map(|t| self.tools.get(t))
+            .flat_map(|t| &t.calls)
+            .map(|c| Direction {
+                name: c.name,
+                params: c.params.clone(),
+                guard: None,
+                guard_spans: None,
+                transition: None,
+                span: c.span.clone(),
+            })
+            .collect()
+    }
+
+    /// The directions at `interface.position` whose guards hold under
+    /// `bindings`, in declaration order. A guard over the direction's own
+    /// params counts as holding unless no args can satisfy it.
+    pub fn enabled_directions(
+        &self,
+        interface: Sym,
+        position: Sym,
+        bindings: &Bindings,
+    ) -> Result<Vec<&Direction<Sym>>, QueryError> {
+        let iface = self
+            .interfaces
+            .get(&interface)
+            .ok_or_else(|| QueryError::UnknownInterface(self.resolve(interface).to_string()))?;
+        let pos = iface.position(&position).ok_or_else(|| QueryError::UnknownPosition {
+            interface: self.resolve(interface).to_string(),
+            position: self.resolve(position).to_string(),
+        })?;
+        let mut enabled = Vec::new();
+        for d in &pos.directions {
+            let open = match &d.guard {
+                Some(g) if !d.params.is_empty() => {
+                    let types = scope(iface.params.iter().chain(&pos.params).chain(&d.params));
+                    self.satisfiable(&reduce(self, &types, g, bindings), &d.params) != Satisfiability::Unsat
+                }
+                Some(g) => eval_bool(self, g, bindings).map_err(QueryError::EvalFailed)?,
+                None => true,
+            };
+            if open {
+                enabled.push(d);
+            }
+        }
+        Ok(enabled)
+    }
+
+    /// `Name[param: Type, ...]`, or just `Name` without params.
+    pub fn fmt_action_signature(&self, d: &Direction<Sym>) -> String {
+        let name = self.resolve(d.name);
+        if d.params.is_empty() {
+            name.to_string()
+        } else {
+            format!("{name}{}", self.fmt_param_list(&d.params))
+        }
+    }
+
+    /// Strings go in as their text; everything else as a literal.
+    fn fmt_prompt_value(&self, v: &Value) -> String {
+        match v {
+            Value::Str(s) => s.clone(),
+            _ => self.fmt_value(v),
+        }
+    }
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::EngineError;
+    use super::*;
+
+    fn writer_reviewer() -> Engine {
+        let src = std::fs::read_to_string("examples/old/writer_reviewer.poly").unwrap();
+        Engine::load(&src).expect("load writer_reviewer")
+    }
+
+    fn str_bindings(eng: &Engine, pairs: &[(&str, &str)]) -> Bindings {
+        pairs
+            .iter()
+            .map(|(k, v)| (eng.interner.find(k).unwrap(), Value::Str(v.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn prompts_fill_holes_and_list_enabled_actions() {
+        let eng = writer_reviewer();
+        let b = str_bindings(&eng, &[("topic", "tides"), ("content", "The moon pulls.")]);
+        assert_eq!(
+            eng.render_prompt("Editor", &b).unwrap(),
+            "You are an editor. Review the following draft:\n\
+             \n\
+             The moon pulls.\n\
+             \n\
+             Either provide edits to improve it, or finish if it's ready.\n\
+             \n\
+             Reply with exactly one of these actions:\n  \
+             Edits[notes: String]\n  \
+             Finish\n",
+        );
+        let err = eng.render_prompt("Editor", &str_bindings(&eng, &[("topic", "tides")])).unwrap_err();
+        assert_eq!(eng.fmt_query_error(&err), "evaluation failed: unbound variable: content");
+        let err = eng.render_prompt("Critic", &b).unwrap_err();
+        assert_eq!(eng.fmt_query_error(&err), "unknown agent: Critic");
+    }
+
+    #[test]
+    fn record_fields_and_guards() {
+        let src = "schema Task
+                id: Int,
+                description: String
+            interface Worker
+                Working[task: Task, tries: Int] {
+                    Complete[summary: String],
+                    Retry if (tries < 3)
+                }
+            agent Worker : Worker.Working
+                \"\"\"
+                Task #{task.id}: {task.description}
+                Reply as {{\"action\": ...}}.
+                \"\"\"";
+        let eng = Engine::load(src).unwrap_or_else(|e| panic!("{e:?}"));
+        let sym = |s: &str| eng.interner.find(s).unwrap();
+        let task = Value::Record {
+            schema: sym("Task"),
+            fields: [(sym("id"), Value::Int(7)), (sym("description"), Value::Str("ship it".into()))].into(),
+        };
+        let b = Bindings::from([(sym("task"), task), (sym("tries"), Value::Int(3))]);
+        assert_eq!(
+            eng.render_prompt("Worker", &b).unwrap(),
+            "Task #7: ship it\n\
+             Reply as {\"action\": ...}.\n\
+             \n\
+             Reply with exactly one of these actions:\n  \
+             Complete[summary: String]\n",
+        );
+        assert!(eng.fmt_agent(&eng.agents[&sym("Worker")]).contains("Reply as {{\"action\": ...}}."));
+    }
+
+    #[test]
+    fn holes_are_checked_against_the_position() {
+        let errors = |template: &str| {
+            let src = format!(
+                "schema Task
+                    id: Int
+                interface Worker
+                    Working[task: Task] {{ Done }}
+                agent W : Worker.Working
+                    \"\"\"{template}\"\"\""
+            );
+            match Engine::load(&src) {
+                Ok(_) => Vec::new(),
+                Err(EngineError::Parse(d)) | Err(EngineError::Validate(d)) => {
+                    d.into_iter().map(|d| d.message).collect()
+                }
+            }
+        };
+        assert!(errors("Task {task.id}").is_empty());
+        assert_eq!(errors("Task {tsak}"), vec!["agent W: unbound variable `tsak`"]);
+        assert_eq!(errors("Task {task.name}"), vec!["agent W: schema Task has no field `name`"]);
+        assert_eq!(
+            errors("Task {task id}"),
+            vec!["expected `{name}` or `{name.field}` in template, got `{task id}`"],
+        );
+    }
+}
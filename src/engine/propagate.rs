@@ -0,0 +1,309 @@
+use super::eval::{eval_bool, Bindings};
+use super::query::{bind_pattern, GuardKind, QueryError, Step};
+use super::runtime::{Runtime, RuntimeError};
+use super::{DirMapping, DirRef, Engine, Sym};
+
+
+// ============================================================================
+// Propagation through defers
+// ============================================================================
+//
+// A defer `D : S -> T` says how `T`'s actions are carried out by `S`: at each
+// `S` position, every mapped `T` direction names an `S` direction (or, when `S`
+// is a `state` block, an abstract transition between `S` states).
+// A `Link` wires one instance playing `S` to one playing `T`. An instance of
+// `X` also plays a state block that is `X`'s own state: one with `X`'s
+// positions that some defer realizes `X` from, such as the `X::Internal` of a
+// desugared interface. The two share the same state.
+//
+// Firing an action on an instance fires it locally through `next_position`,
+// then, for every link that targets the instance, fires the induced action on
+// the link's source — recursively, so a chain of defers produces one
+// `Propagation` covering every instance it moves. Nothing is committed until
+// the whole chain resolves; an instance reached twice is a cycle (or, if it
+// was reached along two branches, a conflict), and two mappings that both
+// apply to the same action are ambiguous.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Link {
+    pub defer: Sym,
+    pub source: String,
+    pub target: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Move {
+    pub instance: String,
+    /// For an abstract transition, `step.action` is the target action that
+    /// induced it.
+    pub step: Step,
+}
+
+/// Every move one action causes, the directly fired one first.
+#[derive(Clone, Debug)]
+pub struct Propagation {
+    pub moves: Vec<Move>,
+}
+
+impl Engine {
+    /// Whether an instance of `iface` can stand in for `role` in a defer
+    /// signature: either the interface itself or a state block holding its
+    /// state.
+    pub(super) fn plays(&self, iface: Sym, role: Sym) -> bool {
+        iface == role || self.is_state_of(role, iface)
+    }
+}
+
+impl Runtime {
+    /// Resolve `action` on `name` and everything it induces through linked
+    /// defers, without changing any instance.
+    pub fn propagate(
+        &self,
+        name: &str,
+        action: &str,
+        args: Bindings,
+    ) -> Result<Propagation, RuntimeError> {
+        let mut moves = Vec::new();
+        self.fire(name, action, args, &mut Vec::new(), &mut moves)?;
+        Ok(Propagation { moves })
+    }
+
+    fn fire(
+        &self,
+        name: &str,
+        action: &str,
+        args: Bindings,
+        path: &mut Vec<String>,
+        moves: &mut Vec<Move>,
+    ) -> Result<(), RuntimeError> {
+        enter(name, path, moves)?;
+        let inst = self
+            .instance(name)
+            .ok_or_else(|| RuntimeError::UnknownInstance(name.to_string()))?;
+        let mut bindings = inst.bindings.clone();
+        bindings.extend(args.clone());
+        let eng = &self.engine;
+        let step = eng
+            .next_position(eng.resolve(inst.interface), eng.resolve(inst.position), action, bindings)
+            .map_err(|error| RuntimeError::Rejected { instance: name.to_string(), error })?;
+        let action = step.action;
+        moves.push(Move { instance: name.to_string(), step });
+
+        path.push(name.to_string());
+        for link in self.links().iter().filter(|l| l.target == name) {
+            self.induce(link, action, &args, path, moves)?;
+        }
+        path.pop();
+        Ok(())
+    }
+
+    /// Carry `action`, just fired on `link.target` with `args`, over to
+    /// `link.source`. The args are in scope for the mapped direction, as the
+    /// typechecker has them. A link whose defer does not map `action` at the
+    /// source's current position induces nothing.
+    fn induce(
+        &self,
+        link: &Link,
+        action: Sym,
+        args: &Bindings,
+        path: &mut Vec<String>,
+        moves: &mut Vec<Move>,
+    ) -> Result<(), RuntimeError> {
+        let eng = &self.engine;
+        let defer = eng.defers.iter().find(|d| d.name == link.defer).unwrap();
+        let src = self
+            .instance(&link.source)
+            .ok_or_else(|| RuntimeError::UnknownInstance(link.source.clone()))?;
+        let rejected = |error| RuntimeError::Rejected { instance: link.source.clone(), error };
+        let src_iface = eng.signature(defer.source).unwrap();
+        let Some(pos) = src_iface.position(&src.position) else {
+            return Ok(());
+        };
+
+        let mut candidates: Vec<&DirMapping<Sym>> = Vec::new();
+        for entry in defer.entries.iter().filter(|e| e.source_pos == src.position) {
+            let local = bind_pattern(pos, &src.bindings, &entry.source_pattern);
+            if let Some(g) = &entry.source_guard {
+                if !eval_bool(eng, g, &local).map_err(|e| rejected(QueryError::EvalFailed(e)))? {
+                    continue;
+                }
+            }
+            candidates.extend(entry.directions.iter().filter(|m| {
+                m.target_dir == DirRef::Named(action)
+                    && match &m.source_dir {
+                        DirRef::Named(_) => true,
+                        DirRef::Abstract { src_pos, .. } => *src_pos == src.position,
+                    }
+            }));
+        }
+
+        let mapping = match candidates[..] {
+            [] => return Ok(()),
+            [m] => m,
+            _ => {
+                return Err(RuntimeError::Ambiguous {
+                    defer: link.defer,
+                    instance: link.target.clone(),
+                    action,
+                    candidates: candidates.len(),
+                })
+            }
+        };
+        match &mapping.source_dir {
+            DirRef::Named(a) => {
+                self.fire(&link.source, eng.resolve(*a), args.clone(), path, moves)
+            }
+            DirRef::Abstract { src_pattern, tgt_pos, tgt_args, .. } => {
+                enter(&link.source, path, moves)?;
+                let mut local = bind_pattern(pos, &src.bindings, src_pattern);
+                local.extend(args.clone());
+                let iface_name = eng.resolve(defer.source);
+                let (target_position, target_bindings) = eng
+                    .apply_transition(iface_name, &local, tgt_pos, tgt_args)
+                    .map_err(rejected)?;
+                let target = src_iface.position(&target_position).unwrap();
+                if let Some(g) = &target.guard {
+                    let ok = eval_bool(eng, g, &target_bindings)
+                        .map_err(|e| rejected(QueryError::EvalFailed(e)))?;
+                    if !ok {
+                        return Err(rejected(QueryError::GuardFailed {
+                            interface: eng.resolve(src.interface).to_string(),
+                            position: eng.resolve(target_position).to_string(),
+                            kind: GuardKind::TargetPosition,
+                        }));
+                    }
+                }
+                moves.push(Move {
+                    instance: link.source.clone(),
+                    step: Step {
+                        interface: src.interface,
+                        source_position: src.position,
+                        source_bindings: src.bindings.clone(),
+                        action,
+                        target_position,
+                        target_bindings,
+                    },
+                });
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Refuse to move `name` a second time in one propagation.
+fn enter(name: &str, path: &[String], moves: &[Move]) -> Result<(), RuntimeError> {
+    if let Some(i) = path.iter().position(|p| p == name) {
+        let mut cycle = path[i..].to_vec();
+        cycle.push(name.to_string());
+        return Err(RuntimeError::Cycle(cycle));
+    }
+    if moves.iter().any(|m| m.instance == name) {
+        return Err(RuntimeError::MovedTwice(name.to_string()));
+    }
+    Ok(())
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::eval::Value;
+    use super::super::trajectory::{Event, HEADER};
+    use super::*;
+
+    fn counter() -> Runtime {
+        let src = std::fs::read_to_string("examples/counter.poly").unwrap();
+        let mut rt = Runtime::new(Engine::load(&src).expect("load counter"));
+        let n = rt.engine.interner.find("n").unwrap();
+        rt.spawn("c", "Counter", "Count", Bindings::from([(n, Value::Int(3))])).unwrap();
+        rt.spawn("b", "Button", "Button", Bindings::new()).unwrap();
+        rt
+    }
+
+    #[test]
+    fn press_moves_the_linked_counter() {
+        let mut rt = counter();
+        rt.link("SetTo10", "c", "b").unwrap();
+        let p = rt.apply("b", "Press", Bindings::new()).unwrap();
+        let moves: String =
+            p.moves.iter().map(|m| format!("{} : {}", m.instance, rt.engine.fmt_step(&m.step))).collect();
+        assert_eq!(
+            moves,
+            "b : Button.Button --Press--> Button.Button\n\
+             c : Counter.Count[n=3] --Press--> Counter.Count[n=10]\n",
+        );
+        assert_eq!(rt.fmt_instances(), "b : Button.Button\nc : Counter.Count[n=10]\n");
+
+        let log: String = rt.trajectory().iter().map(|e| rt.engine.fmt_event(e) + "\n").collect();
+        assert!(log.contains("2\tlink\tSetTo10\tc\tb\n"), "{log}");
+        assert!(log.ends_with("4\tinduced\tc\tCount\t[n=3]\tPress\t[]\tCount\t[n=10]\n"), "{log}");
+        let events = rt.engine.parse_trajectory(&format!("{HEADER}\n{log}")).unwrap();
+        let mut replayed = Runtime::new(rt.engine.clone());
+        replayed.replay(&events).unwrap();
+        assert_eq!(replayed.fmt_instances(), rt.fmt_instances());
+    }
+
+    #[test]
+    fn links_check_the_defer_signature() {
+        let mut rt = counter();
+        let err = rt.link("SetTo10", "b", "c").unwrap_err();
+        assert_eq!(
+            rt.engine.fmt_runtime_error(&err),
+            "defer SetTo10 expects Counter::Internal, but b is a Button",
+        );
+    }
+
+    #[test]
+    fn cycles_and_ambiguous_mappings_are_rejected() {
+        let src = "interface A { Go }\n\
+                   interface B { Go }\n\
+                   defer AB : A -> B\n    A -> { Go -> Go }\n\
+                   defer BA : B -> A\n    B -> { Go -> Go }\n\
+                   defer Twice : A -> B\n    A -> { Go -> Go, Go -> Go }\n";
+        let mut rt = Runtime::new(Engine::load(src).unwrap_or_else(|e| panic!("{e:?}")));
+        rt.spawn("a", "A", "A", Bindings::new()).unwrap();
+        rt.spawn("b", "B", "B", Bindings::new()).unwrap();
+        rt.link("AB", "a", "b").unwrap();
+        rt.link("BA", "b", "a").unwrap();
+        let err = rt.apply("b", "Go", Bindings::new()).unwrap_err();
+        assert_eq!(rt.engine.fmt_runtime_error(&err), "propagation cycle: b -> a -> b");
+        assert!(rt.trajectory().iter().all(|e| !matches!(e, Event::Step { .. })));
+
+        let mut rt = Runtime::new(rt.engine);
+        rt.spawn("a", "A", "A", Bindings::new()).unwrap();
+        rt.spawn("b", "B", "B", Bindings::new()).unwrap();
+        rt.link("Twice", "a", "b").unwrap();
+        let err = rt.apply("b", "Go", Bindings::new()).unwrap_err();
+        assert_eq!(
+            rt.engine.fmt_runtime_error(&err),
+            "defer Twice: Go on b has 2 applicable mappings",
+        );
+    }
+
+    #[test]
+    fn the_fired_action_args_reach_the_source() {
+        let src = "interface Counter\n    Count[n: Int] { Increment -> Count[n + 1] }\n\
+                   interface Button\n    { Set[v: Int] }\n\
+                   defer SetTo : Counter::Internal -> Button\n    Count[_] -> {\n        Set -> Count[_] => Count[v]\n    }\n\
+                   interface Dial\n    At[at: Int] { Turn[v: Int] -> At[v] }\n\
+                   defer Forward : Dial -> Button\n    At[_] -> Button { Set -> Turn }\n";
+        let mut rt = Runtime::new(Engine::load(src).unwrap_or_else(|e| panic!("{e:?}")));
+        let n = rt.engine.interner.find("n").unwrap();
+        let v = rt.engine.interner.find("v").unwrap();
+        rt.spawn("c", "Counter", "Count", Bindings::from([(n, Value::Int(0))])).unwrap();
+        let at = rt.engine.interner.find("at").unwrap();
+        rt.spawn("d", "Dial", "At", Bindings::from([(at, Value::Int(0))])).unwrap();
+        rt.spawn("b", "Button", "Button", Bindings::new()).unwrap();
+        rt.link("SetTo", "c", "b").unwrap();
+        rt.link("Forward", "d", "b").unwrap();
+        let p = rt.apply("b", "Set", Bindings::from([(v, Value::Int(5))])).unwrap();
+        assert_eq!(p.moves.len(), 3);
+        assert_eq!(
+            rt.fmt_instances(),
+            "b : Button.Button\nc : Counter.Count[n=5]\nd : Dial.At[at=5]\n",
+        );
+    }
+}
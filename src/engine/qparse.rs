@@ -0,0 +1,557 @@
+use std::collections::BTreeSet;
+
+use chumsky::prelude::*;
+
+use super::diag::Diagnostic;
+use super::facts::Facts;
+use super::lower::lower_expr;
+use super::parse::{expr_parser, keyword, qualified_ident, ws};
+use super::eval::Value;
+use super::uquery::{DataSlot, DirRefPat, Goal, IndexSlot, Query, Slot, Term, VarGen, VarId};
+use super::{Engine, Expr, Interner, Span, Sym};
+
+
+// ============================================================================
+// Query surface syntax
+// ============================================================================
+//
+// Datalog-style text over the relations `Engine::facts` produces:
+//
+//     position(I, P, _, G), direction(I, P, Decrement, _, _), where n > 5
+//     defer(D, Counter::Internal, _) ; defer(D, _, Counter::Internal)
+//
+// Goals are comma-separated; `;` separates disjuncts (each becomes one body
+// of the `Query`). Argument forms:
+//
+//   - `?X` is always a variable.
+//   - A bare name is a constant when it names something of the slot's kind
+//     in the facts queried: an interface or state block in an interface
+//     slot, a position in a position slot, an action in an action slot, and
+//     likewise for schemas, defers, instances and binding names. Anywhere
+//     else it is a variable, so a variable `n` stays one beside a param `n`.
+//     Slots that only ever hold structured values (params, guards, patterns,
+//     args, entry indices) treat every bare name as a variable.
+//   - `_` matches anything; each `_` is distinct.
+//   - Integers are literal entry indices / event sequence numbers.
+//   - The value slot of `binding` also takes integer, string and
+//     `true`/`false` literals.
+//   - In `defer_dir`, `named(A)` and `abstract(P, Pat, Q, Args)` match the two
+//     `DirRef` shapes; a variable there binds the whole ref.
+//
+// With a trajectory loaded, `instance(Inst, I)`, `step(T, Inst, P, A, P2)`
+// and `binding(T, Var, Value)` join history with the schema relations.
+//
+// `where <expr>` adds a residual constraint, written in `.poly` expression
+// syntax and resolved by the simplifier like any guard.
+
+#[derive(Clone, Debug)]
+pub struct ParsedQuery {
+    pub query: Query,
+    /// Named variables in order of first appearance, for rendering answers.
+    pub vars: Vec<(String, VarId)>,
+}
+
+#[derive(Clone, Debug)]
+enum RawArg {
+    Var(String),
+    Name(String),
+    Anon,
+    Int(i64),
+    Str(String),
+    Call(String, Vec<(RawArg, Span)>),
+}
+
+#[derive(Clone, Debug)]
+enum RawGoal {
+    Atom(String, Vec<(RawArg, Span)>, Span),
+    Where(Expr<String>),
+}
+
+
+// ============================================================================
+// Parser
+// ============================================================================
+
+fn arg_parser() -> impl Parser<char, (RawArg, Span), Error = Simple<char>> + Clone {
+    recursive(|arg| {
+        let var = just('?').ignore_then(text::ident()).map(RawArg::Var);
+        let int = just('-').or_not().then(text::int(10)).try_map(|(neg, s): (_, String), span| {
+            let s = if neg.is_some() { format!("-{s}") } else { s };
+            s.parse::<i64>()
+                .map(RawArg::Int)
+                .map_err(|e| Simple::custom(span, e.to_string()))
+        });
+        let string = none_of("\"")
+            .repeated()
+            .collect::<String>()
+            .delimited_by(just('"'), just('"'))
+            .map(RawArg::Str);
+        let name = qualified_ident()
+            .then(
+                arg.separated_by(just(',').padded_by(ws()))
+                    .delimited_by(just('(').padded_by(ws()), just(')').padded_by(ws()))
+                    .or_not(),
+            )
+            .map(|(name, args)| match args {
+                Some(args) => RawArg::Call(name, args),
+                None if name == "_" => RawArg::Anon,
+                None => RawArg::Name(name),
+            });
+        var.or(int)
+            .or(string)
+            .padded_by(ws())
+            .or(name)
+            .map_with_span(|a, span: Span| (a, span))
+    })
+}
+
+fn goal_parser() -> impl Parser<char, RawGoal, Error = Simple<char>> + Clone {
+    let where_goal = keyword("where").ignore_then(expr_parser()).map(RawGoal::Where);
+    let atom = qualified_ident()
+        .then(
+            arg_parser()
+                .separated_by(just(',').padded_by(ws()))
+                .delimited_by(just('(').padded_by(ws()), just(')').padded_by(ws())),
+        )
+        .map_with_span(|(name, args), span: Span| RawGoal::Atom(name, args, span));
+    where_goal.or(atom)
+}
+
+fn query_parser() -> impl Parser<char, Vec<Vec<RawGoal>>, Error = Simple<char>> {
+    let body = goal_parser().separated_by(just(',').padded_by(ws())).at_least(1);
+    ws().ignore_then(body.separated_by(just(';').padded_by(ws())).at_least(1))
+        .then_ignore(end())
+}
+
+
+// ============================================================================
+// Resolution to `Goal`s
+// ============================================================================
+
+/// What a name slot holds, and so which names are constants there.
+#[derive(Clone, Copy)]
+enum Kind {
+    Schema,
+    Iface,
+    Position,
+    Action,
+    Defer,
+    Instance,
+    Param,
+}
+
+/// The names of each kind that appear in the facts queried.
+#[derive(Default)]
+struct Declared {
+    schemas: BTreeSet<Sym>,
+    ifaces: BTreeSet<Sym>,
+    positions: BTreeSet<Sym>,
+    actions: BTreeSet<Sym>,
+    defers: BTreeSet<Sym>,
+    instances: BTreeSet<Sym>,
+    params: BTreeSet<Sym>,
+}
+
+impl Declared {
+    fn new(f: &Facts) -> Self {
+        let mut d = Declared::default();
+        d.schemas.extend(f.schema_records.iter().map(|r| r.schema));
+        d.schemas.extend(f.schema_sums.iter().map(|s| s.schema));
+        d.ifaces.extend(f.ifaces.iter().map(|i| i.iface));
+        d.ifaces.extend(f.state_blocks.iter().map(|s| s.state));
+        for p in &f.positions {
+            d.positions.insert(p.position);
+            d.params.extend(p.params.iter().map(|p| p.name));
+        }
+        for dir in &f.directions {
+            d.actions.insert(dir.action);
+            d.params.extend(dir.params.iter().map(|p| p.name));
+        }
+        d.defers.extend(f.defers.iter().map(|d| d.defer));
+        d.instances.extend(f.instances.iter().map(|i| i.instance));
+        d.params.extend(f.bindings.iter().map(|b| b.name));
+        d
+    }
+
+    fn has(&self, kind: Kind, sym: Sym) -> bool {
+        let names = match kind {
+            Kind::Schema => &self.schemas,
+            Kind::Iface => &self.ifaces,
+            Kind::Position => &self.positions,
+            Kind::Action => &self.actions,
+            Kind::Defer => &self.defers,
+            Kind::Instance => &self.instances,
+            Kind::Param => &self.params,
+        };
+        names.contains(&sym)
+    }
+}
+
+struct Resolver<'a> {
+    interner: &'a mut Interner,
+    declared: Declared,
+    gen: VarGen,
+    vars: Vec<(String, VarId)>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Resolver<'_> {
+    fn var(&mut self, name: &str) -> VarId {
+        if let Some((_, v)) = self.vars.iter().find(|(n, _)| n == name) {
+            return *v;
+        }
+        let v = self.gen.fresh();
+        self.vars.push((name.to_string(), v));
+        v
+    }
+
+    fn error(&mut self, msg: impl Into<String>, span: &Span) {
+        self.errors.push(Diagnostic::new(msg, span.clone()));
+    }
+
+    fn term(&mut self, kind: Kind, (arg, span): &(RawArg, Span)) -> Term {
+        match arg {
+            RawArg::Var(n) => Term::Var(self.var(n)),
+            RawArg::Name(n) => match self.interner.find(n) {
+                Some(sym) if self.declared.has(kind, sym) => Term::Sym(sym),
+                _ => Term::Var(self.var(n)),
+            },
+            RawArg::Anon => Term::Anon,
+            _ => {
+                self.error("expected a name, variable or `_`", span);
+                Term::Anon
+            }
+        }
+    }
+
+    fn slot(&mut self, (arg, span): &(RawArg, Span)) -> Slot {
+        match arg {
+            RawArg::Var(n) | RawArg::Name(n) => Slot::Var(self.var(n)),
+            RawArg::Anon => Slot::Anon,
+            _ => {
+                self.error("this slot takes a variable or `_`", span);
+                Slot::Anon
+            }
+        }
+    }
+
+    fn index(&mut self, (arg, span): &(RawArg, Span)) -> IndexSlot {
+        match arg {
+            RawArg::Var(n) | RawArg::Name(n) => IndexSlot::Var(self.var(n)),
+            RawArg::Anon => IndexSlot::Anon,
+            RawArg::Int(i) if *i >= 0 => IndexSlot::Lit(*i as usize),
+            _ => {
+                self.error("an index is a non-negative integer, a variable or `_`", span);
+                IndexSlot::Anon
+            }
+        }
+    }
+
+    fn data(&mut self, (arg, span): &(RawArg, Span)) -> DataSlot {
+        match arg {
+            RawArg::Name(n) if n == "true" || n == "false" => DataSlot::Lit(Value::Bool(n == "true")),
+            RawArg::Var(n) | RawArg::Name(n) => DataSlot::Var(self.var(n)),
+            RawArg::Anon => DataSlot::Anon,
+            RawArg::Int(i) => DataSlot::Lit(Value::Int(*i)),
+            RawArg::Str(s) => DataSlot::Lit(Value::Str(s.clone())),
+            RawArg::Call(..) => {
+                self.error("a value is an integer, string, `true`/`false`, a variable or `_`", span);
+                DataSlot::Anon
+            }
+        }
+    }
+
+    fn dir_ref(&mut self, a: &(RawArg, Span)) -> DirRefPat {
+        let (arg, span) = a;
+        match arg {
+            RawArg::Var(n) => DirRefPat::Var(self.var(n)),
+            RawArg::Name(_) => match self.term(Kind::Action, a) {
+                Term::Var(v) => DirRefPat::Var(v),
+                t => DirRefPat::Named(t),
+            },
+            RawArg::Anon => DirRefPat::Anon,
+            RawArg::Call(f, args) if f == "named" && args.len() == 1 => {
+                DirRefPat::Named(self.term(Kind::Action, &args[0]))
+            }
+            RawArg::Call(f, args) if f == "abstract" && args.len() == 4 => DirRefPat::Abstract {
+                src_pos: self.term(Kind::Position, &args[0]),
+                src_pattern: self.slot(&args[1]),
+                tgt_pos: self.term(Kind::Position, &args[2]),
+                tgt_args: self.slot(&args[3]),
+            },
+            _ => {
+                self.error(
+                    "expected a direction ref: a name, a variable, `named(A)` or `abstract(P, Pat, Q, Args)`",
+                    span,
+                );
+                DirRefPat::Anon
+            }
+        }
+    }
+
+    fn goal(&mut self, g: RawGoal) -> Option<Goal> {
+        let (name, a, span) = match g {
+            RawGoal::Where(e) => return Some(Goal::Where(lower_expr(e, self.interner))),
+            RawGoal::Atom(name, args, span) => (name, args, span),
+        };
+        let goal = match (name.as_str(), a.len()) {
+            ("schema_record", 2) => Goal::SchemaRecord {
+                schema: self.term(Kind::Schema, &a[0]),
+                fields: self.slot(&a[1]),
+            },
+            ("schema_sum", 2) => Goal::SchemaSum {
+                schema: self.term(Kind::Schema, &a[0]),
+                variants: self.slot(&a[1]),
+            },
+            ("iface", 2) => Goal::Iface { iface: self.term(Kind::Iface, &a[0]), params: self.slot(&a[1]) },
+            ("state_block", 2) => Goal::StateBlock {
+                state: self.term(Kind::Iface, &a[0]),
+                params: self.slot(&a[1]),
+            },
+            ("position", 4) => Goal::Position {
+                iface: self.term(Kind::Iface, &a[0]),
+                position: self.term(Kind::Position, &a[1]),
+                params: self.slot(&a[2]),
+                guard: self.slot(&a[3]),
+            },
+            ("direction", 5) => Goal::Direction {
+                iface: self.term(Kind::Iface, &a[0]),
+                position: self.term(Kind::Position, &a[1]),
+                action: self.term(Kind::Action, &a[2]),
+                params: self.slot(&a[3]),
+                guard: self.slot(&a[4]),
+            },
+            ("transition", 5) => Goal::Transition {
+                iface: self.term(Kind::Iface, &a[0]),
+                position: self.term(Kind::Position, &a[1]),
+                action: self.term(Kind::Action, &a[2]),
+                target_pos: self.term(Kind::Position, &a[3]),
+                args: self.slot(&a[4]),
+            },
+            ("defer", 3) => Goal::Defer {
+                defer: self.term(Kind::Defer, &a[0]),
+                source: self.term(Kind::Iface, &a[1]),
+                target: self.term(Kind::Iface, &a[2]),
+            },
+            ("defer_entry", 7) => Goal::DeferEntry {
+                defer: self.term(Kind::Defer, &a[0]),
+                entry_idx: self.index(&a[1]),
+                source_pos: self.term(Kind::Position, &a[2]),
+                src_pattern: self.slot(&a[3]),
+                src_guard: self.slot(&a[4]),
+                target_pos: self.term(Kind::Position, &a[5]),
+                target_args: self.slot(&a[6]),
+            },
+            ("defer_dir", 4) => Goal::DeferDir {
+                defer: self.term(Kind::Defer, &a[0]),
+                entry_idx: self.index(&a[1]),
+                target_dir: self.dir_ref(&a[2]),
+                source_dir: self.dir_ref(&a[3]),
+            },
+            ("instance", 2) => Goal::Instance {
+                instance: self.term(Kind::Instance, &a[0]),
+                iface: self.term(Kind::Iface, &a[1]),
+            },
+            ("step", 5) => Goal::Step {
+                seq: self.index(&a[0]),
+                instance: self.term(Kind::Instance, &a[1]),
+                position: self.term(Kind::Position, &a[2]),
+                action: self.term(Kind::Action, &a[3]),
+                target_pos: self.term(Kind::Position, &a[4]),
+            },
+            ("binding", 3) => Goal::Binding {
+                seq: self.index(&a[0]),
+                name: self.term(Kind::Param, &a[1]),
+                value: self.data(&a[2]),
+            },
+            (rel, got) => {
+                match relation_arity(rel) {
+                    Some(expected) => self.error(
+                        format!("relation `{rel}` takes {expected} argument(s), got {got}"),
+                        &span,
+                    ),
+                    None => self.error(format!("unknown relation `{rel}`"), &span),
+                }
+                return None;
+            }
+        };
+        Some(goal)
+    }
+}
+
+fn relation_arity(rel: &str) -> Option<usize> {
+    Some(match rel {
+        "schema_record" | "schema_sum" | "iface" | "state_block" | "instance" => 2,
+        "defer" | "binding" => 3,
+        "position" | "defer_dir" => 4,
+        "direction" | "transition" | "step" => 5,
+        "defer_entry" => 7,
+        _ => return None,
+    })
+}
+
+
+// ============================================================================
+// Entry point
+// ============================================================================
+
+impl Engine {
+    /// Parse query text against the names in `facts`, which should be the
+    /// facts the query is run on. `where` expressions may intern new names.
+    pub fn parse_query(&mut self, src: &str, facts: &Facts) -> Result<ParsedQuery, Vec<Diagnostic>> {
+        let bodies = query_parser()
+            .parse(src)
+            .map_err(|errs| errs.iter().map(Diagnostic::from_parse_error).collect::<Vec<_>>())?;
+        let mut r = Resolver {
+            interner: &mut self.interner,
+            declared: Declared::new(facts),
+            gen: VarGen::new(),
+            vars: Vec::new(),
+            errors: Vec::new(),
+        };
+        let bodies: Vec<Vec<Goal>> = bodies
+            .into_iter()
+            .map(|body| body.into_iter().filter_map(|g| r.goal(g)).collect())
+            .collect();
+        if !r.errors.is_empty() {
+            return Err(r.errors);
+        }
+        Ok(ParsedQuery { query: Query::or(bodies), vars: r.vars })
+    }
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::eval::Bindings;
+    use super::super::uquery::run_query;
+    use super::*;
+
+    fn load(path: &str) -> Engine {
+        let src = std::fs::read_to_string(path).expect("read example");
+        Engine::load(&src).expect("load engine")
+    }
+
+    fn answers(eng: &mut Engine, q: &str) -> Vec<String> {
+        let facts = eng.facts();
+        let parsed = eng.parse_query(q, &facts).unwrap_or_else(|e| panic!("{q}: {e:?}"));
+        run_query(eng, &facts, &parsed.query, &Bindings::new())
+            .iter()
+            .map(|a| eng.fmt_answer(a, &parsed.vars))
+            .collect()
+    }
+
+    #[test]
+    fn names_in_the_file_are_constants() {
+        let mut eng = load("examples/counter.poly");
+        assert_eq!(
+            answers(&mut eng, "direction(I, P, Decrement, _, _)"),
+            vec!["I = Counter, P = Count where n > 0"],
+        );
+    }
+
+    #[test]
+    fn names_are_constants_only_in_their_own_slots() {
+        let mut eng = load("examples/counter.poly");
+        // `n` is a param and `Decrement` an action, so neither is a constant
+        // in an interface or position slot.
+        assert_eq!(
+            answers(&mut eng, "direction(n, P, Decrement, _, _)"),
+            vec!["n = Counter, P = Count where n > 0"],
+        );
+        assert_eq!(
+            answers(&mut eng, "direction(Counter, Decrement, Increment, _, _)"),
+            vec!["Decrement = Count"],
+        );
+        assert_eq!(
+            answers(&mut eng, "position(n, Count, _, _)"),
+            vec!["n = Counter where n >= 0", "n = Counter::Internal where n >= 0"],
+        );
+    }
+
+    #[test]
+    fn where_narrows_the_residual() {
+        let mut eng = load("examples/counter.poly");
+        assert_eq!(
+            answers(&mut eng, "direction(Counter, Count, Decrement, _, _), where n > 5"),
+            vec!["true where n > 5"],
+        );
+        assert!(answers(&mut eng, "direction(Counter, Count, Decrement, _, _), where n < 0").is_empty());
+    }
+
+    #[test]
+    fn disjunction_and_abstract_refs() {
+        let mut eng = load("examples/counter.poly");
+        let got = answers(
+            &mut eng,
+            "defer_dir(Counter::Run, _, Increment, abstract(Count, _, Q, Args)) ; \
+             defer(D, _, Button)",
+        );
+        assert_eq!(got, vec!["Q = Count, Args = [n + 1]", "D = SetTo10"]);
+    }
+
+    #[test]
+    fn state_blocks_are_their_own_relation() {
+        let mut eng = load("examples/counter.poly");
+        assert_eq!(
+            answers(&mut eng, "state_block(S, _), defer(D, S, Button)"),
+            vec!["S = Counter::Internal, D = SetTo10"],
+        );
+        assert!(answers(&mut eng, "state_block(Counter, _)").is_empty());
+    }
+
+    #[test]
+    fn bodies_with_the_same_answer_merge() {
+        let mut eng = load("examples/counter.poly");
+        let dec = "direction(Counter, Count, Decrement, _, _)";
+        assert_eq!(
+            answers(&mut eng, &format!("{dec}, where n > 5 ; {dec}, where n < 2 ; {dec}, where n > 9")),
+            vec!["true where n > 5 or n == 1"],
+        );
+    }
+
+    #[test]
+    fn history_joins_with_schema_facts() {
+        let src = std::fs::read_to_string("examples/counter.poly").unwrap();
+        let mut rt = super::super::runtime::Runtime::new(Engine::load(&src).unwrap());
+        let n = rt.engine.interner.find("n").unwrap();
+        for (name, start) in [("a", 0), ("b", 1)] {
+            rt.spawn(name, "Counter", "Count", Bindings::from([(n, Value::Int(start))])).unwrap();
+        }
+        rt.apply("a", "Increment", Bindings::new()).unwrap();
+        rt.apply("a", "Decrement", Bindings::new()).unwrap();
+        rt.apply("b", "Decrement", Bindings::new()).unwrap();
+        let events = rt.trajectory().to_vec();
+        let mut eng = rt.engine;
+        let mut facts = eng.facts();
+        eng.add_trajectory_facts(&mut facts, &events);
+        let parsed = eng
+            .parse_query("step(T, Inst, Count, Decrement, _), binding(T, n, 1), instance(Inst, Counter)", &facts)
+            .unwrap();
+        let got: Vec<String> = run_query(&eng, &facts, &parsed.query, &Bindings::new())
+            .iter()
+            .map(|a| eng.fmt_answer(a, &parsed.vars))
+            .collect();
+        assert_eq!(got, vec!["T = 3, Inst = a", "T = 4, Inst = b"]);
+    }
+
+    #[test]
+    fn unknown_relation_and_bad_arity_are_located() {
+        let mut eng = load("examples/counter.poly");
+        let facts = eng.facts();
+        let errs = eng.parse_query("positon(I, P), direction(I)", &facts).unwrap_err();
+        let msgs: Vec<&str> = errs.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            msgs,
+            vec![
+                "unknown relation `positon`",
+                "relation `direction` takes 5 argument(s), got 1",
+            ],
+        );
+        assert_eq!(errs[0].span, 0..13);
+    }
+}
@@ -1,5 +1,7 @@
 use std::collections::BTreeMap;
 use super::eval::{eval, eval_bool, Bindings, EvalError};
+use super::simplify::reduce;
+use super::typecheck::scope;
 use super::{BinOp, DirMapping, DirRef, Engine, Expr, Param, Pattern, Position, Sym};
 
 // ============================================================================
@@ -9,6 +11,7 @@ use super::{BinOp, DirMapping, DirRef, Engine, Expr, Param, Pattern, Position, S
 #[derive(Clone, Debug)]
 pub enum QueryError {
     UnknownInterface(String),
+    UnknownAgent(String),
     UnknownPosition { interface: String, position: String },
     UnknownAction { interface: String, position: String, action: String },
     NoTransition { interface: String, position: String, action: String },
@@ -72,6 +75,8 @@ pub struct ActionLocation {
     pub interface: Sym,
     pub position: Sym,
     pub params: Vec<Param<Sym>>,
+    /// Position and direction guard together, simplified; `None` when the
+    /// action is available unconditionally.
     pub constraint: Option<Expr<Sym>>,
 }
 
@@ -101,8 +106,7 @@ impl Engine {
             .find(interface)
             .ok_or_else(|| QueryError::UnknownInterface(interface.to_string()))?;
         let iface = self
-            .interfaces
-            .get(&iface_sym)
+            .signature(iface_sym)
             .ok_or_else(|| QueryError::UnknownInterface(interface.to_string()))?;
         let pos_sym = self.interner.find(position).ok_or_else(|| QueryError::UnknownPosition {
             interface: interface.to_string(),
@@ -179,8 +183,7 @@ impl Engine {
             .find(interface)
             .ok_or_else(|| QueryError::UnknownInterface(interface.to_string()))?;
         let iface = self
-            .interfaces
-            .get(&iface_sym)
+            .signature(iface_sym)
             .ok_or_else(|| QueryError::UnknownInterface(interface.to_string()))?;
         let pos_sym = self.interner.find(position).ok_or_else(|| QueryError::UnknownPosition {
             interface: interface.to_string(),
@@ -222,9 +225,7 @@ impl Engine {
                 if let Some(trans) = &dir.transition {
                     self.apply_transition(interface, &bindings, &trans.target_pos, &trans.args)?
                 } else {
-                    self.apply_realization(
-                        interface, position, action, iface_sym, pos_sym, action_sym, &bindings,
-                    )?
+                    self.apply_realization(interface, iface_sym, pos, pos_sym, action_sym, &bindings)?
                 }
             }
             None => self.apply_via_defer_source(
@@ -258,14 +259,14 @@ impl Engine {
         })
     }
 
-    fn apply_transition(
+    pub(super) fn apply_transition(
         &self,
         interface: &str,
         bindings: &Bindings,
         target_pos: &Sym,
         args: &[Expr<Sym>],
     ) -> Result<(Sym, Bindings), QueryError> {
-        let iface = self.interfaces.get(&self.interner.find(interface).unwrap()).unwrap();
+        let iface = self.signature(self.interner.find(interface).unwrap()).unwrap();
         let tgt_pos = iface.position(target_pos).ok_or_else(|| QueryError::UnknownPosition {
             interface: interface.to_string(),
             position: self.resolve(*target_pos).to_string(),
@@ -279,7 +280,7 @@ impl Engine {
             });
         }
         let mut new_bindings: Bindings = BTreeMap::new();
-        for p in &iface.params {
+        for p in iface.params() {
             if let Some(v) = bindings.get(&p.name) {
                 new_bindings.insert(p.name, v.clone());
             }
@@ -291,22 +292,19 @@ impl Engine {
         Ok((*target_pos, new_bindings))
     }
 
+    /// Fire `action` through a defer realizing the interface from its own
+    /// state block, whose abstract direction ref names the transition.
     fn apply_realization(
         &self,
         interface: &str,
-        _position: &str,
-        _action: &str,
         iface_sym: Sym,
+        pos: &Position<Sym>,
         pos_sym: Sym,
         action_sym: Sym,
         bindings: &Bindings,
     ) -> Result<(Sym, Bindings), QueryError> {
-        let internal_name = format!("{interface}::Internal");
         for d in &self.defers {
-            if d.target != iface_sym {
-                continue;
-            }
-            if self.resolve(d.source) != internal_name {
+            if d.target != iface_sym || !self.is_state_of(d.source, iface_sym) {
                 continue;
             }
             for entry in &d.entries {
@@ -318,14 +316,22 @@ impl Engine {
                         if name != action_sym {
                             continue;
                         }
-                        if let DirRef::Abstract { tgt_pos, tgt_args, .. } = &m.source_dir {
-                            return self.apply_transition(interface, bindings, tgt_pos, tgt_args);
+                        if let DirRef::Abstract { src_pattern, tgt_pos, tgt_args, .. } = &m.source_dir {
+                            let local = bind_pattern(pos, bindings, src_pattern);
+                            return self.apply_transition(interface, &local, tgt_pos, tgt_args);
                         }
                     }
                 }
             }
         }
-        Ok((pos_sym, bindings.clone()))
+        // Nothing moves the instance: it keeps its state, but not the args.
+        let iface = self.signature(iface_sym).unwrap();
+        let kept = bindings
+            .iter()
+            .filter(|(k, _)| iface.params().iter().chain(&pos.params).any(|p| p.name == **k))
+            .map(|(k, v)| (*k, v.clone()))
+            .collect();
+        Ok((pos_sym, kept))
     }
 
     fn apply_via_defer_source(
@@ -352,7 +358,7 @@ impl Engine {
                     if name != action_sym {
                         continue;
                     }
-                    if let DirRef::Abstract { src_pos, src_pattern, tgt_pos, tgt_args } =
+                    if let DirRef::Abstract { src_pos, src_pattern, tgt_pos, tgt_args, .. } =
                         &m.source_dir
                     {
                         if *src_pos != pos_sym {
@@ -377,7 +383,14 @@ impl Engine {
             for (iname, iface) in &self.interfaces {
                 for pos in &iface.positions {
                     if let Some(dir) = pos.directions.iter().find(|d| d.name == action_sym) {
-                        let constraint = conjoin(pos.guard.as_ref(), dir.guard.as_ref());
+                        let constraint = match conjoin(pos.guard.as_ref(), dir.guard.as_ref()) {
+                            Some(c) => match reduce(self, &scope(iface.params.iter().chain(&pos.params).chain(&dir.params)), &c, &Bindings::new()) {
+                                Expr::LitBool(false) => continue,
+                                Expr::LitBool(true) => None,
+                                c => Some(c),
+                            },
+                            None => None,
+                        };
                         locations.push(ActionLocation {
                             interface: *iname,
                             position: pos.name,
@@ -401,6 +414,7 @@ impl Engine {
     pub fn fmt_query_error(&self, err: &QueryError) -> String {
         match err {
             QueryError::UnknownInterface(name) => format!("unknown interface: {name}"),
+            QueryError::UnknownAgent(name) => format!("unknown agent: {name}"),
             QueryError::UnknownPosition { interface, position } => {
                 format!("unknown position: {interface}.{position}")
             }
@@ -553,7 +567,7 @@ impl Engine {
 // Helpers
 // ============================================================================
 
-fn bind_pattern(pos: &Position<Sym>, bindings: &Bindings, pat: &[Pattern<Sym>]) -> Bindings {
+pub(super) fn bind_pattern(pos: &Position<Sym>, bindings: &Bindings, pat: &[Pattern<Sym>]) -> Bindings {
     let mut new_bindings = bindings.clone();
     for (param, p) in pos.params.iter().zip(pat.iter()) {
         if let Pattern::Bind(name) = p {
@@ -0,0 +1,344 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use super::eval::{eval_bool, Bindings, Value};
+use super::query::{GuardKind, QueryError, Step};
+use super::simplify::int_bounds;
+use super::typecheck::scope;
+use super::{Engine, Interface, Param, Sym, Type};
+
+
+// ============================================================================
+// Reachability
+// ============================================================================
+//
+// Breadth-first search over concrete states (position, bindings) of one
+// interface, firing every direction through `next_position`. Parameterized
+// state spaces are usually infinite, so the search is bounded:
+//
+//   - `max_depth` caps the number of steps from the start;
+//   - each `Int` position param is held to the interval its position guard
+//     implies (via `simplify::int_bounds`), with an unbounded side replaced by
+//     the start value ± `int_span`;
+//   - `Int` direction params are enumerated over their direction guard's
+//     interval, clamped to ±`int_span` (a clamped or open interval makes
+//     the search non-exhaustive); `Bool` params over both values;
+//     params of any other type take the witness search's sample values
+//     (the empty string, records and variants built from small values);
+//   - `max_states` caps the number of distinct states visited.
+//
+// A search that never hit a bound (and never sampled a direction whose params
+// it cannot enumerate) has explored the whole state space, so "unreachable"
+// is then a proof rather than a bounded answer.
+
+#[derive(Clone, Debug)]
+pub struct ReachBounds {
+    pub max_depth: usize,
+    pub int_span: i64,
+    pub max_states: usize,
+}
+
+impl Default for ReachBounds {
+    fn default() -> Self {
+        Self { max_depth: 64, int_span: 64, max_states: 100_000 }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Reach {
+    /// Steps from the start to the first state matching the goal.
+    Witness(Vec<Step>),
+    Unreachable {
+        states: usize,
+        /// No bound cut the search short.
+        exhaustive: bool,
+    },
+}
+
+struct Node {
+    position: Sym,
+    bindings: Bindings,
+    depth: usize,
+    parent: Option<(usize, Step)>,
+}
+
+impl Engine {
+    /// Search from `from` at `interface` for a state at `to.0` whose bindings
+    /// include every binding in `to.1`. `from.1` binds the interface params
+    /// as well as the start position's.
+    pub fn reachable(
+        &self,
+        interface: &str,
+        from: (Sym, Bindings),
+        to: (Sym, &Bindings),
+        bounds: &ReachBounds,
+    ) -> Result<Reach, QueryError> {
+        let iface = self.start_state(interface, &from)?;
+        let ranges = self.param_ranges(iface, &from.1, bounds.int_span);
+        let is_goal = |pos: Sym, b: &Bindings| {
+            pos == to.0 && to.1.iter().all(|(k, v)| b.get(k) == Some(v))
+        };
+
+        let mut nodes = vec![Node { position: from.0, bindings: from.1, depth: 0, parent: None }];
+        let mut seen: BTreeSet<(Sym, String)> = BTreeSet::new();
+        seen.insert((from.0, self.fmt_bindings(&nodes[0].bindings)));
+        let mut queue = VecDeque::from([0usize]);
+        let mut exhaustive = true;
+
+        while let Some(i) = queue.pop_front() {
+            if is_goal(nodes[i].position, &nodes[i].bindings) {
+                return Ok(Reach::Witness(witness(nodes, i)));
+            }
+            let pos = iface.position(&nodes[i].position).unwrap();
+            if pos.directions.is_empty() {
+                continue;
+            }
+            if nodes[i].depth == bounds.max_depth {
+                exhaustive = false;
+                continue;
+            }
+            for dir in &pos.directions {
+                let (arg_sets, complete) =
+                    self.direction_args(&dir.params, dir.guard.as_ref(), &nodes[i].bindings, bounds);
+                exhaustive &= complete;
+                for args in arg_sets {
+                    let mut b = nodes[i].bindings.clone();
+                    b.extend(args);
+                    let Ok(step) = self.next_position(
+                        interface,
+                        self.resolve(nodes[i].position),
+                        self.resolve(dir.name),
+                        b,
+                    ) else {
+                        continue;
+                    };
+                    if !in_ranges(&ranges, step.target_position, &step.target_bindings) {
+                        exhaustive = false;
+                        continue;
+                    }
+                    let key = (step.target_position, self.fmt_bindings(&step.target_bindings));
+                    if !seen.insert(key) {
+                        continue;
+                    }
+                    if nodes.len() == bounds.max_states {
+                        return Ok(Reach::Unreachable { states: nodes.len(), exhaustive: false });
+                    }
+                    queue.push_back(nodes.len());
+                    nodes.push(Node {
+                        position: step.target_position,
+                        bindings: step.target_bindings.clone(),
+                        depth: nodes[i].depth + 1,
+                        parent: Some((i, step)),
+                    });
+                }
+            }
+        }
+        Ok(Reach::Unreachable { states: nodes.len(), exhaustive })
+    }
+
+    /// The interface of a search starting at `from`, after checking that
+    /// `from` satisfies its position guard.
+    pub(super) fn start_state(
+        &self,
+        interface: &str,
+        from: &(Sym, Bindings),
+    ) -> Result<&Interface<Sym>, QueryError> {
+        let iface = self
+            .interner
+            .find(interface)
+            .and_then(|s| self.interfaces.get(&s))
+            .ok_or_else(|| QueryError::UnknownInterface(interface.to_string()))?;
+        let start = iface.position(&from.0).ok_or_else(|| QueryError::UnknownPosition {
+            interface: interface.to_string(),
+            position: self.resolve(from.0).to_string(),
+        })?;
+        if let Some(g) = &start.guard {
+            if !eval_bool(self, g, &from.1).map_err(QueryError::EvalFailed)? {
+                return Err(QueryError::GuardFailed {
+                    interface: interface.to_string(),
+                    position: self.resolve(from.0).to_string(),
+                    kind: GuardKind::Position,
+                });
+            }
+        }
+        Ok(iface)
+    }
+
+    /// Inclusive range for every `Int` position param, keyed by
+    /// (position, param).
+    pub(super) fn param_ranges(
+        &self,
+        iface: &Interface<Sym>,
+        start: &Bindings,
+        span: i64,
+    ) -> BTreeMap<(Sym, Sym), (i64, i64)> {
+        let mut out = BTreeMap::new();
+        for pos in &iface.positions {
+            let guard_bounds = pos
+                .guard
+                .as_ref()
+                .and_then(|g| {
+                    let types = scope(iface.params.iter().chain(&pos.params));
+                    int_bounds(self, &types, g, &interface_env(iface, start))
+                })
+                .unwrap_or_default();
+            for p in pos.params.iter().filter(|p| p.ty == Type::Int) {
+                let anchor = match start.get(&p.name) {
+                    Some(Value::Int(k)) => *k,
+                    _ => 0,
+                };
+                let (lo, hi) = guard_bounds.get(&p.name).copied().unwrap_or((None, None));
+                let lo = lo.unwrap_or(anchor.saturating_sub(span));
+                let hi = hi.unwrap_or(anchor.saturating_add(span));
+                out.insert((pos.name, p.name), (lo, hi));
+            }
+        }
+        out
+    }
+
+    /// Every assignment of the direction params to try, and whether those
+    /// cover every value (false once some param had to be sampled, or an
+    /// `Int` param's interval was open or clamped to the span).
+    pub(super) fn direction_args(
+        &self,
+        params: &[Param<Sym>],
+        guard: Option<&super::Expr<Sym>>,
+        bindings: &Bindings,
+        bounds: &ReachBounds,
+    ) -> (Vec<Bindings>, bool) {
+        let guard_bounds = guard.and_then(|g| int_bounds(self, &scope(params), g, bindings)).unwrap_or_default();
+        let mut sets = vec![Bindings::new()];
+        let mut complete = true;
+        for p in params {
+            let values: Vec<Value> = match p.ty {
+                Type::Int => {
+                    let (lo, hi) = guard_bounds.get(&p.name).copied().unwrap_or((None, None));
+                    // A side the guard leaves open, or that the span cuts
+                    // down, leaves values untried.
+                    complete &= lo.is_some_and(|lo| lo >= -bounds.int_span);
+                    complete &= hi.is_some_and(|hi| hi <= bounds.int_span);
+                    let lo = lo.unwrap_or(-bounds.int_span).max(-bounds.int_span);
+                    let hi = hi.unwrap_or(bounds.int_span).min(bounds.int_span);
+                    (lo..=hi).map(Value::Int).collect()
+                }
+                Type::Bool => vec![Value::Bool(false), Value::Bool(true)],
+                _ => {
+                    complete = false;
+                    self.sample_values(&p.ty, 0)
+                }
+            };
+            sets = sets
+                .into_iter()
+                .flat_map(|s| {
+                    values.iter().map(move |v| {
+                        let mut s = s.clone();
+                        s.insert(p.name, v.clone());
+                        s
+                    })
+                })
+                .collect();
+        }
+        (sets, complete)
+    }
+
+    pub fn fmt_reach(&self, r: &Reach) -> String {
+        match r {
+            Reach::Witness(steps) => {
+                let mut out = format!("reachable in {} step(s)\n", steps.len());
+                for s in steps {
+                    out.push_str(&format!("  {}", self.fmt_step(s)));
+                }
+                out
+            }
+            Reach::Unreachable { states, exhaustive: true } => {
+                format!("unreachable: explored all {states} state(s)\n")
+            }
+            Reach::Unreachable { states, exhaustive: false } => {
+                format!("unreachable within bounds ({states} state(s) explored)\n")
+            }
+        }
+    }
+}
+
+fn interface_env(iface: &Interface<Sym>, start: &Bindings) -> Bindings {
+    iface
+        .params
+        .iter()
+        .filter_map(|p| start.get(&p.name).map(|v| (p.name, v.clone())))
+        .collect()
+}
+
+pub(super) fn in_ranges(ranges: &BTreeMap<(Sym, Sym), (i64, i64)>, pos: Sym, b: &Bindings) -> bool {
+    ranges.iter().filter(|((p, _), _)| *p == pos).all(|((_, param), (lo, hi))| {
+        match b.get(param) {
+            Some(Value::Int(k)) => lo <= k && k <= hi,
+            _ => true,
+        }
+    })
+}
+
+fn witness(mut nodes: Vec<Node>, mut i: usize) -> Vec<Step> {
+    let mut steps = Vec::new();
+    while let Some((parent, step)) = nodes[i].parent.take() {
+        steps.push(step);
+        i = parent;
+    }
+    steps.reverse();
+    steps
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(path: &str) -> Engine {
+        let src = std::fs::read_to_string(path).unwrap();
+        Engine::load(&src).unwrap_or_else(|e| panic!("load {path}: {e:?}"))
+    }
+
+    fn reach(eng: &Engine, iface: &str, from: &str, to: &str, extra: &str) -> String {
+        let (pos, mut b) = eng.parse_state(iface, from).unwrap();
+        b.extend(eng.parse_bindings(extra).unwrap());
+        let (to_pos, to_b) = eng.parse_state(iface, to).unwrap();
+        let r = eng.reachable(iface, (pos, b), (to_pos, &to_b), &ReachBounds::default()).unwrap();
+        eng.fmt_reach(&r)
+    }
+
+    #[test]
+    fn counter_reaches_ten_by_incrementing() {
+        let eng = load("examples/counter.poly");
+        let out = reach(&eng, "Counter", "Count[0]", "Count[10]", "");
+        assert!(out.starts_with("reachable in 10 step(s)\n"), "{out}");
+        assert!(out.ends_with("Counter.Count[n=9] --Increment--> Counter.Count[n=10]\n"), "{out}");
+        let out = reach(&eng, "Counter", "Count[0]", "Count[n=100]", "");
+        assert_eq!(out, "unreachable within bounds (65 state(s) explored)\n");
+    }
+
+    #[test]
+    fn grid_search_is_exhaustive() {
+        let eng = load("examples/grid.poly");
+        let out = reach(&eng, "Grid", "Cell[Coordinate(1, 1)]", "Cell[Coordinate(3, 3)]", "Width=3, Height=3");
+        assert!(out.starts_with("reachable in 4 step(s)\n"), "{out}");
+        let out = reach(&eng, "Grid", "Cell[Coordinate(1, 1)]", "Cell[Coordinate(4, 1)]", "Width=3, Height=3");
+        assert_eq!(out, "unreachable: explored all 9 state(s)\n");
+    }
+
+    #[test]
+    fn clamped_direction_params_are_not_a_proof() {
+        let eng = Engine::load(
+            "interface Dial
+                At[n: Int] if (n >= 0 and n <= 1000) {
+                    Set[k: Int] -> At[k]
+                }",
+        )
+        .expect("load dial");
+        let out = reach(&eng, "Dial", "At[0]", "At[500]", "");
+        assert_eq!(out, "unreachable within bounds (65 state(s) explored)\n");
+        let out = reach(&eng, "Dial", "At[0]", "At[50]", "");
+        assert_eq!(out, "reachable in 1 step(s)\n  Dial.At[n=0, k=50] --Set--> Dial.At[n=50]\n");
+    }
+}
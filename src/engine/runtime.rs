@@ -0,0 +1,479 @@
+use std::collections::BTreeMap;
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use super::eval::{eval_bool, Bindings, Value};
+use super::propagate::{Link, Propagation};
+use super::query::{GuardKind, QueryError};
+use super::trajectory::{Event, HEADER};
+use super::{Engine, Sym};
+
+
+// ============================================================================
+// Live configuration
+// ============================================================================
+//
+// The schema says which states and evolutions are allowed; the `Runtime` holds
+// which state each live instance is actually in. An instance is an interface
+// at a position with bindings for the interface params (Grid's Width/Height)
+// and the position params (the current cell). Every change goes through
+// `Engine::next_position`, so a rejected action never touches the store.
+// Instances wired together with `link` move together (see `propagate.rs`).
+// Accepted changes are also appended to the trajectory (see `trajectory.rs`),
+// and to a log file when one is attached with `log_to`. Instances of an
+// `external` interface that enter a position are queued for the host (see
+// `host.rs`); tool instances are driven through a `ToolHost` (see `tools.rs`).
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instance {
+    pub interface: Sym,
+    pub position: Sym,
+    pub bindings: Bindings,
+}
+
+/// The configuration as it stood when `Runtime::snapshot` was called:
+/// every instance, the links between them, and the entries not yet told to
+/// the host.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub(super) instances: BTreeMap<String, Instance>,
+    pub(super) links: Vec<Link>,
+    pub(super) entered: Vec<(String, Instance)>,
+    /// Sequence number of the first event after the snapshot.
+    pub(super) at: u64,
+}
+
+#[derive(Clone, Debug)]
+pub enum RuntimeError {
+    UnknownInstance(String),
+    DuplicateInstance(String),
+    MissingBinding { instance: String, param: Sym },
+    Rejected { instance: String, error: QueryError },
+    UnknownDefer(String),
+    LinkMismatch { defer: Sym, instance: String, expected: Sym, actual: Sym },
+    /// Instance names along the propagation path, first and last the same.
+    Cycle(Vec<String>),
+    /// Reached along two branches of one propagation.
+    MovedTwice(String),
+    Ambiguous { defer: Sym, instance: String, action: Sym, candidates: usize },
+    Log(String),
+    /// A host submitted an action for an instance it does not own.
+    NotExternal(String),
+    /// `apply` on an external instance, whose actions come from its host.
+    External(String),
+    /// `call_tool` on an instance that is not a tool.
+    NotATool(String),
+    /// The tool host had no result for the call, after retries.
+    ToolFailed { instance: String, call: String, message: String },
+}
+
+#[derive(Clone, Debug)]
+pub struct Runtime {
+    pub engine: Engine,
+    instances: BTreeMap<String, Instance>,
+    links: Vec<Link>,
+    events: Vec<Event>,
+    next_seq: u64,
+    log: Option<PathBuf>,
+    /// External instances that entered a position since the host was last
+    /// told, oldest first, each as it stood on entering.
+    pub(super) entered: Vec<(String, Instance)>,
+    /// Results of tool calls, by `Tool.Call[args]` (see `tools.rs`).
+    pub(super) tool_results: BTreeMap<String, Value>,
+}
+
+impl Runtime {
+    pub fn new(engine: Engine) -> Self {
+        Self {
+            engine,
+            instances: BTreeMap::new(),
+            links: Vec::new(),
+            events: Vec::new(),
+            next_seq: 0,
+            log: None,
+            entered: Vec::new(),
+            tool_results: BTreeMap::new(),
+        }
+    }
+
+    /// Every event accepted so far, oldest first. Restoring a snapshot does
+    /// not rewind it; the restore is an event of its own.
+    pub fn trajectory(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Append every future event to `path`, writing the header if the file
+    /// is new or empty. A file that already has events is replayed first, so
+    /// the runtime continues from where the log left off; that needs a
+    /// runtime with no events of its own.
+    pub fn log_to(&mut self, path: impl Into<PathBuf>) -> Result<(), RuntimeError> {
+        let path = path.into();
+        let existing = std::fs::read_to_string(&path).unwrap_or_default();
+        if existing.is_empty() {
+            std::fs::write(&path, format!("{HEADER}\n"))
+                .map_err(|e| RuntimeError::Log(format!("{}: {e}", path.display())))?;
+        } else {
+            let events = self.engine.parse_trajectory(&existing).map_err(|e| {
+                RuntimeError::Log(format!("{}:{}: {}", path.display(), e.line, e.message))
+            })?;
+            if !events.is_empty() && !self.events.is_empty() {
+                return Err(RuntimeError::Log(format!(
+                    "{}: cannot continue a log on a runtime that already has events",
+                    path.display(),
+                )));
+            }
+            self.replay(&events).map_err(|d| {
+                RuntimeError::Log(format!("{}: {}", path.display(), self.engine.fmt_divergence(&d)))
+            })?;
+        }
+        self.log = Some(path);
+        Ok(())
+    }
+
+    /// Write `event` to the attached log (if any), then keep it in memory.
+    /// Called before the store changes, so a failed write rejects the change.
+    fn record(&mut self, event: Event) -> Result<(), RuntimeError> {
+        if let Some(path) = &self.log {
+            let line = self.engine.fmt_event(&event);
+            std::fs::OpenOptions::new()
+                .append(true)
+                .open(path)
+                .and_then(|mut f| writeln!(f, "{line}"))
+                .map_err(|e| RuntimeError::Log(format!("{}: {e}", path.display())))?;
+        }
+        self.events.push(event);
+        self.next_seq += 1;
+        Ok(())
+    }
+
+    pub fn instance(&self, name: &str) -> Option<&Instance> {
+        self.instances.get(name)
+    }
+
+    /// Create `name` at `interface.position`. Every interface and position
+    /// param must be bound and the position guard must hold.
+    pub fn spawn(
+        &mut self,
+        name: &str,
+        interface: &str,
+        position: &str,
+        bindings: Bindings,
+    ) -> Result<&Instance, RuntimeError> {
+        if self.instances.contains_key(name) {
+            return Err(RuntimeError::DuplicateInstance(name.to_string()));
+        }
+        let rejected = |error| RuntimeError::Rejected { instance: name.to_string(), error };
+        let eng = &self.engine;
+        let iface_sym = eng
+            .interner
+            .find(interface)
+            .filter(|s| eng.interfaces.contains_key(s))
+            .ok_or_else(|| rejected(QueryError::UnknownInterface(interface.to_string())))?;
+        let iface = &eng.interfaces[&iface_sym];
+        let unknown_pos = || QueryError::UnknownPosition {
+            interface: interface.to_string(),
+            position: position.to_string(),
+        };
+        let pos_sym = eng.interner.find(position).ok_or_else(|| rejected(unknown_pos()))?;
+        let pos = iface.position(&pos_sym).ok_or_else(|| rejected(unknown_pos()))?;
+
+        for p in iface.params.iter().chain(&pos.params) {
+            if !bindings.contains_key(&p.name) {
+                return Err(RuntimeError::MissingBinding {
+                    instance: name.to_string(),
+                    param: p.name,
+                });
+            }
+        }
+        if let Some(g) = &pos.guard {
+            if !eval_bool(eng, g, &bindings).map_err(|e| rejected(QueryError::EvalFailed(e)))? {
+                return Err(rejected(QueryError::GuardFailed {
+                    interface: interface.to_string(),
+                    position: position.to_string(),
+                    kind: GuardKind::Position,
+                }));
+            }
+        }
+
+        self.record(Event::Spawn {
+            seq: self.next_seq,
+            instance: name.to_string(),
+            interface: iface_sym,
+            position: pos_sym,
+            bindings: bindings.clone(),
+        })?;
+        let inst = Instance { interface: iface_sym, position: pos_sym, bindings };
+        if self.engine.interfaces[&iface_sym].external {
+            self.entered.push((name.to_string(), inst.clone()));
+        }
+        Ok(self.instances.entry(name.to_string()).or_insert(inst))
+    }
+
+    pub fn links(&self) -> &[Link] {
+        &self.links
+    }
+
+    /// Wire `source` to `target` through `defer`, so actions on `target`
+    /// are carried out by `source`. Each instance must play its side of the
+    /// defer signature.
+    pub fn link(&mut self, defer: &str, source: &str, target: &str) -> Result<(), RuntimeError> {
+        let eng = &self.engine;
+        let d = eng
+            .interner
+            .find(defer)
+            .and_then(|s| eng.defers.iter().find(|d| d.name == s))
+            .ok_or_else(|| RuntimeError::UnknownDefer(defer.to_string()))?;
+        for (name, role) in [(source, d.source), (target, d.target)] {
+            let inst = self
+                .instances
+                .get(name)
+                .ok_or_else(|| RuntimeError::UnknownInstance(name.to_string()))?;
+            if !eng.plays(inst.interface, role) {
+                return Err(RuntimeError::LinkMismatch {
+                    defer: d.name,
+                    instance: name.to_string(),
+                    expected: role,
+                    actual: inst.interface,
+                });
+            }
+        }
+        let link = Link { defer: d.name, source: source.to_string(), target: target.to_string() };
+        self.record(Event::Link {
+            seq: self.next_seq,
+            defer: link.defer,
+            source: link.source.clone(),
+            target: link.target.clone(),
+        })?;
+        self.links.push(link);
+        Ok(())
+    }
+
+    /// Fire `action` on `name` and every action it induces through links.
+    /// `args` binds the direction's params for this step only; they are not
+    /// kept on the instance. The fired action is logged as a step, then each
+    /// move it induces as an induced step. An external instance's actions
+    /// come from its host, through `submit`.
+    pub fn apply(
+        &mut self,
+        name: &str,
+        action: &str,
+        args: Bindings,
+    ) -> Result<Propagation, RuntimeError> {
+        let inst = self
+            .instance(name)
+            .ok_or_else(|| RuntimeError::UnknownInstance(name.to_string()))?;
+        if self.engine.interfaces[&inst.interface].external {
+            return Err(RuntimeError::External(name.to_string()));
+        }
+        self.apply_any(name, action, args)
+    }
+
+    /// `apply`, whoever owns `name`: for the host's submissions, and for
+    /// replaying a log that has them.
+    pub(super) fn apply_any(
+        &mut self,
+        name: &str,
+        action: &str,
+        args: Bindings,
+    ) -> Result<Propagation, RuntimeError> {
+        let prop = self.propagate(name, action, args.clone())?;
+        let root = &prop.moves[0].step;
+        let inst = &self.instances[name];
+        self.record(Event::Step {
+            seq: self.next_seq,
+            instance: name.to_string(),
+            source: inst.position,
+            source_bindings: inst.bindings.clone(),
+            action: root.action,
+            args: args.clone(),
+            target: root.target_position,
+            target_bindings: root.target_bindings.clone(),
+            induced: false,
+        })?;
+        for m in &prop.moves[1..] {
+            let inst = &self.instances[&m.instance];
+            self.record(Event::Step {
+                seq: self.next_seq,
+                instance: m.instance.clone(),
+                source: inst.position,
+                source_bindings: inst.bindings.clone(),
+                action: m.step.action,
+                args: args.clone(),
+                target: m.step.target_position,
+                target_bindings: m.step.target_bindings.clone(),
+                induced: true,
+            })?;
+        }
+        for m in &prop.moves {
+            let inst = self.instances.get_mut(&m.instance).unwrap();
+            inst.position = m.step.target_position;
+            inst.bindings = m.step.target_bindings.clone();
+            if self.engine.interfaces[&inst.interface].external {
+                self.entered.push((m.instance.clone(), inst.clone()));
+            }
+        }
+        Ok(prop)
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            instances: self.instances.clone(),
+            links: self.links.clone(),
+            entered: self.entered.clone(),
+            at: self.next_seq,
+        }
+    }
+
+    /// Go back to `snapshot`. The restore is recorded as going back to the
+    /// configuration before event `snapshot.at`, so a replay can rebuild it.
+    pub fn restore(&mut self, snapshot: Snapshot) -> Result<(), RuntimeError> {
+        self.record(Event::Restore { seq: self.next_seq, to: snapshot.at })?;
+        self.instances = snapshot.instances;
+        self.links = snapshot.links;
+        self.entered = snapshot.entered;
+        Ok(())
+    }
+}
+
+
+// ============================================================================
+// Display
+// ============================================================================
+
+impl Runtime {
+    /// One line per instance, `name : Interface.Position[bindings]`.
+    pub fn fmt_instances(&self) -> String {
+        self.instances
+            .iter()
+            .map(|(name, inst)| format!("{name} : {}\n", self.engine.fmt_instance(inst)))
+            .collect()
+    }
+}
+
+impl Engine {
+    pub fn fmt_instance(&self, inst: &Instance) -> String {
+        format!(
+            "{}.{}{}",
+            self.resolve(inst.interface),
+            self.resolve(inst.position),
+            self.fmt_bindings(&inst.bindings),
+        )
+    }
+
+    pub fn fmt_runtime_error(&self, e: &RuntimeError) -> String {
+        match e {
+            RuntimeError::UnknownInstance(name) => format!("unknown instance: {name}"),
+            RuntimeError::DuplicateInstance(name) => format!("instance already exists: {name}"),
+            RuntimeError::MissingBinding { instance, param } => {
+                format!("{instance}: no binding for parameter `{}`", self.resolve(*param))
+            }
+            RuntimeError::Rejected { instance, error } => {
+                format!("{instance}: {}", self.fmt_query_error(error))
+            }
+            RuntimeError::UnknownDefer(name) => format!("unknown defer: {name}"),
+            RuntimeError::LinkMismatch { defer, instance, expected, actual } => format!(
+                "defer {} expects {}, but {instance} is a {}",
+                self.resolve(*defer),
+                self.resolve(*expected),
+                self.resolve(*actual),
+            ),
+            RuntimeError::Cycle(path) => format!("propagation cycle: {}", path.join(" -> ")),
+            RuntimeError::MovedTwice(name) => {
+                format!("{name} would move twice in one propagation")
+            }
+            RuntimeError::Ambiguous { defer, instance, action, candidates } => format!(
+                "defer {}: {} on {instance} has {candidates} applicable mappings",
+                self.resolve(*defer),
+                self.resolve(*action),
+            ),
+            RuntimeError::Log(msg) => format!("could not write trajectory log: {msg}"),
+            RuntimeError::NotExternal(name) => {
+                format!("{name} is not an external instance; its actions do not come from the host")
+            }
+            RuntimeError::External(name) => {
+                format!("{name} is an external instance; its actions come from the host")
+            }
+            RuntimeError::NotATool(name) => format!("{name} is not a tool instance"),
+            RuntimeError::ToolFailed { instance, call, message } => {
+                format!("{instance}: {call} failed: {message}")
+            }
+        }
+    }
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::eval::Value;
+    use super::*;
+
+    fn grid() -> Runtime {
+        let src = std::fs::read_to_string("examples/grid.poly").unwrap();
+        Runtime::new(Engine::load(&src).expect("load grid"))
+    }
+
+    fn cell(rt: &Runtime, x: i64, y: i64) -> Value {
+        let sym = |s: &str| rt.engine.interner.find(s).unwrap();
+        Value::Record {
+            schema: sym("Coordinate"),
+            fields: BTreeMap::from([(sym("x"), Value::Int(x)), (sym("y"), Value::Int(y))]),
+        }
+    }
+
+    fn spawn_at(rt: &mut Runtime, name: &str, x: i64, y: i64) -> Result<(), RuntimeError> {
+        let sym = |s: &str| rt.engine.interner.find(s).unwrap();
+        let b = Bindings::from([
+            (sym("Width"), Value::Int(3)),
+            (sym("Height"), Value::Int(3)),
+            (sym("c"), cell(rt, x, y)),
+        ]);
+        rt.spawn(name, "Grid", "Cell", b).map(|_| ())
+    }
+
+    #[test]
+    fn actions_move_instances_and_keep_interface_params() {
+        let mut rt = grid();
+        spawn_at(&mut rt, "g", 1, 1).unwrap();
+        rt.apply("g", "Right", Bindings::new()).unwrap();
+        rt.apply("g", "Down", Bindings::new()).unwrap();
+        assert_eq!(rt.fmt_instances(), "g : Grid.Cell[Width=3, Height=3, c=Coordinate(x=2, y=2)]\n");
+    }
+
+    #[test]
+    fn rejected_actions_leave_the_store_untouched() {
+        let mut rt = grid();
+        spawn_at(&mut rt, "g", 1, 1).unwrap();
+        let before = rt.snapshot();
+        let err = rt.apply("g", "Left", Bindings::new()).unwrap_err();
+        assert_eq!(rt.engine.fmt_runtime_error(&err), "g: direction guard failed at Grid.Cell");
+        assert_eq!(rt.snapshot(), before);
+    }
+
+    #[test]
+    fn spawn_checks_bindings_and_guard() {
+        let mut rt = grid();
+        let err = spawn_at(&mut rt, "g", 5, 1).unwrap_err();
+        assert_eq!(rt.engine.fmt_runtime_error(&err), "g: position guard failed at Grid.Cell");
+        let err = rt.spawn("h", "Grid", "Cell", Bindings::new()).unwrap_err();
+        assert_eq!(rt.engine.fmt_runtime_error(&err), "h: no binding for parameter `Width`");
+        spawn_at(&mut rt, "g", 1, 1).unwrap();
+        assert!(matches!(spawn_at(&mut rt, "g", 2, 2), Err(RuntimeError::DuplicateInstance(_))));
+    }
+
+    #[test]
+    fn snapshot_and_restore() {
+        let mut rt = grid();
+        spawn_at(&mut rt, "g", 1, 1).unwrap();
+        let snap = rt.snapshot();
+        rt.apply("g", "Right", Bindings::new()).unwrap();
+        spawn_at(&mut rt, "h", 3, 3).unwrap();
+        rt.restore(snap).unwrap();
+        assert_eq!(rt.instance("g").unwrap().bindings[&rt.engine.interner.find("c").unwrap()], cell(&rt, 1, 1));
+        assert!(rt.instance("h").is_none());
+    }
+}
@@ -3,14 +3,27 @@
 //
 // Pipeline:
 //   1. apply_identities       — boolean & arithmetic algebraic laws + folding
-//   2. flatten_and            — top-level And-tree → flat conjunct list
-//   3. extract_equalities     — `var = expr` → substitution map
+//   2. dnf                    — And/Or/Not tree → disjuncts of conjunct lists
+//      (capped at DNF_LIMIT; past it, only the top-level And is flattened)
+//   3. decompose              — `path = Record(a, b)` → per-field equalities;
+//      equal constructors pair up their args, distinct ones are `false`
+//   4. extract_equalities     — `path = expr`, and a boolean path `p` / `not p`
+//      as `p = true` / `p = false` → substitution map
 //      → substitute through the rest, re-fold, re-apply identities
-//   4. extract_intervals      — linearize each comparison atom; fold single-
-//      variable |coef|=1 atoms into per-variable Intervals
-//   5. detect contradictions  — empty interval ⇒ false
-//   6. promote singletons     — closed [k,k] interval ⇒ `var = k`
-//   7. dedupe + reassemble    — emit one canonical conjunction
+//   5. extract_intervals      — linearize each comparison atom; fold single-
+//      path atoms into per-path Intervals (rounding when |coef| > 1)
+//   6. detect contradictions  — empty interval ⇒ false; Fourier–Motzkin over
+//      the multi-variable atoms and the intervals ⇒ false
+//   7. promote singletons     — closed [k,k] interval ⇒ `path = k`
+//   8. dedupe + reassemble    — emit one canonical conjunction; `e ∧ ¬e` ⇒ false
+//   9. disjunction            — with several disjuncts, steps 3–8 run on each;
+//      false ones drop, subsumed ones drop, and conjuncts common to all are
+//      factored back out
+//
+// A "path" is a variable or a chain of field accesses on one (`c`, `c.x`);
+// steps 4–7 treat every path as a variable of its own. Steps 5–7 reason over
+// the integers, so they skip paths that are `Float` in the caller's scope, the
+// `TypeEnv` of the interface, position or direction the expression sits in.
 //
 // Iterated to a fixpoint (bounded). Each pass is monotone in residual size,
 // so it converges in 2–3 iterations on every example we currently produce.
@@ -19,6 +32,7 @@
 use std::collections::{BTreeMap, BTreeSet};
 
 use super::eval::{const_fold, Bindings};
+use super::typecheck::TypeEnv;
 use super::*;
 
 
@@ -26,10 +40,10 @@ use super::*;
 // Top-level entry
 // =============================================================================
 
-pub fn reduce(eng: &Engine, expr: &Expr<Sym>, env: &Bindings) -> Expr<Sym> {
+pub fn reduce(eng: &Engine, types: &TypeEnv, expr: &Expr<Sym>, env: &Bindings) -> Expr<Sym> {
     let mut current = const_fold(eng, expr, env);
     for _ in 0..8 {
-        let next = pass(eng, &current, env);
+        let next = pass(eng, types, &current, env);
         if next == current {
             return current;
         }
@@ -38,22 +52,35 @@ pub fn reduce(eng: &Engine, expr: &Expr<Sym>, env: &Bindings) -> Expr<Sym> {
     current
 }
 
-fn pass(eng: &Engine, expr: &Expr<Sym>, env: &Bindings) -> Expr<Sym> {
-    let expr = apply_identities(expr);
+fn pass(eng: &Engine, types: &TypeEnv, expr: &Expr<Sym>, env: &Bindings) -> Expr<Sym> {
+    let expr = apply_identities(eng, types, expr);
+    let mut disjuncts = dnf(eng, types, &expr).unwrap_or_else(|| vec![flatten_and(&expr)]);
+    if disjuncts.len() == 1 {
+        return conjunction(eng, types, disjuncts.pop().unwrap(), env);
+    }
+    disjunction(eng, types, disjuncts, env)
+}
 
-    let raw = flatten_and(&expr);
+fn conjunction(eng: &Engine, types: &TypeEnv, raw: Vec<Expr<Sym>>, env: &Bindings) -> Expr<Sym> {
     if raw.iter().any(is_false) {
         return Expr::LitBool(false);
     }
-    let conjuncts: Vec<Expr<Sym>> = raw.into_iter().filter(|c| !is_true(c)).collect();
+    let mut conjuncts: Vec<Expr<Sym>> = Vec::new();
+    for c in raw {
+        decompose(eng, types, c, &mut conjuncts);
+    }
+    if conjuncts.iter().any(is_false) {
+        return Expr::LitBool(false);
+    }
+    conjuncts.retain(|c| !is_true(c));
 
     // Equality substitution.
     let (subst, rest) = extract_equalities(&conjuncts);
     let rest: Vec<Expr<Sym>> = rest
         .iter()
-        .map(|e| substitute(e, &subst))
+        .map(|e| substitute_paths(e, &subst))
         .map(|e| const_fold(eng, &e, env))
-        .map(|e| apply_identities(&e))
+        .map(|e| apply_identities(eng, types, &e))
         .collect();
 
     // Re-flatten in case substitution exposed nested Ands or trues/falses.
@@ -67,10 +94,14 @@ fn pass(eng: &Engine, expr: &Expr<Sym>, env: &Bindings) -> Expr<Sym> {
     }
 
     // Interval extraction.
-    let mut intervals: BTreeMap<Sym, Interval> = BTreeMap::new();
+    let mut intervals: BTreeMap<Path, Interval> = BTreeMap::new();
     let mut others: Vec<Expr<Sym>> = Vec::new();
     for c in flat {
-        if let Some((var, ivl)) = atom_to_interval(&c) {
+        if let Some(holds) = constant_atom(eng, types, &c) {
+            if !holds {
+                return Expr::LitBool(false);
+            }
+        } else if let Some((var, ivl)) = atom_to_interval(eng, types, &c) {
             intervals.entry(var).or_default().merge(&ivl);
         } else {
             others.push(c);
@@ -81,11 +112,20 @@ fn pass(eng: &Engine, expr: &Expr<Sym>, env: &Bindings) -> Expr<Sym> {
             return Expr::LitBool(false);
         }
     }
+    let mut system: Vec<Constraint> = others.iter().flat_map(|c| multi_var_constraints(eng, types, c)).collect();
+    if !system.is_empty() {
+        for (v, ivl) in &intervals {
+            system.extend(ivl.constraints(v));
+        }
+        if infeasible(system) {
+            return Expr::LitBool(false);
+        }
+    }
     // Promote singletons.
-    let mut singletons: Vec<(Sym, i64)> = Vec::new();
+    let mut singletons: Vec<(Path, i64)> = Vec::new();
     intervals.retain(|var, ivl| match ivl.singleton() {
         Some(k) => {
-            singletons.push((*var, k));
+            singletons.push((var.clone(), k));
             false
         }
         None => true,
@@ -94,17 +134,21 @@ fn pass(eng: &Engine, expr: &Expr<Sym>, env: &Bindings) -> Expr<Sym> {
     // Reassemble.
     let mut atoms: Vec<Expr<Sym>> = Vec::new();
     for (v, e) in &subst {
-        atoms.push(eq_atom(*v, e.clone()));
+        atoms.push(eq_atom(v, e.clone()));
     }
     for (v, k) in &singletons {
-        atoms.push(eq_atom(*v, Expr::LitInt(*k)));
+        atoms.push(eq_atom(v, Expr::LitInt(*k)));
     }
     for (v, ivl) in &intervals {
-        atoms.extend(ivl.to_atoms(*v));
+        atoms.extend(ivl.to_atoms(v));
     }
     atoms.extend(others);
 
     dedupe(&mut atoms);
+    // Complementary literals: `e ∧ ¬e`.
+    if atoms.iter().any(|a| matches!(a, Expr::UnOp(UnOp::Not, x) if atoms.contains(x))) {
+        return Expr::LitBool(false);
+    }
     if atoms.is_empty() {
         return Expr::LitBool(true);
     }
@@ -112,28 +156,138 @@ fn pass(eng: &Engine, expr: &Expr<Sym>, env: &Bindings) -> Expr<Sym> {
 }
 
 
+/// Inclusive `(lo, hi)` bounds; `None` on a side means unbounded.
+pub type IntBounds = (Option<i64>, Option<i64>);
+
+/// Inclusive integer bounds the top-level conjuncts of `expr` put on each
+/// variable once reduced under `env`, multi-variable atoms included; bounds
+/// on fields are left out. Returns `None` when `expr` reduces to false.
+pub fn int_bounds(
+    eng: &Engine,
+    types: &TypeEnv,
+    expr: &Expr<Sym>,
+    env: &Bindings,
+) -> Option<BTreeMap<Sym, IntBounds>> {
+    let system = int_system(eng, types, expr, env)?;
+    let unassumed = BTreeMap::new();
+    system.vars().into_iter().map(|v| Some((v, system.bounds(v, &unassumed)?))).collect()
+}
+
+/// The linear integer constraints the top-level conjuncts of an expression
+/// put on its paths, as a Fourier–Motzkin system.
+#[derive(Clone, Debug, Default)]
+pub struct IntSystem {
+    constraints: Vec<Constraint>,
+}
+
+/// The system `expr` forms once reduced under `env`, or `None` when it
+/// reduces to false.
+pub fn int_system(eng: &Engine, types: &TypeEnv, expr: &Expr<Sym>, env: &Bindings) -> Option<IntSystem> {
+    let reduced = reduce(eng, types, expr, env);
+    if is_false(&reduced) {
+        return None;
+    }
+    let conjuncts = flatten_and(&reduced);
+    let mut constraints: Vec<Constraint> =
+        conjuncts.iter().flat_map(|c| multi_var_constraints(eng, types, c)).collect();
+    for (v, (lo, hi)) in conjunct_bounds(eng, types, &conjuncts) {
+        constraints.extend(closed_constraints(&v, lo, hi));
+    }
+    Some(IntSystem { constraints })
+}
+
+impl IntSystem {
+    /// The variables the system constrains, fields aside.
+    pub fn vars(&self) -> BTreeSet<Sym> {
+        self.constraints.iter().flat_map(|c| c.terms.keys()).filter(|p| p.fields.is_empty()).map(|p| p.root).collect()
+    }
+
+    /// Inclusive bounds on `var`, with every other variable eliminated and
+    /// those in `assume` confined to their bounds there first. `None` when
+    /// that leaves no integer solution.
+    pub fn bounds(&self, var: Sym, assume: &BTreeMap<Sym, IntBounds>) -> Option<IntBounds> {
+        let keep = Path::var(var);
+        let mut system = self.constraints.clone();
+        for (v, (lo, hi)) in assume.iter().filter(|(v, _)| **v != var) {
+            system.extend(closed_constraints(&Path::var(*v), *lo, *hi));
+        }
+        let (mut lo, mut hi): (Option<i128>, Option<i128>) = (None, None);
+        for c in eliminate(system, Some(&keep))? {
+            // Tightened, a constraint on `var` alone is `±var + k ≥ 0`.
+            if c.terms[&keep] > 0 {
+                lo = lo.max(Some(-c.constant));
+            } else {
+                hi = Some(hi.map_or(c.constant, |h| h.min(c.constant)));
+            }
+        }
+        let clamp = |k: i128| k.clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+        Some((lo.map(clamp), hi.map(clamp)))
+    }
+}
+
+/// A disjunction bounds a variable by the hull of its disjuncts' bounds, so
+/// only variables every disjunct bounds are kept.
+fn conjunct_bounds(eng: &Engine, types: &TypeEnv, conjuncts: &[Expr<Sym>]) -> BTreeMap<Path, IntBounds> {
+    let mut intervals: BTreeMap<Path, Interval> = BTreeMap::new();
+    for c in conjuncts {
+        if let Some((var, ivl)) = atom_to_interval(eng, types, c) {
+            intervals.entry(var).or_default().merge(&ivl);
+        } else if matches!(c, Expr::BinOp(BinOp::Or, ..)) {
+            let hull = flatten_or(c)
+                .iter()
+                .map(|d| conjunct_bounds(eng, types, &flatten_and(d)))
+                .reduce(|a, b| {
+                    let widen = |x: Option<i64>, y: Option<i64>, f: fn(i64, i64) -> i64| Some(f(x?, y?));
+                    a.into_iter()
+                        .filter_map(|(v, (lo, hi))| {
+                            let (lo2, hi2) = b.get(&v)?;
+                            Some((v, (widen(lo, *lo2, i64::min), widen(hi, *hi2, i64::max))))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            for (v, (lo, hi)) in hull.into_iter().filter(|(_, b)| *b != (None, None)) {
+                let ivl = Interval { lo: lo.map(|k| (k, true)), hi: hi.map(|k| (k, true)), ne: BTreeSet::new() };
+                intervals.entry(v).or_default().merge(&ivl);
+            }
+        }
+    }
+    let clamp = |k: i128| k.clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+    intervals
+        .into_iter()
+        .map(|(v, ivl)| {
+            let (lo, hi) = ivl.closed();
+            (v, (lo.map(clamp), hi.map(clamp)))
+        })
+        .collect()
+}
+
+
 // =============================================================================
 // Identities (bottom-up rewrite)
 // =============================================================================
 
-fn apply_identities(e: &Expr<Sym>) -> Expr<Sym> {
+fn apply_identities(eng: &Engine, types: &TypeEnv, e: &Expr<Sym>) -> Expr<Sym> {
     use BinOp::*;
     use UnOp::*;
     match e {
-        Expr::LitInt(_) | Expr::LitStr(_) | Expr::LitBool(_) | Expr::Var(_) => e.clone(),
+        Expr::LitInt(_) | Expr::LitFloat(_) | Expr::LitStr(_) | Expr::LitBool(_) | Expr::Var(_) => e.clone(),
         Expr::UnOp(op, inner) => {
-            let inner = apply_identities(inner);
+            let inner = apply_identities(eng, types, inner);
             match (op, &inner) {
-                (Neg, Expr::LitInt(n)) => Expr::LitInt(-n),
+                (Neg, Expr::LitInt(n)) if *n != i64::MIN => Expr::LitInt(-n),
                 (Neg, Expr::UnOp(Neg, x)) => (**x).clone(),
                 (Not, Expr::LitBool(b)) => Expr::LitBool(!b),
                 (Not, Expr::UnOp(Not, x)) => (**x).clone(),
+                (Not, Expr::BinOp(cmp, l, r)) if negate_cmp(*cmp).is_some() => {
+                    Expr::BinOp(negate_cmp(*cmp).unwrap(), l.clone(), r.clone())
+                }
                 _ => Expr::UnOp(*op, Box::new(inner)),
             }
         }
         Expr::BinOp(op, l, r) => {
-            let l = apply_identities(l);
-            let r = apply_identities(r);
+            let l = apply_identities(eng, types, l);
+            let r = apply_identities(eng, types, r);
 
             // Both literals → fold.
             if let (Some(a), Some(b)) = (lit_int(&l), lit_int(&r)) {
@@ -169,8 +323,23 @@ fn apply_identities(e: &Expr<Sym>) -> Expr<Sym> {
                 _ => {}
             }
 
+            // A comparison with a boolean literal is the other side or its negation.
+            if matches!(op, Eq | Neq) {
+                let pinned = match (lit_bool(&l), lit_bool(&r)) {
+                    (None, Some(b)) => Some((&l, b)),
+                    (Some(b), None) => Some((&r, b)),
+                    _ => None,
+                };
+                if let Some((e, b)) = pinned {
+                    if b == matches!(op, Eq) {
+                        return e.clone();
+                    }
+                    return apply_identities(eng, types, &Expr::UnOp(Not, Box::new(e.clone())));
+                }
+            }
+
             // Syntactic-equality reductions.
-            if matches!(op, Sub) && l == r {
+            if matches!(op, Sub) && l == r && to_linear(eng, types, &l).is_some() {
                 return Expr::LitInt(0);
             }
             if matches!(op, And | Or) && l == r {
@@ -186,24 +355,50 @@ fn apply_identities(e: &Expr<Sym>) -> Expr<Sym> {
             Expr::BinOp(*op, Box::new(l), Box::new(r))
         }
         Expr::Field(base, name) => {
-            let base = apply_identities(base);
+            let base = apply_identities(eng, types, base);
             Expr::Field(Box::new(base), *name)
         }
         Expr::Construct(name, args) => {
-            let args: Vec<_> = args.iter().map(apply_identities).collect();
+            let args: Vec<_> = args.iter().map(|a| apply_identities(eng, types, a)).collect();
             Expr::Construct(*name, args)
         }
+        Expr::Variant(schema, variant, args) => {
+            let args: Vec<_> = args.iter().map(|a| apply_identities(eng, types, a)).collect();
+            Expr::Variant(*schema, *variant, args)
+        }
+        Expr::Is(inner, variant) => {
+            let inner = apply_identities(eng, types, inner);
+            if let Expr::Variant(_, v, _) = &inner {
+                return Expr::LitBool(v == variant);
+            }
+            Expr::Is(Box::new(inner), *variant)
+        }
     }
 }
 
+/// `¬(a op b)` as `a op' b`, for comparisons.
+fn negate_cmp(op: BinOp) -> Option<BinOp> {
+    use BinOp::*;
+    Some(match op {
+        Lt => Ge,
+        Le => Gt,
+        Gt => Le,
+        Ge => Lt,
+        Eq => Neq,
+        Neq => Eq,
+        _ => return None,
+    })
+}
+
 fn fold_int_binop(op: BinOp, a: i64, b: i64) -> Option<Expr<Sym>> {
     use BinOp::*;
     Some(match op {
-        Add => Expr::LitInt(a + b),
-        Sub => Expr::LitInt(a - b),
-        Mul => Expr::LitInt(a * b),
-        Div if b != 0 => Expr::LitInt(a / b),
-        Mod if b != 0 => Expr::LitInt(a % b),
+        // Overflow, like division by zero, is left for evaluation to report.
+        Add => Expr::LitInt(a.checked_add(b)?),
+        Sub => Expr::LitInt(a.checked_sub(b)?),
+        Mul => Expr::LitInt(a.checked_mul(b)?),
+        Div => Expr::LitInt(a.checked_div(b)?),
+        Mod => Expr::LitInt(a.checked_rem(b)?),
         Eq => Expr::LitBool(a == b),
         Neq => Expr::LitBool(a != b),
         Lt => Expr::LitBool(a < b),
@@ -235,7 +430,7 @@ fn is_false(e: &Expr<Sym>) -> bool { matches!(e, Expr::LitBool(false)) }
 // Conjunction tools
 // =============================================================================
 
-fn flatten_and(e: &Expr<Sym>) -> Vec<Expr<Sym>> {
+pub(super) fn flatten_and(e: &Expr<Sym>) -> Vec<Expr<Sym>> {
     let mut out = Vec::new();
     fn go(e: &Expr<Sym>, out: &mut Vec<Expr<Sym>>) {
         if let Expr::BinOp(BinOp::And, l, r) = e {
@@ -249,6 +444,17 @@ fn flatten_and(e: &Expr<Sym>) -> Vec<Expr<Sym>> {
     out
 }
 
+fn flatten_or(e: &Expr<Sym>) -> Vec<Expr<Sym>> {
+    match e {
+        Expr::BinOp(BinOp::Or, l, r) => {
+            let mut out = flatten_or(l);
+            out.extend(flatten_or(r));
+            out
+        }
+        _ => vec![e.clone()],
+    }
+}
+
 fn conjoin_n(atoms: Vec<Expr<Sym>>) -> Expr<Sym> {
     let mut iter = atoms.into_iter();
     let first = iter.next().expect("conjoin_n called on empty list");
@@ -269,8 +475,118 @@ fn dedupe(atoms: &mut Vec<Expr<Sym>>) {
     *atoms = out;
 }
 
-fn eq_atom(v: Sym, rhs: Expr<Sym>) -> Expr<Sym> {
-    Expr::BinOp(BinOp::Eq, Box::new(Expr::Var(v)), Box::new(rhs))
+fn eq_atom(p: &Path, rhs: Expr<Sym>) -> Expr<Sym> {
+    match rhs {
+        Expr::LitBool(true) => p.to_expr(),
+        Expr::LitBool(false) => Expr::UnOp(UnOp::Not, Box::new(p.to_expr())),
+        _ => Expr::BinOp(BinOp::Eq, Box::new(p.to_expr()), Box::new(rhs)),
+    }
+}
+
+
+// =============================================================================
+// Disjunctions
+// =============================================================================
+
+/// Disjunctive normal form stops distributing past this many disjuncts.
+const DNF_LIMIT: usize = 16;
+
+/// `e` as a disjunction of conjunct lists, pushing `not` through `and` / `or`
+/// on the way down, or `None` past `DNF_LIMIT` disjuncts.
+fn dnf(eng: &Engine, types: &TypeEnv, e: &Expr<Sym>) -> Option<Vec<Vec<Expr<Sym>>>> {
+    let not = |x: &Expr<Sym>| apply_identities(eng, types, &Expr::UnOp(UnOp::Not, Box::new(x.clone())));
+    let out = match e {
+        Expr::BinOp(BinOp::Or, l, r) => {
+            let mut out = dnf(eng, types, l)?;
+            out.extend(dnf(eng, types, r)?);
+            out
+        }
+        Expr::BinOp(BinOp::And, l, r) => {
+            let (l, r) = (dnf(eng, types, l)?, dnf(eng, types, r)?);
+            if l.len() * r.len() > DNF_LIMIT {
+                return None;
+            }
+            l.iter()
+                .flat_map(|a| r.iter().map(move |b| a.iter().chain(b).cloned().collect()))
+                .collect()
+        }
+        Expr::UnOp(UnOp::Not, x) => match &**x {
+            Expr::BinOp(BinOp::And, l, r) => {
+                return dnf(eng, types, &Expr::BinOp(BinOp::Or, Box::new(not(l)), Box::new(not(r))));
+            }
+            Expr::BinOp(BinOp::Or, l, r) => {
+                return dnf(eng, types, &Expr::BinOp(BinOp::And, Box::new(not(l)), Box::new(not(r))));
+            }
+            _ => vec![vec![e.clone()]],
+        },
+        _ => vec![vec![e.clone()]],
+    };
+    (out.len() <= DNF_LIMIT).then_some(out)
+}
+
+/// Reduce each disjunct as a conjunction, then drop the ones another
+/// disjunct subsumes and factor out the conjuncts they all share.
+fn disjunction(eng: &Engine, types: &TypeEnv, disjuncts: Vec<Vec<Expr<Sym>>>, env: &Bindings) -> Expr<Sym> {
+    let mut kept: Vec<Vec<Expr<Sym>>> = Vec::new();
+    for d in disjuncts {
+        match conjunction(eng, types, d, env) {
+            Expr::LitBool(false) => {}
+            Expr::LitBool(true) => return Expr::LitBool(true),
+            e => {
+                let atoms = flatten_and(&e);
+                if !kept.contains(&atoms) {
+                    kept.push(atoms);
+                }
+            }
+        }
+    }
+    // `a ∨ b` where `a ⇒ b` is `b`.
+    let mut i = 0;
+    while i < kept.len() {
+        if (0..kept.len()).any(|j| j != i && implies(eng, types, &kept[i], &kept[j], env)) {
+            kept.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    // `c ∨ d` where `¬c ⇒ d` is `true`.
+    for (i, d) in kept.iter().enumerate() {
+        if let [c] = &d[..] {
+            let not_c = vec![apply_identities(eng, types, &Expr::UnOp(UnOp::Not, Box::new(c.clone())))];
+            if kept.iter().enumerate().any(|(j, e)| j != i && implies(eng, types, &not_c, e, env)) {
+                return Expr::LitBool(true);
+            }
+        }
+    }
+
+    let Some(first) = kept.first() else { return Expr::LitBool(false) };
+    let common: Vec<Expr<Sym>> = first.iter().filter(|c| kept.iter().all(|d| d.contains(c))).cloned().collect();
+    let rests: Vec<Vec<Expr<Sym>>> =
+        kept.iter().map(|d| d.iter().filter(|c| !common.contains(c)).cloned().collect()).collect();
+    // `c ∨ (c ∧ x)` is `c`.
+    if rests.iter().any(Vec::is_empty) {
+        return conjoin_n(common);
+    }
+    let or = rests
+        .into_iter()
+        .map(conjoin_n)
+        .reduce(|a, b| Expr::BinOp(BinOp::Or, Box::new(a), Box::new(b)))
+        .unwrap();
+    let mut atoms = common;
+    atoms.push(or);
+    conjoin_n(atoms)
+}
+
+/// Whether every conjunct of `b` follows from the conjunction `a`.
+fn implies(eng: &Engine, types: &TypeEnv, a: &[Expr<Sym>], b: &[Expr<Sym>], env: &Bindings) -> bool {
+    b.iter().all(|c| {
+        if a.contains(c) {
+            return true;
+        }
+        let mut refute = a.to_vec();
+        refute.push(apply_identities(eng, types, &Expr::UnOp(UnOp::Not, Box::new(c.clone()))));
+        is_false(&conjunction(eng, types, refute, env))
+    })
 }
 
 
@@ -278,24 +594,30 @@ fn eq_atom(v: Sym, rhs: Expr<Sym>) -> Expr<Sym> {
 // Equality substitution
 // =============================================================================
 
+/// Split off the conjuncts that pin a path. Each binding is substituted
+/// into the ones already taken and vice versa, so the map is idempotent and
+/// a pinned boolean propagates into every other conjunct, clauses included.
 fn extract_equalities(
     conjuncts: &[Expr<Sym>],
-) -> (BTreeMap<Sym, Expr<Sym>>, Vec<Expr<Sym>>) {
-    let mut subst: BTreeMap<Sym, Expr<Sym>> = BTreeMap::new();
+) -> (BTreeMap<Path, Expr<Sym>>, Vec<Expr<Sym>>) {
+    let mut subst: BTreeMap<Path, Expr<Sym>> = BTreeMap::new();
     let mut rest: Vec<Expr<Sym>> = Vec::new();
-    for c in conjuncts {
-        if let Expr::BinOp(BinOp::Eq, l, r) = c {
-            if let Expr::Var(v) = **l {
-                if !contains_var(r, v) && !subst.contains_key(&v) {
-                    subst.insert(v, (**r).clone());
-                    continue;
-                }
-            }
-            if let Expr::Var(v) = **r {
-                if !contains_var(l, v) && !subst.contains_key(&v) {
-                    subst.insert(v, (**l).clone());
-                    continue;
+    'next: for c in conjuncts {
+        let candidates = match c {
+            Expr::BinOp(BinOp::Eq, l, r) => vec![(&**l, (**r).clone()), (&**r, (**l).clone())],
+            Expr::UnOp(UnOp::Not, x) => vec![(&**x, Expr::LitBool(false))],
+            _ => vec![(c, Expr::LitBool(true))],
+        };
+        for (side, other) in candidates {
+            let Some(p) = Path::of(side) else { continue };
+            let other = substitute_paths(&other, &subst);
+            if !mentions(&other, &p) && !subst.keys().any(|q| q.overlaps(&p)) {
+                let one = BTreeMap::from([(p.clone(), other.clone())]);
+                for e in subst.values_mut() {
+                    *e = substitute_paths(e, &one);
                 }
+                subst.insert(p, other);
+                continue 'next;
             }
         }
         rest.push(c.clone());
@@ -303,32 +625,147 @@ fn extract_equalities(
     (subst, rest)
 }
 
-fn contains_var(e: &Expr<Sym>, v: Sym) -> bool {
+pub(super) fn contains_var(e: &Expr<Sym>, v: Sym) -> bool {
     match e {
         Expr::Var(s) => *s == v,
-        Expr::LitInt(_) | Expr::LitStr(_) | Expr::LitBool(_) => false,
+        Expr::LitInt(_) | Expr::LitFloat(_) | Expr::LitStr(_) | Expr::LitBool(_) => false,
         Expr::UnOp(_, x) => contains_var(x, v),
         Expr::BinOp(_, l, r) => contains_var(l, v) || contains_var(r, v),
         Expr::Field(b, _) => contains_var(b, v),
-        Expr::Construct(_, args) => args.iter().any(|a| contains_var(a, v)),
+        Expr::Construct(_, args) | Expr::Variant(_, _, args) => {
+            args.iter().any(|a| contains_var(a, v))
+        }
+        Expr::Is(x, _) => contains_var(x, v),
     }
 }
 
-fn substitute(e: &Expr<Sym>, subst: &BTreeMap<Sym, Expr<Sym>>) -> Expr<Sym> {
+pub(super) fn substitute(e: &Expr<Sym>, subst: &BTreeMap<Sym, Expr<Sym>>) -> Expr<Sym> {
+    let paths = subst.iter().map(|(v, e)| (Path::var(*v), e.clone())).collect();
+    substitute_paths(e, &paths)
+}
+
+fn substitute_paths(e: &Expr<Sym>, subst: &BTreeMap<Path, Expr<Sym>>) -> Expr<Sym> {
+    if let Some(to) = Path::of(e).and_then(|p| subst.get(&p)) {
+        return to.clone();
+    }
     match e {
-        Expr::Var(s) => subst.get(s).cloned().unwrap_or_else(|| e.clone()),
-        Expr::LitInt(_) | Expr::LitStr(_) | Expr::LitBool(_) => e.clone(),
-        Expr::UnOp(op, x) => Expr::UnOp(*op, Box::new(substitute(x, subst))),
+        Expr::Var(_) | Expr::LitInt(_) | Expr::LitFloat(_) | Expr::LitStr(_) | Expr::LitBool(_) => e.clone(),
+        Expr::UnOp(op, x) => Expr::UnOp(*op, Box::new(substitute_paths(x, subst))),
         Expr::BinOp(op, l, r) => Expr::BinOp(
             *op,
-            Box::new(substitute(l, subst)),
-            Box::new(substitute(r, subst)),
+            Box::new(substitute_paths(l, subst)),
+            Box::new(substitute_paths(r, subst)),
         ),
-        Expr::Field(b, n) => Expr::Field(Box::new(substitute(b, subst)), *n),
+        Expr::Field(b, n) => Expr::Field(Box::new(substitute_paths(b, subst)), *n),
         Expr::Construct(name, args) => Expr::Construct(
             *name,
-            args.iter().map(|a| substitute(a, subst)).collect(),
+            args.iter().map(|a| substitute_paths(a, subst)).collect(),
+        ),
+        Expr::Variant(schema, variant, args) => Expr::Variant(
+            *schema,
+            *variant,
+            args.iter().map(|a| substitute_paths(a, subst)).collect(),
         ),
+        Expr::Is(x, variant) => Expr::Is(Box::new(substitute_paths(x, subst)), *variant),
+    }
+}
+
+
+// =============================================================================
+// Field paths & record equalities
+// =============================================================================
+
+/// A variable followed by zero or more field accesses: `c`, `c.x`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Path {
+    root: Sym,
+    fields: Vec<Sym>,
+}
+
+impl Path {
+    fn var(root: Sym) -> Self { Self { root, fields: Vec::new() } }
+    fn of(e: &Expr<Sym>) -> Option<Self> {
+        match e {
+            Expr::Var(s) => Some(Self::var(*s)),
+            Expr::Field(base, f) => {
+                let mut p = Self::of(base)?;
+                p.fields.push(*f);
+                Some(p)
+            }
+            _ => None,
+        }
+    }
+    fn to_expr(&self) -> Expr<Sym> {
+        self.fields.iter().fold(Expr::Var(self.root), |e, f| Expr::Field(Box::new(e), *f))
+    }
+    /// Whether one path is a prefix of the other, so that pinning one
+    /// constrains the other.
+    fn overlaps(&self, other: &Path) -> bool {
+        self.root == other.root && self.fields.iter().zip(&other.fields).all(|(a, b)| a == b)
+    }
+}
+
+/// Whether `e` reads `p`, a field of it, or a record containing it.
+fn mentions(e: &Expr<Sym>, p: &Path) -> bool {
+    if let Some(q) = Path::of(e) {
+        return q.overlaps(p);
+    }
+    match e {
+        Expr::Var(_) | Expr::LitInt(_) | Expr::LitFloat(_) | Expr::LitStr(_) | Expr::LitBool(_) => false,
+        Expr::UnOp(_, x) | Expr::Field(x, _) | Expr::Is(x, _) => mentions(x, p),
+        Expr::BinOp(_, l, r) => mentions(l, p) || mentions(r, p),
+        Expr::Construct(_, args) | Expr::Variant(_, _, args) => args.iter().any(|a| mentions(a, p)),
+    }
+}
+
+/// Push `c` onto `out`, with an equality between records split into one
+/// equality per field. Equal constructors pair up their args; distinct
+/// records or variants push `false`.
+fn decompose(eng: &Engine, types: &TypeEnv, c: Expr<Sym>, out: &mut Vec<Expr<Sym>>) {
+    let Expr::BinOp(BinOp::Eq, l, r) = &c else {
+        out.push(c);
+        return;
+    };
+    let pairs: Vec<(Expr<Sym>, Expr<Sym>)> = match (&**l, &**r) {
+        (Expr::Construct(a, xs), Expr::Construct(b, ys)) if xs.len() == ys.len() => {
+            if a != b {
+                out.push(Expr::LitBool(false));
+                return;
+            }
+            xs.iter().cloned().zip(ys.iter().cloned()).collect()
+        }
+        (Expr::Variant(s, a, xs), Expr::Variant(t, b, ys)) if s == t && xs.len() == ys.len() => {
+            if a != b {
+                out.push(Expr::LitBool(false));
+                return;
+            }
+            xs.iter().cloned().zip(ys.iter().cloned()).collect()
+        }
+        (Expr::Construct(name, args), path) | (path, Expr::Construct(name, args))
+            if Path::of(path).is_some() =>
+        {
+            let Some(SchemaBody::Record(fields)) = eng.schemas.get(name).map(|s| &s.body) else {
+                out.push(c);
+                return;
+            };
+            if fields.len() != args.len() {
+                out.push(c);
+                return;
+            }
+            fields
+                .iter()
+                .zip(args)
+                .map(|(f, a)| (Expr::Field(Box::new(path.clone()), f.name), a.clone()))
+                .collect()
+        }
+        _ => {
+            out.push(c);
+            return;
+        }
+    };
+    for (a, b) in pairs {
+        let e = apply_identities(eng, types, &Expr::BinOp(BinOp::Eq, Box::new(a), Box::new(b)));
+        decompose(eng, types, e, out);
     }
 }
 
@@ -340,54 +777,58 @@ fn substitute(e: &Expr<Sym>, subst: &BTreeMap<Sym, Expr<Sym>>) -> Expr<Sym> {
 #[derive(Clone, Debug, Default)]
 struct Linear {
     constant: i64,
-    terms: BTreeMap<Sym, i64>,
+    terms: BTreeMap<Path, i64>,
 }
 
 impl Linear {
     fn lit(n: i64) -> Self { Self { constant: n, terms: BTreeMap::new() } }
-    fn var(s: Sym) -> Self {
+    fn path(p: Path) -> Self {
         let mut t = BTreeMap::new();
-        t.insert(s, 1);
+        t.insert(p, 1);
         Self { constant: 0, terms: t }
     }
-    fn add(mut self, other: Self) -> Self {
-        self.constant += other.constant;
+    // Arithmetic on linear forms is `None` on overflow.
+    fn add(mut self, other: Self) -> Option<Self> {
+        self.constant = self.constant.checked_add(other.constant)?;
         for (k, v) in other.terms {
-            *self.terms.entry(k).or_insert(0) += v;
+            let t = self.terms.entry(k).or_insert(0);
+            *t = t.checked_add(v)?;
         }
         self.terms.retain(|_, v| *v != 0);
-        self
+        Some(self)
     }
-    fn neg(mut self) -> Self {
-        self.constant = -self.constant;
-        for v in self.terms.values_mut() { *v = -*v; }
-        self
-    }
-    fn sub(self, other: Self) -> Self { self.add(other.neg()) }
-    fn scale(mut self, k: i64) -> Self {
-        if k == 0 { return Self::lit(0); }
-        self.constant *= k;
-        for v in self.terms.values_mut() { *v *= k; }
-        self
+    fn neg(self) -> Option<Self> { self.scale(-1) }
+    fn sub(self, other: Self) -> Option<Self> { self.add(other.neg()?) }
+    fn scale(mut self, k: i64) -> Option<Self> {
+        if k == 0 { return Some(Self::lit(0)); }
+        self.constant = self.constant.checked_mul(k)?;
+        for v in self.terms.values_mut() { *v = v.checked_mul(k)?; }
+        Some(self)
     }
 }
 
-fn to_linear(e: &Expr<Sym>) -> Option<Linear> {
+/// `e` as a linear form over integer paths. Paths that hold a `Float` in
+/// scope `types` are left out, since rounding strict bounds is only sound
+/// over the ints; names out of scope count as ints.
+fn to_linear(eng: &Engine, types: &TypeEnv, e: &Expr<Sym>) -> Option<Linear> {
     use BinOp::*;
     match e {
         Expr::LitInt(n) => Some(Linear::lit(*n)),
-        Expr::Var(s) => Some(Linear::var(*s)),
-        Expr::UnOp(UnOp::Neg, inner) => Some(to_linear(inner)?.neg()),
-        Expr::BinOp(Add, l, r) => Some(to_linear(l)?.add(to_linear(r)?)),
-        Expr::BinOp(Sub, l, r) => Some(to_linear(l)?.sub(to_linear(r)?)),
+        Expr::Var(_) | Expr::Field(..) => {
+            let p = Path::of(e)?;
+            (!matches!(eng.infer_type(e, types), Ok(Type::Float))).then(|| Linear::path(p))
+        }
+        Expr::UnOp(UnOp::Neg, inner) => to_linear(eng, types, inner)?.neg(),
+        Expr::BinOp(Add, l, r) => to_linear(eng, types, l)?.add(to_linear(eng, types, r)?),
+        Expr::BinOp(Sub, l, r) => to_linear(eng, types, l)?.sub(to_linear(eng, types, r)?),
         Expr::BinOp(Mul, l, r) => {
-            let ll = to_linear(l)?;
-            let lr = to_linear(r)?;
+            let ll = to_linear(eng, types, l)?;
+            let lr = to_linear(eng, types, r)?;
             // Linear only if at least one side is a pure constant.
             if ll.terms.is_empty() {
-                Some(lr.scale(ll.constant))
+                lr.scale(ll.constant)
             } else if lr.terms.is_empty() {
-                Some(ll.scale(lr.constant))
+                ll.scale(lr.constant)
             } else {
                 None
             }
@@ -396,16 +837,22 @@ fn to_linear(e: &Expr<Sym>) -> Option<Linear> {
     }
 }
 
+/// A comparison with linear sides as `linear op 0`.
+fn linear_atom(eng: &Engine, types: &TypeEnv, e: &Expr<Sym>) -> Option<(BinOp, Linear)> {
+    use BinOp::*;
+    match e {
+        Expr::BinOp(op, l, r) if matches!(op, Lt | Le | Gt | Ge | Eq | Neq) => {
+            Some((*op, to_linear(eng, types, l)?.sub(to_linear(eng, types, r)?)?))
+        }
+        _ => None,
+    }
+}
+
 #[derive(Clone, Debug)]
-struct SimpleAtom { var: Sym, op: BinOp, rhs: i64 }
+struct SimpleAtom { var: Path, op: BinOp, rhs: i64 }
 
-fn atom_to_simple(e: &Expr<Sym>) -> Option<SimpleAtom> {
-    use BinOp::*;
-    let (op, l, r) = match e {
-        Expr::BinOp(op, l, r) if matches!(op, Lt | Le | Gt | Ge | Eq | Neq) => (*op, l, r),
-        _ => return None,
-    };
-    let combined = to_linear(l)?.sub(to_linear(r)?);
+fn atom_to_simple(eng: &Engine, types: &TypeEnv, e: &Expr<Sym>) -> Option<SimpleAtom> {
+    let (op, combined) = linear_atom(eng, types, e)?;
     if combined.terms.len() != 1 {
         return None;
     }
@@ -415,7 +862,7 @@ fn atom_to_simple(e: &Expr<Sym>) -> Option<SimpleAtom> {
         return None;
     }
     let (rhs, op) = if coef == 1 {
-        (-constant, op)
+        (constant.checked_neg()?, op)
     } else {
         // -var + constant op 0  ↔  var op_flipped constant.
         (constant, flip_inequality(op))
@@ -464,6 +911,60 @@ impl Interval {
         }
         i
     }
+    /// `coef·var + constant op 0`, rounded to inclusive integer bounds.
+    /// An equality `coef` does not divide is empty; such a disequality
+    /// constrains nothing. Worked in i128, like the Fourier–Motzkin system:
+    /// a bound past the i64 range is empty on one side, no bound on the other.
+    fn from_scaled(coef: i64, constant: i64, op: BinOp) -> Self {
+        use BinOp::*;
+        let (coef, constant) = (coef as i128, constant as i128);
+        let empty = Self { lo: Some((1, true)), hi: Some((0, true)), ne: BTreeSet::new() };
+        let mut i = Self::default();
+        match op {
+            Eq | Neq if constant % coef != 0 => {
+                if op == Eq {
+                    return empty;
+                }
+            }
+            Eq => match i64::try_from(-constant / coef) {
+                Ok(k) => {
+                    i.lo = Some((k, true));
+                    i.hi = Some((k, true));
+                }
+                Err(_) => return empty,
+            },
+            Neq => {
+                if let Ok(k) = i64::try_from(-constant / coef) {
+                    i.ne.insert(k);
+                }
+            }
+            _ => {
+                // As `c·var ≥ m`; a strict bound tightens by one over the integers.
+                let (c, m) = match op {
+                    Gt => (coef, 1 - constant),
+                    Ge => (coef, -constant),
+                    Lt => (-coef, constant + 1),
+                    _ => (-coef, constant),
+                };
+                if c > 0 {
+                    let lo = -(-m).div_euclid(c);
+                    match i64::try_from(lo) {
+                        Ok(k) => i.lo = Some((k, true)),
+                        Err(_) if lo > 0 => return empty,
+                        Err(_) => {}
+                    }
+                } else {
+                    let hi = (-m).div_euclid(-c);
+                    match i64::try_from(hi) {
+                        Ok(k) => i.hi = Some((k, true)),
+                        Err(_) if hi < 0 => return empty,
+                        Err(_) => {}
+                    }
+                }
+            }
+        }
+        i
+    }
     fn merge(&mut self, other: &Interval) {
         if let Some(b) = other.lo {
             self.lo = Some(match self.lo {
@@ -479,10 +980,19 @@ impl Interval {
         }
         self.ne.extend(other.ne.iter().copied());
     }
+    /// The bounds as inclusive ones: over the integers `> k` is `≥ k+1` and
+    /// `< k` is `≤ k-1`. In i128, since `> i64::MAX` leaves the i64 range.
+    fn closed(&self) -> (Option<i128>, Option<i128>) {
+        let lo = self.lo.map(|(k, inc)| k as i128 + i128::from(!inc));
+        let hi = self.hi.map(|(k, inc)| k as i128 - i128::from(!inc));
+        (lo, hi)
+    }
     fn is_empty(&self) -> bool {
-        if let (Some((lo, lo_inc)), Some((hi, hi_inc))) = (self.lo, self.hi) {
-            if lo > hi { return true; }
-            if lo == hi && !(lo_inc && hi_inc) { return true; }
+        match self.closed() {
+            (Some(lo), _) if lo > i64::MAX as i128 => return true,
+            (_, Some(hi)) if hi < i64::MIN as i128 => return true,
+            (Some(lo), Some(hi)) if lo > hi => return true,
+            _ => {}
         }
         if let Some(k) = self.singleton_pre_ne() {
             if self.ne.contains(&k) { return true; }
@@ -490,19 +1000,19 @@ impl Interval {
         false
     }
     fn singleton_pre_ne(&self) -> Option<i64> {
-        if let (Some((lo, true)), Some((hi, true))) = (self.lo, self.hi) {
-            if lo == hi { return Some(lo); }
+        match self.closed() {
+            (Some(lo), Some(hi)) if lo == hi => i64::try_from(lo).ok(),
+            _ => None,
         }
-        None
     }
     fn singleton(&self) -> Option<i64> {
         let k = self.singleton_pre_ne()?;
         if self.ne.contains(&k) { return None; }
         Some(k)
     }
-    fn to_atoms(&self, var: Sym) -> Vec<Expr<Sym>> {
+    fn to_atoms(&self, var: &Path) -> Vec<Expr<Sym>> {
         let mut out = Vec::new();
-        let mk = |op, k| Expr::BinOp(op, Box::new(Expr::Var(var)), Box::new(Expr::LitInt(k)));
+        let mk = |op, k| Expr::BinOp(op, Box::new(var.to_expr()), Box::new(Expr::LitInt(k)));
         if let Some((lo, inc)) = self.lo {
             out.push(mk(if inc { BinOp::Ge } else { BinOp::Gt }, lo));
         }
@@ -537,9 +1047,184 @@ fn tighter_hi(a: (i64, bool), b: (i64, bool)) -> (i64, bool) {
     else { (a.0, a.1 && b.1) }
 }
 
-fn atom_to_interval(e: &Expr<Sym>) -> Option<(Sym, Interval)> {
-    let s = atom_to_simple(e)?;
-    Some((s.var, Interval::from_atom(&s)))
+/// Whether a comparison whose variables cancel out, like `n > n`, holds.
+fn constant_atom(eng: &Engine, types: &TypeEnv, e: &Expr<Sym>) -> Option<bool> {
+    use BinOp::*;
+    let (op, lin) = linear_atom(eng, types, e)?;
+    if !lin.terms.is_empty() {
+        return None;
+    }
+    let c = lin.constant;
+    Some(match op {
+        Lt => c < 0,
+        Le => c <= 0,
+        Gt => c > 0,
+        Ge => c >= 0,
+        Eq => c == 0,
+        _ => c != 0,
+    })
+}
+
+fn atom_to_interval(eng: &Engine, types: &TypeEnv, e: &Expr<Sym>) -> Option<(Path, Interval)> {
+    if let Some(s) = atom_to_simple(eng, types, e) {
+        let ivl = Interval::from_atom(&s);
+        return Some((s.var, ivl));
+    }
+    let (op, lin) = linear_atom(eng, types, e)?;
+    if lin.terms.len() != 1 {
+        return None;
+    }
+    let (var, coef) = lin.terms.into_iter().next().unwrap();
+    Some((var, Interval::from_scaled(coef, lin.constant, op)))
+}
+
+
+// =============================================================================
+// Multi-variable feasibility (Fourier–Motzkin)
+// =============================================================================
+//
+// Atoms over two or more variables stay in the residual as written, but they
+// still take part in contradiction detection: together with the per-variable
+// intervals they form a system of `Σ aᵢ·vᵢ + c ≥ 0` constraints, from which
+// variables are eliminated one at a time by pairing every upper bound with
+// every lower bound. A constant constraint `c ≥ 0` with `c < 0` proves the
+// conjunction false. Each derived constraint is divided through by the gcd
+// of its coefficients and its constant rounded down, which is sound over the
+// integers and catches parity conflicts like `2x + 2y = 5`.
+//
+// Elimination can square the system's size, so it gives up (assuming
+// satisfiable) past `FM_LIMIT` constraints.
+//
+// The same elimination projects bounds for one variable (`IntSystem::bounds`):
+// eliminate every other, and the constraints left on it alone bound it.
+
+const FM_LIMIT: usize = 256;
+
+/// `Σ terms + constant ≥ 0`.
+#[derive(Clone, Debug, PartialEq)]
+struct Constraint {
+    terms: BTreeMap<Path, i128>,
+    constant: i128,
+}
+
+impl Constraint {
+    fn from_linear(lin: &Linear, sign: i128, shift: i128) -> Self {
+        Self {
+            terms: lin.terms.iter().map(|(v, k)| (v.clone(), sign * *k as i128)).collect(),
+            constant: sign * lin.constant as i128 + shift,
+        }
+    }
+
+    fn tightened(mut self) -> Self {
+        let g = self.terms.values().fold(0, |g, k| gcd(g, k.abs()));
+        if g > 1 {
+            self.terms.values_mut().for_each(|k| *k /= g);
+            self.constant = self.constant.div_euclid(g);
+        }
+        self
+    }
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+fn multi_var_constraints(eng: &Engine, types: &TypeEnv, e: &Expr<Sym>) -> Vec<Constraint> {
+    use BinOp::*;
+    let Some((op, lin)) = linear_atom(eng, types, e) else { return Vec::new() };
+    if lin.terms.len() < 2 {
+        return Vec::new();
+    }
+    match op {
+        Ge => vec![Constraint::from_linear(&lin, 1, 0)],
+        Gt => vec![Constraint::from_linear(&lin, 1, -1)],
+        Le => vec![Constraint::from_linear(&lin, -1, 0)],
+        Lt => vec![Constraint::from_linear(&lin, -1, -1)],
+        Eq => vec![Constraint::from_linear(&lin, 1, 0), Constraint::from_linear(&lin, -1, 0)],
+        _ => Vec::new(),
+    }
+}
+
+fn closed_constraints(var: &Path, lo: Option<i64>, hi: Option<i64>) -> Vec<Constraint> {
+    Interval { lo: lo.map(|k| (k, true)), hi: hi.map(|k| (k, true)), ne: BTreeSet::new() }.constraints(var)
+}
+
+impl Interval {
+    fn constraints(&self, var: &Path) -> Vec<Constraint> {
+        let bound = |sign: i128, k: i128| Constraint { terms: BTreeMap::from([(var.clone(), sign)]), constant: k };
+        let mut out = Vec::new();
+        if let Some((lo, inc)) = self.lo {
+            out.push(bound(1, -(lo as i128) - if inc { 0 } else { 1 }));
+        }
+        if let Some((hi, inc)) = self.hi {
+            out.push(bound(-1, hi as i128 - if inc { 0 } else { 1 }));
+        }
+        out
+    }
+}
+
+/// Whether the constraints have no integer solution, as far as
+/// Fourier–Motzkin elimination can tell within `FM_LIMIT`.
+fn infeasible(system: Vec<Constraint>) -> bool {
+    eliminate(system, None).is_none()
+}
+
+/// Eliminate every variable but `keep`, returning the tightened constraints
+/// left on `keep` alone, or `None` once a constant one fails. Past
+/// `FM_LIMIT`, or on overflow, it stops with those derived so far: fewer
+/// constraints, so looser bounds, but still sound ones.
+fn eliminate(mut system: Vec<Constraint>, keep: Option<&Path>) -> Option<Vec<Constraint>> {
+    let mut kept: Vec<Constraint> = Vec::new();
+    loop {
+        let mut next: Vec<Constraint> = Vec::new();
+        for c in system.into_iter().map(Constraint::tightened) {
+            if c.terms.is_empty() {
+                if c.constant < 0 {
+                    return None;
+                }
+            } else if keep.is_some_and(|k| c.terms.len() == 1 && c.terms.contains_key(k)) {
+                if !kept.contains(&c) {
+                    kept.push(c);
+                }
+            } else if !next.contains(&c) {
+                next.push(c);
+            }
+        }
+        let vars: BTreeSet<Path> = next.iter().flat_map(|c| c.terms.keys().cloned()).filter(|v| Some(v) != keep).collect();
+        let count = |v: &Path, positive: bool| {
+            next.iter().filter(|c| c.terms.get(v).is_some_and(|k| (*k > 0) == positive)).count()
+        };
+        let Some(v) = vars.into_iter().min_by_key(|v| count(v, true) * count(v, false)) else {
+            return Some(kept);
+        };
+        let (with, rest): (Vec<_>, Vec<_>) = next.into_iter().partition(|c| c.terms.contains_key(&v));
+        let (lower, upper): (Vec<_>, Vec<_>) = with.into_iter().partition(|c| c.terms[&v] > 0);
+        if rest.len() + lower.len() * upper.len() > FM_LIMIT {
+            return Some(kept);
+        }
+        system = rest;
+        for l in &lower {
+            for u in &upper {
+                let (a, b) = (l.terms[&v], -u.terms[&v]);
+                let Some(c) = combine(l, b, u, a) else { return Some(kept) };
+                system.push(c);
+            }
+        }
+    }
+}
+
+/// `x·l + y·u`, or `None` on overflow.
+fn combine(l: &Constraint, x: i128, u: &Constraint, y: i128) -> Option<Constraint> {
+    let mut terms: BTreeMap<Path, i128> = BTreeMap::new();
+    for (c, k) in [(l, x), (u, y)] {
+        for (v, a) in &c.terms {
+            let t = terms.entry(v.clone()).or_insert(0);
+            *t = t.checked_add(a.checked_mul(k)?)?;
+        }
+    }
+    terms.retain(|_, k| *k != 0);
+    let constant = l.constant.checked_mul(x)?.checked_add(u.constant.checked_mul(y)?)?;
+    Some(Constraint { terms, constant })
 }
 
 
@@ -586,7 +1271,7 @@ mod tests {
         // n > 0 ∧ n > 5 → n > 5
         let eng = load();
         let n = n_sym(&eng);
-        let r = reduce(&eng, &and(gt(var(n), lit(0)), gt(var(n), lit(5))), &Bindings::default());
+        let r = reduce(&eng, &TypeEnv::new(), &and(gt(var(n), lit(0)), gt(var(n), lit(5))), &Bindings::default());
         assert_eq!(r, gt(var(n), lit(5)));
     }
 
@@ -595,7 +1280,7 @@ mod tests {
         // n >= 0 ∧ n > 0 → n > 0
         let eng = load();
         let n = n_sym(&eng);
-        let r = reduce(&eng, &and(ge(var(n), lit(0)), gt(var(n), lit(0))), &Bindings::default());
+        let r = reduce(&eng, &TypeEnv::new(), &and(ge(var(n), lit(0)), gt(var(n), lit(0))), &Bindings::default());
         assert_eq!(r, gt(var(n), lit(0)));
     }
 
@@ -604,7 +1289,18 @@ mod tests {
         // n > 5 ∧ n < 3 → false
         let eng = load();
         let n = n_sym(&eng);
-        let r = reduce(&eng, &and(gt(var(n), lit(5)), lt(var(n), lit(3))), &Bindings::default());
+        let r = reduce(&eng, &TypeEnv::new(), &and(gt(var(n), lit(5)), lt(var(n), lit(3))), &Bindings::default());
+        assert_eq!(r, Expr::LitBool(false));
+    }
+
+    #[test]
+    fn complementary_literals_drop_to_false() {
+        // b ∧ n > 0 ∧ ¬b → false
+        let eng = load();
+        let n = n_sym(&eng);
+        let b = var(eng.interner.find("Count").unwrap());
+        let not_b = Expr::UnOp(UnOp::Not, Box::new(b.clone()));
+        let r = reduce(&eng, &TypeEnv::new(), &and(and(b, gt(var(n), lit(0))), not_b), &Bindings::default());
         assert_eq!(r, Expr::LitBool(false));
     }
 
@@ -614,7 +1310,7 @@ mod tests {
         let eng = load();
         let n = n_sym(&eng);
         let inp = gt(add(add(var(n), lit(0)), lit(0)), lit(5));
-        let r = reduce(&eng, &inp, &Bindings::default());
+        let r = reduce(&eng, &TypeEnv::new(), &inp, &Bindings::default());
         assert_eq!(r, gt(var(n), lit(5)));
     }
 
@@ -623,7 +1319,7 @@ mod tests {
         // n + 1 > 5 → n > 4
         let eng = load();
         let n = n_sym(&eng);
-        let r = reduce(&eng, &gt(add(var(n), lit(1)), lit(5)), &Bindings::default());
+        let r = reduce(&eng, &TypeEnv::new(), &gt(add(var(n), lit(1)), lit(5)), &Bindings::default());
         assert_eq!(r, gt(var(n), lit(4)));
     }
 
@@ -634,7 +1330,7 @@ mod tests {
         let n = n_sym(&eng);
         let m = eng.interner.intern("m");
         let inp = and(eq(var(n), lit(5)), gt(add(var(n), var(m)), lit(10)));
-        let r = reduce(&eng, &inp, &Bindings::default());
+        let r = reduce(&eng, &TypeEnv::new(), &inp, &Bindings::default());
         // After substitution: n = 5 ∧ 5 + m > 10 → n = 5 ∧ m > 5.
         // Reassembly emits the equality first, then the narrowed atom.
         assert_eq!(r, and(eq(var(n), lit(5)), gt(var(m), lit(5))));
@@ -645,7 +1341,7 @@ mod tests {
         // (n > 0) ∧ (n > 0) → n > 0
         let eng = load();
         let n = n_sym(&eng);
-        let r = reduce(&eng, &and(gt(var(n), lit(0)), gt(var(n), lit(0))), &Bindings::default());
+        let r = reduce(&eng, &TypeEnv::new(), &and(gt(var(n), lit(0)), gt(var(n), lit(0))), &Bindings::default());
         assert_eq!(r, gt(var(n), lit(0)));
     }
 
@@ -655,7 +1351,7 @@ mod tests {
         let eng = load();
         let n = n_sym(&eng);
         let inp = and(ge(var(n), lit(5)), Expr::BinOp(BinOp::Le, Box::new(var(n)), Box::new(lit(5))));
-        let r = reduce(&eng, &inp, &Bindings::default());
+        let r = reduce(&eng, &TypeEnv::new(), &inp, &Bindings::default());
         assert_eq!(r, eq(var(n), lit(5)));
     }
 
@@ -665,13 +1361,172 @@ mod tests {
         let mut eng = load();
         let n = n_sym(&eng);
         let m = eng.interner.intern("m");
-        let r = reduce(&eng, &and(gt(var(n), lit(0)), gt(var(m), lit(0))), &Bindings::default());
+        let r = reduce(&eng, &TypeEnv::new(), &and(gt(var(n), lit(0)), gt(var(m), lit(0))), &Bindings::default());
         // Order is by variable Sym (BTreeMap iteration); we just check the conjunct set.
         let expected_a = and(gt(var(n), lit(0)), gt(var(m), lit(0)));
         let expected_b = and(gt(var(m), lit(0)), gt(var(n), lit(0)));
         assert!(r == expected_a || r == expected_b, "got {r:?}");
     }
 
+    #[test]
+    fn scaled_atoms_round_to_integer_bounds() {
+        // 2n > 5 → n >= 3;  3n <= -4 → n <= -2;  2n = 5 → false
+        let eng = load();
+        let n = n_sym(&eng);
+        let two_n = Expr::BinOp(BinOp::Mul, Box::new(lit(2)), Box::new(var(n)));
+        let r = reduce(&eng, &TypeEnv::new(), &gt(two_n.clone(), lit(5)), &Bindings::default());
+        assert_eq!(r, ge(var(n), lit(3)));
+        let three_n = Expr::BinOp(BinOp::Mul, Box::new(var(n)), Box::new(lit(3)));
+        let r = reduce(&eng, &TypeEnv::new(), &Expr::BinOp(BinOp::Le, Box::new(three_n), Box::new(lit(-4))), &Bindings::default());
+        assert_eq!(r, Expr::BinOp(BinOp::Le, Box::new(var(n)), Box::new(lit(-2))));
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &eq(two_n, lit(5)), &Bindings::default()), Expr::LitBool(false));
+    }
+
+    #[test]
+    fn strict_int_bounds_round_and_cancelled_atoms_fold() {
+        // n > 5 ∧ n < 6 → false; n > 5 ∧ n < 7 → n = 6; n > n → false.
+        let eng = load();
+        let n = n_sym(&eng);
+        let none = Bindings::default();
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &and(gt(var(n), lit(5)), lt(var(n), lit(6))), &none), Expr::LitBool(false));
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &and(gt(var(n), lit(5)), lt(var(n), lit(7))), &none), eq(var(n), lit(6)));
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &gt(var(n), var(n)), &none), Expr::LitBool(false));
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &ge(var(n), var(n)), &none), Expr::LitBool(true));
+    }
+
+    #[test]
+    fn bounds_near_the_i64_limits_do_not_overflow() {
+        let eng = load();
+        let n = n_sym(&eng);
+        let none = Bindings::default();
+        let three_n = Expr::BinOp(BinOp::Mul, Box::new(lit(3)), Box::new(var(n)));
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &gt(three_n, lit(i64::MAX)), &none), ge(var(n), lit(i64::MAX / 3 + 1)));
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &gt(var(n), lit(i64::MAX)), &none), Expr::LitBool(false));
+        // -n - i64::MIN is no linear form in i64, so the atom stays as written.
+        let below = lt(Expr::UnOp(UnOp::Neg, Box::new(var(n))), lit(i64::MIN));
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &below, &none), below);
+    }
+
+    #[test]
+    fn multi_variable_contradictions() {
+        // x + y > 10 ∧ x < 3 ∧ y < 3 → false, but x + y > 10 ∧ x < 3 stays.
+        let mut eng = load();
+        let (x, y) = (eng.interner.intern("x"), eng.interner.intern("y"));
+        let sum = gt(add(var(x), var(y)), lit(10));
+        let r = reduce(&eng, &TypeEnv::new(), &and(and(sum.clone(), lt(var(x), lit(3))), lt(var(y), lit(3))), &Bindings::default());
+        assert_eq!(r, Expr::LitBool(false));
+        let r = reduce(&eng, &TypeEnv::new(), &and(sum.clone(), lt(var(x), lit(3))), &Bindings::default());
+        assert_eq!(r, and(lt(var(x), lit(3)), sum));
+
+        // 2x + 2y = 5 has no integer solution.
+        let two = |v| Expr::BinOp(BinOp::Mul, Box::new(lit(2)), Box::new(var(v)));
+        let r = reduce(&eng, &TypeEnv::new(), &eq(add(two(x), two(y)), lit(5)), &Bindings::default());
+        assert_eq!(r, Expr::LitBool(false));
+    }
+
+    #[test]
+    fn disjunctions_narrow_per_disjunct() {
+        let mut eng = load();
+        let n = n_sym(&eng);
+        let m = eng.interner.intern("m");
+        let or = |l, r| Expr::BinOp(BinOp::Or, Box::new(l), Box::new(r));
+        let le = |l, r| Expr::BinOp(BinOp::Le, Box::new(l), Box::new(r));
+        let env = Bindings::default();
+
+        // n > 5 ∨ n > 10 → n > 5
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &or(gt(var(n), lit(5)), gt(var(n), lit(10))), &env), gt(var(n), lit(5)));
+        // n > 5 ∨ n <= 5 → true
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &or(gt(var(n), lit(5)), le(var(n), lit(5))), &env), Expr::LitBool(true));
+        // n >= 0 ∧ (n < 0 ∨ n > 10) → n > 10
+        let inp = and(ge(var(n), lit(0)), or(lt(var(n), lit(0)), gt(var(n), lit(10))));
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &inp, &env), gt(var(n), lit(10)));
+        // m > 0 ∧ (n > 5 ∨ n < 3) keeps its shape.
+        let inp = and(gt(var(m), lit(0)), or(gt(var(n), lit(5)), lt(var(n), lit(3))));
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &inp, &env), inp);
+        // ¬(n > 5 ∨ m > 0) → n <= 5 ∧ m <= 0
+        let inp = Expr::UnOp(UnOp::Not, Box::new(or(gt(var(n), lit(5)), gt(var(m), lit(0)))));
+        let r = reduce(&eng, &TypeEnv::new(), &inp, &env);
+        assert_eq!(flatten_and(&r).len(), 2, "{r:?}");
+
+        let bounds = int_bounds(&eng, &TypeEnv::new(), &or(gt(var(n), lit(5)), lt(var(n), lit(-3))), &env).unwrap();
+        assert!(!bounds.contains_key(&n));
+        let bounds = int_bounds(&eng, &TypeEnv::new(), &or(eq(var(n), lit(5)), eq(var(n), lit(-3))), &env).unwrap();
+        assert_eq!(bounds[&n], (Some(-3), Some(5)));
+    }
+
+    #[test]
+    fn record_equalities_decompose_into_fields() {
+        let src = std::fs::read_to_string("examples/grid.poly").expect("read grid");
+        let mut eng = Engine::load(&src).expect("load grid");
+        let sym = |eng: &mut Engine, s: &str| eng.interner.intern(s);
+        let (coord, c, h) = (sym(&mut eng, "Coordinate"), sym(&mut eng, "c"), sym(&mut eng, "h"));
+        let (x, y) = (sym(&mut eng, "x"), sym(&mut eng, "y"));
+        let field = |f| Expr::Field(Box::new(var(c)), f);
+        let point = |a, b| Expr::Construct(coord, vec![a, b]);
+        let env = Bindings::default();
+
+        // c = Coordinate(1, 2) ∧ c.y < h → c.x = 1 ∧ c.y = 2 ∧ h > 2
+        let r = reduce(&eng, &TypeEnv::new(), &and(eq(var(c), point(lit(1), lit(2))), lt(field(y), var(h))), &env);
+        assert_eq!(r, and(and(eq(field(x), lit(1)), eq(field(y), lit(2))), gt(var(h), lit(2))));
+        // c = Coordinate(1, h) ∧ c.x > 1 → false
+        let r = reduce(&eng, &TypeEnv::new(), &and(eq(var(c), point(lit(1), var(h))), gt(field(x), lit(1))), &env);
+        assert_eq!(r, Expr::LitBool(false));
+        // Conflicting constructor equalities.
+        let r = reduce(&eng, &TypeEnv::new(), &and(eq(var(c), point(lit(1), lit(2))), eq(var(c), point(lit(3), lit(4)))), &env);
+        assert_eq!(r, Expr::LitBool(false));
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &eq(point(var(h), lit(1)), point(lit(2), lit(3))), &env), Expr::LitBool(false));
+    }
+
+    #[test]
+    fn field_paths_narrow_like_variables() {
+        // 1 <= c.x ∧ c.x > 1 ∧ c.x <= 1 → false;  c.x >= 2 ∧ c.x <= 2 → c.x = 2
+        let src = std::fs::read_to_string("examples/grid.poly").expect("read grid");
+        let mut eng = Engine::load(&src).expect("load grid");
+        let (c, x) = (eng.interner.intern("c"), eng.interner.intern("x"));
+        let cx = Expr::Field(Box::new(var(c)), x);
+        let le = |l, r| Expr::BinOp(BinOp::Le, Box::new(l), Box::new(r));
+        let env = Bindings::default();
+        let inp = and(and(le(lit(1), cx.clone()), gt(cx.clone(), lit(1))), le(cx.clone(), lit(1)));
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &inp, &env), Expr::LitBool(false));
+        let inp = and(ge(cx.clone(), lit(2)), le(cx.clone(), lit(2)));
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &inp, &env), eq(cx, lit(2)));
+    }
+
+    #[test]
+    fn string_and_boolean_literals() {
+        let mut eng = load();
+        let [s, t, f, g] = ["s", "t", "f", "g"].map(|n| var(eng.interner.intern(n)));
+        let st = |x: &str| Expr::LitStr(x.into());
+        let ne = |l, r| Expr::BinOp(BinOp::Neq, Box::new(l), Box::new(r));
+        let or = |l, r| Expr::BinOp(BinOp::Or, Box::new(l), Box::new(r));
+        let not = |x| Expr::UnOp(UnOp::Not, Box::new(x));
+        let env = Bindings::default();
+
+        let r = reduce(&eng, &TypeEnv::new(), &and(eq(s.clone(), st("open")), eq(s.clone(), st("closed"))), &env);
+        assert_eq!(r, Expr::LitBool(false));
+        let r = reduce(&eng, &TypeEnv::new(), &and(ne(s.clone(), st("open")), eq(s.clone(), st("closed"))), &env);
+        assert_eq!(r, eq(s.clone(), st("closed")));
+        // s = t ∧ s = "a" ∧ t != "a" → false
+        let inp = and(and(eq(s.clone(), t.clone()), eq(s.clone(), st("a"))), ne(t.clone(), st("a")));
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &inp, &env), Expr::LitBool(false));
+
+        // f == true → f;  f != true → not f;  f != g ∧ f ∧ g → false
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &eq(f.clone(), Expr::LitBool(true)), &env), f);
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &ne(f.clone(), Expr::LitBool(true)), &env), not(f.clone()));
+        let inp = and(ne(f.clone(), g.clone()), and(f.clone(), g.clone()));
+        assert_eq!(reduce(&eng, &TypeEnv::new(), &inp, &env), Expr::LitBool(false));
+
+        // Unit propagation through clauses too wide to distribute:
+        // not f ∧ (f ∨ g) ∧ (s = "a" ∨ t = "a") ∧ … → not f ∧ g ∧ …
+        let clause = || or(eq(s.clone(), st("a")), eq(t.clone(), st("a")));
+        let mut inp = and(not(f.clone()), or(f.clone(), g.clone()));
+        for _ in 0..5 {
+            inp = and(inp, clause());
+        }
+        let r = reduce(&eng, &TypeEnv::new(), &inp, &env);
+        assert_eq!(flatten_and(&r)[..2], [not(f), g], "{r:?}");
+    }
+
     #[test]
     fn env_substitution_then_simplify() {
         // n > 0 with env n=3 → true
@@ -679,7 +1534,7 @@ mod tests {
         let n = n_sym(&eng);
         let mut env = Bindings::default();
         env.insert(n, super::super::eval::Value::Int(3));
-        let r = reduce(&eng, &gt(var(n), lit(0)), &env);
+        let r = reduce(&eng, &TypeEnv::new(), &gt(var(n), lit(0)), &env);
         assert_eq!(r, Expr::LitBool(true));
     }
 }
@@ -0,0 +1,209 @@
+use chumsky::prelude::*;
+
+use super::diag::Diagnostic;
+use super::eval::Bindings;
+use super::lower::{lower_expr, lower_param};
+use super::parse::{expr_parser, param, ws};
+use super::simplify::reduce;
+use super::typecheck::{scope, TypeError};
+use super::{Engine, Expr, Param, Sym, Type};
+
+
+// ============================================================================
+// Satisfiability
+// ============================================================================
+//
+// `reduce` answers "is this residual false?"; `satisfiable` also answers
+// "with what values is it true?". An expression that reduces to `false` is
+// unsatisfiable outright. Otherwise the witness search tries small values for
+// the variables, with ints drawn from the bounds the reduced expression puts
+// on them. When the samples cover every variable's whole domain — bools, ints
+// bounded on both sides, strings (only ever compared for equality, so the
+// literals plus a fresh string per variable are every case), and records and
+// variants of bools — a search that finds nothing proves unsatisfiability
+// too. Anything else it misses is `Unknown`.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Satisfiability {
+    /// Values for the variables under which the expression is true.
+    Sat(Bindings),
+    Unsat,
+    /// Neither refuted nor witnessed within the search budget.
+    Unknown,
+}
+
+impl Engine {
+    /// Whether some values of `vars` make `expr` true, with a model if so.
+    /// `expr` should mention no other variables.
+    pub fn satisfiable(&self, expr: &Expr<Sym>, vars: &[Param<Sym>]) -> Satisfiability {
+        let condition = reduce(self, &scope(vars), expr, &Bindings::new());
+        if condition == Expr::LitBool(false) {
+            return Satisfiability::Unsat;
+        }
+        let params: Vec<&Param<Sym>> = vars.iter().collect();
+        self.search_witness(&params, expr, &condition)
+    }
+
+    /// Parse a `name: Type` variable declaration.
+    pub fn parse_var(&mut self, src: &str) -> Result<Param<Sym>, String> {
+        let raw = ws()
+            .ignore_then(param())
+            .then_ignore(ws())
+            .then_ignore(end())
+            .parse(src)
+            .map_err(|_| format!("expected `name: Type`, got `{src}`"))?;
+        let p = lower_param(raw, &mut self.interner);
+        if let Type::Named(s) = p.ty {
+            if !self.schemas.contains_key(&s) {
+                return Err(format!("unknown type `{}`", self.resolve(s)));
+            }
+        }
+        Ok(p)
+    }
+
+    /// Parse a boolean expression over `vars`, in `.poly` syntax.
+    pub fn parse_constraint(
+        &mut self,
+        src: &str,
+        vars: &[Param<Sym>],
+    ) -> Result<Expr<Sym>, Vec<Diagnostic>> {
+        let raw = ws()
+            .ignore_then(expr_parser())
+            .then_ignore(end())
+            .parse(src)
+            .map_err(|errs| errs.iter().map(Diagnostic::from_parse_error).collect::<Vec<_>>())?;
+        let e = lower_expr(raw, &mut self.interner);
+        let err = match self.infer_type(&e, &scope(vars)) {
+            Ok(Type::Bool) => return Ok(e),
+            Ok(found) => TypeError::Mismatch { expected: Type::Bool, found },
+            Err(e) => e,
+        };
+        Err(vec![Diagnostic::new(self.fmt_type_error(&err), 0..src.len())])
+    }
+
+    pub fn fmt_satisfiability(&self, s: &Satisfiability) -> String {
+        match s {
+            Satisfiability::Sat(b) => {
+                let mut out = String::from("sat\n");
+                for (k, v) in b {
+                    out.push_str(&format!("  {} = {}\n", self.resolve(*k), self.fmt_value(v)));
+                }
+                out
+            }
+            Satisfiability::Unsat => "unsat\n".to_string(),
+            Satisfiability::Unknown => "unknown: no model among small values\n".to_string(),
+        }
+    }
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::eval::Value;
+    use super::*;
+
+    fn solve(eng: &mut Engine, src: &str, vars: &[&str]) -> Satisfiability {
+        let vars: Vec<Param<Sym>> = vars.iter().map(|v| eng.parse_var(v).unwrap()).collect();
+        let e = eng.parse_constraint(src, &vars).unwrap();
+        eng.satisfiable(&e, &vars)
+    }
+
+    fn grid() -> Engine {
+        let src = std::fs::read_to_string("examples/grid.poly").unwrap();
+        Engine::load(&src).expect("load grid")
+    }
+
+    #[test]
+    fn models_satisfy_the_constraint() {
+        let mut eng = grid();
+        let Satisfiability::Sat(b) = solve(&mut eng, "n > 5 and n % 7 == 3 and s != \"a\" and f", &["n: Int", "s: String", "f: Bool"])
+        else {
+            panic!("expected a model");
+        };
+        let get = |eng: &Engine, n: &str| b[&eng.interner.find(n).unwrap()].clone();
+        assert_eq!(get(&eng, "n"), Value::Int(10));
+        assert_eq!(get(&eng, "f"), Value::Bool(true));
+        assert_ne!(get(&eng, "s"), Value::Str("a".into()));
+
+        let r = solve(&mut eng, "c.x == c.y + 1 and c.y > 2", &["c: Coordinate"]);
+        assert!(matches!(r, Satisfiability::Sat(_)), "{r:?}");
+    }
+
+    #[test]
+    fn finite_domains_are_refuted_by_search() {
+        let mut eng = grid();
+        // Only the simplifier's interval reasoning sees through this one.
+        assert_eq!(solve(&mut eng, "n > 5 and n < 3", &["n: Int"]), Satisfiability::Unsat);
+        // These need the search: 2 bools can't be pairwise distinct 3 ways,
+        // and s ∈ {"a", "b"} can't differ from both.
+        let r = solve(&mut eng, "f != g and g != h and h != f", &["f: Bool", "g: Bool", "h: Bool"]);
+        assert_eq!(r, Satisfiability::Unsat);
+        let r = solve(&mut eng, "(s == \"a\" or s == \"b\") and s != t and t != \"a\" and t != \"b\"", &["s: String", "t: String"]);
+        assert!(matches!(r, Satisfiability::Sat(_)), "{r:?}");
+        let r = solve(&mut eng, "n * n == 2 and n >= -100 and n <= 100", &["n: Int"]);
+        assert_eq!(r, Satisfiability::Unsat);
+        // Past the budget, only the ends are sampled.
+        let r = solve(&mut eng, "n * n == 2 and n >= -1000000 and n <= 1000000", &["n: Int"]);
+        assert_eq!(r, Satisfiability::Unknown);
+        // The samples below the bound saturate at i64::MIN, whose magnitude
+        // has no i64.
+        let Satisfiability::Sat(b) = solve(&mut eng, "-n > 9223372036854775806", &["n: Int"]) else {
+            panic!("expected a model");
+        };
+        assert_eq!(eng.fmt_bindings(&b), format!("[n={}]", -i64::MAX));
+    }
+
+    #[test]
+    fn models_are_sought_where_the_bounds_put_them() {
+        let mut eng = grid();
+        let model = |eng: &mut Engine, src: &str, vars: &[&str]| match solve(eng, src, vars) {
+            Satisfiability::Sat(b) => eng.fmt_bindings(&b),
+            other => panic!("expected a model for `{src}`, got {other:?}"),
+        };
+        // Inside a closed range, away from both ends.
+        assert_eq!(model(&mut eng, "n * n == 100 and n >= 0 and n <= 20", &["n: Int"]), "[n=10]");
+        // Bounded only jointly: the system puts `x` at 7 or more.
+        assert_eq!(model(&mut eng, "x + y > 10", &["x: Int", "y: Int"]), "[x=11, y=0]");
+        assert_eq!(model(&mut eng, "x + y == 20 and x - y == 6", &["x: Int", "y: Int"]), "[x=13, y=7]");
+        // And projected bounds make a search exhaustive.
+        let r = solve(&mut eng, "x + y == 7 and x * y == 13 and x >= 0 and x <= 9", &["x: Int", "y: Int"]);
+        assert_eq!(r, Satisfiability::Unsat);
+    }
+
+    #[test]
+    fn constraints_are_type_checked() {
+        let mut eng = grid();
+        let vars = [eng.parse_var("n: Int").unwrap()];
+        let msg = |r: Result<Expr<Sym>, Vec<Diagnostic>>| r.unwrap_err()[0].message.clone();
+        assert_eq!(msg(eng.parse_constraint("n + 1", &vars)), "expected Bool, found Int");
+        assert_eq!(msg(eng.parse_constraint("m > 0", &vars)), "unbound variable `m`");
+        assert_eq!(eng.parse_var("p: Point").unwrap_err(), "unknown type `Point`");
+    }
+
+    #[test]
+    fn floats_are_not_rounded_like_ints() {
+        let mut eng = grid();
+        let Satisfiability::Sat(b) = solve(&mut eng, "x > 0.0 and x < 1.0", &["x: Float"]) else {
+            panic!("expected a model");
+        };
+        assert_eq!(eng.fmt_bindings(&b), "[x=0.5]");
+        assert_eq!(solve(&mut eng, "x * 2.0 == 5.0", &["x: Float"]), Satisfiability::Unknown);
+        let vars = [eng.parse_var("x: Float").unwrap()];
+        let msg = eng.parse_constraint("x + 1 > 0.0", &vars).unwrap_err()[0].message.clone();
+        assert_eq!(msg, "expected Float, found Int");
+    }
+
+    #[test]
+    fn a_float_elsewhere_does_not_stop_int_rounding() {
+        let src = std::fs::read_to_string("examples/counter.poly").expect("read counter");
+        let src = format!("{src}\ninterface Gauge\n    Read[n: Float] {{ Tick -> Read[n + 0.5] }}\n");
+        let mut eng = Engine::load(&src).expect("load gauge");
+        assert_eq!(solve(&mut eng, "n > 5 and n < 6", &["n: Int"]), Satisfiability::Unsat);
+        let r = solve(&mut eng, "n > 0.0 and n < 1.0", &["n: Float"]);
+        assert!(matches!(r, Satisfiability::Sat(_)), "{r:?}");
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::BTreeMap;
+
+use super::eval::{Bindings, Value};
+use super::propagate::Propagation;
+use super::query::QueryError;
+use super::runtime::{Runtime, RuntimeError};
+use super::{Agent, Defer, DeferEntry, DirMapping, DirRef, Engine, Expr, Pattern, Sym, Tool};
+
+
+// ============================================================================
+// Tool hosts
+// ============================================================================
+//
+// A `tool` desugars to an external interface whose calls the host carries
+// out in-process: given a call's args it returns the result, and the runtime
+// fires the call with the result filled in, then `Collect`s it so the tool is
+// Idle again. Linked through an agent's `<Agent>::<Tool>` defer, the call also
+// steps the agent's instance back to its own position, so the call is on the
+// agent's trajectory too. Tools are deterministic: the runtime answers a
+// repeated call from its cache of results, and retries a failed call before
+// giving up.
+
+/// A call's args by param name, as the host sees them.
+pub type ToolArgs = BTreeMap<String, Value>;
+
+pub trait ToolHost {
+    /// The result of `tool.call` on `args`, or why there is none.
+    fn invoke(&mut self, engine: &Engine, tool: &str, call: &str, args: &ToolArgs) -> Result<Value, String>;
+}
+
+/// Tries per call, the first included.
+pub const TOOL_ATTEMPTS: usize = 3;
+
+impl Runtime {
+    /// Carry out `call` on tool instance `name` through `host` (or from the
+    /// cache), fire it with the result, and collect the result. Returns the
+    /// result and the two propagations, the call's first.
+    pub fn call_tool(
+        &mut self,
+        name: &str,
+        call: &str,
+        args: Bindings,
+        host: &mut dyn ToolHost,
+    ) -> Result<(Value, Vec<Propagation>), RuntimeError> {
+        let inst = self
+            .instance(name)
+            .ok_or_else(|| RuntimeError::UnknownInstance(name.to_string()))?;
+        let eng = &self.engine;
+        let iface = &eng.interfaces[&inst.interface];
+        let tool = eng
+            .tools
+            .get(&inst.interface)
+            .filter(|_| iface.deterministic)
+            .ok_or_else(|| RuntimeError::NotATool(name.to_string()))?;
+        let open = iface
+            .position(&inst.position)
+            .is_some_and(|p| p.directions.iter().any(|d| eng.resolve(d.name) == call));
+        let c = tool.calls.iter().find(|c| eng.resolve(c.name) == call).filter(|_| open);
+        let Some(c) = c else {
+            let error = QueryError::UnknownAction {
+                interface: eng.resolve(inst.interface).to_string(),
+                position: eng.resolve(inst.position).to_string(),
+                action: call.to_string(),
+            };
+            return Err(RuntimeError::Rejected { instance: name.to_string(), error });
+        };
+
+        let key = format!("{}.{call}{}", eng.resolve(tool.name), eng.fmt_bindings(&args));
+        let result = match self.tool_results.get(&key) {
+            Some(v) => v.clone(),
+            None => {
+                let named: ToolArgs = args.iter().map(|(k, v)| (eng.resolve(*k).to_string(), v.clone())).collect();
+                let mut outcome = Err(String::new());
+                for _ in 0..TOOL_ATTEMPTS {
+                    outcome = host
+                        .invoke(eng, eng.resolve(tool.name), call, &named)
+                        .and_then(|v| eng.check_value(&v, &c.result.ty).map(|()| v));
+                    if outcome.is_ok() {
+                        break;
+                    }
+                }
+                outcome.map_err(|message| RuntimeError::ToolFailed {
+                    instance: name.to_string(),
+                    call: call.to_string(),
+                    message,
+                })?
+            }
+        };
+        self.tool_results.insert(key, result.clone());
+
+        let mut with_result = args;
+        with_result.insert(c.result.name, result.clone());
+        let fired = self.submit(name, call, with_result)?;
+        let collected = self.submit(name, "Collect", Bindings::new())?;
+        Ok((result, vec![fired, collected]))
+    }
+}
+
+
+// ============================================================================
+// Routing
+// ============================================================================
+
+impl Engine {
+    /// Add the routing defer for each tool an agent lists: `<Agent>::<Tool>`,
+    /// from the state block realizing the agent's interface to the tool,
+    /// carrying every call at the agent's position out as a step back to that
+    /// position with the same params. Agents whose interface has no state
+    /// block, or whose names don't resolve, get no defer; validation reports
+    /// them.
+    /// The routing defer `route_tools` added for `tool` on `agent`: out of
+    /// the agent's position, from its interface's state block to the tool.
+    pub fn tool_defer(&self, agent: &Agent<Sym>, tool: Sym) -> Option<&Defer<Sym>> {
+        self.defers.iter().find(|d| {
+            d.target == tool
+                && self.is_state_of(d.source, agent.interface)
+                && d.entries.iter().any(|e| e.source_pos == agent.position)
+        })
+    }
+
+    pub(super) fn route_tools(&mut self) {
+        let agents: Vec<Agent<Sym>> = self.agents.values().cloned().collect();
+        for a in agents {
+            let Some(state) = self.states.keys().copied().find(|s| self.is_state_of(*s, a.interface)) else {
+                continue;
+            };
+            let Some(position) = self.states[&state].positions.iter().find(|p| p.name == a.position) else {
+                continue;
+            };
+            let pattern: Vec<Pattern<Sym>> = position.params.iter().map(|p| Pattern::Bind(p.name)).collect();
+            let args: Vec<Expr<Sym>> = position.params.iter().map(|p| Expr::Var(p.name)).collect();
+            let tools: Vec<Tool<Sym>> = a.tools.iter().filter_map(|t| self.tools.get(t)).cloned().collect();
+            for tool in tools {
+                let directions = tool
+                    .calls
+                    .iter()
+                    .map(|c| DirMapping {
+                        target_dir: DirRef::Named(c.name),
+                        source_dir: DirRef::Abstract {
+                            src_pos: a.position,
+                            src_pattern: pattern.clone(),
+                            tgt_pos: a.position,
+                            tgt_args: args.clone(),
+                            arg_spans: Vec::new(),
+                        },
+                        span: a.span.clone(),
+                    })
+                    .collect();
+                let name = format!("{}::{}", self.resolve(a.name), self.resolve(tool.name));
+                let idle = self.interner.intern("Idle");
+                self.defers.push(Defer {
+                    name: self.interner.intern(&name),
+                    source: state,
+                    target: tool.name,
+                    entries: vec![DeferEntry {
+                        source_pos: a.position,
+                        source_pattern: pattern.clone(),
+                        source_guard: None,
+                        guard_spans: None,
+                        target_pos: idle,
+                        target_args: Vec::new(),
+                        arg_spans: Vec::new(),
+                        directions,
+                        span: a.span.clone(),
+                    }],
+                    span: a.span.clone(),
+                });
+            }
+        }
+    }
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::super::{EngineError, Float};
+    use super::*;
+
+    /// A host that answers every call with `f`.
+    struct Answer<F>(F);
+
+    impl<F: FnMut(&ToolArgs) -> Result<Value, String>> ToolHost for Answer<F> {
+        fn invoke(&mut self, _engine: &Engine, _tool: &str, _call: &str, args: &ToolArgs) -> Result<Value, String> {
+            (self.0)(args)
+        }
+    }
+
+    fn tool_use() -> Runtime {
+        let src = std::fs::read_to_string("examples/01_tool_use/tool_use.poly").unwrap();
+        Runtime::new(Engine::load(&src).unwrap_or_else(|e| panic!("{e:?}")))
+    }
+
+    fn text(rt: &Runtime, pairs: &[(&str, &str)]) -> Bindings {
+        pairs
+            .iter()
+            .map(|(k, v)| (rt.engine.interner.find(k).unwrap(), Value::Str(v.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn tools_desugar_and_agents_route_to_them() {
+        let rt = tool_use();
+        let eng = &rt.engine;
+        let sym = |s: &str| eng.interner.find(s).unwrap();
+        let calc = &eng.interfaces[&sym("Calculator")];
+        assert!(calc.external && calc.deterministic);
+        assert_eq!(
+            eng.fmt_interface(calc),
+            "external interface Calculator\n    \
+             Idle {\n        \
+             Calculate[expression: String, result: Float]\n    },\n    \
+             Returned[result: Float] { Collect }",
+        );
+        assert_eq!(
+            eng.fmt_tool(&eng.tools[&sym("Clock")]),
+            "tool Clock\n    GetCurrentTime -> time: String",
+        );
+        let route = eng.defers.iter().find(|d| d.name == sym("Solver::Weather")).unwrap();
+        assert_eq!(
+            eng.fmt_defer(route),
+            "defer Solver::Weather : Loop::Internal -> Weather\n    \
+             Working[question] -> Idle {\n        \
+             GetWeather -> Working[question] => Working[question]\n    }",
+        );
+        assert!(eng.fmt_agent(&eng.agents[&sym("Solver")]).contains("\n    tools: [Calculator, Weather, Clock]\n"));
+    }
+
+    #[test]
+    fn agents_list_tools_they_can_reach() {
+        let errors = |src: &str| match Engine::load(src) {
+            Ok(_) => Vec::new(),
+            Err(EngineError::Parse(d)) | Err(EngineError::Validate(d)) => {
+                d.into_iter().map(|d| d.message).collect()
+            }
+        };
+        let tool = "tool Clock\n    Now -> time: String\n";
+        assert_eq!(
+            errors(&format!("{tool}interface Chat {{ Say[text: String] }}\n\
+                agent Bot : Chat.Chat\n    tools: [Clock, Pager]\n    \"\"\"Hi\"\"\"")),
+            vec![
+                "agent Bot: `Pager` is not a tool",
+                "agent Bot: tool calls need interface `Chat` to have transitions, so they can return to the agent's position",
+            ],
+        );
+    }
+
+    #[test]
+    fn agents_route_from_hand_written_state_blocks() {
+        let eng = Engine::load(
+            "tool Clock
+                Now -> time: String
+            state Chat::Flow
+                Open
+            interface Chat
+                Open { Say[text: String] }
+            defer Talk : Chat::Flow -> Chat
+                Open -> Open { Say -> Open => Open }
+            agent Bot : Chat.Open
+                tools: [Clock]
+                \"\"\"Hi\"\"\"",
+        )
+        .expect("load chat");
+        let route = eng.defers.iter().find(|d| eng.resolve(d.name) == "Bot::Clock").unwrap();
+        assert_eq!(
+            eng.fmt_defer(route),
+            "defer Bot::Clock : Chat::Flow -> Clock\n    Open -> Idle {\n        Now -> Open => Open\n    }",
+        );
+    }
+
+    #[test]
+    fn calls_run_the_host_and_step_the_agent() {
+        let mut rt = tool_use();
+        rt.spawn("loop", "Loop", "Working", text(&rt, &[("question", "weather in Oslo?")])).unwrap();
+        rt.spawn("weather", "Weather", "Idle", Bindings::new()).unwrap();
+        rt.link("Solver::Weather", "loop", "weather").unwrap();
+
+        let runs = Rc::new(Cell::new(0));
+        let counter = runs.clone();
+        let mut tools = Answer(move |args: &ToolArgs| {
+            counter.set(counter.get() + 1);
+            match &args["location"] {
+                Value::Str(city) => Ok(Value::Str(format!("{city}: -3C, snow"))),
+                _ => Err("location must be text".into()),
+            }
+        });
+
+        let args = text(&rt, &[("location", "Oslo")]);
+        let (result, props) = rt.call_tool("weather", "GetWeather", args.clone(), &mut tools).unwrap();
+        assert_eq!(result, Value::Str("Oslo: -3C, snow".into()));
+        let moves: String =
+            props[0].moves.iter().map(|m| format!("{} : {}", m.instance, rt.engine.fmt_step(&m.step))).collect();
+        assert_eq!(
+            moves,
+            "weather : Weather.Idle[location=\"Oslo\", report=\"Oslo: -3C, snow\"] --GetWeather--> \
+             Weather.Returned[report=\"Oslo: -3C, snow\"]\n\
+             loop : Loop.Working[question=\"weather in Oslo?\"] --GetWeather--> \
+             Loop.Working[question=\"weather in Oslo?\"]\n",
+        );
+        assert_eq!(rt.fmt_instances(), "loop : Loop.Working[question=\"weather in Oslo?\"]\nweather : Weather.Idle\n");
+
+        // The same call again is answered from the cache.
+        rt.call_tool("weather", "GetWeather", args, &mut tools).unwrap();
+        assert_eq!(runs.get(), 1);
+    }
+
+    #[test]
+    fn failing_calls_are_retried_then_reported() {
+        let mut rt = tool_use();
+        rt.spawn("calc", "Calculator", "Idle", Bindings::new()).unwrap();
+        rt.spawn("loop", "Loop", "Working", text(&rt, &[("question", "2+2?")])).unwrap();
+
+        let tries = Rc::new(Cell::new(0));
+        let counter = tries.clone();
+        let mut tools = Answer(move |_: &ToolArgs| {
+            counter.set(counter.get() + 1);
+            if counter.get() < 3 { Err("busy".into()) } else { Ok(Value::Float(Float(4.0))) }
+        });
+        let args = text(&rt, &[("expression", "2+2")]);
+        assert_eq!(rt.call_tool("calc", "Calculate", args, &mut tools).unwrap().0, Value::Float(Float(4.0)));
+        assert_eq!(tries.get(), TOOL_ATTEMPTS);
+
+        let err = |rt: &mut Runtime, name: &str, call: &str, tools: &mut dyn ToolHost| {
+            let args = text(rt, &[("expression", "1/0")]);
+            let e = rt.call_tool(name, call, args, tools).unwrap_err();
+            rt.engine.fmt_runtime_error(&e)
+        };
+        let mut tools = Answer(|_: &ToolArgs| Ok(Value::Int(1)));
+        assert_eq!(err(&mut rt, "calc", "Calculate", &mut tools), "calc: Calculate failed: expected Float, got 1");
+        assert_eq!(err(&mut rt, "calc", "Collect", &mut tools), "calc: unknown action: Calculator.Idle.Collect");
+        assert_eq!(err(&mut rt, "loop", "Answer", &mut tools), "loop is not a tool instance");
+        assert_eq!(rt.fmt_instances(), "calc : Calculator.Idle\nloop : Loop.Working[question=\"2+2?\"]\n");
+    }
+}
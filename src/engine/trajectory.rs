@@ -0,0 +1,498 @@
+use std::collections::BTreeMap;
+
+use super::eval::Bindings;
+use super::runtime::{Instance, Runtime, RuntimeError, Snapshot};
+use super::{Engine, Sym};
+
+
+// ============================================================================
+// Trajectory events
+// ============================================================================
+//
+// Every change a `Runtime` accepts is recorded as an `Event`. On disk a
+// trajectory is one event per line, tab-separated, after a version header:
+//
+//     # poly trajectory v1
+//     0  spawn  g  Grid  Cell  [Width=3, Height=3, c=Coordinate(x=1, y=1)]
+//     1  step   g  Cell  [...]  Right  []  Cell  [...]
+//     2  link   SetTo10  c  b
+//     3  step   b  Button  []  Press  []  Button  []
+//     4  induced  c  Count  [n=3]  Press  []  Count  [n=10]
+//     5  restore  1
+//
+// Columns for `step` are: seq, instance, source position, source bindings,
+// action, action args, target position, target bindings. Each move a step
+// induces through links follows it as an `induced` line with the same
+// columns; replay re-derives those moves from the step and checks them
+// against the log. A `link` names the defer, then the source and target
+// instances. A `restore` returns every instance and link to how they stood
+// before the event with the given seq. Bindings use the `fmt_value` literal
+// form, which escapes tabs and newlines inside strings, so a line never
+// contains a stray separator.
+
+pub const HEADER: &str = "# poly trajectory v1";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    Spawn {
+        seq: u64,
+        instance: String,
+        interface: Sym,
+        position: Sym,
+        bindings: Bindings,
+    },
+    Step {
+        seq: u64,
+        instance: String,
+        source: Sym,
+        source_bindings: Bindings,
+        action: Sym,
+        args: Bindings,
+        target: Sym,
+        target_bindings: Bindings,
+        /// Caused through a link by the step before it, not fired directly.
+        induced: bool,
+    },
+    Link {
+        seq: u64,
+        defer: Sym,
+        source: String,
+        target: String,
+    },
+    /// Back to the configuration before event `to` (see `Runtime::restore`).
+    Restore {
+        seq: u64,
+        to: u64,
+    },
+}
+
+impl Event {
+    pub fn seq(&self) -> u64 {
+        match self {
+            Event::Spawn { seq, .. }
+            | Event::Step { seq, .. }
+            | Event::Link { seq, .. }
+            | Event::Restore { seq, .. } => *seq,
+        }
+    }
+
+    /// The instance the event is about; for a link, the one whose actions
+    /// it carries out. A restore is about every instance.
+    pub fn instance(&self) -> Option<&str> {
+        match self {
+            Event::Spawn { instance, .. } | Event::Step { instance, .. } => Some(instance),
+            Event::Link { target, .. } => Some(target),
+            Event::Restore { .. } => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TrajectoryError {
+    /// 1-based line in the log.
+    pub line: usize,
+    pub message: String,
+}
+
+/// Where and how a replay stopped agreeing with the recorded log.
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    pub seq: u64,
+    pub kind: DivergenceKind,
+}
+
+#[derive(Clone, Debug)]
+pub enum DivergenceKind {
+    /// The runtime refused an event the log says was accepted.
+    Rejected(RuntimeError),
+    /// The instance was not where the log says the step started.
+    SourceMismatch { recorded: Instance, actual: Instance },
+    /// The step was accepted but landed somewhere else.
+    TargetMismatch { recorded: Instance, actual: Instance },
+    /// The log has an induced step the step before it did not cause.
+    NotInduced(String),
+}
+
+
+// ============================================================================
+// Text format
+// ============================================================================
+
+impl Engine {
+    pub fn fmt_event(&self, e: &Event) -> String {
+        match e {
+            Event::Spawn { seq, instance, interface, position, bindings } => format!(
+                "{seq}\tspawn\t{instance}\t{}\t{}\t{}",
+                self.resolve(*interface),
+                self.resolve(*position),
+                self.fmt_binding_column(bindings),
+            ),
+            Event::Step {
+                seq, instance, source, source_bindings, action, args, target, target_bindings, induced,
+            } => format!(
+                "{seq}\t{}\t{instance}\t{}\t{}\t{}\t{}\t{}\t{}",
+                if *induced { "induced" } else { "step" },
+                self.resolve(*source),
+                self.fmt_binding_column(source_bindings),
+                self.resolve(*action),
+                self.fmt_binding_column(args),
+                self.resolve(*target),
+                self.fmt_binding_column(target_bindings),
+            ),
+            Event::Link { seq, defer, source, target } => {
+                format!("{seq}\tlink\t{}\t{source}\t{target}", self.resolve(*defer))
+            }
+            Event::Restore { seq, to } => format!("{seq}\trestore\t{to}"),
+        }
+    }
+
+    fn fmt_binding_column(&self, b: &Bindings) -> String {
+        if b.is_empty() {
+            "[]".to_string()
+        } else {
+            self.fmt_bindings(b)
+        }
+    }
+
+    pub fn parse_trajectory(&self, text: &str) -> Result<Vec<Event>, TrajectoryError> {
+        let mut events = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let err = |message: String| TrajectoryError { line: i + 1, message };
+            if i == 0 {
+                if line != HEADER {
+                    return Err(err(format!("expected `{HEADER}` header")));
+                }
+                continue;
+            }
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            events.push(self.parse_event(line).map_err(err)?);
+        }
+        Ok(events)
+    }
+
+    fn parse_event(&self, line: &str) -> Result<Event, String> {
+        let cols: Vec<&str> = line.split('\t').collect();
+        let seq: u64 = cols[0].parse().map_err(|_| format!("bad sequence number `{}`", cols[0]))?;
+        let name = |s: &str| self.interner.find(s).ok_or_else(|| format!("unknown name `{s}`"));
+        match (cols.get(1).copied(), cols.len()) {
+            (Some("spawn"), 6) => Ok(Event::Spawn {
+                seq,
+                instance: cols[2].to_string(),
+                interface: name(cols[3])?,
+                position: name(cols[4])?,
+                bindings: self.parse_bindings(cols[5])?,
+            }),
+            (Some(kind @ ("step" | "induced")), 9) => Ok(Event::Step {
+                seq,
+                instance: cols[2].to_string(),
+                source: name(cols[3])?,
+                source_bindings: self.parse_bindings(cols[4])?,
+                action: name(cols[5])?,
+                args: self.parse_bindings(cols[6])?,
+                target: name(cols[7])?,
+                target_bindings: self.parse_bindings(cols[8])?,
+                induced: kind == "induced",
+            }),
+            (Some("link"), 5) => Ok(Event::Link {
+                seq,
+                defer: name(cols[2])?,
+                source: cols[3].to_string(),
+                target: cols[4].to_string(),
+            }),
+            (Some("restore"), 3) => {
+                let to = cols[2].parse().map_err(|_| format!("bad sequence number `{}`", cols[2]))?;
+                Ok(Event::Restore { seq, to })
+            }
+            (Some(kind @ ("spawn" | "step" | "induced" | "link" | "restore")), n) => {
+                Err(format!("`{kind}` event has {n} column(s)"))
+            }
+            (kind, _) => Err(format!("unknown event kind `{}`", kind.unwrap_or(""))),
+        }
+    }
+
+    pub fn fmt_divergence(&self, d: &Divergence) -> String {
+        match &d.kind {
+            DivergenceKind::Rejected(e) => {
+                format!("seq {}: rejected: {}", d.seq, self.fmt_runtime_error(e))
+            }
+            DivergenceKind::SourceMismatch { recorded, actual } => format!(
+                "seq {}: recorded step starts at {}, but instance is at {}",
+                d.seq,
+                self.fmt_instance(recorded),
+                self.fmt_instance(actual),
+            ),
+            DivergenceKind::TargetMismatch { recorded, actual } => format!(
+                "seq {}: recorded step ends at {}, replay reached {}",
+                d.seq,
+                self.fmt_instance(recorded),
+                self.fmt_instance(actual),
+            ),
+            DivergenceKind::NotInduced(instance) => {
+                format!("seq {}: recorded induced step on {instance}, but nothing moved it", d.seq)
+            }
+        }
+    }
+}
+
+
+// ============================================================================
+// Replay
+// ============================================================================
+
+impl Runtime {
+    /// Re-run every event through `spawn` / `link` / `apply` / `restore`,
+    /// checking that each step starts and ends where the log says, and that
+    /// each induced step is a move the step before it caused. Stops at the
+    /// first divergence.
+    pub fn replay(&mut self, events: &[Event]) -> Result<(), Divergence> {
+        self.replay_until(events, u64::MAX)
+    }
+
+    /// Replay events with `seq <= until` — the configuration as it stood
+    /// right after that event.
+    pub fn replay_until(&mut self, events: &[Event], until: u64) -> Result<(), Divergence> {
+        // Where the instances the last step induced moves on stood before it.
+        let mut induced: BTreeMap<String, Instance> = BTreeMap::new();
+        for (i, e) in events.iter().enumerate().take_while(|(_, e)| e.seq() <= until) {
+            let diverge = |kind| Divergence { seq: e.seq(), kind };
+            match e {
+                Event::Restore { to, .. } => {
+                    // Rebuild the configuration from the events before `to`
+                    // without recording them again.
+                    let mut past = Runtime::new(self.engine.clone());
+                    let before: Vec<Event> = events[..i].iter().take_while(|e| e.seq() < *to).cloned().collect();
+                    past.replay(&before)?;
+                    let snapshot = Snapshot { at: *to, ..past.snapshot() };
+                    self.restore(snapshot).map_err(|err| diverge(DivergenceKind::Rejected(err)))?;
+                }
+                Event::Spawn { instance, interface, position, bindings, .. } => {
+                    let (i, p) = (self.engine.resolve(*interface), self.engine.resolve(*position));
+                    let (i, p) = (i.to_string(), p.to_string());
+                    self.spawn(instance, &i, &p, bindings.clone())
+                        .map_err(|err| diverge(DivergenceKind::Rejected(err)))?;
+                }
+                Event::Link { defer, source, target, .. } => {
+                    let defer = self.engine.resolve(*defer).to_string();
+                    self.link(&defer, source, target)
+                        .map_err(|err| diverge(DivergenceKind::Rejected(err)))?;
+                }
+                Event::Step {
+                    instance,
+                    source,
+                    source_bindings,
+                    target,
+                    target_bindings,
+                    induced: true,
+                    ..
+                } => {
+                    let actual = induced
+                        .remove(instance)
+                        .ok_or_else(|| diverge(DivergenceKind::NotInduced(instance.clone())))?;
+                    let recorded = Instance {
+                        interface: actual.interface,
+                        position: *source,
+                        bindings: source_bindings.clone(),
+                    };
+                    if recorded != actual {
+                        return Err(diverge(DivergenceKind::SourceMismatch { recorded, actual }));
+                    }
+                    let actual = self.instance(instance).unwrap().clone();
+                    let recorded = Instance {
+                        interface: actual.interface,
+                        position: *target,
+                        bindings: target_bindings.clone(),
+                    };
+                    if recorded != actual {
+                        return Err(diverge(DivergenceKind::TargetMismatch { recorded, actual }));
+                    }
+                }
+                Event::Step {
+                    instance, source, source_bindings, action, args, target, target_bindings, ..
+                } => {
+                    let actual = self
+                        .instance(instance)
+                        .cloned()
+                        .ok_or_else(|| {
+                            diverge(DivergenceKind::Rejected(RuntimeError::UnknownInstance(
+                                instance.clone(),
+                            )))
+                        })?;
+                    let recorded = Instance {
+                        interface: actual.interface,
+                        position: *source,
+                        bindings: source_bindings.clone(),
+                    };
+                    if recorded != actual {
+                        return Err(diverge(DivergenceKind::SourceMismatch { recorded, actual }));
+                    }
+                    let action = self.engine.resolve(*action).to_string();
+                    let before = self.snapshot();
+                    let prop = self
+                        .apply_any(instance, &action, args.clone())
+                        .map_err(|err| diverge(DivergenceKind::Rejected(err)))?;
+                    induced = prop.moves[1..]
+                        .iter()
+                        .map(|m| (m.instance.clone(), before.instances[&m.instance].clone()))
+                        .collect();
+                    let actual = self.instance(instance).unwrap().clone();
+                    let recorded = Instance {
+                        interface: actual.interface,
+                        position: *target,
+                        bindings: target_bindings.clone(),
+                    };
+                    if recorded != actual {
+                        return Err(diverge(DivergenceKind::TargetMismatch { recorded, actual }));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::eval::Value;
+    use super::*;
+
+    fn counter() -> Runtime {
+        let src = std::fs::read_to_string("examples/counter.poly").unwrap();
+        Runtime::new(Engine::load(&src).expect("load counter"))
+    }
+
+    fn recorded_log() -> String {
+        let mut rt = counter();
+        let n = rt.engine.interner.find("n").unwrap();
+        rt.spawn("c", "Counter", "Count", Bindings::from([(n, Value::Int(0))])).unwrap();
+        rt.apply("c", "Increment", Bindings::new()).unwrap();
+        rt.apply("c", "Increment", Bindings::new()).unwrap();
+        rt.apply("c", "Decrement", Bindings::new()).unwrap();
+        let lines: Vec<String> = rt.trajectory().iter().map(|e| rt.engine.fmt_event(e)).collect();
+        format!("{HEADER}\n{}\n", lines.join("\n"))
+    }
+
+    #[test]
+    fn log_round_trips_and_replays() {
+        let log = recorded_log();
+        assert!(log.contains("1\tstep\tc\tCount\t[n=0]\tIncrement\t[]\tCount\t[n=1]\n"), "{log}");
+        let mut rt = counter();
+        let events = rt.engine.parse_trajectory(&log).unwrap();
+        assert_eq!(events.len(), 4);
+        rt.replay(&events).unwrap();
+        assert_eq!(rt.fmt_instances(), "c : Counter.Count[n=1]\n");
+        assert_eq!(rt.trajectory(), &events[..]);
+    }
+
+    #[test]
+    fn replay_until_stops_at_a_sequence_number() {
+        let mut rt = counter();
+        let events = rt.engine.parse_trajectory(&recorded_log()).unwrap();
+        rt.replay_until(&events, 2).unwrap();
+        assert_eq!(rt.fmt_instances(), "c : Counter.Count[n=2]\n");
+    }
+
+    #[test]
+    fn replay_reports_first_divergence() {
+        let log = recorded_log().replace("Count\t[n=2]\n", "Count\t[n=7]\n");
+        let mut rt = counter();
+        let events = rt.engine.parse_trajectory(&log).unwrap();
+        let d = rt.replay(&events).unwrap_err();
+        assert_eq!(
+            rt.engine.fmt_divergence(&d),
+            "seq 2: recorded step ends at Counter.Count[n=7], replay reached Counter.Count[n=2]",
+        );
+    }
+
+    #[test]
+    fn induced_moves_are_logged_and_checked() {
+        let mut rt = counter();
+        let n = rt.engine.interner.find("n").unwrap();
+        rt.spawn("c", "Counter", "Count", Bindings::from([(n, Value::Int(3))])).unwrap();
+        rt.spawn("b", "Button", "Button", Bindings::new()).unwrap();
+        rt.link("SetTo10", "c", "b").unwrap();
+        rt.apply("b", "Press", Bindings::new()).unwrap();
+        let events = rt.trajectory().to_vec();
+        let lines: Vec<String> = events.iter().map(|e| rt.engine.fmt_event(e)).collect();
+        assert_eq!(lines[4], "4\tinduced\tc\tCount\t[n=3]\tPress\t[]\tCount\t[n=10]");
+
+        let mut eng = rt.engine.clone();
+        let mut facts = eng.facts();
+        eng.add_trajectory_facts(&mut facts, &events);
+        let q = eng.parse_query("step(T, c, _, A, _)", &facts).unwrap();
+        let got: Vec<String> = super::super::uquery::run_query(&eng, &facts, &q.query, &Bindings::new())
+            .iter()
+            .map(|a| eng.fmt_answer(a, &q.vars))
+            .collect();
+        assert_eq!(got, ["T = 4, A = Press"]);
+
+        let log = format!("{HEADER}\n{}\n", lines.join("\n"));
+        let mut again = counter();
+        again.replay(&again.engine.parse_trajectory(&log).unwrap()).unwrap();
+        assert_eq!(again.trajectory(), rt.trajectory());
+
+        let unlinked = log.replace("2\tlink\tSetTo10\tc\tb\n", "");
+        let mut again = counter();
+        let d = again.replay(&again.engine.parse_trajectory(&unlinked).unwrap()).unwrap_err();
+        assert_eq!(
+            again.engine.fmt_divergence(&d),
+            "seq 4: recorded induced step on c, but nothing moved it",
+        );
+    }
+
+    #[test]
+    fn restores_are_logged_and_replayed() {
+        let mut rt = counter();
+        let n = rt.engine.interner.find("n").unwrap();
+        rt.spawn("c", "Counter", "Count", Bindings::from([(n, Value::Int(0))])).unwrap();
+        rt.apply("c", "Increment", Bindings::new()).unwrap();
+        let snap = rt.snapshot();
+        rt.apply("c", "Increment", Bindings::new()).unwrap();
+        rt.apply("c", "Increment", Bindings::new()).unwrap();
+        rt.restore(snap).unwrap();
+        rt.apply("c", "Decrement", Bindings::new()).unwrap();
+        let lines: Vec<String> = rt.trajectory().iter().map(|e| rt.engine.fmt_event(e)).collect();
+        assert_eq!(lines[4], "4\trestore\t2");
+
+        let log = format!("{HEADER}\n{}\n", lines.join("\n"));
+        let mut again = counter();
+        let events = again.engine.parse_trajectory(&log).unwrap();
+        again.replay(&events).unwrap();
+        assert_eq!(again.fmt_instances(), "c : Counter.Count[n=0]\n");
+        assert_eq!(again.trajectory(), rt.trajectory());
+    }
+
+    #[test]
+    fn attaching_a_log_continues_it() {
+        let path = std::env::temp_dir().join(format!("poly-log-{}.tsv", std::process::id()));
+        std::fs::write(&path, recorded_log()).unwrap();
+        let mut rt = counter();
+        rt.log_to(&path).unwrap();
+        assert_eq!(rt.fmt_instances(), "c : Counter.Count[n=1]\n");
+        rt.apply("c", "Increment", Bindings::new()).unwrap();
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert!(log.ends_with("3\tstep\tc\tCount\t[n=2]\tDecrement\t[]\tCount\t[n=1]\n\
+                               4\tstep\tc\tCount\t[n=1]\tIncrement\t[]\tCount\t[n=2]\n"), "{log}");
+
+        let mut fresh = counter();
+        fresh.replay(&fresh.engine.parse_trajectory(&log).unwrap()).unwrap();
+        assert_eq!(fresh.fmt_instances(), "c : Counter.Count[n=2]\n");
+        let err = fresh.log_to(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(fresh.engine.fmt_runtime_error(&err).ends_with("cannot continue a log on a runtime that already has events"));
+    }
+
+    #[test]
+    fn parse_errors_carry_line_numbers() {
+        let rt = counter();
+        let log = format!("{HEADER}\n0\tspawn\tc\tCounter\tCount\t[n=0]\n1\tstep\tc\tCount\n");
+        let err = rt.engine.parse_trajectory(&log).unwrap_err();
+        assert_eq!((err.line, err.message.as_str()), (3, "`step` event has 4 column(s)"));
+    }
+}
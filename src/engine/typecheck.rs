@@ -0,0 +1,745 @@
+use std::collections::BTreeMap;
+
+use super::validate::ValidationError;
+use super::*;
+
+
+// ============================================================================
+// Static type checking
+// ============================================================================
+//
+// `eval` only notices ill-typed expressions when it happens to run them. This
+// pass infers a `Type<Sym>` for every expression in the engine ahead of time,
+// in the scope the expression is evaluated in:
+//
+//   - position guards see interface params + position params;
+//   - direction guards and transition args add the direction's params;
+//   - defer entry guards and target args see the source interface params plus
+//     the entry's source-pattern binders;
+//   - abstract direction refs add their own pattern binders and the params of
+//     the direction on the other side of the mapping (the action that fires
+//     the transition);
+//   - invariants see interface params + the params of each position they
+//     apply at, and must mention only names some position binds;
+//   - agent prompt holes see interface params + the bound position's params,
+//     and may have any type.
+//
+// Errors are reported as `ValidationError::IllTyped`, so `Engine::load`
+// rejects ill-typed files the same way it rejects malformed defers. Each
+// underlines the subexpression at fault, found through the `ExprSpans` the
+// parser keeps beside every expression; generated expressions have none and
+// fall back to the span of their declaration.
+
+pub type TypeEnv = BTreeMap<Sym, Type<Sym>>;
+
+/// The variables `params` bring into scope, a later param shadowing an
+/// earlier one of the same name: chain an interface's, a position's and a
+/// direction's params for the scope of that direction's guard.
+pub fn scope<'a>(params: impl IntoIterator<Item = &'a Param<Sym>>) -> TypeEnv {
+    params.into_iter().map(|p| (p.name, p.ty.clone())).collect()
+}
+
+#[derive(Clone, Debug)]
+pub enum TypeError {
+    Unbound(Sym),
+    UnknownSchema(Sym),
+    Mismatch { expected: Type<Sym>, found: Type<Sym> },
+    NotARecord(Type<Sym>),
+    UnknownField { schema: Sym, field: Sym },
+    ConstructArity { schema: Sym, expected: usize, got: usize },
+    NotASum(Type<Sym>),
+    NotARecordSchema(Sym),
+    UnknownVariant { schema: Sym, variant: Sym },
+}
+
+/// Where an ill-typed expression sits, for error messages.
+#[derive(Clone, Debug)]
+pub enum TypeSite {
+    PositionGuard { interface: Sym, position: Sym },
+    DirectionGuard { interface: Sym, position: Sym, action: Sym },
+    /// `param` of `target`, entered by `action` from `position`.
+    TransitionArg { interface: Sym, position: Sym, action: Sym, target: Sym, param: Sym },
+    DeferGuard { defer: Sym, position: Sym },
+    DeferTargetArg { defer: Sym, position: Sym, param: Sym },
+    AbstractArg { defer: Sym, position: Sym, param: Sym },
+    Invariant { interface: Sym },
+    PromptHole { agent: Sym },
+}
+
+
+// ============================================================================
+// Inference
+// ============================================================================
+
+impl Engine {
+    pub fn infer_type(&self, e: &Expr<Sym>, env: &TypeEnv) -> Result<Type<Sym>, TypeError> {
+        self.infer_at(e, env, &mut Vec::new())
+    }
+
+    /// `infer_type`, leaving in `at` the path (child indices, as in
+    /// `ExprSpans`) from `e` to the subexpression an error is about.
+    fn infer_at(&self, e: &Expr<Sym>, env: &TypeEnv, at: &mut Vec<usize>) -> Result<Type<Sym>, TypeError> {
+        use BinOp::*;
+        match e {
+            Expr::LitInt(_) => Ok(Type::Int),
+            Expr::LitFloat(_) => Ok(Type::Float),
+            Expr::LitStr(_) => Ok(Type::Str),
+            Expr::LitBool(_) => Ok(Type::Bool),
+            Expr::Var(s) => env.get(s).cloned().ok_or(TypeError::Unbound(*s)),
+            Expr::Field(base, field) => {
+                let ty = self.infer_child(0, base, env, at)?;
+                let Type::Named(schema) = ty else {
+                    at.push(0);
+                    return Err(TypeError::NotARecord(ty));
+                };
+                let s = self.schemas.get(&schema).ok_or(TypeError::UnknownSchema(schema))?;
+                let SchemaBody::Record(fields) = &s.body else {
+                    at.push(0);
+                    return Err(TypeError::NotARecord(Type::Named(schema)));
+                };
+                fields
+                    .iter()
+                    .find(|p| p.name == *field)
+                    .map(|p| p.ty.clone())
+                    .ok_or(TypeError::UnknownField { schema, field: *field })
+            }
+            Expr::Construct(name, args) => {
+                let s = self.schemas.get(name).ok_or(TypeError::UnknownSchema(*name))?;
+                let SchemaBody::Record(params) = &s.body else {
+                    return Err(TypeError::NotARecordSchema(*name));
+                };
+                if params.len() != args.len() {
+                    return Err(TypeError::ConstructArity {
+                        schema: *name,
+                        expected: params.len(),
+                        got: args.len(),
+                    });
+                }
+                for (i, (p, a)) in params.iter().zip(args).enumerate() {
+                    self.expect_child(i, a, &p.ty, env, at)?;
+                }
+                Ok(Type::Named(*name))
+            }
+            Expr::Variant(name, variant, args) => {
+                let s = self.schemas.get(name).ok_or(TypeError::UnknownSchema(*name))?;
+                let SchemaBody::Sum(variants) = &s.body else {
+                    return Err(TypeError::NotASum(Type::Named(*name)));
+                };
+                let v = variants
+                    .iter()
+                    .find(|v| v.name == *variant)
+                    .ok_or(TypeError::UnknownVariant { schema: *name, variant: *variant })?;
+                if v.params.len() != args.len() {
+                    return Err(TypeError::ConstructArity {
+                        schema: *name,
+                        expected: v.params.len(),
+                        got: args.len(),
+                    });
+                }
+                for (i, (p, a)) in v.params.iter().zip(args).enumerate() {
+                    self.expect_child(i, a, &p.ty, env, at)?;
+                }
+                Ok(Type::Named(*name))
+            }
+            Expr::Is(inner, variant) => {
+                let ty = self.infer_child(0, inner, env, at)?;
+                let Type::Named(schema) = ty else {
+                    at.push(0);
+                    return Err(TypeError::NotASum(ty));
+                };
+                let s = self.schemas.get(&schema).ok_or(TypeError::UnknownSchema(schema))?;
+                let SchemaBody::Sum(variants) = &s.body else {
+                    at.push(0);
+                    return Err(TypeError::NotASum(Type::Named(schema)));
+                };
+                if !variants.iter().any(|v| v.name == *variant) {
+                    return Err(TypeError::UnknownVariant { schema, variant: *variant });
+                }
+                Ok(Type::Bool)
+            }
+            Expr::UnOp(UnOp::Neg, inner) => match self.infer_child(0, inner, env, at)? {
+                Type::Float => Ok(Type::Float),
+                found => {
+                    at.push(0);
+                    expect_found(found, &Type::Int)?;
+                    at.pop();
+                    Ok(Type::Int)
+                }
+            },
+            Expr::UnOp(UnOp::Not, inner) => {
+                self.expect_child(0, inner, &Type::Bool, env, at)?;
+                Ok(Type::Bool)
+            }
+            Expr::BinOp(op, l, r) => match op {
+                Mod => {
+                    self.expect_child(0, l, &Type::Int, env, at)?;
+                    self.expect_child(1, r, &Type::Int, env, at)?;
+                    Ok(Type::Int)
+                }
+                Add | Sub | Mul | Div => self.infer_numeric(l, r, env, at),
+                Lt | Le | Gt | Ge => {
+                    self.infer_numeric(l, r, env, at)?;
+                    Ok(Type::Bool)
+                }
+                Eq | Neq => {
+                    let lt = self.infer_child(0, l, env, at)?;
+                    self.expect_child(1, r, &lt, env, at)?;
+                    Ok(Type::Bool)
+                }
+                And | Or => {
+                    self.expect_child(0, l, &Type::Bool, env, at)?;
+                    self.expect_child(1, r, &Type::Bool, env, at)?;
+                    Ok(Type::Bool)
+                }
+            },
+        }
+    }
+
+    fn infer_child(
+        &self,
+        i: usize,
+        e: &Expr<Sym>,
+        env: &TypeEnv,
+        at: &mut Vec<usize>,
+    ) -> Result<Type<Sym>, TypeError> {
+        at.push(i);
+        let ty = self.infer_at(e, env, at)?;
+        at.pop();
+        Ok(ty)
+    }
+
+    fn expect_child(
+        &self,
+        i: usize,
+        e: &Expr<Sym>,
+        expected: &Type<Sym>,
+        env: &TypeEnv,
+        at: &mut Vec<usize>,
+    ) -> Result<(), TypeError> {
+        at.push(i);
+        expect_found(self.infer_at(e, env, at)?, expected)?;
+        at.pop();
+        Ok(())
+    }
+
+    /// Both sides `Int` or both `Float`; never mixed.
+    fn infer_numeric(
+        &self,
+        l: &Expr<Sym>,
+        r: &Expr<Sym>,
+        env: &TypeEnv,
+        at: &mut Vec<usize>,
+    ) -> Result<Type<Sym>, TypeError> {
+        let ty = match self.infer_child(0, l, env, at)? {
+            Type::Float => Type::Float,
+            found => {
+                at.push(0);
+                expect_found(found, &Type::Int)?;
+                at.pop();
+                Type::Int
+            }
+        };
+        self.expect_child(1, r, &ty, env, at)?;
+        Ok(ty)
+    }
+}
+
+fn expect_found(found: Type<Sym>, expected: &Type<Sym>) -> Result<(), TypeError> {
+    if &found != expected {
+        return Err(TypeError::Mismatch { expected: expected.clone(), found });
+    }
+    Ok(())
+}
+
+
+// ============================================================================
+// Whole-engine pass
+// ============================================================================
+
+impl Engine {
+    pub fn typecheck(&self) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        self.check_param_types(&mut errors);
+        for iface in self.interfaces.values() {
+            self.typecheck_signature(Signature::Interface(iface), &mut errors);
+        }
+        for s in self.states.values().filter(|s| !s.elaborated) {
+            self.typecheck_signature(Signature::State(s), &mut errors);
+        }
+        for d in &self.defers {
+            self.typecheck_defer(d, &mut errors);
+        }
+        for inv in &self.invariants {
+            self.typecheck_invariant(inv, &mut errors);
+        }
+        for a in self.agents.values() {
+            self.typecheck_agent(a, &mut errors);
+        }
+        errors
+    }
+
+    fn check_param_types(&self, errors: &mut Vec<ValidationError>) {
+        let mut check = |params: &[Param<Sym>], span: &Span| {
+            for p in params {
+                if let Type::Named(s) = p.ty {
+                    if !self.schemas.contains_key(&s) {
+                        errors.push(ValidationError::UnknownType { name: s, span: span.clone() });
+                    }
+                }
+            }
+        };
+        for s in self.schemas.values() {
+            match &s.body {
+                SchemaBody::Record(fields) => check(fields, &s.span),
+                SchemaBody::Sum(variants) => {
+                    for v in variants {
+                        check(&v.params, &s.span);
+                    }
+                }
+            }
+        }
+        for iface in self.interfaces.values() {
+            check(&iface.params, &iface.span);
+            for pos in &iface.positions {
+                check(&pos.params, &pos.span);
+                for dir in &pos.directions {
+                    check(&dir.params, &dir.span);
+                }
+            }
+        }
+        // An elaborated state block shares its interface's params, and
+        // checking both would report every error twice.
+        for s in self.states.values().filter(|s| !s.elaborated) {
+            check(&s.params, &s.span);
+            for pos in &s.positions {
+                check(&pos.params, &pos.span);
+            }
+        }
+    }
+
+
+    fn typecheck_signature(&self, iface: Signature<'_, Sym>, errors: &mut Vec<ValidationError>) {
+        let name = *iface.name();
+        for pos in iface.positions() {
+            let mut env = TypeEnv::new();
+            extend_env(&mut env, iface.params());
+            extend_env(&mut env, &pos.params);
+            if let Some(g) = &pos.guard {
+                let site = TypeSite::PositionGuard { interface: name, position: pos.name };
+                errors.extend(self.check_against(g, pos.guard_spans.as_ref(), &Type::Bool, &env, site, &pos.span));
+            }
+            for dir in &pos.directions {
+                let mut env = env.clone();
+                extend_env(&mut env, &dir.params);
+                if let Some(g) = &dir.guard {
+                    let site = TypeSite::DirectionGuard {
+                        interface: name,
+                        position: pos.name,
+                        action: dir.name,
+                    };
+                    errors.extend(self.check_against(g, dir.guard_spans.as_ref(), &Type::Bool, &env, site, &dir.span));
+                }
+                let Some(t) = &dir.transition else { continue };
+                let Some(target) = iface.position(&t.target_pos) else { continue };
+                for (i, (param, arg)) in target.params.iter().zip(&t.args).enumerate() {
+                    let site = TypeSite::TransitionArg {
+                        interface: name,
+                        position: pos.name,
+                        action: dir.name,
+                        target: t.target_pos,
+                        param: param.name,
+                    };
+                    errors.extend(self.check_against(arg, t.arg_spans.get(i), &param.ty, &env, site, &t.span));
+                }
+            }
+        }
+    }
+
+    fn typecheck_invariant(&self, inv: &Invariant<Sym>, errors: &mut Vec<ValidationError>) {
+        let Some(iface) = self.interfaces.get(&inv.interface) else { return };
+        let site = || TypeSite::Invariant { interface: inv.interface };
+        let mut applied = false;
+        for pos in iface.positions.iter().filter(|p| self.invariant_applies(inv, iface, p)) {
+            applied = true;
+            let mut env = TypeEnv::new();
+            extend_env(&mut env, &iface.params);
+            extend_env(&mut env, &pos.params);
+            errors.extend(self.check_against(&inv.expr, Some(&inv.expr_spans), &Type::Bool, &env, site(), &inv.span));
+        }
+        if !applied {
+            // Reports the first name no position binds.
+            let mut env = TypeEnv::new();
+            extend_env(&mut env, &iface.params);
+            errors.extend(self.check_against(&inv.expr, Some(&inv.expr_spans), &Type::Bool, &env, site(), &inv.span));
+        }
+    }
+
+    fn typecheck_agent(&self, a: &Agent<Sym>, errors: &mut Vec<ValidationError>) {
+        let Some(iface) = self.interfaces.get(&a.interface) else { return };
+        let Some(pos) = iface.position(&a.position) else { return };
+        let mut env = TypeEnv::new();
+        extend_env(&mut env, &iface.params);
+        extend_env(&mut env, &pos.params);
+        for seg in &a.template {
+            if let Segment::Hole(e, span) = seg {
+                if let Err(error) = self.infer_type(e, &env) {
+                    let site = TypeSite::PromptHole { agent: a.name };
+                    errors.push(ValidationError::IllTyped { site, error, span: span.clone() });
+                }
+            }
+        }
+    }
+
+    fn typecheck_defer(&self, d: &Defer<Sym>, errors: &mut Vec<ValidationError>) {
+        let (Some(src), Some(tgt)) = (self.signature(d.source), self.signature(d.target))
+        else {
+            return;
+        };
+        // Its abstract directions are transitions written on the interface.
+        let elaborated = self.elaborated_defer(d.target).is_some_and(|r| r.name == d.name);
+        for entry in &d.entries {
+            let (Some(src_pos), Some(tgt_pos)) =
+                (src.position(&entry.source_pos), tgt.position(&entry.target_pos))
+            else {
+                continue;
+            };
+            let mut env = TypeEnv::new();
+            extend_env(&mut env, src.params());
+            bind_pattern_types(&mut env, &src_pos.params, &entry.source_pattern);
+
+            if let Some(g) = &entry.source_guard {
+                let site = TypeSite::DeferGuard { defer: d.name, position: entry.source_pos };
+                errors.extend(self.check_against(g, entry.guard_spans.as_ref(), &Type::Bool, &env, site, &entry.span));
+            }
+            for (i, (param, arg)) in tgt_pos.params.iter().zip(&entry.target_args).enumerate() {
+                let site = TypeSite::DeferTargetArg {
+                    defer: d.name,
+                    position: entry.target_pos,
+                    param: param.name,
+                };
+                errors.extend(self.check_against(arg, entry.arg_spans.get(i), &param.ty, &env, site, &entry.span));
+            }
+
+            for m in &entry.directions {
+                let sides = [
+                    (&m.source_dir, src, &m.target_dir, tgt_pos, &env),
+                    (&m.target_dir, tgt, &m.source_dir, src_pos, &TypeEnv::new()),
+                ];
+                for (dir_ref, iface, other_ref, other_pos, base) in sides {
+                    let DirRef::Abstract { src_pos, src_pattern, tgt_pos, tgt_args, arg_spans } = dir_ref
+                    else {
+                        continue;
+                    };
+                    let (Some(from), Some(to)) = (iface.position(src_pos), iface.position(tgt_pos))
+                    else {
+                        continue;
+                    };
+                    let mut env = base.clone();
+                    extend_env(&mut env, iface.params());
+                    bind_pattern_types(&mut env, &from.params, src_pattern);
+                    if let DirRef::Named(action) = other_ref {
+                        if let Some(dir) = other_pos.directions.iter().find(|x| x.name == *action) {
+                            extend_env(&mut env, &dir.params);
+                        }
+                    }
+                    for (i, (param, arg)) in to.params.iter().zip(tgt_args).enumerate() {
+                        let site = match other_ref {
+                            // Report a transition against the direction it was written on.
+                            DirRef::Named(action) if elaborated => TypeSite::TransitionArg {
+                                interface: d.target,
+                                position: *src_pos,
+                                action: *action,
+                                target: *tgt_pos,
+                                param: param.name,
+                            },
+                            _ => TypeSite::AbstractArg { defer: d.name, position: *tgt_pos, param: param.name },
+                        };
+                        errors.extend(self.check_against(arg, arg_spans.get(i), &param.ty, &env, site, &m.span));
+                    }
+                }
+            }
+        }
+    }
+
+    /// An error unless `e` has type `expected`. The error underlines the
+    /// offending subexpression when `spans` locates it, else `span`, the
+    /// declaration the expression sits in.
+    fn check_against(
+        &self,
+        e: &Expr<Sym>,
+        spans: Option<&ExprSpans>,
+        expected: &Type<Sym>,
+        env: &TypeEnv,
+        site: TypeSite,
+        span: &Span,
+    ) -> Option<ValidationError> {
+        let mut at = Vec::new();
+        let error = self.expect_child(0, e, expected, env, &mut at).err()?;
+        let span = spans.and_then(|s| s.at(&at[1..])).unwrap_or(span).clone();
+        Some(ValidationError::IllTyped { site, error, span })
+    }
+}
+
+fn extend_env(env: &mut TypeEnv, params: &[Param<Sym>]) {
+    for p in params {
+        env.insert(p.name, p.ty.clone());
+    }
+}
+
+fn bind_pattern_types(env: &mut TypeEnv, params: &[Param<Sym>], pattern: &[Pattern<Sym>]) {
+    for (p, pat) in params.iter().zip(pattern) {
+        if let Pattern::Bind(name) = pat {
+            env.insert(*name, p.ty.clone());
+        }
+    }
+}
+
+
+// ============================================================================
+// Display
+// ============================================================================
+
+impl Engine {
+    pub fn fmt_type_error(&self, e: &TypeError) -> String {
+        match e {
+            TypeError::Unbound(s) => format!("unbound variable `{}`", self.resolve(*s)),
+            TypeError::UnknownSchema(s) => format!("unknown schema `{}`", self.resolve(*s)),
+            TypeError::Mismatch { expected, found } => format!(
+                "expected {}, found {}",
+                self.fmt_type(expected),
+                self.fmt_type(found),
+            ),
+            TypeError::NotARecord(ty) => {
+                format!("field access on non-record type {}", self.fmt_type(ty))
+            }
+            TypeError::UnknownField { schema, field } => format!(
+                "schema {} has no field `{}`",
+                self.resolve(*schema),
+                self.resolve(*field),
+            ),
+            TypeError::ConstructArity { schema, expected, got } => format!(
+                "constructor {} has {} arg(s), expected {}",
+                self.resolve(*schema),
+                got,
+                expected,
+            ),
+            TypeError::NotASum(ty) => {
+                format!("`is` test on non-sum type {}", self.fmt_type(ty))
+            }
+            TypeError::NotARecordSchema(s) => format!(
+                "{} is a sum schema; construct it with {}::<Variant>",
+                self.resolve(*s),
+                self.resolve(*s),
+            ),
+            TypeError::UnknownVariant { schema, variant } => format!(
+                "schema {} has no variant {}",
+                self.resolve(*schema),
+                self.resolve(*variant),
+            ),
+        }
+    }
+
+    pub fn fmt_type_site(&self, site: &TypeSite) -> String {
+        match site {
+            TypeSite::PositionGuard { interface, position } => format!(
+                "guard of {}.{}",
+                self.resolve(*interface),
+                self.resolve(*position),
+            ),
+            TypeSite::DirectionGuard { interface, position, action } => format!(
+                "guard of {}.{}.{}",
+                self.resolve(*interface),
+                self.resolve(*position),
+                self.resolve(*action),
+            ),
+            TypeSite::TransitionArg { interface, position, action, target, param } => format!(
+                "argument `{}` of {} in {}.{}.{}",
+                self.resolve(*param),
+                self.resolve(*target),
+                self.resolve(*interface),
+                self.resolve(*position),
+                self.resolve(*action),
+            ),
+            TypeSite::DeferGuard { defer, position } => format!(
+                "defer {}: guard on {}",
+                self.resolve(*defer),
+                self.resolve(*position),
+            ),
+            TypeSite::Invariant { interface } => {
+                format!("invariant on {}", self.resolve(*interface))
+            }
+            TypeSite::PromptHole { agent } => format!("agent {}", self.resolve(*agent)),
+            TypeSite::DeferTargetArg { defer, position, param }
+            | TypeSite::AbstractArg { defer, position, param } => format!(
+                "defer {}: argument `{}` of {}",
+                self.resolve(*defer),
+                self.resolve(*param),
+                self.resolve(*position),
+            ),
+        }
+    }
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::super::eval::{Bindings, Value};
+    use super::*;
+
+    fn errors(src: &str) -> Vec<String> {
+        match Engine::load(src) {
+            Ok(_) => Vec::new(),
+            Err(EngineError::Validate(diags)) => diags.into_iter().map(|d| d.message).collect(),
+            Err(EngineError::Parse(diags)) => panic!("parse error: {diags:?}"),
+        }
+    }
+
+    #[test]
+    fn examples_typecheck() {
+        for path in ["examples/counter.poly", "examples/grid.poly", "examples/task_router.poly"] {
+            let src = std::fs::read_to_string(path).unwrap();
+            assert!(errors(&src).is_empty(), "{path}: {:?}", errors(&src));
+        }
+    }
+
+    #[test]
+    fn arithmetic_on_strings_is_rejected() {
+        let errs = errors(
+            "interface Counter
+                Count[n: Int] { Increment -> Count[n + \"x\"] }",
+        );
+        assert_eq!(errs, vec!["argument `n` of Count in Counter.Count.Increment: expected Int, found String"]);
+    }
+
+    #[test]
+    fn unknown_field_is_rejected() {
+        let errs = errors(
+            "schema Coordinate
+                x: Int,
+                y: Int
+            interface Grid
+                Cell[c: Coordinate] if (c.z > 0) { Stay }",
+        );
+        assert_eq!(errs, vec!["guard of Grid.Cell: schema Coordinate has no field `z`"]);
+    }
+
+    #[test]
+    fn non_bool_guard_is_rejected() {
+        let errs = errors(
+            "interface Counter
+                Count[n: Int] { Decrement if (n - 1) -> Count[n - 1] }",
+        );
+        assert_eq!(errs, vec!["guard of Counter.Count.Decrement: expected Bool, found Int"]);
+    }
+
+    #[test]
+    fn errors_underline_the_ill_typed_subexpression() {
+        let src = "schema Coordinate
+                x: Int,
+                y: Int
+            interface Grid
+                Cell[c: Coordinate] if (c.x > 0 and (c.z == 1)) {
+                    Move -> Cell[Coordinate(c.x + 1, \"up\")]
+                }
+            invariant Grid: not (c.y - true > 0)";
+        let Err(EngineError::Validate(diags)) = Engine::load(src) else { panic!("expected errors") };
+        let underlined: Vec<&str> = diags.iter().map(|d| &src[d.span.clone()]).collect();
+        assert_eq!(underlined, vec!["c.z", "\"up\"", "true"]);
+    }
+
+    #[test]
+    fn direction_params_are_in_scope_for_transition_args() {
+        let errs = errors(
+            "interface Loop
+                Working[question: String] { Answer[response: String] -> Done[response] },
+                Done[response: String]",
+        );
+        assert!(errs.is_empty(), "{errs:?}");
+        let errs = errors(
+            "interface Loop
+                Working[question: String] { Answer[response: Int] -> Done[response] },
+                Done[response: String]",
+        );
+        assert_eq!(
+            errs,
+            vec!["argument `response` of Done in Loop.Working.Answer: expected String, found Int"],
+        );
+    }
+
+    const LOCK: &str = "state Lock::Bolt
+            Locked[tries: Int] if (tries >= 0),
+            Open
+        interface Lock
+            Locked[tries: Int] { Unlock, Kick },
+            Open { Close }
+        defer Turn : Lock::Bolt -> Lock
+            Locked[t] -> Locked[t] {
+                Unlock -> Locked[t] => Open,
+                Kick -> Locked[t] => Locked[t + 1]
+            },
+            Open -> Open {
+                Close -> Open => Locked[0]
+            }";
+
+    #[test]
+    fn state_blocks_realize_interfaces() {
+        let eng = Engine::load(LOCK).unwrap_or_else(|e| panic!("{e:?}"));
+        let bolt = eng.interner.find("Lock::Bolt").unwrap();
+        assert!(eng.is_state_of(bolt, eng.interner.find("Lock").unwrap()));
+        assert!(eng.fmt_state_block(&eng.states[&bolt]).starts_with("state Lock::Bolt\n    Locked[tries: Int] if (tries >= 0),\n"));
+
+        let tries = eng.interner.find("tries").unwrap();
+        let step = eng.next_position("Lock", "Locked", "Kick", Bindings::from([(tries, Value::Int(2))])).unwrap();
+        assert_eq!(eng.fmt_step(&step), "Lock.Locked[tries=2] --Kick--> Lock.Locked[tries=3]\n");
+
+        let errs = errors(&LOCK.replace("Locked[t + 1]", "Locked[\"x\"]"));
+        assert_eq!(errs, vec!["defer Turn: argument `tries` of Locked: expected Int, found String"]);
+    }
+
+    #[test]
+    fn elaborated_state_blocks_are_flagged() {
+        let eng = Engine::load(LOCK).unwrap_or_else(|e| panic!("{e:?}"));
+        assert!(!eng.states[&eng.interner.find("Lock::Bolt").unwrap()].elaborated);
+        assert!(eng.elaborated_defer(eng.interner.find("Lock").unwrap()).is_none());
+        let src = std::fs::read_to_string("examples/counter.poly").expect("read counter");
+        let eng = Engine::load(&src).expect("load counter");
+        assert!(eng.states[&eng.interner.find("Counter::Internal").unwrap()].elaborated);
+        let run = eng.elaborated_defer(eng.interner.find("Counter").unwrap()).unwrap();
+        assert_eq!(eng.resolve(run.name), "Counter::Run");
+
+        // A hand-written block's guard is checked on the block; an elaborated
+        // one's only on its interface.
+        let errs = errors(&LOCK.replace("if (tries >= 0)", "if (tries)"));
+        assert_eq!(errs, vec!["guard of Lock::Bolt.Locked: expected Bool, found Int"]);
+        let errs = errors(
+            "interface Counter
+                Count[n: Int] if (n) { Increment -> Count[n + 1] }",
+        );
+        assert_eq!(errs, vec!["guard of Counter.Count: expected Bool, found Int"]);
+    }
+
+    #[test]
+    fn abstract_refs_need_a_state_source() {
+        let errs = errors(&LOCK.replace("state Lock::Bolt", "interface Bolt").replace("Lock::Bolt ->", "Bolt ->"));
+        assert!(
+            errs.contains(&"defer Turn: abstract direction ref is not valid here; it needs a `state` block, but `Bolt` is an `interface`".to_string()),
+            "{errs:?}",
+        );
+    }
+
+    #[test]
+    fn is_checks_the_variant_exists() {
+        let errs = errors(
+            "schema Priority
+                Low,
+                High
+            interface Queue
+                Waiting[p: Priority] { Go if (p is Medium) }",
+        );
+        assert_eq!(errs, vec!["guard of Queue.Waiting.Go: schema Priority has no variant Medium"]);
+    }
+}
@@ -1,3 +1,13 @@
+// ============================================================================
+// Source locations
+// ============================================================================
+
+/// Byte range into the source text a declaration was parsed from.
+/// Sugar-derived declarations reuse the span of the construct they were
+/// generated from.
+pub type Span = std::ops::Range<usize>;
+
+
 // ============================================================================
 // Types and parameters
 // ============================================================================
@@ -5,11 +15,25 @@
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Type<T> {
     Int,
+    Float,
     Str,
     Bool,
     Named(T),
 }
 
+/// A `Float` literal or value. Compared bit for bit, so that expressions
+/// and values stay `Eq`; arithmetic comparisons go through `f64` instead.
+#[derive(Clone, Copy, Debug)]
+pub struct Float(pub f64);
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Float {}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Param<T> {
     pub name: T,
@@ -24,6 +48,7 @@ pub struct Param<T> {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Expr<T> {
     LitInt(i64),
+    LitFloat(Float),
     LitStr(String),
     LitBool(bool),
     Var(T),
@@ -31,6 +56,10 @@ pub enum Expr<T> {
     BinOp(BinOp, Box<Expr<T>>, Box<Expr<T>>),
     UnOp(UnOp, Box<Expr<T>>),
     Construct(T, Vec<Expr<T>>),
+    /// `Schema::Variant` or `Schema::Variant(args)` for a sum schema.
+    Variant(T, T, Vec<Expr<T>>),
+    /// `e is Variant` — true when `e` evaluates to that variant of its sum.
+    Is(Box<Expr<T>>, T),
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -43,6 +72,29 @@ pub enum BinOp {
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum UnOp { Neg, Not }
 
+/// Where a parsed expression and each of its subexpressions sit in the
+/// source, in the same shape as the `Expr`: `children` follow the order the
+/// node holds its operands (`l` then `r`, args left to right, a field's
+/// base). Kept beside the expression rather than in it, so the rewriting
+/// passes can build and compare expressions without them; generated
+/// expressions have none.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExprSpans {
+    pub span: Span,
+    pub children: Vec<ExprSpans>,
+}
+
+impl ExprSpans {
+    /// The span of the subexpression reached by `path`, child index by
+    /// child index.
+    pub fn at(&self, path: &[usize]) -> Option<&Span> {
+        match path.split_first() {
+            None => Some(&self.span),
+            Some((i, rest)) => self.children.get(*i)?.at(rest),
+        }
+    }
+}
+
 
 // ============================================================================
 // Schema declarations
@@ -52,6 +104,7 @@ pub enum UnOp { Neg, Not }
 pub struct Schema<T> {
     pub name: T,
     pub body: SchemaBody<T>,
+    pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -73,17 +126,27 @@ pub struct Variant<T> {
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Interface<T> {
+    /// Marked `external`: the host supplies its actions (see `host.rs`).
+    pub external: bool,
+    /// Declared with `tool`: a call's result depends only on its args, so the
+    /// runtime may cache and retry it.
+    pub deterministic: bool,
     pub name: T,
     pub params: Vec<Param<T>>,
     pub positions: Vec<Position<T>>,
+    pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Position<T> {
+    /// Marked `terminal`: having no enabled direction here is intended.
+    pub terminal: bool,
     pub name: T,
     pub params: Vec<Param<T>>,
     pub guard: Option<Expr<T>>,
+    pub guard_spans: Option<ExprSpans>,
     pub directions: Vec<Direction<T>>,
+    pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -91,13 +154,17 @@ pub struct Direction<T> {
     pub name: T,
     pub params: Vec<Param<T>>,
     pub guard: Option<Expr<T>>,
+    pub guard_spans: Option<ExprSpans>,
     pub transition: Option<Transition<T>>,
+    pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Transition<T> {
     pub target_pos: T,
     pub args: Vec<Expr<T>>,
+    pub arg_spans: Vec<ExprSpans>,
+    pub span: Span,
 }
 
 impl<T> Interface<T> {
@@ -118,6 +185,69 @@ impl<T: PartialEq> Interface<T> {
 }
 
 
+// ============================================================================
+// State declarations
+// ============================================================================
+
+/// `state Name[params]`: positions whose directions are implicitly every
+/// transition between them, constrained by the position guards. Defers out of
+/// a state block may use abstract direction refs (`P[x] => Q[x + 1]`). An
+/// interface with transitions elaborates to one named `<Interface>::Internal`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StateBlock<T> {
+    pub name: T,
+    pub params: Vec<Param<T>>,
+    /// Each with no directions.
+    pub positions: Vec<Position<T>>,
+    /// Elaborated from an interface's transitions, not written as `state`.
+    pub elaborated: bool,
+    pub span: Span,
+}
+
+/// Either end of a defer: an interface or a state block.
+#[derive(Clone, Copy, Debug)]
+pub enum Signature<'a, T> {
+    Interface(&'a Interface<T>),
+    State(&'a StateBlock<T>),
+}
+
+impl<'a, T: PartialEq> Signature<'a, T> {
+    pub fn name(&self) -> &'a T {
+        match self {
+            Signature::Interface(i) => &i.name,
+            Signature::State(s) => &s.name,
+        }
+    }
+
+    pub fn params(&self) -> &'a [Param<T>] {
+        match self {
+            Signature::Interface(i) => &i.params,
+            Signature::State(s) => &s.params,
+        }
+    }
+
+    pub fn positions(&self) -> &'a [Position<T>] {
+        match self {
+            Signature::Interface(i) => &i.positions,
+            Signature::State(s) => &s.positions,
+        }
+    }
+
+    pub fn position(&self, name: &T) -> Option<&'a Position<T>> {
+        self.positions().iter().find(|p| &p.name == name)
+    }
+
+    pub fn is_parameterized(&self) -> bool {
+        match self {
+            Signature::Interface(i) => i.is_parameterized(),
+            Signature::State(s) => {
+                !s.params.is_empty() || s.positions.iter().any(|p| !p.params.is_empty() || p.guard.is_some())
+            }
+        }
+    }
+}
+
+
 // ============================================================================
 // Defer declarations
 // ============================================================================
@@ -128,6 +258,7 @@ pub struct Defer<T> {
     pub source: T,
     pub target: T,
     pub entries: Vec<DeferEntry<T>>,
+    pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -135,9 +266,12 @@ pub struct DeferEntry<T> {
     pub source_pos: T,
     pub source_pattern: Vec<Pattern<T>>,
     pub source_guard: Option<Expr<T>>,
+    pub guard_spans: Option<ExprSpans>,
     pub target_pos: T,
     pub target_args: Vec<Expr<T>>,
+    pub arg_spans: Vec<ExprSpans>,
     pub directions: Vec<DirMapping<T>>,
+    pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -150,6 +284,7 @@ pub enum Pattern<T> {
 pub struct DirMapping<T> {
     pub target_dir: DirRef<T>,
     pub source_dir: DirRef<T>,
+    pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -160,10 +295,77 @@ pub enum DirRef<T> {
         src_pattern: Vec<Pattern<T>>,
         tgt_pos: T,
         tgt_args: Vec<Expr<T>>,
+        arg_spans: Vec<ExprSpans>,
     },
 }
 
 
+// ============================================================================
+// Invariant declarations
+// ============================================================================
+
+/// `invariant Iface: expr`. Applies at every position of `Iface` whose params,
+/// together with the interface params, bind all of `expr`'s variables.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Invariant<T> {
+    pub interface: T,
+    pub expr: Expr<T>,
+    pub expr_spans: ExprSpans,
+    pub span: Span,
+}
+
+
+// ============================================================================
+// Agent declarations
+// ============================================================================
+
+/// `agent Name : Iface.Position """template"""`. Whoever fills `Iface` at
+/// `Position` is prompted with the template, filled from the instance's
+/// bindings.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Agent<T> {
+    pub name: T,
+    pub interface: T,
+    pub position: T,
+    pub template: Vec<Segment<T>>,
+    /// `tools: [...]`: tools it may call from its position, each routed by a
+    /// generated `<Agent>::<Tool>` defer.
+    pub tools: Vec<T>,
+    pub span: Span,
+}
+
+/// A piece of a prompt template: literal text, or a `{param.field}` hole
+/// (a variable, possibly with field accesses).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Segment<T> {
+    Text(String),
+    Hole(Expr<T>, Span),
+}
+
+
+// ============================================================================
+// Tool declarations
+// ============================================================================
+
+/// `tool Name  Call[params] -> result: Type, ...`. Sugar for an external,
+/// deterministic interface going `Idle` -> `Returned[result]` -> `Idle`; kept
+/// alongside it so hosts know which param of a call is its result.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tool<T> {
+    pub name: T,
+    pub calls: Vec<ToolCall<T>>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ToolCall<T> {
+    pub name: T,
+    pub params: Vec<Param<T>>,
+    pub result: Param<T>,
+    pub span: Span,
+}
+
+
 // ============================================================================
 // Top-level declaration
 // ============================================================================
@@ -171,8 +373,12 @@ pub enum DirRef<T> {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Decl<T> {
     Interface(Interface<T>),
+    State(StateBlock<T>),
     Defer(Defer<T>),
     Schema(Schema<T>),
+    Invariant(Invariant<T>),
+    Agent(Agent<T>),
+    Tool(Tool<T>),
 }
 
 
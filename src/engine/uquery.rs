@@ -1,8 +1,9 @@
 use std::collections::BTreeMap;
 
-use super::eval::{conjoin, Bindings};
+use super::eval::{self, conjoin, Bindings};
 use super::facts::Facts;
 use super::simplify::reduce;
+use super::typecheck::{scope, TypeEnv};
 use super::*;
 
 
@@ -38,6 +39,15 @@ pub enum IndexSlot {
     Lit(usize),
 }
 
+/// A runtime value position (`binding`'s value): matched by equality when
+/// given as a literal.
+#[derive(Clone, Debug)]
+pub enum DataSlot {
+    Var(VarId),
+    Anon,
+    Lit(eval::Value),
+}
+
 #[derive(Clone, Debug)]
 pub enum DirRefPat {
     Var(VarId),
@@ -54,7 +64,7 @@ pub enum DirRefPat {
 #[derive(Clone, Debug)]
 pub enum Goal {
     Iface { iface: Term, params: Slot },
-    IfaceInternal { internal: Term, external: Term },
+    StateBlock { state: Term, params: Slot },
     SchemaRecord { schema: Term, fields: Slot },
     SchemaSum { schema: Term, variants: Slot },
     Position { iface: Term, position: Term, params: Slot, guard: Slot },
@@ -74,6 +84,11 @@ pub enum Goal {
         defer: Term, entry_idx: IndexSlot,
         target_dir: DirRefPat, source_dir: DirRefPat,
     },
+    Instance { instance: Term, iface: Term },
+    Step {
+        seq: IndexSlot, instance: Term, position: Term, action: Term, target_pos: Term,
+    },
+    Binding { seq: IndexSlot, name: Term, value: DataSlot },
     /// A user-written constraint. The expression is added to the answer's
     /// residual; goals never short-circuit on residuals during search — the
     /// simplifier resolves them once at the end of the query.
@@ -109,6 +124,7 @@ pub enum Value {
     Args(Vec<Expr<Sym>>),
     Pattern(Vec<Pattern<Sym>>),
     DirRef(DirRef<Sym>),
+    Data(eval::Value),
 }
 
 pub type Subst = BTreeMap<VarId, Value>;
@@ -121,14 +137,17 @@ pub struct Answer {
     /// simplifier before `run_query` returns. An empty residual means the
     /// answer is unconditionally true.
     pub residual: Vec<Expr<Sym>>,
+    /// The types of the variables the residual's guards range over, so the
+    /// simplifier tells an Int path from a Float one.
+    pub scope: TypeEnv,
 }
 
 impl Answer {
     pub fn empty() -> Self {
-        Self { subst: Subst::default(), residual: Vec::new() }
+        Self { subst: Subst::default(), residual: Vec::new(), scope: TypeEnv::new() }
     }
     pub fn with_subst(&self, subst: Subst) -> Self {
-        Self { subst, residual: self.residual.clone() }
+        Self { subst, residual: self.residual.clone(), scope: self.scope.clone() }
     }
     pub fn push_residual(&self, e: Expr<Sym>) -> Self {
         let mut next = self.clone();
@@ -196,6 +215,14 @@ fn unify_index_slot(slot: &IndexSlot, idx: usize, subst: &Subst) -> Option<Subst
     }
 }
 
+fn unify_data_slot(slot: &DataSlot, val: &eval::Value, subst: &Subst) -> Option<Subst> {
+    match slot {
+        DataSlot::Anon => Some(subst.clone()),
+        DataSlot::Lit(v) => if v == val { Some(subst.clone()) } else { None },
+        DataSlot::Var(v) => bind(subst, *v, Value::Data(val.clone())),
+    }
+}
+
 fn unify_dir_ref_pat(
     pat: &DirRefPat,
     dr: &DirRef<Sym>,
@@ -209,7 +236,7 @@ fn unify_dir_ref_pat(
         (
             DirRefPat::Abstract { src_pos, src_pattern, tgt_pos, tgt_args },
             DirRef::Abstract {
-                src_pos: sp, src_pattern: spat, tgt_pos: tp, tgt_args: targs,
+                src_pos: sp, src_pattern: spat, tgt_pos: tp, tgt_args: targs, ..
             },
         ) => {
             let s = unify_term(src_pos, *sp, subst)?;
@@ -237,12 +264,12 @@ fn match_goal(goal: &Goal, facts: &Facts, ans: &Answer) -> Vec<Answer> {
                 Some(ans.with_subst(s))
             })
             .collect(),
-        Goal::IfaceInternal { internal, external } => facts
-            .iface_internals
+        Goal::StateBlock { state, params } => facts
+            .state_blocks
             .iter()
             .filter_map(|f| {
-                let s = unify_term(internal, f.internal, &ans.subst)?;
-                let s = unify_term(external, f.external, &s)?;
+                let s = unify_term(state, f.state, &ans.subst)?;
+                let s = unify_slot(params, Value::Params(f.params.clone()), &s)?;
                 Some(ans.with_subst(s))
             })
             .collect(),
@@ -275,6 +302,7 @@ fn match_goal(goal: &Goal, facts: &Facts, ans: &Answer) -> Vec<Answer> {
                 let mut next = ans.with_subst(s);
                 if let Some(g) = &f.guard {
                     next.residual.push(g.clone());
+                    next.scope.extend(guard_scope(facts, f.iface, f.position, &[]));
                 }
                 Some(next)
             })
@@ -291,6 +319,7 @@ fn match_goal(goal: &Goal, facts: &Facts, ans: &Answer) -> Vec<Answer> {
                 let mut next = ans.with_subst(s);
                 if let Some(g) = &f.guard {
                     next.residual.push(g.clone());
+                    next.scope.extend(guard_scope(facts, f.iface, f.position, &f.params));
                 }
                 Some(next)
             })
@@ -345,6 +374,37 @@ fn match_goal(goal: &Goal, facts: &Facts, ans: &Answer) -> Vec<Answer> {
                 Some(ans.with_subst(s))
             })
             .collect(),
+        Goal::Instance { instance, iface } => facts
+            .instances
+            .iter()
+            .filter_map(|f| {
+                let s = unify_term(instance, f.instance, &ans.subst)?;
+                let s = unify_term(iface, f.iface, &s)?;
+                Some(ans.with_subst(s))
+            })
+            .collect(),
+        Goal::Step { seq, instance, position, action, target_pos } => facts
+            .steps
+            .iter()
+            .filter_map(|f| {
+                let s = unify_index_slot(seq, f.seq, &ans.subst)?;
+                let s = unify_term(instance, f.instance, &s)?;
+                let s = unify_term(position, f.position, &s)?;
+                let s = unify_term(action, f.action, &s)?;
+                let s = unify_term(target_pos, f.target_pos, &s)?;
+                Some(ans.with_subst(s))
+            })
+            .collect(),
+        Goal::Binding { seq, name, value } => facts
+            .bindings
+            .iter()
+            .filter_map(|f| {
+                let s = unify_index_slot(seq, f.seq, &ans.subst)?;
+                let s = unify_term(name, f.name, &s)?;
+                let s = unify_data_slot(value, &f.value, &s)?;
+                Some(ans.with_subst(s))
+            })
+            .collect(),
         Goal::Where(expr) => vec![ans.push_residual(expr.clone())],
     }
 }
@@ -354,6 +414,15 @@ fn match_goal(goal: &Goal, facts: &Facts, ans: &Answer) -> Vec<Answer> {
 // Solver
 // ============================================================================
 
+/// The scope of a guard on `position` of `iface` (an interface or a state
+/// block): their params, then `params` of the direction it guards, if any.
+fn guard_scope(facts: &Facts, iface: Sym, position: Sym, params: &[Param<Sym>]) -> TypeEnv {
+    let outer = facts.ifaces.iter().filter(|i| i.iface == iface).flat_map(|i| &i.params);
+    let block = facts.state_blocks.iter().filter(|b| b.state == iface).flat_map(|b| &b.params);
+    let pos = facts.positions.iter().filter(|p| p.iface == iface && p.position == position).flat_map(|p| &p.params);
+    scope(outer.chain(block).chain(pos).chain(params))
+}
+
 fn solve(goals: &[Goal], facts: &Facts, ans: Answer) -> Vec<Answer> {
     let Some((first, rest)) = goals.split_first() else {
         return vec![ans];
@@ -365,18 +434,34 @@ fn solve(goals: &[Goal], facts: &Facts, ans: Answer) -> Vec<Answer> {
     out
 }
 
+/// Answers with the same substitution (typically from different bodies) are
+/// merged into the first, with their residuals joined by `or`.
 pub fn run_query(eng: &Engine, facts: &Facts, query: &Query, env: &Bindings) -> Vec<Answer> {
-    let mut out = Vec::new();
+    let mut out: Vec<Answer> = Vec::new();
     for body in &query.bodies {
         for ans in solve(body, facts, Answer::empty()) {
-            if let Some(simplified) = simplify_answer(eng, &ans, env) {
-                out.push(simplified);
+            let Some(simplified) = simplify_answer(eng, &ans, env) else { continue };
+            match out.iter_mut().find(|a| a.subst == simplified.subst) {
+                Some(prev) => {
+                    prev.scope.extend(simplified.scope.clone());
+                    prev.residual = merge_residuals(eng, &prev.scope, &prev.residual, &simplified.residual, env);
+                }
+                None => out.push(simplified),
             }
         }
     }
     out
 }
 
+/// `r1 ∨ r2`, simplified; an empty residual is `true`.
+fn merge_residuals(eng: &Engine, types: &TypeEnv, r1: &[Expr<Sym>], r2: &[Expr<Sym>], env: &Bindings) -> Vec<Expr<Sym>> {
+    let (Some(a), Some(b)) = (conjoin(r1), conjoin(r2)) else { return Vec::new() };
+    match reduce(eng, types, &Expr::BinOp(BinOp::Or, Box::new(a), Box::new(b)), env) {
+        Expr::LitBool(true) => Vec::new(),
+        other => vec![other],
+    }
+}
+
 /// Conjoin and simplify an answer's residual against `env`. Returns `None`
 /// when the residual reduces to `false` (the answer is dropped). When the
 /// residual reduces to `true`, the residual is cleared. Otherwise the
@@ -385,11 +470,56 @@ fn simplify_answer(eng: &Engine, ans: &Answer, env: &Bindings) -> Option<Answer>
     let Some(joined) = conjoin(&ans.residual) else {
         return Some(ans.clone());
     };
-    let reduced = reduce(eng, &joined, env);
+    let reduced = reduce(eng, &ans.scope, &joined, env);
     match reduced {
-        Expr::LitBool(true) => Some(Answer { subst: ans.subst.clone(), residual: Vec::new() }),
+        Expr::LitBool(true) => Some(Answer { residual: Vec::new(), ..ans.clone() }),
         Expr::LitBool(false) => None,
-        other => Some(Answer { subst: ans.subst.clone(), residual: vec![other] }),
+        other => Some(Answer { residual: vec![other], ..ans.clone() }),
+    }
+}
+
+
+// ============================================================================
+// Display
+// ============================================================================
+
+impl Engine {
+    pub fn fmt_query_value(&self, v: &Value) -> String {
+        match v {
+            Value::Sym(s) => self.resolve(*s).to_string(),
+            Value::Index(i) => i.to_string(),
+            Value::Params(ps) => self.fmt_param_list(ps),
+            Value::Variants(vs) => {
+                let parts: Vec<String> = vs.iter().map(|v| self.fmt_variant(v)).collect();
+                format!("[{}]", parts.join(", "))
+            }
+            Value::Guard(None) => "_".to_string(),
+            Value::Guard(Some(g)) => self.fmt_expr(g, 0),
+            Value::Args(args) => {
+                let parts: Vec<String> = args.iter().map(|a| self.fmt_expr(a, 0)).collect();
+                format!("[{}]", parts.join(", "))
+            }
+            Value::Pattern(ps) => self.fmt_pattern_list(ps),
+            Value::DirRef(r) => self.fmt_dir_ref(r),
+            Value::Data(v) => self.fmt_value(v),
+        }
+    }
+
+    /// One line per answer: `X = v, Y = w where <residual>`. `vars` names the
+    /// query's variables in the order they should be listed; an answer with
+    /// nothing to report renders as `true`.
+    pub fn fmt_answer(&self, ans: &Answer, vars: &[(String, VarId)]) -> String {
+        let parts: Vec<String> = vars
+            .iter()
+            .filter_map(|(name, v)| {
+                ans.subst.get(v).map(|val| format!("{name} = {}", self.fmt_query_value(val)))
+            })
+            .collect();
+        let mut out = if parts.is_empty() { "true".to_string() } else { parts.join(", ") };
+        if let Some(r) = conjoin(&ans.residual) {
+            out.push_str(&format!(" where {}", self.fmt_expr(&r, 0)));
+        }
+        out
     }
 }
 
@@ -479,20 +609,17 @@ mod tests {
         let tgt_pos = g.fresh();
         let tgt_args = g.fresh();
 
-        // Realization disjunct: I is target of a defer whose source is I::Internal.
-        let r_int = g.fresh();
+        // Realization disjunct: I is target of a defer whose source is a state block.
+        let r_state = g.fresh();
         let r_defer = g.fresh();
         let r_entry = g.fresh();
         let realization = vec![
-            Goal::IfaceInternal {
-                internal: Term::Var(r_int),
-                external: Term::Sym(i_sym),
-            },
             Goal::Defer {
                 defer: Term::Var(r_defer),
-                source: Term::Var(r_int),
+                source: Term::Var(r_state),
                 target: Term::Sym(i_sym),
             },
+            Goal::StateBlock { state: Term::Var(r_state), params: Slot::Anon },
             Goal::DeferEntry {
                 defer: Term::Var(r_defer),
                 entry_idx: IndexSlot::Var(r_entry),
@@ -1,4 +1,5 @@
-use super::{Defer, DirRef, Engine, Interface, Sym};
+use super::typecheck::{TypeError, TypeSite};
+use super::{Defer, DirRef, Engine, Signature, Span, Sym};
 
 
 // ============================================================================
@@ -7,14 +8,47 @@ use super::{Defer, DirRef, Engine, Interface, Sym};
 
 #[derive(Clone, Debug)]
 pub enum ValidationError {
-    UnknownInterface(Sym),
-    DeferUnknownPosition { defer: Sym, interface: Sym, position: Sym },
-    DeferPatternArity { defer: Sym, interface: Sym, position: Sym, expected: usize, got: usize },
-    DeferTargetArity { defer: Sym, interface: Sym, position: Sym, expected: usize, got: usize },
-    DirRefUnknown { defer: Sym, interface: Sym, position: Sym, name: Sym },
-    DirRefAbstractNotPermitted { defer: Sym, interface: Sym },
-    AbstractUnknownPos { defer: Sym, interface: Sym, position: Sym },
-    AbstractArity { defer: Sym, interface: Sym, position: Sym, expected: usize, got: usize },
+    UnknownInterface { name: Sym, span: Span },
+    DeferUnknownPosition { defer: Sym, interface: Sym, position: Sym, span: Span },
+    DeferPatternArity {
+        defer: Sym, interface: Sym, position: Sym, expected: usize, got: usize, span: Span,
+    },
+    DeferTargetArity {
+        defer: Sym, interface: Sym, position: Sym, expected: usize, got: usize, span: Span,
+    },
+    DirRefUnknown { defer: Sym, interface: Sym, position: Sym, name: Sym, span: Span },
+    DirRefAbstractNotPermitted { defer: Sym, interface: Sym, span: Span },
+    AbstractUnknownPos { defer: Sym, interface: Sym, position: Sym, span: Span },
+    AbstractArity {
+        defer: Sym, interface: Sym, position: Sym, expected: usize, got: usize, span: Span,
+    },
+    UnknownType { name: Sym, span: Span },
+    AgentUnknownPosition { agent: Sym, interface: Sym, position: Sym, span: Span },
+    AgentUnknownTool { agent: Sym, tool: Sym, span: Span },
+    /// The agent lists tools, but its interface has no state block for the
+    /// routing defers to start from.
+    AgentToolsUnroutable { agent: Sym, interface: Sym, span: Span },
+    IllTyped { site: TypeSite, error: TypeError, span: Span },
+}
+
+impl ValidationError {
+    pub fn span(&self) -> Span {
+        match self {
+            ValidationError::UnknownInterface { span, .. }
+            | ValidationError::DeferUnknownPosition { span, .. }
+            | ValidationError::DeferPatternArity { span, .. }
+            | ValidationError::DeferTargetArity { span, .. }
+            | ValidationError::DirRefUnknown { span, .. }
+            | ValidationError::DirRefAbstractNotPermitted { span, .. }
+            | ValidationError::AbstractUnknownPos { span, .. }
+            | ValidationError::AbstractArity { span, .. }
+            | ValidationError::UnknownType { span, .. }
+            | ValidationError::AgentUnknownPosition { span, .. }
+            | ValidationError::AgentUnknownTool { span, .. }
+            | ValidationError::AgentToolsUnroutable { span, .. }
+            | ValidationError::IllTyped { span, .. } => span.clone(),
+        }
+    }
 }
 
 
@@ -28,24 +62,70 @@ impl Engine {
         for d in &self.defers {
             self.validate_defer(d, &mut errors);
         }
+        for i in &self.invariants {
+            if !self.interfaces.contains_key(&i.interface) {
+                errors.push(ValidationError::UnknownInterface {
+                    name: i.interface,
+                    span: i.span.clone(),
+                });
+            }
+        }
+        for a in self.agents.values() {
+            match self.interfaces.get(&a.interface) {
+                None => errors.push(ValidationError::UnknownInterface {
+                    name: a.interface,
+                    span: a.span.clone(),
+                }),
+                Some(iface) if iface.position(&a.position).is_none() => {
+                    errors.push(ValidationError::AgentUnknownPosition {
+                        agent: a.name,
+                        interface: a.interface,
+                        position: a.position,
+                        span: a.span.clone(),
+                    })
+                }
+                Some(_) => {}
+            }
+            for t in &a.tools {
+                if !self.tools.contains_key(t) {
+                    errors.push(ValidationError::AgentUnknownTool {
+                        agent: a.name,
+                        tool: *t,
+                        span: a.span.clone(),
+                    });
+                }
+            }
+            let routed = |t: &Sym| self.tool_defer(a, *t).is_some();
+            let known = self.interfaces.get(&a.interface).is_some_and(|i| i.position(&a.position).is_some());
+            if known && a.tools.iter().any(|t| self.tools.contains_key(t) && !routed(t)) {
+                errors.push(ValidationError::AgentToolsUnroutable {
+                    agent: a.name,
+                    interface: a.interface,
+                    span: a.span.clone(),
+                });
+            }
+        }
+        errors.extend(self.typecheck());
         errors
     }
 
     fn validate_defer(&self, d: &Defer<Sym>, errors: &mut Vec<ValidationError>) {
-        let src_iface = self.interfaces.get(&d.source);
-        let tgt_iface = self.interfaces.get(&d.target);
+        let src_iface = self.signature(d.source);
+        let tgt_iface = self.signature(d.target);
         if src_iface.is_none() {
-            errors.push(ValidationError::UnknownInterface(d.source));
+            errors.push(ValidationError::UnknownInterface { name: d.source, span: d.span.clone() });
         }
         if tgt_iface.is_none() {
-            errors.push(ValidationError::UnknownInterface(d.target));
+            errors.push(ValidationError::UnknownInterface { name: d.target, span: d.span.clone() });
         }
         let (Some(src_iface), Some(tgt_iface)) = (src_iface, tgt_iface) else {
             return;
         };
 
-        let src_internal = self.resolve(d.source).ends_with("::Internal");
-        let tgt_internal = self.resolve(d.target).ends_with("::Internal");
+        // Abstract direction refs name transitions of a state block's implicit
+        // S·y^S dynamics; an interface's directions are the ones it lists.
+        let src_is_state = matches!(src_iface, Signature::State(_));
+        let tgt_is_state = matches!(tgt_iface, Signature::State(_));
 
         for entry in &d.entries {
             let src_pos = src_iface.position(&entry.source_pos);
@@ -55,6 +135,7 @@ impl Engine {
                     defer: d.name,
                     interface: d.source,
                     position: entry.source_pos,
+                    span: entry.span.clone(),
                 });
             }
             if tgt_pos.is_none() {
@@ -62,6 +143,7 @@ impl Engine {
                     defer: d.name,
                     interface: d.target,
                     position: entry.target_pos,
+                    span: entry.span.clone(),
                 });
             }
             if let Some(sp) = src_pos {
@@ -72,6 +154,7 @@ impl Engine {
                         position: sp.name,
                         expected: sp.params.len(),
                         got: entry.source_pattern.len(),
+                        span: entry.span.clone(),
                     });
                 }
             }
@@ -83,17 +166,18 @@ impl Engine {
                         position: tp.name,
                         expected: tp.params.len(),
                         got: entry.target_args.len(),
+                        span: entry.span.clone(),
                     });
                 }
             }
 
             for m in &entry.directions {
-                self.validate_dir_ref(
-                    d, &m.target_dir, tgt_iface, entry.target_pos, tgt_internal, errors,
-                );
-                self.validate_dir_ref(
-                    d, &m.source_dir, src_iface, entry.source_pos, src_internal, errors,
-                );
+                errors.extend(self.validate_dir_ref(
+                    d, &m.target_dir, tgt_iface, entry.target_pos, tgt_is_state, &m.span,
+                ));
+                errors.extend(self.validate_dir_ref(
+                    d, &m.source_dir, src_iface, entry.source_pos, src_is_state, &m.span,
+                ));
             }
         }
     }
@@ -102,83 +186,91 @@ impl Engine {
         &self,
         d: &Defer<Sym>,
         r: &DirRef<Sym>,
-        iface: &Interface<Sym>,
+        iface: Signature<'_, Sym>,
         pos: Sym,
-        iface_is_internal: bool,
-        errors: &mut Vec<ValidationError>,
-    ) {
+        iface_is_state: bool,
+        span: &Span,
+    ) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
         match r {
             DirRef::Named(name) => {
                 if let Some(p) = iface.position(&pos) {
                     if !p.directions.iter().any(|dir| dir.name == *name) {
                         errors.push(ValidationError::DirRefUnknown {
                             defer: d.name,
-                            interface: iface.name,
+                            interface: *iface.name(),
                             position: pos,
                             name: *name,
+                            span: span.clone(),
                         });
                     }
                 }
             }
-            DirRef::Abstract { src_pos, src_pattern, tgt_pos, tgt_args } => {
-                if !iface_is_internal {
+            DirRef::Abstract { src_pos, src_pattern, tgt_pos, tgt_args, .. } => {
+                if !iface_is_state {
                     errors.push(ValidationError::DirRefAbstractNotPermitted {
                         defer: d.name,
-                        interface: iface.name,
+                        interface: *iface.name(),
+                        span: span.clone(),
                     });
-                    return;
+                    return errors;
                 }
                 if let Some(sp) = iface.position(src_pos) {
                     if sp.params.len() != src_pattern.len() {
                         errors.push(ValidationError::AbstractArity {
                             defer: d.name,
-                            interface: iface.name,
+                            interface: *iface.name(),
                             position: *src_pos,
                             expected: sp.params.len(),
                             got: src_pattern.len(),
+                            span: span.clone(),
                         });
                     }
                 } else {
                     errors.push(ValidationError::AbstractUnknownPos {
                         defer: d.name,
-                        interface: iface.name,
+                        interface: *iface.name(),
                         position: *src_pos,
+                        span: span.clone(),
                     });
                 }
                 if let Some(tp) = iface.position(tgt_pos) {
                     if tp.params.len() != tgt_args.len() {
                         errors.push(ValidationError::AbstractArity {
                             defer: d.name,
-                            interface: iface.name,
+                            interface: *iface.name(),
                             position: *tgt_pos,
                             expected: tp.params.len(),
                             got: tgt_args.len(),
+                            span: span.clone(),
                         });
                     }
                 } else {
                     errors.push(ValidationError::AbstractUnknownPos {
                         defer: d.name,
-                        interface: iface.name,
+                        interface: *iface.name(),
                         position: *tgt_pos,
+                        span: span.clone(),
                     });
                 }
             }
         }
+        errors
     }
 
     pub fn fmt_validation_error(&self, e: &ValidationError) -> String {
         match e {
-            ValidationError::UnknownInterface(s) => {
-                format!("unknown interface: {}", self.resolve(*s))
+            ValidationError::UnknownInterface { name, .. } => {
+                format!("unknown interface: {}", self.resolve(*name))
             }
-            ValidationError::DeferUnknownPosition { defer, interface, position } => format!(
+            ValidationError::DeferUnknownPosition { defer, interface, position, .. } => format!(
                 "defer {}: position `{}` not found in interface `{}`",
                 self.resolve(*defer),
                 self.resolve(*position),
                 self.resolve(*interface),
             ),
             ValidationError::DeferPatternArity {
-                defer, interface, position, expected, got,
+                defer, interface, position, expected, got, ..
             } => format!(
                 "defer {}: source pattern at {}.{} has {} arg(s), expected {}",
                 self.resolve(*defer),
@@ -188,7 +280,7 @@ impl Engine {
                 expected,
             ),
             ValidationError::DeferTargetArity {
-                defer, interface, position, expected, got,
+                defer, interface, position, expected, got, ..
             } => format!(
                 "defer {}: target args at {}.{} have arity {}, expected {}",
                 self.resolve(*defer),
@@ -197,26 +289,26 @@ impl Engine {
                 got,
                 expected,
             ),
-            ValidationError::DirRefUnknown { defer, interface, position, name } => format!(
+            ValidationError::DirRefUnknown { defer, interface, position, name, .. } => format!(
                 "defer {}: action `{}` is not a direction of {}.{}",
                 self.resolve(*defer),
                 self.resolve(*name),
                 self.resolve(*interface),
                 self.resolve(*position),
             ),
-            ValidationError::DirRefAbstractNotPermitted { defer, interface } => format!(
-                "defer {}: abstract transition is not a direction of `{}`; abstract refs require an `::Internal` interface",
+            ValidationError::DirRefAbstractNotPermitted { defer, interface, .. } => format!(
+                "defer {}: abstract direction ref is not valid here; it needs a `state` block, but `{}` is an `interface`",
                 self.resolve(*defer),
                 self.resolve(*interface),
             ),
-            ValidationError::AbstractUnknownPos { defer, interface, position } => format!(
+            ValidationError::AbstractUnknownPos { defer, interface, position, .. } => format!(
                 "defer {}: abstract transition references unknown position `{}` in `{}`",
                 self.resolve(*defer),
                 self.resolve(*position),
                 self.resolve(*interface),
             ),
             ValidationError::AbstractArity {
-                defer, interface, position, expected, got,
+                defer, interface, position, expected, got, ..
             } => format!(
                 "defer {}: abstract transition at {}.{} has arity {}, expected {}",
                 self.resolve(*defer),
@@ -225,6 +317,26 @@ impl Engine {
                 got,
                 expected,
             ),
+            ValidationError::UnknownType { name, .. } => {
+                format!("unknown type: {}", self.resolve(*name))
+            }
+            ValidationError::AgentUnknownPosition { agent, interface, position, .. } => format!(
+                "agent {}: position `{}` not found in interface `{}`",
+                self.resolve(*agent),
+                self.resolve(*position),
+                self.resolve(*interface),
+            ),
+            ValidationError::AgentUnknownTool { agent, tool, .. } => {
+                format!("agent {}: `{}` is not a tool", self.resolve(*agent), self.resolve(*tool))
+            }
+            ValidationError::AgentToolsUnroutable { agent, interface, .. } => format!(
+                "agent {}: tool calls need interface `{}` to have transitions, so they can return to the agent's position",
+                self.resolve(*agent),
+                self.resolve(*interface),
+            ),
+            ValidationError::IllTyped { site, error, .. } => {
+                format!("{}: {}", self.fmt_type_site(site), self.fmt_type_error(error))
+            }
         }
     }
 }
@@ -0,0 +1,246 @@
+use std::collections::BTreeMap;
+
+use super::eval::{eval_bool, Bindings, Value};
+use super::simplify::{int_system, IntBounds};
+use super::solve::Satisfiability;
+use super::typecheck::scope;
+use super::{Engine, Expr, Float, Param, SchemaBody, Sym, Type};
+
+
+// ============================================================================
+// Concrete witnesses
+// ============================================================================
+//
+// The simplifier can prove a condition unsatisfiable but not produce a model
+// of it. The analyses that need one (deadlocks, invariants) enumerate small
+// values for each variable instead: ints near the bounds the condition's
+// Fourier–Motzkin system projects onto them, a few floats around zero, both
+// bools, the string literals the condition mentions plus one fresh string per
+// string variable, and every record / variant built from those. A miss
+// proves unsatisfiability only when the samples cover each variable's whole
+// domain (see `Engine::satisfiable`).
+//
+// An int the condition leaves open on a side is sampled where the system
+// puts it once the ints before it are held to their samples and those after
+// it to theirs by default: `x + y > 10` tries `x` from 7, then `y` around 0.
+
+/// Int params range over `-WITNESS_SPAN..=WITNESS_SPAN` unless the condition
+/// bounds them.
+const WITNESS_SPAN: i64 = 4;
+const WITNESS_BUDGET: usize = 200_000;
+
+impl Engine {
+    /// Bindings for `params` that satisfy `full`, if the search finds any.
+    pub(super) fn find_witness(
+        &self,
+        params: &[&Param<Sym>],
+        full: &Expr<Sym>,
+        condition: &Expr<Sym>,
+    ) -> Option<Bindings> {
+        match self.search_witness(params, full, condition) {
+            Satisfiability::Sat(b) => Some(b),
+            Satisfiability::Unsat | Satisfiability::Unknown => None,
+        }
+    }
+
+    /// Odometer search over sample values for `params`, smallest magnitudes
+    /// first, for bindings that satisfy `full`. `condition` is `full`
+    /// reduced, and bounds the ints.
+    pub(super) fn search_witness(
+        &self,
+        params: &[&Param<Sym>],
+        full: &Expr<Sym>,
+        condition: &Expr<Sym>,
+    ) -> Satisfiability {
+        let system = int_system(self, &scope(params.iter().copied()), condition, &Bindings::new()).unwrap_or_default();
+        let unassumed = BTreeMap::new();
+        let bounds: BTreeMap<Sym, IntBounds> = params
+            .iter()
+            .filter(|p| p.ty == Type::Int)
+            .map(|p| (p.name, system.bounds(p.name, &unassumed).unwrap_or_default()))
+            .collect();
+        // Where each int's samples lie, narrowed as the ones before it are placed.
+        let mut placed: BTreeMap<Sym, IntBounds> =
+            bounds.iter().map(|(v, &(lo, hi))| (*v, extent(&int_window(lo, hi)))).collect();
+        let strings = string_samples(full, params.iter().filter(|p| p.ty == Type::Str).count());
+        let mut exhaustive = true;
+        let samples: Vec<Vec<Value>> = params
+            .iter()
+            .map(|p| match &p.ty {
+                Type::Int => {
+                    let (lo, hi) = bounds[&p.name];
+                    exhaustive &= covers(lo, hi);
+                    let (near_lo, near_hi) = system.bounds(p.name, &placed).unwrap_or_default();
+                    let window = match (lo, hi) {
+                        (None, None) => int_window(near_lo, near_hi),
+                        (None, Some(h)) => int_window(None, Some(near_hi.map_or(h, |k| k.min(h)))),
+                        (Some(l), None) => int_window(Some(near_lo.map_or(l, |k| k.max(l))), None),
+                        (Some(_), Some(_)) => int_window(lo, hi),
+                    };
+                    // Placed bounds that cross say nothing about where to look.
+                    let window = if window.is_empty() { int_window(lo, hi) } else { window };
+                    placed.insert(p.name, extent(&window));
+                    window
+                }
+                Type::Str => strings.clone(),
+                ty => {
+                    exhaustive &= self.finite(ty, 0);
+                    self.sample_values(ty, 0)
+                }
+            })
+            .collect();
+        let give_up = |exhaustive| if exhaustive { Satisfiability::Unsat } else { Satisfiability::Unknown };
+        if samples.iter().any(Vec::is_empty) {
+            return give_up(exhaustive);
+        }
+        let mut idx = vec![0usize; params.len()];
+        for _ in 0..WITNESS_BUDGET {
+            let b: Bindings =
+                params.iter().zip(&samples).zip(&idx).map(|((p, s), i)| (p.name, s[*i].clone())).collect();
+            match eval_bool(self, full, &b) {
+                Ok(true) => return Satisfiability::Sat(b),
+                Ok(false) => {}
+                // Division by zero, say: not a model, but not a refutation.
+                Err(_) => exhaustive = false,
+            }
+            let mut k = 0;
+            loop {
+                if k == idx.len() {
+                    return give_up(exhaustive);
+                }
+                idx[k] += 1;
+                if idx[k] < samples[k].len() {
+                    break;
+                }
+                idx[k] = 0;
+                k += 1;
+            }
+        }
+        Satisfiability::Unknown
+    }
+
+    /// Whether `sample_values(ty, depth)` is every value of `ty`.
+    fn finite(&self, ty: &Type<Sym>, depth: usize) -> bool {
+        match ty {
+            Type::Bool => true,
+            Type::Int | Type::Float | Type::Str => false,
+            Type::Named(_) if depth > 2 => false,
+            Type::Named(s) => match self.schemas.get(s).map(|s| &s.body) {
+                Some(SchemaBody::Record(fields)) => fields.iter().all(|f| self.finite(&f.ty, depth + 1)),
+                Some(SchemaBody::Sum(variants)) => {
+                    variants.iter().flat_map(|v| &v.params).all(|f| self.finite(&f.ty, depth + 1))
+                }
+                None => false,
+            },
+        }
+    }
+
+    pub(super) fn sample_values(&self, ty: &Type<Sym>, depth: usize) -> Vec<Value> {
+        match ty {
+            Type::Int => int_window(None, None),
+            Type::Float => [0.0, 1.0, -1.0, 0.5, -0.5, 2.0, -2.0].map(|x| Value::Float(Float(x))).to_vec(),
+            Type::Bool => vec![Value::Bool(false), Value::Bool(true)],
+            Type::Str => vec![Value::Str(String::new())],
+            Type::Named(_) if depth > 2 => Vec::new(),
+            Type::Named(s) => match self.schemas.get(s).map(|s| &s.body) {
+                Some(SchemaBody::Record(fields)) => self
+                    .sample_fields(fields, depth)
+                    .into_iter()
+                    .map(|fields| Value::Record { schema: *s, fields })
+                    .collect(),
+                Some(SchemaBody::Sum(variants)) => variants
+                    .iter()
+                    .flat_map(|v| {
+                        self.sample_fields(&v.params, depth).into_iter().map(|fields| {
+                            Value::Variant { schema: *s, variant: v.name, fields }
+                        })
+                    })
+                    .collect(),
+                None => Vec::new(),
+            },
+        }
+    }
+
+    fn sample_fields(
+        &self,
+        fields: &[Param<Sym>],
+        depth: usize,
+    ) -> Vec<BTreeMap<Sym, Value>> {
+        let mut out = vec![BTreeMap::new()];
+        for f in fields {
+            let values = self.sample_values(&f.ty, depth + 1);
+            out = out
+                .into_iter()
+                .flat_map(|m| {
+                    values.iter().map(move |v| {
+                        let mut m = m.clone();
+                        m.insert(f.name, v.clone());
+                        m
+                    })
+                })
+                .collect();
+        }
+        out
+    }
+}
+
+/// Strings are only ever compared for equality, so the literals `e`
+/// mentions plus `fresh` strings it does not are every distinct case for
+/// `fresh` string variables.
+fn string_samples(e: &Expr<Sym>, fresh: usize) -> Vec<Value> {
+    fn literals(e: &Expr<Sym>, out: &mut Vec<String>) {
+        match e {
+            Expr::LitStr(s) if !out.contains(s) => out.push(s.clone()),
+            Expr::LitInt(_) | Expr::LitFloat(_) | Expr::LitStr(_) | Expr::LitBool(_) | Expr::Var(_) => {}
+            Expr::UnOp(_, x) | Expr::Field(x, _) | Expr::Is(x, _) => literals(x, out),
+            Expr::BinOp(_, l, r) => {
+                literals(l, out);
+                literals(r, out);
+            }
+            Expr::Construct(_, args) | Expr::Variant(_, _, args) => args.iter().for_each(|a| literals(a, out)),
+        }
+    }
+    let mut lits = Vec::new();
+    literals(e, &mut lits);
+    let unused: Vec<String> = std::iter::once(String::new())
+        .chain((0..).map(|i| format!("s{i}")))
+        .filter(|s| !lits.contains(s))
+        .take(fresh.max(1))
+        .collect();
+    // The first fresh string (the empty one, unless mentioned) leads.
+    let mut out: Vec<String> = unused.iter().take(1).cloned().collect();
+    out.extend(lits);
+    out.extend(unused.into_iter().skip(1));
+    out.into_iter().map(Value::Str).collect()
+}
+
+/// Integers in `lo..=hi`, smallest magnitude first. An open side extends
+/// `2 * WITNESS_SPAN` past the other (or to ±`WITNESS_SPAN`); a closed range
+/// is enumerated whole if it fits the budget, and sampled near both ends,
+/// where guards usually bite, if not.
+fn int_window(lo: Option<i64>, hi: Option<i64>) -> Vec<Value> {
+    let edge = 2 * WITNESS_SPAN;
+    let mut v: Vec<i64> = match (lo, hi) {
+        (Some(l), Some(h)) if covers(lo, hi) => (l..=h).collect(),
+        (Some(l), Some(h)) => (l..=l + edge).chain(h - edge..=h).collect(),
+        (Some(l), None) => (l..=l.saturating_add(edge)).collect(),
+        (None, Some(h)) => (h.saturating_sub(edge)..=h).collect(),
+        (None, None) => (-WITNESS_SPAN..=WITNESS_SPAN).collect(),
+    };
+    v.sort_by_key(|k| (k.unsigned_abs(), *k < 0));
+    v.into_iter().map(Value::Int).collect()
+}
+
+/// Whether `int_window(lo, hi)` is the whole of `lo..=hi`.
+fn covers(lo: Option<i64>, hi: Option<i64>) -> bool {
+    matches!((lo, hi), (Some(l), Some(h)) if (h as i128) - (l as i128) < WITNESS_BUDGET as i128)
+}
+
+/// The closed range `int_window` samples lie in.
+fn extent(window: &[Value]) -> IntBounds {
+    let ints = window.iter().filter_map(|v| match v {
+        Value::Int(k) => Some(*k),
+        _ => None,
+    });
+    (ints.clone().min(), ints.max())
+}
@@ -1,7 +1,8 @@
 mod engine;
+mod repl;
 
-use engine::eval::{Bindings, Value};
-use engine::{Engine, EngineError, SchemaBody};
+use engine::eval::Bindings;
+use engine::{Engine, EngineError};
 
 fn main() {
     let args: Vec<String> = std::env::args().skip(1).collect();
@@ -24,6 +25,16 @@ fn run_cli(args: &[String]) -> i32 {
         "locate" => cmd_locate(rest),
         "actions" => cmd_actions(rest),
         "step" => cmd_step(rest),
+        "prompt" => cmd_prompt(rest),
+        "run" => cmd_run(rest),
+        "serve" => cmd_serve(rest),
+        "query" => cmd_query(rest),
+        "repl" => cmd_repl(rest),
+        "replay" => cmd_replay(rest),
+        "reach" => cmd_reach(rest),
+        "ltl" => cmd_ltl(rest),
+        "check" => cmd_check(rest),
+        "solve" => cmd_solve(rest),
         "help" | "-h" | "--help" => {
             print_usage();
             0
@@ -40,11 +51,13 @@ fn print_usage() {
     eprintln!(
         "Usage:
   poly show <file>
-      Print all schemas, interfaces, and defers in <file>.
+      Print all schemas, interfaces, state blocks, defers, tools, and
+      agents in <file>.
 
-  poly facts <file>
+  poly facts <file> [--log <log>]
       Project <file> into the relation tuples used by the (in-progress)
-      query layer. One Datalog-style fact per line.
+      query layer. One Datalog-style fact per line. With --log, also emit
+      instance/step/binding facts for every event in the trajectory log.
 
   poly explain <file> <interface> <position>
       Show what is determined elsewhere when <interface> is at <position>.
@@ -60,6 +73,84 @@ fn print_usage() {
       bindings; print the resulting position and bindings. Values may be
       integers, true/false, or quoted strings.
 
+  poly prompt <file> <agent> [name=value ...]
+      Render the prompt <agent> would be given with its position's params
+      bound as given: the filled-in template, then the actions enabled
+      there with their parameter signatures.
+
+  poly run <file> <agent> [name=value ...] (--script <script> | --random <seed>)
+           [--max-turns N] [--retries N] [--show-prompts] [--log <log>]
+      Start <agent> at its position with the given bindings and drive it:
+      prompt the backend, fire the action it replies with or call the tool
+      it names, and repeat until the agent leaves its position. --script
+      replays replies and tool results from <script> (sections separated
+      by '---' lines; '--- tool Tool.Call' sections are tool results);
+      --random picks enabled actions and tool results from <seed>. Prints
+      one JSON trace event per line. --max-turns (default 20) caps the
+      turns; --retries (default 2) caps the replies re-asked for in a turn.
+      --show-prompts writes each prompt the backend is given to stderr.
+      --log appends the run's steps to the trajectory log <log>; if the log
+      already has <agent>, the run resumes it where the log left off.
+
+  poly serve <file> <replies> <interface> <position> [name=value ...]
+      Start an instance of external <interface> at <position> and answer
+      it from <replies>: each time it enters a position, the next reply
+      for that position is submitted, until none is left. <replies> has
+      one reply per line, 'Interface.Position Action[name=value, ...]'.
+      Prints each position the instance enters.
+
+  poly query <file> '<query>' [--log <log>] [name=value ...]
+      Run a Datalog-style query over the relations printed by `poly facts`,
+      e.g. 'direction(I, P, Decrement, _, _), where n > 5'. Separate
+      disjuncts with `;`. A name is a constant where <file> (or <log>)
+      declares one of that kind, e.g. an action in an action slot; other
+      names are variables (`?X` forces a variable). Bindings are applied when
+      simplifying each answer's residual constraint. With --log, history
+      relations are available too, e.g. 'step(T, c, _, Decrement, _),
+      binding(T, n, 0)'.
+
+  poly repl <file> <interface> <position> [name=value ...]
+      Walk <interface> interactively from <position>. Lists the available
+      actions with their guard status, applies `Action [name=value ...]`,
+      and supports undo, reset, history and save <trace-file>.
+
+  poly replay <file> <log> [--at <seq>]
+      Re-run a trajectory log against <file>, checking every recorded step
+      starts and ends where the log says. Reports the first divergence, or
+      prints the instances as they stood after the last (or <seq>th) event.
+
+  poly reach <file> <interface> <from> <to> [--depth N] [--span N] [name=value ...]
+      Search breadth-first from state <from> (e.g. 'Count[0]') for a state
+      matching <to> ('Count[10]', or a bare position name), printing the
+      shortest path found. Bindings supply interface params. Integer params
+      are bounded by their guards, widened by --span (default 64) where a
+      guard leaves them open; --depth (default 64) caps the path length.
+
+  poly ltl <file> <interface> <from> <formula> [--depth N] [--span N] [name=value ...]
+      Check a temporal formula on every path from state <from>, over the
+      states 'poly reach' would explore, e.g.
+          G (at Working -> F at Done)
+          G not ({{n == 1}} and enabled Decrement and X {{n == 1}})
+      Atoms are 'at <position>', 'enabled <action>' and '{{ <expr> }}';
+      connectives are not, and, or, ->, X, F, G and U. Prints a
+      counterexample as the steps to a loop and the loop itself, or to a
+      state where no action is enabled.
+
+  poly check <file> [--deadlocks] [--invariants]
+      Load and validate <file>, then run the selected analyses (all of
+      them when none is given). --deadlocks reports positions where the
+      guard can hold while every direction guard is false, or that have no
+      directions, with example bindings. Positions declared `terminal` are
+      skipped. --invariants checks that every transition preserves each
+      `invariant` declaration, with example bindings where one breaks.
+
+  poly solve <file> '<expr>' [name:Type ...]
+      Find values for the declared variables that make <expr> true, e.g.
+      poly solve grid.poly 'c.x == c.y + 1 and c.y > 2' c:Coordinate.
+      Types are Int, Bool, String or a schema from <file>. Prints the
+      values found, 'unsat' when there are none, or 'unknown' when the
+      search over small values gives up.
+
   poly help
       Print this message."
     );
@@ -75,15 +166,9 @@ fn load(path: &str) -> Option<Engine> {
     };
     match Engine::load(&src) {
         Ok(e) => Some(e),
-        Err(EngineError::Parse(errs)) => {
-            for e in errs {
-                eprintln!("parse error in {path}: {e:?}");
-            }
-            None
-        }
-        Err(EngineError::Validate(msgs)) => {
-            for m in msgs {
-                eprintln!("validation error in {path}: {m}");
+        Err(EngineError::Parse(diags)) | Err(EngineError::Validate(diags)) => {
+            for d in diags {
+                eprint!("{}", d.render(&src, path));
             }
             None
         }
@@ -105,22 +190,39 @@ fn cmd_show(args: &[String]) -> i32 {
     for iface in eng.interfaces.values() {
         println!("{}", eng.fmt_interface(iface));
     }
+    for s in eng.states.values() {
+        println!("{}", eng.fmt_state_block(s));
+    }
     for d in &eng.defers {
         println!("{}", eng.fmt_defer(d));
     }
+    for i in &eng.invariants {
+        println!("{}", eng.fmt_invariant(i));
+    }
+    for t in eng.tools.values() {
+        println!("{}", eng.fmt_tool(t));
+    }
+    for a in eng.agents.values() {
+        println!("{}", eng.fmt_agent(a));
+    }
     0
 }
 
 fn cmd_facts(args: &[String]) -> i32 {
-    let path = match args {
+    let (log_path, args) = take_log_flag(args);
+    let path = match &args[..] {
         [p] => p,
         _ => {
-            eprintln!("usage: poly facts <file>");
+            eprintln!("usage: poly facts <file> [--log <log>]");
             return 1;
         }
     };
-    let Some(eng) = load(path) else { return 1 };
-    let facts = eng.facts();
+    let Some(mut eng) = load(path) else { return 1 };
+    let mut facts = eng.facts();
+    if let Some(log_path) = log_path {
+        let Some(events) = load_trajectory(&eng, &log_path) else { return 1 };
+        eng.add_trajectory_facts(&mut facts, &events);
+    }
     print!("{}", eng.fmt_facts(&facts));
     0
 }
@@ -173,24 +275,13 @@ fn cmd_step(args: &[String]) -> i32 {
         }
     };
     let Some(eng) = load(path) else { return 1 };
-    let mut bindings: Bindings = std::collections::BTreeMap::new();
-    for kv in rest {
-        let Some((k, v)) = kv.split_once('=') else {
-            eprintln!("expected name=value, got: {kv}");
+    let bindings = match parse_bindings(&eng, rest) {
+        Ok(b) => b,
+        Err(msg) => {
+            eprintln!("{msg}");
             return 1;
-        };
-        let Some(key) = eng.interner.find(k) else {
-            eprintln!("unknown parameter: {k}");
-            return 1;
-        };
-        match parse_value(&eng, v) {
-            Ok(val) => { bindings.insert(key, val); }
-            Err(msg) => {
-                eprintln!("could not parse value for {k}: {msg}");
-                return 1;
-            }
         }
-    }
+    };
     match eng.next_position(iface, pos, action, bindings) {
         Ok(step) => {
             print!("{}", eng.fmt_step(&step));
@@ -203,99 +294,528 @@ fn cmd_step(args: &[String]) -> i32 {
     }
 }
 
-fn parse_value(eng: &Engine, s: &str) -> Result<Value, String> {
-    let s = s.trim();
-    if let Ok(n) = s.parse::<i64>() {
-        return Ok(Value::Int(n));
-    }
-    if s == "true" {
-        return Ok(Value::Bool(true));
-    }
-    if s == "false" {
-        return Ok(Value::Bool(false));
-    }
-    if let Some((name, args_str)) = parse_construct_head(s) {
-        let key = eng
-            .interner
-            .find(name)
-            .ok_or_else(|| format!("unknown schema: {name}"))?;
-        let schema = eng
-            .schemas
-            .get(&key)
-            .ok_or_else(|| format!("unknown schema: {name}"))?;
-        let params = match &schema.body {
-            SchemaBody::Record(ps) => ps,
-            SchemaBody::Sum(_) => {
-                return Err(format!("sum constructors not yet supported: {name}"));
+fn cmd_prompt(args: &[String]) -> i32 {
+    let (path, agent, rest) = match args {
+        [p, a, rest @ ..] => (p, a, rest),
+        _ => {
+            eprintln!("usage: poly prompt <file> <agent> [name=value ...]");
+            return 1;
+        }
+    };
+    let Some(eng) = load(path) else { return 1 };
+    let bindings = match parse_bindings(&eng, rest) {
+        Ok(b) => b,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return 1;
+        }
+    };
+    match eng.render_prompt(agent, &bindings) {
+        Ok(prompt) => {
+            print!("{prompt}");
+            0
+        }
+        Err(err) => {
+            eprintln!("{}", eng.fmt_query_error(&err));
+            1
+        }
+    }
+}
+
+fn cmd_run(args: &[String]) -> i32 {
+    use engine::backend::{ModelBackend, RandomBackend, ScriptedBackend, ScriptedTools, ShowPrompts};
+    use engine::driver::{DriveConfig, RunEnd};
+    use engine::tools::ToolHost;
+
+    let usage = "usage: poly run <file> <agent> [name=value ...] (--script <script> | --random <seed>) \
+                 [--max-turns N] [--retries N] [--show-prompts] [--log <log>]";
+    let (path, agent, rest) = match args {
+        [p, a, rest @ ..] => (p, a, rest),
+        _ => {
+            eprintln!("{usage}");
+            return 1;
+        }
+    };
+    let mut config = DriveConfig::default();
+    let mut backends: Option<(Box<dyn ModelBackend>, Box<dyn ToolHost>)> = None;
+    let mut show_prompts = false;
+    let mut log = None;
+    let mut kvs = Vec::new();
+    let mut it = rest.iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--show-prompts" => show_prompts = true,
+            "--log" => {
+                let Some(file) = it.next() else {
+                    eprintln!("--log expects a file");
+                    return 1;
+                };
+                log = Some(file.clone());
             }
-        };
-        let arg_strs = split_top_commas(args_str)?;
-        if arg_strs.len() != params.len() {
-            return Err(format!(
-                "{name} expects {} arg(s), got {}",
-                params.len(),
-                arg_strs.len(),
-            ));
-        }
-        let mut fields: std::collections::BTreeMap<engine::Sym, Value> =
-            std::collections::BTreeMap::new();
-        for (p, arg) in params.iter().zip(arg_strs.iter()) {
-            fields.insert(p.name, parse_value(eng, arg)?);
-        }
-        return Ok(Value::Record { schema: key, fields });
-    }
-    let trimmed = s.trim_matches('"');
-    Ok(Value::Str(trimmed.to_string()))
+            flag @ ("--max-turns" | "--retries") => {
+                let Some(n) = it.next().and_then(|n| n.parse::<usize>().ok()) else {
+                    eprintln!("{flag} expects a non-negative number");
+                    return 1;
+                };
+                if flag == "--max-turns" {
+                    config.max_turns = n;
+                } else {
+                    config.retries = n;
+                }
+            }
+            "--script" => {
+                let Some(file) = it.next() else {
+                    eprintln!("--script expects a file");
+                    return 1;
+                };
+                let script = match std::fs::read_to_string(file) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        eprintln!("could not read {file}: {e}");
+                        return 1;
+                    }
+                };
+                backends = Some((Box::new(ScriptedBackend::parse(&script)), Box::new(ScriptedTools::parse(&script))));
+            }
+            "--random" => {
+                let Some(seed) = it.next().and_then(|n| n.parse::<u64>().ok()) else {
+                    eprintln!("--random expects a seed");
+                    return 1;
+                };
+                backends = Some((Box::new(RandomBackend::new(seed)), Box::new(RandomBackend::new(!seed))));
+            }
+            _ => kvs.push(a.clone()),
+        }
+    }
+    let Some((mut backend, mut tools)) = backends else {
+        eprintln!("{usage}");
+        return 1;
+    };
+    if show_prompts {
+        backend = Box::new(ShowPrompts { inner: backend, out: std::io::stderr() });
+    }
+    let Some(eng) = load(path) else { return 1 };
+    let bindings = match parse_bindings(&eng, &kvs) {
+        Ok(b) => b,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return 1;
+        }
+    };
+    let mut rt = engine::runtime::Runtime::new(eng);
+    if let Some(log) = log {
+        if let Err(e) = rt.log_to(log) {
+            eprintln!("{}", rt.engine.fmt_runtime_error(&e));
+            return 1;
+        }
+    }
+    let start = if rt.instance(agent).is_some() { Ok(()) } else { rt.start_agent(agent, bindings) };
+    let end = start.and_then(|()| {
+        let eng = rt.engine.clone();
+        let mut trace = |e: &engine::driver::TraceEvent| println!("{}", eng.fmt_trace_event(e));
+        rt.drive(agent, backend.as_mut(), tools.as_mut(), &config, &mut trace)
+    });
+    match end {
+        Ok(end) => i32::from(end != RunEnd::Left),
+        Err(e) => {
+            eprintln!("{}", rt.engine.fmt_drive_error(&e));
+            1
+        }
+    }
 }
 
-fn parse_construct_head(s: &str) -> Option<(&str, &str)> {
-    let open = s.find('(')?;
-    if !s.ends_with(')') {
-        return None;
+fn cmd_serve(args: &[String]) -> i32 {
+    use engine::host::MockHost;
+    use engine::runtime::Runtime;
+
+    let (path, replies_path, iface, pos, rest) = match args {
+        [p, r, i, q, rest @ ..] => (p, r, i, q, rest),
+        _ => {
+            eprintln!("usage: poly serve <file> <replies> <interface> <position> [name=value ...]");
+            return 1;
+        }
+    };
+    let Some(eng) = load(path) else { return 1 };
+    let bindings = match parse_bindings(&eng, rest) {
+        Ok(b) => b,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return 1;
+        }
+    };
+    let replies = match std::fs::read_to_string(replies_path) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("could not read {replies_path}: {e}");
+            return 1;
+        }
+    };
+    let mut host = match MockHost::parse(&eng, &replies) {
+        Ok(h) => h,
+        Err(msg) => {
+            eprintln!("{replies_path}: {msg}");
+            return 1;
+        }
+    };
+    let mut rt = Runtime::new(eng);
+    let served = rt.spawn(iface, iface, pos, bindings).map(|inst| inst.interface).and_then(|i| {
+        if !rt.engine.interfaces[&i].external {
+            return Err(engine::runtime::RuntimeError::NotExternal(iface.to_string()));
+        }
+        rt.serve(&mut host)
+    });
+    for line in &host.seen {
+        println!("{line}");
     }
-    let name = s[..open].trim();
-    if name.is_empty() {
-        return None;
+    match served {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("{}", rt.engine.fmt_runtime_error(&e));
+            1
+        }
     }
-    if !name.chars().next().unwrap().is_alphabetic() {
-        return None;
+}
+
+fn cmd_repl(args: &[String]) -> i32 {
+    let (path, iface, pos, rest) = match args {
+        [p, i, q, rest @ ..] => (p, i, q, rest),
+        _ => {
+            eprintln!("usage: poly repl <file> <interface> <position> [name=value ...]");
+            return 1;
+        }
+    };
+    let Some(eng) = load(path) else { return 1 };
+    let bindings = match parse_bindings(&eng, rest) {
+        Ok(b) => b,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return 1;
+        }
+    };
+    let mut session = match repl::Session::new(&eng, iface, pos, bindings) {
+        Ok(s) => s,
+        Err(err) => {
+            eprintln!("{}", eng.fmt_query_error(&err));
+            return 1;
+        }
+    };
+    match repl::run(&eng, &mut session, std::io::stdin().lock(), std::io::stdout()) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
     }
-    if !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
-        return None;
+}
+
+fn cmd_replay(args: &[String]) -> i32 {
+    let (path, log_path, until) = match args {
+        [p, l] => (p, l, u64::MAX),
+        [p, l, flag, n] if flag == "--at" => match n.parse() {
+            Ok(n) => (p, l, n),
+            Err(_) => {
+                eprintln!("--at expects a sequence number, got: {n}");
+                return 1;
+            }
+        },
+        _ => {
+            eprintln!("usage: poly replay <file> <log> [--at <seq>]");
+            return 1;
+        }
+    };
+    let Some(eng) = load(path) else { return 1 };
+    let Some(events) = load_trajectory(&eng, log_path) else { return 1 };
+    let mut rt = engine::runtime::Runtime::new(eng);
+    match rt.replay_until(&events, until) {
+        Ok(()) => {
+            println!("replayed {} event(s)", rt.trajectory().len());
+            print!("{}", rt.fmt_instances());
+            0
+        }
+        Err(d) => {
+            println!("diverged at {}", rt.engine.fmt_divergence(&d));
+            1
+        }
+    }
+}
+
+/// Read and parse a trajectory log, reporting errors against `log_path`.
+fn load_trajectory(eng: &Engine, log_path: &str) -> Option<Vec<engine::trajectory::Event>> {
+    let text = match std::fs::read_to_string(log_path) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("could not read {log_path}: {e}");
+            return None;
+        }
+    };
+    match eng.parse_trajectory(&text) {
+        Ok(events) => Some(events),
+        Err(e) => {
+            eprintln!("{log_path}:{}: {}", e.line, e.message);
+            None
+        }
+    }
+}
+
+/// Pull a `--log <path>` pair out of `args`, wherever it appears.
+fn take_log_flag(args: &[String]) -> (Option<String>, Vec<String>) {
+    let mut log = None;
+    let mut rest = Vec::new();
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        match it.clone().next() {
+            Some(path) if a == "--log" && log.is_none() => {
+                log = Some(path.clone());
+                it.next();
+            }
+            _ => rest.push(a.clone()),
+        }
+    }
+    (log, rest)
+}
+
+fn cmd_check(args: &[String]) -> i32 {
+    let (path, flags) = match args {
+        [p, flags @ ..] => (p, flags),
+        _ => {
+            eprintln!("usage: poly check <file> [--deadlocks] [--invariants]");
+            return 1;
+        }
+    };
+    if let Some(f) = flags.iter().find(|f| !matches!(f.as_str(), "--deadlocks" | "--invariants")) {
+        eprintln!("unknown check: {f}");
+        return 1;
+    }
+    let Some(eng) = load(path) else { return 1 };
+    let mut findings = 0;
+    let all = flags.is_empty();
+    if all || flags.iter().any(|f| f == "--deadlocks") {
+        for d in eng.deadlocks() {
+            print!("{}", eng.fmt_deadlock(&d));
+            findings += 1;
+        }
+    }
+    if all || flags.iter().any(|f| f == "--invariants") {
+        for b in eng.check_invariants() {
+            print!("{}", eng.fmt_invariant_break(&b));
+            findings += 1;
+        }
+    }
+    if findings == 0 {
+        println!("ok");
+        0
+    } else {
+        println!("{findings} finding(s)");
+        1
     }
-    let inner = &s[open + 1..s.len() - 1];
-    Some((name, inner))
 }
 
-fn split_top_commas(s: &str) -> Result<Vec<&str>, String> {
-    let s = s.trim();
-    if s.is_empty() {
-        return Ok(Vec::new());
-    }
-    let mut out = Vec::new();
-    let mut depth = 0i32;
-    let mut start = 0usize;
-    for (i, c) in s.char_indices() {
-        match c {
-            '(' => depth += 1,
-            ')' => {
-                depth -= 1;
-                if depth < 0 {
-                    return Err("unbalanced parentheses".to_string());
+fn cmd_solve(args: &[String]) -> i32 {
+    let (path, text, decls) = match args {
+        [p, e, decls @ ..] => (p, e, decls),
+        _ => {
+            eprintln!("usage: poly solve <file> '<expr>' [name:Type ...]");
+            return 1;
+        }
+    };
+    let Some(mut eng) = load(path) else { return 1 };
+    let vars = match decls.iter().map(|d| eng.parse_var(d)).collect::<Result<Vec<_>, _>>() {
+        Ok(v) => v,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return 1;
+        }
+    };
+    let expr = match eng.parse_constraint(text, &vars) {
+        Ok(e) => e,
+        Err(diags) => {
+            for d in diags {
+                eprint!("{}", d.render(text, "<expr>"));
+            }
+            return 1;
+        }
+    };
+    let result = eng.satisfiable(&expr, &vars);
+    print!("{}", eng.fmt_satisfiability(&result));
+    if matches!(result, engine::solve::Satisfiability::Sat(_)) { 0 } else { 1 }
+}
+
+fn cmd_reach(args: &[String]) -> i32 {
+    let usage = "usage: poly reach <file> <interface> <from> <to> [--depth N] [--span N] [name=value ...]";
+    let (path, iface, from, to, rest) = match args {
+        [p, i, f, t, rest @ ..] => (p, i, f, t, rest),
+        _ => {
+            eprintln!("{usage}");
+            return 1;
+        }
+    };
+    let (bounds, kvs) = match take_bounds(rest) {
+        Ok(b) => b,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return 1;
+        }
+    };
+    let Some(eng) = load(path) else { return 1 };
+    let states = eng.parse_state(iface, from).and_then(|start| {
+        let goal = eng.parse_state(iface, to)?;
+        Ok((start, goal))
+    });
+    let ((start_pos, mut start), (goal_pos, goal)) = match states {
+        Ok(s) => s,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return 1;
+        }
+    };
+    match parse_bindings(&eng, &kvs) {
+        Ok(b) => start.extend(b),
+        Err(msg) => {
+            eprintln!("{msg}");
+            return 1;
+        }
+    }
+    match eng.reachable(iface, (start_pos, start), (goal_pos, &goal), &bounds) {
+        Ok(r) => {
+            print!("{}", eng.fmt_reach(&r));
+            if matches!(r, engine::reach::Reach::Witness(_)) { 0 } else { 1 }
+        }
+        Err(e) => {
+            eprintln!("{}", eng.fmt_query_error(&e));
+            1
+        }
+    }
+}
+
+fn cmd_ltl(args: &[String]) -> i32 {
+    let usage = "usage: poly ltl <file> <interface> <from> <formula> [--depth N] [--span N] [name=value ...]";
+    let (path, iface, from, text, rest) = match args {
+        [p, i, f, t, rest @ ..] => (p, i, f, t, rest),
+        _ => {
+            eprintln!("{usage}");
+            return 1;
+        }
+    };
+    let (bounds, kvs) = match take_bounds(rest) {
+        Ok(b) => b,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return 1;
+        }
+    };
+    let Some(mut eng) = load(path) else { return 1 };
+    let formula = match eng.parse_formula(iface, text) {
+        Ok(f) => f,
+        Err(diags) => {
+            for d in diags {
+                eprint!("{}", d.render(text, "<formula>"));
+            }
+            return 1;
+        }
+    };
+    let start = eng.parse_state(iface, from).and_then(|(pos, mut b)| {
+        b.extend(parse_bindings(&eng, &kvs)?);
+        Ok((pos, b))
+    });
+    let start = match start {
+        Ok(s) => s,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return 1;
+        }
+    };
+    match eng.check_formula(iface, start, &formula, &bounds) {
+        Ok(v) => {
+            print!("{}", eng.fmt_verdict(&v));
+            if matches!(v, engine::modelcheck::Verdict::Holds { .. }) { 0 } else { 1 }
+        }
+        Err(e) => {
+            eprintln!("{}", eng.fmt_query_error(&e));
+            1
+        }
+    }
+}
+
+/// Split `--depth N` / `--span N` off the trailing `name=value` arguments.
+fn take_bounds(args: &[String]) -> Result<(engine::reach::ReachBounds, Vec<String>), String> {
+    let mut bounds = engine::reach::ReachBounds::default();
+    let mut rest = Vec::new();
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            flag @ ("--depth" | "--span") => {
+                let Some(n) = it.next().and_then(|n| n.parse::<i64>().ok()).filter(|n| *n >= 0) else {
+                    return Err(format!("{flag} expects a non-negative number"));
+                };
+                if flag == "--depth" {
+                    bounds.max_depth = n as usize;
+                } else {
+                    bounds.int_span = n;
                 }
             }
-            ',' if depth == 0 => {
-                out.push(s[start..i].trim());
-                start = i + c.len_utf8();
+            _ => rest.push(a.clone()),
+        }
+    }
+    Ok((bounds, rest))
+}
+
+fn cmd_query(args: &[String]) -> i32 {
+    let (log_path, args) = take_log_flag(args);
+    let (path, text, rest) = match &args[..] {
+        [p, q, rest @ ..] => (p, q, rest),
+        _ => {
+            eprintln!("usage: poly query <file> '<query>' [--log <log>] [name=value ...]");
+            return 1;
+        }
+    };
+    let Some(mut eng) = load(path) else { return 1 };
+    let mut facts = eng.facts();
+    // Instance names are interned here, so the query sees them as constants.
+    if let Some(log_path) = log_path {
+        let Some(events) = load_trajectory(&eng, &log_path) else { return 1 };
+        eng.add_trajectory_facts(&mut facts, &events);
+    }
+    let parsed = match eng.parse_query(text, &facts) {
+        Ok(q) => q,
+        Err(diags) => {
+            for d in diags {
+                eprint!("{}", d.render(text, "<query>"));
             }
-            _ => {}
+            return 1;
         }
+    };
+    let bindings = match parse_bindings(&eng, rest) {
+        Ok(b) => b,
+        Err(msg) => {
+            eprintln!("{msg}");
+            return 1;
+        }
+    };
+    let answers = engine::uquery::run_query(&eng, &facts, &parsed.query, &bindings);
+    for a in &answers {
+        println!("{}", eng.fmt_answer(a, &parsed.vars));
+    }
+    if answers.is_empty() {
+        println!("no answers");
+        1
+    } else {
+        0
     }
-    if depth != 0 {
-        return Err("unbalanced parentheses".to_string());
+}
+
+fn parse_bindings(eng: &Engine, args: &[String]) -> Result<Bindings, String> {
+    let mut bindings: Bindings = std::collections::BTreeMap::new();
+    for kv in args {
+        let Some((k, v)) = kv.split_once('=') else {
+            return Err(format!("expected name=value, got: {kv}"));
+        };
+        let Some(key) = eng.interner.find(k) else {
+            return Err(format!("unknown parameter: {k}"));
+        };
+        let val = eng.parse_value(v).map_err(|msg| format!("could not parse value for {k}: {msg}"))?;
+        bindings.insert(key, val);
     }
-    out.push(s[start..].trim());
-    Ok(out)
+    Ok(bindings)
 }
 
 fn cmd_actions(args: &[String]) -> i32 {
@@ -0,0 +1,335 @@
+use std::io::{BufRead, Write};
+
+use crate::engine::eval::{eval_bool, Bindings, EvalError};
+use crate::engine::query::{GuardKind, QueryError, Step};
+use crate::engine::trajectory::{Event, HEADER};
+use crate::engine::{Engine, Sym};
+use crate::parse_bindings;
+
+
+// ============================================================================
+// Interactive simulation
+// ============================================================================
+//
+// A `Session` is one interface walked from a starting position. Every command
+// goes through `Engine::next_position` / `explain_position`; the REPL only
+// keeps the current position, its bindings, and the steps taken so far.
+
+pub struct Session {
+    interface: String,
+    start: (Sym, Bindings),
+    position: Sym,
+    bindings: Bindings,
+    history: Vec<Step>,
+    /// Bindings before each step in `history`, and the action arguments it
+    /// was given. A step's `source_bindings` has the two merged.
+    prior: Vec<(Bindings, Bindings)>,
+}
+
+enum Command<'a> {
+    Actions,
+    Undo,
+    Reset,
+    History,
+    Save(&'a str),
+    Help,
+    Quit,
+    Act(&'a str, Vec<String>),
+}
+
+fn parse_command(line: &str) -> Option<Command<'_>> {
+    let mut words = line.split_whitespace();
+    let head = words.next()?;
+    Some(match head {
+        "actions" | "ls" => Command::Actions,
+        "undo" => Command::Undo,
+        "reset" => Command::Reset,
+        "history" => Command::History,
+        "save" => Command::Save(words.next().unwrap_or("")),
+        "help" | "?" => Command::Help,
+        "quit" | "exit" => Command::Quit,
+        action => Command::Act(action, words.map(str::to_string).collect()),
+    })
+}
+
+impl Session {
+    pub fn new(
+        eng: &Engine,
+        interface: &str,
+        position: &str,
+        bindings: Bindings,
+    ) -> Result<Self, QueryError> {
+        let exp = eng.explain_position(interface, position)?;
+        // Start only where `Runtime::spawn` would: every param bound, and
+        // the position guard holding.
+        let sig = eng.signature(exp.interface).unwrap();
+        let pos = sig.position(&exp.position).unwrap();
+        if let Some(p) = sig.params().iter().chain(&pos.params).find(|p| !bindings.contains_key(&p.name)) {
+            return Err(QueryError::EvalFailed(EvalError::Unbound(p.name)));
+        }
+        if let Some(g) = &pos.guard {
+            if !eval_bool(eng, g, &bindings).map_err(QueryError::EvalFailed)? {
+                return Err(QueryError::GuardFailed {
+                    interface: interface.to_string(),
+                    position: position.to_string(),
+                    kind: GuardKind::Position,
+                });
+            }
+        }
+        Ok(Self {
+            interface: interface.to_string(),
+            start: (exp.position, bindings.clone()),
+            position: exp.position,
+            bindings,
+            history: Vec::new(),
+            prior: Vec::new(),
+        })
+    }
+
+    fn position_name<'e>(&self, eng: &'e Engine) -> &'e str {
+        eng.resolve(self.position)
+    }
+
+    pub fn fmt_state(&self, eng: &Engine) -> String {
+        format!(
+            "{}.{}{}\n",
+            self.interface,
+            self.position_name(eng),
+            eng.fmt_bindings(&self.bindings),
+        )
+    }
+
+    /// Every action available at the current position, with the outcome of
+    /// trying it under the current bindings. Actions whose guard or target
+    /// needs direction params are reported as such rather than as failures.
+    pub fn fmt_actions(&self, eng: &Engine) -> String {
+        let exp = match eng.explain_position(&self.interface, self.position_name(eng)) {
+            Ok(exp) => exp,
+            Err(e) => return format!("{}\n", eng.fmt_query_error(&e)),
+        };
+        if exp.actions.is_empty() {
+            return "  (no actions)\n".to_string();
+        }
+        let pos = &eng.interfaces[&exp.interface].position(&exp.position).unwrap();
+        let mut out = String::new();
+        for a in &exp.actions {
+            let mut label = eng.resolve(*a).to_string();
+            if let Some(dir) = pos.directions.iter().find(|d| d.name == *a) {
+                if !dir.params.is_empty() {
+                    label.push_str(&eng.fmt_param_list(&dir.params));
+                }
+            }
+            let status = match eng.next_position(
+                &self.interface,
+                self.position_name(eng),
+                eng.resolve(*a),
+                self.bindings.clone(),
+            ) {
+                Ok(step) => format!(
+                    "-> {}{}",
+                    eng.resolve(step.target_position),
+                    eng.fmt_bindings(&step.target_bindings),
+                ),
+                Err(QueryError::EvalFailed(EvalError::Unbound(_))) => "needs arguments".to_string(),
+                Err(e) => format!("blocked: {}", eng.fmt_query_error(&e)),
+            };
+            out.push_str(&format!("  {label:<20} {status}\n"));
+        }
+        out
+    }
+
+    pub fn apply(&mut self, eng: &Engine, action: &str, args: &[String]) -> Result<&Step, String> {
+        let args = parse_bindings(eng, args)?;
+        let mut bindings = self.bindings.clone();
+        bindings.extend(args.clone());
+        let step = eng
+            .next_position(&self.interface, self.position_name(eng), action, bindings)
+            .map_err(|e| eng.fmt_query_error(&e))?;
+        self.position = step.target_position;
+        let prior = std::mem::replace(&mut self.bindings, step.target_bindings.clone());
+        self.prior.push((prior, args));
+        self.history.push(step);
+        Ok(self.history.last().unwrap())
+    }
+
+    pub fn undo(&mut self) -> Option<Step> {
+        let step = self.history.pop()?;
+        self.position = step.source_position;
+        self.bindings = self.prior.pop().unwrap().0;
+        Some(step)
+    }
+
+    pub fn reset(&mut self) {
+        self.position = self.start.0;
+        self.bindings = self.start.1.clone();
+        self.history.clear();
+        self.prior.clear();
+    }
+
+    pub fn fmt_history(&self, eng: &Engine) -> String {
+        if self.history.is_empty() {
+            return "  (no steps)\n".to_string();
+        }
+        self.history
+            .iter()
+            .enumerate()
+            .map(|(i, s)| format!("{:>3}  {}", i + 1, eng.fmt_step(s)))
+            .collect()
+    }
+
+    /// Write the session as a trajectory log (one instance, named after the
+    /// interface) that `poly replay` can check against the file.
+    pub fn save(&self, eng: &Engine, path: &str) -> std::io::Result<()> {
+        let instance = self.interface.to_lowercase();
+        let mut events = vec![Event::Spawn {
+            seq: 0,
+            instance: instance.clone(),
+            interface: eng.interner.find(&self.interface).unwrap(),
+            position: self.start.0,
+            bindings: self.start.1.clone(),
+        }];
+        for (i, (step, (before, args))) in self.history.iter().zip(&self.prior).enumerate() {
+            events.push(Event::Step {
+                seq: i as u64 + 1,
+                instance: instance.clone(),
+                source: step.source_position,
+                source_bindings: before.clone(),
+                action: step.action,
+                args: args.clone(),
+                target: step.target_position,
+                target_bindings: step.target_bindings.clone(),
+                induced: false,
+            });
+        }
+        let mut out = format!("{HEADER}\n");
+        for e in &events {
+            out.push_str(&eng.fmt_event(e));
+            out.push('\n');
+        }
+        std::fs::write(path, out)
+    }
+}
+
+
+// ============================================================================
+// Loop
+// ============================================================================
+
+const HELP: &str = "\
+  <Action> [name=value ...]   apply an action (direction params as bindings)
+  actions                     list actions and whether they can fire now
+  undo                        step back one action
+  reset                       return to the starting position
+  history                     list the steps taken so far
+  save <file>                 write the steps taken so far as a trajectory log
+  quit                        leave the REPL
+";
+
+pub fn run(
+    eng: &Engine,
+    session: &mut Session,
+    input: impl BufRead,
+    mut out: impl Write,
+) -> std::io::Result<()> {
+    write!(out, "{}{}", session.fmt_state(eng), session.fmt_actions(eng))?;
+    write!(out, "> ")?;
+    out.flush()?;
+    for line in input.lines() {
+        let line = line?;
+        let Some(cmd) = parse_command(&line) else {
+            write!(out, "> ")?;
+            out.flush()?;
+            continue;
+        };
+        match cmd {
+            Command::Actions => write!(out, "{}", session.fmt_actions(eng))?,
+            Command::Undo => match session.undo() {
+                Some(step) => write!(
+                    out,
+                    "undid {}\n{}",
+                    eng.resolve(step.action),
+                    session.fmt_state(eng),
+                )?,
+                None => writeln!(out, "nothing to undo")?,
+            },
+            Command::Reset => {
+                session.reset();
+                write!(out, "{}{}", session.fmt_state(eng), session.fmt_actions(eng))?;
+            }
+            Command::History => write!(out, "{}", session.fmt_history(eng))?,
+            Command::Save("") => writeln!(out, "usage: save <file>")?,
+            Command::Save(path) => match session.save(eng, path) {
+                Ok(()) => writeln!(out, "saved {} step(s) to {path}", session.history.len())?,
+                Err(e) => writeln!(out, "could not write {path}: {e}")?,
+            },
+            Command::Help => write!(out, "{HELP}")?,
+            Command::Quit => return Ok(()),
+            Command::Act(action, args) => match session.apply(eng, action, &args) {
+                Ok(step) => {
+                    write!(out, "{}{}", eng.fmt_step(step), session.fmt_actions(eng))?
+                }
+                Err(msg) => writeln!(out, "error: {msg}")?,
+            },
+        }
+        write!(out, "> ")?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transcript(script: &str) -> String {
+        let src = std::fs::read_to_string("examples/counter.poly").unwrap();
+        let eng = Engine::load(&src).expect("load counter");
+        let n = eng.interner.find("n").unwrap();
+        let start = Bindings::from([(n, crate::engine::eval::Value::Int(0))]);
+        let mut session = Session::new(&eng, "Counter", "Count", start).unwrap();
+        let mut out = Vec::new();
+        run(&eng, &mut session, script.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn lists_actions_with_guard_status() {
+        let out = transcript("");
+        assert!(out.contains("Increment            -> Count[n=1]"), "{out}");
+        assert!(out.contains("Decrement            blocked: direction guard failed"), "{out}");
+    }
+
+    #[test]
+    fn steps_undo_and_history() {
+        let out = transcript("Increment\nIncrement\nundo\nhistory\n");
+        assert!(out.contains("Counter.Count[n=1] --Increment--> Counter.Count[n=2]"), "{out}");
+        assert!(out.contains("undid Increment\nCounter.Count[n=1]"), "{out}");
+        assert!(out.contains("  1  Counter.Count[n=0] --Increment--> Counter.Count[n=1]\n> "), "{out}");
+    }
+
+    #[test]
+    fn sessions_start_only_where_an_instance_could() {
+        let src = std::fs::read_to_string("examples/counter.poly").unwrap();
+        let eng = Engine::load(&src).expect("load counter");
+        let n = eng.interner.find("n").unwrap();
+        let start = |b: Bindings| match Session::new(&eng, "Counter", "Count", b) {
+            Ok(_) => "ok".to_string(),
+            Err(e) => eng.fmt_query_error(&e),
+        };
+        assert_eq!(start(Bindings::from([(n, crate::engine::eval::Value::Int(-5))])), "position guard failed at Counter.Count");
+        assert_eq!(start(Bindings::new()), "evaluation failed: unbound variable: n");
+        assert_eq!(start(Bindings::from([(n, crate::engine::eval::Value::Int(5))])), "ok");
+    }
+
+    #[test]
+    fn failed_actions_leave_state_unchanged() {
+        let out = transcript("Decrement\nreset\n");
+        assert!(out.contains("error: direction guard failed at Counter.Count"), "{out}");
+        assert!(out.ends_with("Counter.Count[n=0]\n  Increment            -> Count[n=1]\n  Decrement            blocked: direction guard failed at Counter.Count\n> "), "{out}");
+    }
+}